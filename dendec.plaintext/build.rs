@@ -0,0 +1,41 @@
+/// build.rs — Compress the embedded refer tables
+///
+/// Each enabled assembly feature embeds a lookup table from data/. The
+/// tables are stored plain in the repository, where `dendec table verify`
+/// and `table stats` can read them, and zstd-compressed here into OUT_DIR
+/// for include_bytes!. refer decompresses one on first use.
+use std::path::Path;
+
+/// (cargo feature, table in data/, name in OUT_DIR)
+const TABLES: &[(&str, &str, &str)] = &[
+    ("hg19", "hg19.bin", "hg19.bin.zst"),
+    ("hg38", "table.bin", "hg38.bin.zst"),
+    ("mm39", "mm39.bin", "mm39.bin.zst"),
+    ("sacCer3", "sacCer3.bin", "sacCer3.bin.zst"),
+    ("ecoli", "ecoli.bin", "ecoli.bin.zst"),
+];
+
+const LEVEL: i32 = 19;
+
+fn main() {
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR not set by cargo");
+
+    for (feature, source, target) in TABLES {
+        let enabled = std::env::var_os(format!("CARGO_FEATURE_{}", feature.to_uppercase())).is_some();
+        if !enabled {
+            continue;
+        }
+        let source = Path::new("data").join(source);
+        println!("cargo:rerun-if-changed={}", source.display());
+
+        let plain = std::fs::read(&source).unwrap_or_else(|e| {
+            panic!(
+                "feature `{feature}` needs {}: {e} — generate it with `dendec table build` \
+                 or build without the feature",
+                source.display()
+            )
+        });
+        let packed = zstd::bulk::compress(&plain, LEVEL).expect("zstd compression failed");
+        std::fs::write(Path::new(&out_dir).join(target), packed).expect("cannot write to OUT_DIR");
+    }
+}
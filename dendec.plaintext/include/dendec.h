@@ -0,0 +1,128 @@
+#ifndef DENDEC_H
+#define DENDEC_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit; see cbindgen.toml. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a libdendec call.
+ */
+typedef enum DendecStatus {
+  /**
+   * Success; the out-pointers are set.
+   */
+  DENDEC_STATUS_OK = 0,
+  /**
+   * A required pointer was NULL, or a string not valid UTF-8.
+   */
+  DENDEC_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * Decryption failed: wrong password or corrupted data.
+   */
+  DENDEC_STATUS_WRONG_PASSWORD = 2,
+  /**
+   * Any other failure; see dendec_last_error.
+   */
+  DENDEC_STATUS_FAILED = 3,
+  /**
+   * dendec panicked. A bug; please report it with dendec_last_error.
+   */
+  DENDEC_STATUS_PANIC = 4,
+} DendecStatus;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Encrypt `len` bytes at `plaintext` under `password` into a DNA
+ * string, stored in `*dna_out`.
+ *
+ * # Safety
+ * `plaintext` must point to `len` readable bytes (it may be NULL when
+ * `len` is 0), `password` to a NUL-terminated string, and `dna_out` to
+ * writable storage for a pointer.
+ */
+DendecStatus dendec_encode(const uint8_t *plaintext,
+                           size_t len,
+                           const char *password,
+                           char **dna_out);
+
+/**
+ * Decrypt the DNA string `dna` with `password`. The plaintext is stored
+ * in `*plaintext_out` and its length in `*len_out`; it is bytes, not a
+ * NUL-terminated string.
+ *
+ * # Safety
+ * `dna` and `password` must point to NUL-terminated strings, and
+ * `plaintext_out` and `len_out` to writable storage.
+ */
+DendecStatus dendec_decode(const char *dna,
+                           const char *password,
+                           uint8_t **plaintext_out,
+                           size_t *len_out);
+
+/**
+ * Translate the DNA string `dna`, as dendec_encode returns it, into a
+ * BED file of genome coordinates under `assembly` ("hg38", "mm39", …;
+ * NULL for hg38), stored in `*bed_out`. Like `dendec refer` with no
+ * options: chunk names, one random coordinate per chunk.
+ *
+ * # Safety
+ * `dna` must point to a NUL-terminated string, `assembly` to one or be
+ * NULL, and `bed_out` to writable storage for a pointer.
+ */
+DendecStatus dendec_refer(const char *dna, const char *assembly, char **bed_out);
+
+/**
+ * Translate `refer_text`, a file dendec_refer or `dendec refer` wrote in
+ * any format, back into the DNA string, stored in `*dna_out`. Files
+ * written with --shuffle, --keyed names or as bedGraph need a password
+ * and are refused here.
+ *
+ * # Safety
+ * `refer_text` must point to a NUL-terminated string and `dna_out` to
+ * writable storage for a pointer.
+ */
+DendecStatus dendec_unrefer(const char *refer_text, char **dna_out);
+
+/**
+ * Free a string from dendec_encode, dendec_refer or dendec_unrefer.
+ * NULL is ignored.
+ *
+ * # Safety
+ * `s` must be NULL or a string returned by libdendec, not yet freed.
+ */
+void dendec_string_free(char *s);
+
+/**
+ * Free the plaintext from dendec_decode, given its length. NULL is
+ * ignored.
+ *
+ * # Safety
+ * `bytes` must be NULL or a buffer returned by dendec_decode, not yet
+ * freed, and `len` the length returned with it.
+ */
+void dendec_bytes_free(uint8_t *bytes, size_t len);
+
+/**
+ * What the last failed call on this thread went wrong with; empty if
+ * none has. Valid until the next call on the same thread. Do not free.
+ */
+const char *dendec_last_error(void);
+
+/**
+ * The libdendec version, e.g. "0.1.0". Do not free.
+ */
+const char *dendec_version(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DENDEC_H */
@@ -0,0 +1,151 @@
+/// bench.rs — Local performance measurement for `dendec bench`
+///
+/// Times each stage of the pipeline in isolation so users can see where
+/// the cost of an encode goes on their hardware and compare versions:
+///
+///   KDF         Argon2id with the production parameters (per run)
+///   AEAD        ChaCha20-Poly1305 chunk sealing, one core and all cores
+///   DNA         bytes → bases and bases → bytes conversion
+///   end-to-end  encode_stream of the whole buffer into a sink
+///
+/// The data is random bytes generated up front; generation time is not
+/// counted. Nothing is written to disk.
+use std::io;
+use std::time::{Duration, Instant};
+
+use log::info;
+use rand::RngCore;
+use rayon::prelude::*;
+
+use crate::crypto::{
+    derive_dna_mapping, derive_master_key, encrypt_chunk, random_file_salt, random_nonce_prefix,
+    ARGON2_M_COST, ARGON2_T_COST,
+};
+use crate::dna::{bytes_to_dna, dna_to_bytes};
+use crate::encoding::{encode_stream, CHUNK_SIZE};
+use crate::error::Result;
+
+const BENCH_PASSWORD: &str = "dendec-bench";
+
+/// Run every benchmark and print the report to stdout.
+pub fn run_bench(size_mib: usize, kdf_runs: usize) -> Result<()> {
+    let size = size_mib.max(1) * 1024 * 1024;
+    let kdf_runs = kdf_runs.max(1);
+
+    println!(
+        "dendec {} bench  |  {} worker thread(s)  |  {} MiB buffer",
+        env!("CARGO_PKG_VERSION"),
+        rayon::current_num_threads(),
+        size_mib.max(1)
+    );
+    println!();
+
+    // ── KDF ───────────────────────────────────────────────────────────
+    info!("  Timing Argon2id ({kdf_runs} run(s))...");
+    let mut kdf_times = Vec::with_capacity(kdf_runs);
+    let mut master = None;
+    for _ in 0..kdf_runs {
+        let start = Instant::now();
+        master = Some(derive_master_key(BENCH_PASSWORD)?);
+        kdf_times.push(start.elapsed());
+    }
+    let keys = master
+        .expect("kdf_runs is at least 1")
+        .file_keys(&random_file_salt());
+    let kdf_min = kdf_times.iter().min().copied().unwrap_or_default();
+    let kdf_mean = kdf_times.iter().sum::<Duration>() / kdf_runs as u32;
+
+    // ── AEAD ──────────────────────────────────────────────────────────
+    info!("  Timing ChaCha20-Poly1305...");
+    let mut data = vec![0u8; size];
+    rand::thread_rng().fill_bytes(&mut data);
+    let prefix = random_nonce_prefix();
+
+    let start = Instant::now();
+    for (i, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+        encrypt_chunk(&keys.cipher_key, &prefix, i as u32, false, chunk)?;
+    }
+    let aead_single = start.elapsed();
+
+    let start = Instant::now();
+    data.par_chunks(CHUNK_SIZE)
+        .enumerate()
+        .map(|(i, chunk)| encrypt_chunk(&keys.cipher_key, &prefix, i as u32, false, chunk))
+        .collect::<Result<Vec<_>>>()?;
+    let aead_parallel = start.elapsed();
+
+    // ── DNA conversion ────────────────────────────────────────────────
+    info!("  Timing DNA conversion...");
+    let mapping = derive_dna_mapping(keys.mapping_seed);
+
+    let start = Instant::now();
+    let dna = bytes_to_dna(&data, &mapping);
+    let to_dna = start.elapsed();
+
+    let start = Instant::now();
+    dna_to_bytes(&dna, &mapping)?;
+    let from_dna = start.elapsed();
+    drop(dna);
+
+    // ── End to end ────────────────────────────────────────────────────
+    info!("  Timing full encode...");
+    let start = Instant::now();
+    encode_stream(&data[..], io::sink(), BENCH_PASSWORD, None)?;
+    let end_to_end = start.elapsed();
+    info!("");
+
+    // ── Report ────────────────────────────────────────────────────────
+    println!(
+        "  KDF  (Argon2id, {} MiB, t={})   min {}   mean {}",
+        ARGON2_M_COST / 1024,
+        ARGON2_T_COST,
+        fmt_duration(kdf_min),
+        fmt_duration(kdf_mean)
+    );
+    println!("  AEAD seal, 1 thread            {}", fmt_rate(size, aead_single));
+    println!("  AEAD seal, all threads         {}", fmt_rate(size, aead_parallel));
+    println!("  bytes → DNA                    {}", fmt_rate(size, to_dna));
+    println!("  DNA → bytes                    {}", fmt_rate(size, from_dna));
+    println!(
+        "  encode end-to-end              {}  ({} incl. one KDF)",
+        fmt_rate(size, end_to_end.saturating_sub(kdf_mean)),
+        fmt_duration(end_to_end)
+    );
+
+    Ok(())
+}
+
+/// Format a throughput as MiB/s of plaintext.
+pub(crate) fn fmt_rate(bytes: usize, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    if secs <= 0.0 {
+        return "—".to_string();
+    }
+    format!("{:.1} MiB/s", bytes as f64 / (1024.0 * 1024.0) / secs)
+}
+
+/// Format a duration as milliseconds below one second, seconds above.
+pub(crate) fn fmt_duration(d: Duration) -> String {
+    if d < Duration::from_secs(1) {
+        format!("{} ms", d.as_millis())
+    } else {
+        format!("{:.2} s", d.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fmt_rate() {
+        assert_eq!(fmt_rate(2 * 1024 * 1024, Duration::from_secs(1)), "2.0 MiB/s");
+        assert_eq!(fmt_rate(1024, Duration::ZERO), "—");
+    }
+
+    #[test]
+    fn test_fmt_duration() {
+        assert_eq!(fmt_duration(Duration::from_millis(250)), "250 ms");
+        assert_eq!(fmt_duration(Duration::from_millis(1500)), "1.50 s");
+    }
+}
@@ -0,0 +1,155 @@
+/// checksum.rs — BLAKE2b-256 of what encode and decode read and write
+///
+/// `--checksum` prints the hash of the plaintext and of the DNA after an
+/// encode or decode, one `<hex>  <name>` line each, the format and hash
+/// of `b2sum -l 256`, so either side of a transfer can check a file with
+/// standard tools. `--expect HEX` refuses to write anything unless the
+/// plaintext hashes to HEX. On decode either hash is accepted, the
+/// plaintext's or the DNA input's, so the sender may pass on whichever
+/// line they have.
+///
+/// The DNA hash covers the exact bytes of the file or stream: grouping
+/// spaces and the newline ending stdout output included, color and
+/// rulers not.
+///
+/// The lines are the result the user asked for, not status, so they do
+/// not go through the logger and `-q` keeps them: they go to stdout when
+/// the output went to a file, and to stderr when stdout carries it.
+use std::io::{self, Write};
+use std::path::Path;
+
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+
+use crate::error::{DendecError, Result};
+use crate::wrap::manifest::{from_hex, to_hex};
+
+/// A BLAKE2b-256 hash.
+pub type Checksum = [u8; 32];
+
+type Blake2b256 = Blake2b<U32>;
+
+/// BLAKE2b-256 of `bytes`.
+pub fn checksum(bytes: &[u8]) -> Checksum {
+    Blake2b256::digest(bytes).into()
+}
+
+/// Lowercase hex, as `b2sum` prints it.
+pub fn hex(sum: &Checksum) -> String {
+    to_hex(sum)
+}
+
+/// Parse an `--expect` value: 64 hex digits, either case.
+pub fn parse_checksum(s: &str) -> std::result::Result<Checksum, String> {
+    from_hex(s.trim())
+        .ok_or_else(|| format!("invalid checksum '{s}' (expected 64 hex digits of BLAKE2b-256)"))
+}
+
+/// Check `--expect`: `expected`, if given, must equal one of `accepted`.
+pub fn check_expected(expected: Option<Checksum>, accepted: &[Option<Checksum>]) -> Result<()> {
+    match expected {
+        Some(e) if !accepted.contains(&Some(e)) => Err(DendecError::ChecksumMismatch(hex(&e))),
+        _ => Ok(()),
+    }
+}
+
+/// Write `--checksum` lines, `<hex>  <name>` as b2sum prints them;
+/// `-` names stdin, stdout or an argument.
+pub fn write_lines(out: &mut impl Write, sums: &[(Checksum, Option<&Path>)]) -> io::Result<()> {
+    for (sum, path) in sums {
+        let name = path.map(|p| p.display().to_string()).unwrap_or_else(|| "-".into());
+        writeln!(out, "{}  {name}", hex(sum))?;
+    }
+    out.flush()
+}
+
+/// Print `--checksum` lines to stdout, or to stderr when stdout carries
+/// the output.
+pub fn report(sums: &[(Checksum, Option<&Path>)], stdout_is_output: bool) -> io::Result<()> {
+    match stdout_is_output {
+        true => write_lines(&mut io::stderr().lock(), sums),
+        false => write_lines(&mut io::stdout().lock(), sums),
+    }
+}
+
+/// A writer that hashes what passes through it, when asked to.
+pub struct HashWriter<W> {
+    inner: W,
+    hasher: Option<Blake2b256>,
+}
+
+impl<W: Write> HashWriter<W> {
+    pub fn new(inner: W, enabled: bool) -> Self {
+        Self { inner, hasher: enabled.then(Blake2b256::new) }
+    }
+
+    /// The inner writer, and the hash of everything written if enabled.
+    pub fn finish(self) -> (W, Option<Checksum>) {
+        (self.inner, self.hasher.map(|h| h.finalize().into()))
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_matches_b2sum() {
+        // printf abc | b2sum -l 256
+        let abc = "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319";
+        assert_eq!(hex(&checksum(b"abc")), abc);
+        assert_eq!(parse_checksum(&abc.to_uppercase()).unwrap(), checksum(b"abc"));
+        assert!(parse_checksum("abc").is_err());
+
+        let mut w = HashWriter::new(Vec::new(), true);
+        w.write_all(b"a").unwrap();
+        w.write_all(b"bc").unwrap();
+        let (out, sum) = w.finish();
+        assert_eq!(out, b"abc");
+        assert_eq!(sum, Some(checksum(b"abc")));
+        assert_eq!(HashWriter::new(Vec::new(), false).finish().1, None);
+    }
+
+    #[test]
+    fn test_decode_expect_accepts_either_hash() {
+        let (plaintext, dna) = (Some(checksum(b"plain")), Some(checksum(b"ACGT")));
+        assert!(check_expected(None, &[plaintext, dna]).is_ok());
+        assert!(check_expected(plaintext, &[plaintext, dna]).is_ok());
+        assert!(check_expected(dna, &[plaintext, dna]).is_ok());
+        let other = checksum(b"other");
+        assert!(matches!(
+            check_expected(Some(other), &[plaintext, dna]),
+            Err(DendecError::ChecksumMismatch(h)) if h == hex(&other)
+        ));
+        // Encode only has the plaintext to go by
+        assert!(check_expected(dna, &[plaintext]).is_err());
+    }
+
+    #[test]
+    fn test_lines_survive_quiet() {
+        // -q raises the log level to warnings; the lines must not care
+        let before = log::max_level();
+        log::set_max_level(crate::logging::level_for(-1));
+        let mut out = Vec::new();
+        let sums = [(checksum(b"abc"), Some(Path::new("a.txt"))), (checksum(b""), None)];
+        write_lines(&mut out, &sums).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines, [format!("{}  a.txt", hex(&checksum(b"abc"))), format!("{}  -", hex(&checksum(b"")))]);
+        log::set_max_level(before);
+    }
+}
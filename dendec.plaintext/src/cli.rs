@@ -1,6 +1,15 @@
 /// cli.rs — dendec command-line interface
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::checksum::{parse_checksum, Checksum};
+use crate::refer::assembly::Assembly;
+use crate::refer::chrom::ChromStyle;
+use crate::refer::naming::NameStyle;
+use crate::refer::format::Format;
+use crate::logging::LogFormat;
+use crate::view::ColorMode;
 
 /// dendec — DNA Encode/Decode
 #[derive(Parser, Debug)]
@@ -14,61 +23,249 @@ use std::path::PathBuf;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
+
+    /// More detail on stderr: -v for debug, -vv for trace
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only warnings and errors on stderr
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Format of stderr messages: text, or json for one object per line
+    /// [default: text]
+    #[arg(long, value_name = "FORMAT", global = true, default_value_t = LogFormat::Text, hide_default_value = true)]
+    pub log_format: LogFormat,
 }
 
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
+// Parsed once per run; Wrap's many flags are not worth boxing
 pub enum Command {
     /// Encode text or a file into an encrypted DNA sequence
     ///
     /// Examples:
     ///   dendec encode "Hello"
     ///   dendec encode --file src/main.rs --as main.rs.dna
+    ///   dendec encode --file a.txt --file b.pdf --as-dir out/
     Encode {
         /// Inline text to encode. Omit when using --file.
         text: Option<String>,
 
-        /// Read input from this file path (binary-safe, raw bytes)
+        /// Read input from this file path (binary-safe, raw bytes). Repeat
+        /// with --as-dir to encode several files under one password prompt
         #[arg(short, long, value_name = "PATH")]
-        file: Option<PathBuf>,
+        file: Vec<PathBuf>,
 
         /// Write DNA output to this file instead of stdout
         #[arg(long = "as", value_name = "PATH")]
         save_as: Option<PathBuf>,
 
+        /// Write each --file to <NAME>.dna in this directory, created if
+        /// missing. The originals are left in place
+        #[arg(long, value_name = "DIR", requires = "file",
+              conflicts_with_all = ["text", "save_as", "pad", "hidden", "duress", "checksum", "expect", "stats"])]
+        as_dir: Option<PathBuf>,
+
         /// Display DNA output in groups of N bases (default: continuous)
         #[arg(short, long, value_name = "N")]
         group: Option<usize>,
+
+        /// Lay stdout out for reading: colored bases, wrapped to the
+        /// terminal with position rulers. auto does so on a terminal only
+        /// [default: auto]
+        #[arg(long, value_name = "WHEN", default_value_t = ColorMode::Auto, hide_default_value = true)]
+        color: ColorMode,
+
+        /// Overwrite an existing output file
+        #[arg(long, conflicts_with = "backup")]
+        force: bool,
+
+        /// Keep an existing output file as <NAME>.~N~ before writing
+        #[arg(long)]
+        backup: bool,
+
+        /// Encode this file now and again whenever it changes, to --as,
+        /// until interrupted. Each change replaces --as atomically.
+        #[arg(long, value_name = "PATH", requires = "save_as",
+              conflicts_with_all = ["text", "file", "passwords", "hidden", "duress", "checksum", "expect", "stats"])]
+        watch: Option<PathBuf>,
+
+        /// Print the BLAKE2b-256 of the plaintext and of the DNA written,
+        /// as `b2sum -l 256` would
+        #[arg(long)]
+        checksum: bool,
+
+        /// Refuse to encode unless the plaintext's BLAKE2b-256 is HEX
+        #[arg(long, value_name = "HEX", value_parser = parse_checksum)]
+        expect: Option<Checksum>,
+
+        /// Finish with sizes, expansion, and the time spent in the KDF,
+        /// the cipher and DNA conversion
+        #[arg(long)]
+        stats: bool,
+
+        /// Lock with N passwords, any one of which decodes; prompts for each.
+        /// The result can later be rekeyed to add or revoke passwords.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=16))]
+        passwords: Option<u8>,
+
+        /// Follow the payload with random bytes as long as itself, so the
+        /// output cannot be told from one carrying a --hidden payload
+        #[arg(long, requires = "passwords")]
+        pad: bool,
+
+        /// Also hide this file in the output under a second password. The
+        /// decoy password decodes the input, the hidden one this file, and
+        /// the output looks like --passwords 2 --pad
+        #[arg(long, value_name = "PATH", conflicts_with = "passwords")]
+        hidden: Option<PathBuf>,
+
+        /// Register a duress password that decodes to this harmless file
+        /// instead of the input. Same layout as --hidden, with the roles
+        /// named the other way round
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["passwords", "hidden"])]
+        duress: Option<PathBuf>,
     },
 
     /// Decode an encrypted DNA sequence back to text or a file
     ///
+    /// Without --as, decoded bytes go to stdout: verbatim when stdout is a
+    /// pipe or file, as UTF-8 text when it is a terminal.
+    ///
     /// Examples:
     ///   dendec decode "ATGC..."
     ///   dendec decode --file main.rs.dna --as main.rs
+    ///   dendec decode --file backup.tar.dna | tar x
+    ///   dendec decode --file peaks.bed --as secret.pdf
     Decode {
         /// Inline DNA sequence to decode. Omit when using --file.
         dna: Option<String>,
 
-        /// Read DNA input from this file path
+        /// Read DNA input from this file path. A file from `dendec refer`,
+        /// in any --format, is unreferred first.
         #[arg(short, long, value_name = "PATH")]
         file: Option<PathBuf>,
 
-        /// Write decoded output to this file instead of stdout
+        /// Write decoded output to this file instead of stdout ("-" writes raw bytes to stdout)
         #[arg(long = "as", value_name = "PATH")]
         save_as: Option<PathBuf>,
+
+        /// Overwrite an existing output file
+        #[arg(long, conflicts_with = "backup")]
+        force: bool,
+
+        /// Keep an existing output file as <NAME>.~N~ before writing
+        #[arg(long)]
+        backup: bool,
+
+        /// Print the BLAKE2b-256 of the DNA read and of the plaintext, as
+        /// `b2sum -l 256` would
+        #[arg(long)]
+        checksum: bool,
+
+        /// Refuse to write the output unless the plaintext's, or the DNA
+        /// input's, BLAKE2b-256 is HEX
+        #[arg(long, value_name = "HEX", value_parser = parse_checksum)]
+        expect: Option<Checksum>,
+
+        /// Finish with sizes, expansion, and the time spent in the KDF,
+        /// the cipher and DNA conversion
+        #[arg(long)]
+        stats: bool,
+
+        /// Report every failure as one error after one key derivation, so
+        /// neither the message nor the timing says where decoding stopped
+        #[arg(long)]
+        hardened: bool,
+    },
+
+    /// Replace the passwords of a file encoded with --passwords
+    ///
+    /// Unlocks the file with one current password, then writes it back
+    /// locked with a new set of N passwords. A password left out can no
+    /// longer decode the file. Only the key slots change; the payload is
+    /// copied as it is, so rekeying a large archive is quick. Anyone who
+    /// decoded the file before still holds its contents.
+    ///
+    /// Examples:
+    ///   dendec rekey --file team.tar.dna --passwords 3
+    ///   dendec rekey --file team.tar.dna --passwords 2 --as team-v2.tar.dna
+    Rekey {
+        /// File encoded with --passwords
+        #[arg(short, long, value_name = "PATH")]
+        file: PathBuf,
+
+        /// Number of passwords to lock the new copy with
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=16))]
+        passwords: u8,
+
+        /// Write the rekeyed copy here instead of replacing --file
+        #[arg(long = "as", value_name = "PATH")]
+        save_as: Option<PathBuf>,
+
+        /// Display DNA output in groups of N bases (default: continuous)
+        #[arg(short, long, value_name = "N")]
+        group: Option<usize>,
+
+        /// Overwrite an existing --as file
+        #[arg(long, conflicts_with = "backup")]
+        force: bool,
+
+        /// Keep an existing --as file as <NAME>.~N~ before writing
+        #[arg(long)]
+        backup: bool,
+    },
+
+    /// Re-encode payloads written under an outdated format
+    ///
+    /// Walks the given files and directories for payloads whose header
+    /// version is no longer the one encode writes (today: v1), decodes
+    /// each, re-encodes it under the current format and replaces it once
+    /// the new copy decodes back to the same bytes. Permissions and
+    /// modification times are kept. Current payloads are left alone.
+    ///
+    /// Examples:
+    ///   dendec migrate --dry-run ./archive
+    ///   dendec migrate ./archive old.dna
+    Migrate {
+        /// Files or directories to look in (default: current directory)
+        #[arg(value_name = "PATH", default_value = ".")]
+        paths: Vec<PathBuf>,
+
+        /// List the outdated payloads without changing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Display DNA output in groups of N bases (default: continuous)
+        #[arg(short, long, value_name = "N")]
+        group: Option<usize>,
     },
 
     /// Run a command and encode or decode all files it produces
     ///
     /// wrap intercepts the output of any shell command and applies a DNA
     /// transform to every appropriate file. Directory structure is preserved
-    /// exactly. Binary files are skipped automatically.
+    /// exactly. Binary files are skipped automatically, and encode leaves
+    /// out anything .gitignore or .dendecignore excludes. A file whose output
+    /// path already exists is reported as failed unless --force or --backup
+    /// is given.
     ///
     /// Examples:
     ///   dendec wrap -e git clone https://github.com/user/repo
     ///   dendec wrap -d git clone https://github.com/user/repo
     ///   dendec wrap -e curl -o config.toml https://example.com/config.toml
     ///   dendec wrap -d curl -o config.toml.dna https://example.com/config.toml.dna
+    ///   dendec wrap -e --keep ./notes
+    ///   dendec wrap -e --abort-on-error ./myproject
+    ///   dendec wrap -e --resume ./myproject
+    ///   dendec wrap -e --archive myproject.dna ./myproject
+    ///   dendec wrap -d --archive myproject.dna ./restored
+    ///   dendec wrap -e --refer --assembly mm39 ./myproject
+    ///   dendec wrap -d --post-hook 'chmod 600 "$1"' ./secrets
+    ///   dendec wrap -e --jobs 2 --throttle 20ms ./photos
+    ///   dendec wrap -e --scan-root build -- make dist
+    ///   dendec wrap -d -c -- 'curl -sL https://example.com/src.tar.gz | tar xz'
     Wrap {
         /// Encode mode — transform files to .dna
         #[arg(short = 'e', long = "encode")]
@@ -78,25 +275,200 @@ pub enum Command {
         #[arg(short = 'd', long = "decode")]
         decode: bool,
 
+        /// Overwrite existing .dna files (encode) or originals (decode)
+        #[arg(long, conflicts_with = "backup")]
+        force: bool,
+
+        /// Keep existing output files as <NAME>.~N~ before writing
+        #[arg(long)]
+        backup: bool,
+
+        /// Stop at the first file that fails and roll back everything this wrap
+        /// transformed; sources are only removed once every file succeeded
+        #[arg(long, conflicts_with_all = ["continue_on_error", "watch", "archive"])]
+        abort_on_error: bool,
+
+        /// Transform every other file when one fails, and report the failures
+        /// at the end (the default)
+        #[arg(long)]
+        continue_on_error: bool,
+
+        /// Keep source files: originals after encode, .dna files after decode
+        #[arg(long)]
+        keep: bool,
+
+        /// Encode: decode each written .dna again and check it against the
+        /// original before the original is deleted
+        #[arg(long, requires = "encode")]
+        verify: bool,
+
+        /// Continue an interrupted wrap of the same directory, skipping the files
+        /// it already finished (the password must match the one it used)
+        #[arg(long, conflicts_with = "watch")]
+        resume: bool,
+
+        /// Encode files even if .gitignore or .dendecignore excludes them
+        #[arg(long)]
+        no_ignore: bool,
+
+        /// Run the command through the shell (sh -c, or cmd /C on Windows)
+        /// so pipelines and redirections work
+        #[arg(short = 'c', long)]
+        shell: bool,
+
+        /// Run the command under a pseudo-terminal, so credential and host-key
+        /// prompts (ssh, gh, git) reach you and your answers reach it (Unix)
+        #[arg(long)]
+        pty: bool,
+
+        /// Set an environment variable for the command, e.g. GIT_ASKPASS=/usr/bin/askpass
+        /// (repeatable)
+        #[arg(long = "env", value_name = "KEY=VAL", value_parser = parse_env_pair)]
+        env: Vec<(String, String)>,
+
+        /// Run the command with only PATH, HOME, locale and similar basics
+        /// from dendec's environment (plus any --env)
+        #[arg(long)]
+        clean_env: bool,
+
+        /// Encode binary files too (images, archives, PDFs, ...) instead of skipping them
+        #[arg(long)]
+        include_binary: bool,
+
+        /// With --include-binary, skip binaries larger than this (e.g. 512K, 20M, 1G)
+        #[arg(long, value_name = "SIZE", requires = "include_binary", value_parser = parse_size)]
+        binary_max_size: Option<u64>,
+
+        /// Decode: also probe files not named .dna and decode any dendec payload
+        /// found, replacing the file in place
+        #[arg(long, requires = "decode")]
+        sniff: bool,
+
+        /// Skip any file larger than this (e.g. 512K, 20M, 1G), in encode and decode
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_size: Option<u64>,
+
+        /// Also skip directories with this name, like .git (repeatable; adds to .dendec.toml)
+        #[arg(long = "exclude-dir", value_name = "NAME")]
+        exclude_dirs: Vec<String>,
+
+        /// Also treat files with this extension as binary (repeatable or comma-separated;
+        /// adds to .dendec.toml)
+        #[arg(long = "binary-ext", value_name = "EXT", value_delimiter = ',')]
+        binary_extensions: Vec<String>,
+
+        /// Pack everything into one encrypted archive at PATH instead of a .dna
+        /// per file; with -d, unpack PATH into DIR (default: current directory)
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["watch", "resume", "report", "sniff"])]
+        archive: Option<PathBuf>,
+
+        /// Encode: refer each file on to a genomic coordinate BED, written as
+        /// <NAME>.bed instead of <NAME>.dna; decode unrefers such a .bed by itself
+        #[arg(long, requires = "encode", conflicts_with = "archive")]
+        refer: bool,
+
+        /// With --refer, the genome assembly: hg19, hg38, mm39, sacCer3 or
+        /// ecoli [default: hg38]
+        #[arg(long, value_name = "NAME", requires = "refer")]
+        assembly: Option<Assembly>,
+
+        /// Run CMD through the shell before each file is transformed; the file is
+        /// $1 and $DENDEC_FILE, and a failing CMD leaves the file untouched
+        #[arg(long, value_name = "CMD", conflicts_with = "archive")]
+        pre_hook: Option<String>,
+
+        /// Run CMD through the shell after each file is transformed, on the
+        /// file written ($1, $DENDEC_FILE), e.g. 'chmod 600 "$1"'
+        #[arg(long, value_name = "CMD", conflicts_with = "archive")]
+        post_hook: Option<String>,
+
+        /// Transform N files at a time on N worker threads (default: one file at a
+        /// time, each encode using every core)
+        #[arg(short = 'j', long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: Option<usize>,
+
+        /// Pause this long after each file, e.g. 50ms or 1s, to leave disk and CPU
+        /// to other processes
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        throttle: Option<Duration>,
+
+        /// Write a JSON report of every file's outcome, sizes and timing to PATH
+        #[arg(long, value_name = "PATH", conflicts_with = "watch")]
+        report: Option<PathBuf>,
+
+        /// Keep running and transform files as they appear in DIR, instead of
+        /// running a command (stop with Ctrl-C)
+        #[arg(long, value_name = "DIR", conflicts_with = "command")]
+        watch: Option<PathBuf>,
+
+        /// Only look for the command's output under this path
+        /// (default: inferred for git clone, curl -o, wget -O/-P; else cwd)
+        #[arg(long, value_name = "DIR")]
+        scan_root: Option<PathBuf>,
+
         /// The command to run (everything after the flags)
-        #[arg(trailing_var_arg = true, required = true)]
+        #[arg(trailing_var_arg = true, required_unless_present_any = ["watch", "archive"])]
         command: Vec<String>,
     },
 
+    /// Reverse the last wrap in a directory using its manifest
+    ///
+    /// Every wrap records what it did in DIR/.dendec-wrap. --undo reverses
+    /// it: an encode is decoded back (verified against the recorded
+    /// SHA-256 of each original), a decode is re-encoded, and the manifest
+    /// is removed. Files changed since the wrap are left alone unless
+    /// --force is given.
+    ///
+    /// Examples:
+    ///   dendec unwrap --undo
+    ///   dendec unwrap --undo ./myproject
+    Unwrap {
+        /// Undo the wrap recorded in the manifest
+        #[arg(long, required = true)]
+        undo: bool,
+
+        /// Directory the wrap ran in (default: current directory)
+        #[arg(value_name = "DIR")]
+        dir: Option<PathBuf>,
+
+        /// Restore files even if they changed since the wrap
+        #[arg(long)]
+        force: bool,
+    },
+
     /// Convert a .dna file to a genomic coordinate BED file, or back
     ///
     /// refer is a steganographic transport layer. It replaces the raw DNA
     /// bases in a dendec-encoded file with coordinates pointing to real
-    /// locations in the human reference genome (hg38). The output is a
-    /// standard BED file indistinguishable from routine genomics annotation.
+    /// locations in a reference genome (hg38 unless --assembly says
+    /// otherwise). The output is a standard BED file indistinguishable from
+    /// routine genomics annotation.
     ///
     /// The operation is fully offline — all coordinate translation uses
     /// an embedded lookup table compiled into the dendec binary.
     ///
     /// Examples:
     ///   dendec refer -r --from secret.pdf.dna --to annotation_batch7.bed
+    ///   dendec refer -r --assembly mm39 --from secret.pdf.dna --to peaks.bed
+    ///   dendec refer -r --keyed --from secret.pdf.dna --to peaks.bed
+    ///   dendec refer -r --format vcf --from secret.pdf.dna --to calls.vcf
+    ///   dendec refer -r --format gff3 --from secret.pdf.dna --to features.gff3
+    ///   dendec refer -r --format gtf --from secret.pdf.dna --to genes.gtf
+    ///   dendec refer -r --format sam --from secret.pdf.dna --to reads.sam
+    ///   dendec refer -r --format bedgraph --from secret.pdf.dna --to signal.bedgraph
+    ///   dendec refer -r --format fasta --reference hg38.fa.gz --from secret.pdf.dna --to regions.fa
+    ///   dendec refer -r --both-strands --from secret.pdf.dna --to peaks.bed
+    ///   dendec refer -r --chrom-style ucsc --from secret.pdf.dna --to peaks.bed
+    ///   dendec refer -r --name-style refseq --from secret.pdf.dna --to transcripts.bed
+    ///   dendec refer -r --audit --from secret.pdf.dna --to peaks.bed
+    ///   dendec refer -u --shuffle --from peaks.bed --to secret.pdf.dna
     ///   dendec refer -u --from annotation_batch7.bed --to secret.pdf.dna
+    ///   dendec refer verify --bed annotation_batch7.bed --dna secret.pdf.dna
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Refer {
+        #[command(subcommand)]
+        action: Option<ReferCommand>,
+
         /// Refer mode — convert .dna to a genomic coordinate BED file
         #[arg(short = 'r', long = "refer")]
         refer: bool,
@@ -105,13 +477,463 @@ pub enum Command {
         #[arg(short = 'u', long = "unrefer")]
         unrefer: bool,
 
-        /// Input file path (.dna for -r, .bed for -u)
-        #[arg(long, value_name = "PATH")]
-        from: PathBuf,
+        /// Input file path (.dna for -r, .bed for -u). -r also takes any
+        /// A/T/G/C sequence, plain or FASTA, from other tools.
+        #[arg(long, value_name = "PATH", required = true)]
+        from: Option<PathBuf>,
 
         /// Output file path (.bed for -r, .dna for -u)
+        #[arg(long, value_name = "PATH", required = true)]
+        to: Option<PathBuf>,
+
+        /// Genome assembly: hg19, hg38, mm39, sacCer3 or ecoli [default: hg38]. With -u the
+        /// BED header decides; if given, it must match.
+        #[arg(long, value_name = "NAME")]
+        assembly: Option<Assembly>,
+
+        /// Pick coordinates from a password instead of at random (-r only).
+        /// The same password and .dna always give the same BED; unrefer
+        /// does not need the password.
+        #[arg(long, conflicts_with = "unrefer")]
+        keyed: bool,
+
+        /// Write records in a password-derived order, without chunk names.
+        /// Unrefer needs --shuffle and the same password to restore it.
+        #[arg(long)]
+        shuffle: bool,
+
+        /// Output format (-r only): bed, bed12, vcf, gff3, gtf, sam, bedgraph
+        /// (asks for a password) or fasta (needs --reference) [default: bed].
+        /// Unrefer recognises the format by itself.
+        #[arg(long, value_name = "FORMAT", conflicts_with = "unrefer")]
+        format: Option<Format>,
+
+        /// Same as --format bed12: 12-column BED (thick range, colour, one
+        /// block per record) for pipelines that reject BED6.
+        #[arg(long, conflicts_with_all = ["unrefer", "format"])]
+        bed12: bool,
+
+        /// Genome the table was built from, as FASTA (plain, gzipped or
+        /// bgzipped) or .2bit; the flanking sequence for --format fasta is
+        /// read from it.
+        #[arg(long, value_name = "FASTA", required_if_eq("format", "fasta"), conflicts_with = "unrefer")]
+        reference: Option<PathBuf>,
+
+        /// Also place chunks as − strand records where the forward strand
+        /// holds their reverse complement (-r only). Unrefer resolves
+        /// these without the flag.
+        #[arg(long, conflicts_with = "unrefer")]
+        both_strands: bool,
+
+        /// Chromosome names to write (-r only): refseq (NC_000001.11) or
+        /// ucsc (chr1) [default: refseq]. Unrefer reads either.
+        #[arg(long, value_name = "STYLE", conflicts_with = "unrefer")]
+        chrom_style: Option<ChromStyle>,
+
+        /// Record names to write (-r only): chunk (chunk_00000042), or
+        /// refseq (NM_001234567.2) or probe (cg04718469), which ask for a
+        /// password and hide the chunk order [default: chunk]. Unrefer asks
+        /// for the password when it finds such names.
+        #[arg(long, value_name = "STYLE", conflicts_with = "unrefer")]
+        name_style: Option<NameStyle>,
+
+        /// After writing (-r only), read the output back and report its
+        /// statistical tells (uniform lengths, repeated coordinates, name
+        /// pattern, unsorted order, chromosome spread), each rated with
+        /// the option that lowers it.
+        #[arg(long, conflicts_with = "unrefer")]
+        audit: bool,
+
+        /// Overwrite an existing output file
+        #[arg(long, conflicts_with = "backup")]
+        force: bool,
+
+        /// Keep an existing output file as <NAME>.~N~ before writing
+        #[arg(long)]
+        backup: bool,
+    },
+
+    /// Measure KDF, cipher, and DNA conversion speed on this machine
+    ///
+    /// Times Argon2id key derivation, ChaCha20-Poly1305 sealing (one core
+    /// and all cores), DNA conversion in both directions, and a full
+    /// encode, then prints a report. Useful for choosing parameters and
+    /// comparing dendec versions.
+    ///
+    /// Examples:
+    ///   dendec bench
+    ///   dendec bench --size 256 --kdf-runs 5
+    Bench {
+        /// Size of the random test buffer in MiB
+        #[arg(long, value_name = "MIB", default_value_t = 64)]
+        size: usize,
+
+        /// Number of Argon2id runs to time
+        #[arg(long, value_name = "N", default_value_t = 3)]
+        kdf_runs: usize,
+    },
+
+    /// Build and inspect refer lookup tables
+    Table {
+        #[command(subcommand)]
+        action: TableCommand,
+    },
+
+    /// Run as a JSON-RPC 2.0 service on stdin and stdout
+    ///
+    /// For editor plugins and GUIs that keep dendec running as a child
+    /// process. One request per line, one answer per line: encode, decode,
+    /// wrap (with progress notifications), version and shutdown. See
+    /// src/daemon.rs for the methods and their params.
+    ///
+    /// Example:
+    ///   echo '{"jsonrpc":"2.0","id":1,"method":"version"}' | dendec daemon
+    Daemon,
+
+    /// Print dendec's manual pages, in roff
+    ///
+    /// The pages are generated from the same definitions that parse the
+    /// command line, one for dendec and one per subcommand. Name a
+    /// subcommand for its page, or write them all with --dir for
+    /// packaging.
+    ///
+    /// Examples:
+    ///   dendec man | man -l -
+    ///   dendec man table build | man -l -
+    ///   dendec man --dir target/man
+    Man {
+        /// Subcommand whose page to print (default: dendec itself)
+        #[arg(value_name = "COMMAND", conflicts_with = "dir")]
+        command: Vec<String>,
+
+        /// Write every page into this directory as dendec*.1
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+    },
+
+    /// Serve encode, decode and refer over HTTP
+    ///
+    /// POST /encode, /decode and /refer take the input as the request body
+    /// or a multipart/form-data upload, and the password in the
+    /// X-Dendec-Password header or a 'password' form field. GET /health
+    /// reports the version. Put TLS in front of it for anything but
+    /// localhost.
+    ///
+    /// Examples:
+    ///   dendec serve --token-file /etc/dendec/token
+    ///   curl -H "Authorization: Bearer $TOKEN" -H "X-Dendec-Password: $PW" \
+    ///        --data-binary @notes.txt http://127.0.0.1:8480/encode
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8480")]
+        listen: String,
+
+        /// Require 'Authorization: Bearer <token>', the token read from PATH
+        #[arg(long, value_name = "PATH")]
+        token_file: Option<PathBuf>,
+
+        /// Refuse request bodies larger than this (e.g. 512K, 64M, 1G)
+        #[arg(long, value_name = "SIZE", default_value = "64M", value_parser = parse_size)]
+        max_body: u64,
+
+        /// Handle at most N requests at once; more are answered 503
+        #[arg(short = 'j', long, value_name = "N", default_value_t = 2, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: usize,
+
+        /// Answer every failed /decode alike (see decode --hardened)
+        #[arg(long)]
+        hardened: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReferCommand {
+    /// Check a refer file decodes, optionally to a given .dna
+    ///
+    /// Lints the file (record and chunk counts, chunk names, strands,
+    /// coordinates inside their chromosomes and listed in the table),
+    /// then unrefers it in memory and, with --dna, compares the result.
+    /// Each check is reported; exits non-zero if any fails. Run it before
+    /// deleting the sources of an archive.
+    ///
+    /// Examples:
+    ///   dendec refer verify --bed peaks.bed
+    ///   dendec refer verify --bed peaks.bed --dna secret.pdf.dna
+    ///   dendec refer verify --bed peaks.bed --shuffle
+    Verify {
+        /// Refer file to check, in any format refer writes
+        #[arg(long, value_name = "PATH")]
+        bed: PathBuf,
+
+        /// The .dna the file should hold
+        #[arg(long, value_name = "PATH")]
+        dna: Option<PathBuf>,
+
+        /// The file was written with --shuffle; asks for its password
+        #[arg(long)]
+        shuffle: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TableCommand {
+    /// Generate a lookup table from FASTA files
+    ///
+    /// Scans every record of every file (FASTA, plain, gzipped or
+    /// bgzipped, or UCSC .2bit) for k-mers on both strands, and keeps a
+    /// sample of --max-coords coordinates for each of the 4^k (65,536
+    /// 8-mers by default; --chunk-size picks k).
+    /// Each record's accession is the first word of its '>' header, or the
+    /// name --accession-map gives it. To use the result with refer, put it
+    /// at data/<assembly>.bin and rebuild with that assembly's cargo feature.
+    ///
+    /// Examples:
+    ///   dendec table build --fasta chr1.fa.gz --fasta chr2.fa.gz --out table.bin
+    ///   dendec table build --fasta GRCm39.fna.gz --out data/mm39.bin
+    ///   dendec table build --fasta sacCer3.2bit --out data/sacCer3.bin
+    ///   dendec table build --fasta GRCh38.fna.gz --chunk-size 10 --out data/table.bin
+    ///   dendec table build --fasta hg38.fa.gz --accession-map ucsc-to-refseq.tsv --out table.bin
+    ///   dendec table build --fasta hg38.2bit --primary-only --out table.bin
+    ///   dendec table build --fasta GRCh38.fna.gz --parts parts/ --out table.bin
+    ///   dendec table build --fasta hg38.fa.gz --exclude gaps.bed --exclude centromeres.bed --out table.bin
+    ///   dendec table build --fasta GRCh38.fna.gz --out table.bin --report coverage.json
+    Build {
+        /// FASTA or .2bit input, in the order chromosomes are indexed
+        /// (repeatable)
+        #[arg(long, value_name = "PATH", required = true)]
+        fasta: Vec<PathBuf>,
+
+        /// Where to write the table
         #[arg(long, value_name = "PATH")]
-        to: PathBuf,
+        out: PathBuf,
+
+        /// Coordinate options kept per k-mer (1–255)
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = crate::refer::build::DEFAULT_MAX_COORDS,
+            value_parser = clap::value_parser!(u8).range(1..)
+        )]
+        max_coords: u8,
+
+        /// Bases per chunk refer cuts payloads into (8–12); the offsets
+        /// alone take 4^k × 4 bytes, 64 MB at 12
+        #[arg(
+            long,
+            value_name = "K",
+            default_value_t = crate::refer::chunk::DEFAULT_CHUNK_SIZE as u8,
+            value_parser = clap::value_parser!(u8).range(
+                crate::refer::chunk::MIN_CHUNK_SIZE as i64..=crate::refer::chunk::MAX_CHUNK_SIZE as i64
+            )
+        )]
+        chunk_size: u8,
+
+        /// Keep forward-strand coordinates only; refer --both-strands
+        /// then finds the reverse-strand ones through the complement
+        #[arg(long)]
+        forward_only: bool,
+
+        /// Read only assembled chromosomes (NC_ accessions, chr1–chr22,
+        /// chrX, chrY, chrM), skipping scaffolds, alternate loci and
+        /// patches — needed for a full assembly FASTA, which has more
+        /// records than a table can index
+        #[arg(long)]
+        primary_only: bool,
+
+        /// Tab-separated FASTA name and accession per line (e.g. chr1 and
+        /// NC_000001.11); mapped records are indexed under the accession
+        #[arg(long, value_name = "PATH")]
+        accession_map: Option<PathBuf>,
+
+        /// BED file of regions no coordinate may overlap, such as assembly
+        /// gaps, centromeres and telomeres (repeatable)
+        #[arg(long, value_name = "PATH")]
+        exclude: Vec<PathBuf>,
+
+        /// Save each chromosome to DIR/<accession>.part once read, then
+        /// merge the parts; rerun to resume, skipping those already there
+        #[arg(long, value_name = "DIR")]
+        parts: Option<PathBuf>,
+
+        /// Write a coverage report to PATH: options and occurrences per
+        /// k-mer, the options histogram, each chromosome's share and the
+        /// k-mers missing. JSON, or TSV if PATH ends in .tsv
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+
+        /// Overwrite an existing output file
+        #[arg(long, conflicts_with = "backup")]
+        force: bool,
+
+        /// Keep an existing output file as <NAME>.~N~ before writing
+        #[arg(long)]
+        backup: bool,
     },
+
+    /// Combine parts from `table build --parts` into one table
+    ///
+    /// Each part is the sample of one chromosome; parts built on different
+    /// machines, with the same --chunk-size, --max-coords and
+    /// --forward-only, merge as if read by one build. Chromosomes are
+    /// indexed in the order the parts are given.
+    ///
+    /// Examples:
+    ///   dendec table merge parts/chr*.part --out table.bin
+    ///   dendec table merge a/chr1.part b/chr2.part --out table.bin
+    Merge {
+        /// Part files, in the order chromosomes are indexed
+        #[arg(value_name = "PART", required = true)]
+        parts: Vec<PathBuf>,
+
+        /// Where to write the table
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Write a coverage report to PATH: options and occurrences per
+        /// k-mer, the options histogram, each chromosome's share and the
+        /// k-mers missing. JSON, or TSV if PATH ends in .tsv
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+
+        /// Overwrite an existing output file
+        #[arg(long, conflicts_with = "backup")]
+        force: bool,
+
+        /// Keep an existing output file as <NAME>.~N~ before writing
+        #[arg(long)]
+        backup: bool,
+    },
+
+    /// Report coverage and coordinate distribution of a lookup table
+    ///
+    /// Shows size and chunk size, how many of the 4^k k-mers are covered,
+    /// a histogram of coordinate options per k-mer and each chromosome's
+    /// share. Reads
+    /// an embedded table unless --file names one on disk.
+    ///
+    /// Examples:
+    ///   dendec table stats
+    ///   dendec table stats --file data/mm39.bin
+    Stats {
+        /// Table file to inspect, e.g. the output of `table build`
+        #[arg(long, value_name = "PATH", conflicts_with = "assembly")]
+        file: Option<PathBuf>,
+
+        /// Embedded table to inspect [default: hg38]
+        #[arg(long, value_name = "NAME")]
+        assembly: Option<Assembly>,
+    },
+
+    /// Check a lookup table end to end before refer relies on it
+    ///
+    /// Validates magic and version, the stored checksum, every accession
+    /// string and entry, full k-mer coverage and an unambiguous reverse
+    /// index. Each check is reported; exits non-zero if any fails.
+    ///
+    /// Examples:
+    ///   dendec table verify --file data/mm39.bin
+    ///   dendec table verify --assembly hg38
+    Verify {
+        /// Table file to check, e.g. the output of `table build`
+        #[arg(long, value_name = "PATH", conflicts_with = "assembly")]
+        file: Option<PathBuf>,
+
+        /// Embedded table to check [default: hg38]
+        #[arg(long, value_name = "NAME")]
+        assembly: Option<Assembly>,
+    },
+}
+
+/// Parse a byte size such as `4096`, `512K`, `20M`, or `1G` (powers of 1024).
+/// A trailing `B` or `iB` is accepted: `20MB`, `20MiB`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let t = t
+        .strip_suffix("iB")
+        .or_else(|| t.strip_suffix('B'))
+        .unwrap_or(t);
+    let (digits, shift) = match t.chars().last() {
+        Some('K') | Some('k') => (&t[..t.len() - 1], 10),
+        Some('M') | Some('m') => (&t[..t.len() - 1], 20),
+        Some('G') | Some('g') => (&t[..t.len() - 1], 30),
+        Some('T') | Some('t') => (&t[..t.len() - 1], 40),
+        _ => (t, 0),
+    };
+    let n: u64 = digits
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size '{s}' (expected e.g. 4096, 512K, 20M, 1G)"))?;
+    n.checked_mul(1u64 << shift)
+        .ok_or_else(|| format!("size '{s}' is too large"))
+}
+
+/// Parse a duration such as `250ms`, `1.5s`, or `2m`. The unit is required.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let t = s.trim();
+    let (number, scale) = if let Some(n) = t.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = t.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = t.strip_suffix('m') {
+        (n, 60.0)
+    } else {
+        return Err(format!("invalid duration '{s}' (expected e.g. 250ms, 1.5s, 2m)"));
+    };
+    let n: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid duration '{s}' (expected e.g. 250ms, 1.5s, 2m)"))?;
+    Duration::try_from_secs_f64(n * scale).map_err(|_| format!("duration '{s}' is out of range"))
+}
+
+/// Parse a `KEY=VAL` pair for `wrap --env`. VAL may be empty or contain `=`.
+pub fn parse_env_pair(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid --env '{s}' (expected KEY=VAL)")),
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_size("20MB"), Ok(20 << 20));
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn test_serve_max_body_is_a_size() {
+        let max_body = |args: &[&str]| match Cli::try_parse_from([&["dendec", "serve"], args].concat()) {
+            Ok(Cli { command: Command::Serve { max_body, .. }, .. }) => Some(max_body),
+            _ => None,
+        };
+        assert_eq!(max_body(&[]), Some(64 << 20));
+        assert_eq!(max_body(&["--max-body", "512K"]), Some(512 << 10));
+        assert_eq!(max_body(&["--max-body", "8T"]), Some(8 << 40));
+        assert_eq!(max_body(&["--max-body", "99999999999T"]), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+
+    #[test]
+    fn test_parse_env_pair() {
+        assert_eq!(parse_env_pair("A=b=c"), Ok(("A".into(), "b=c".into())));
+        assert_eq!(parse_env_pair("EMPTY="), Ok(("EMPTY".into(), String::new())));
+        assert!(parse_env_pair("=x").is_err());
+        assert!(parse_env_pair("NOEQUALS").is_err());
+    }
+}
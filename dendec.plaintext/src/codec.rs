@@ -0,0 +1,203 @@
+/// codec.rs — Encoder and Decoder for library users
+///
+/// The flat functions in encoding.rs take every setting as a parameter,
+/// so each new setting would change their signatures. Library callers
+/// use these types instead; options are set through builder methods, and
+/// new ones are added as methods, never as parameters:
+///
+///   let opts = EncodeOptions::new("correct horse").group(60);
+///   let encoder = Encoder::new(opts);
+///   encoder.encode(File::open("notes.md")?, File::create("notes.md.dna")?)?;
+///
+///   let decoder = Decoder::new(DecodeOptions::new("correct horse"));
+///   decoder.decode(File::open("notes.md.dna")?, io::stdout())?;
+///
+/// One Encoder runs Argon2id once, on its first encode, and seals every
+/// payload after that under the same master key, each with its own file
+/// salt, keys and mapping, as a wrap batch does. One Decoder remembers
+/// every master key it derives, so a batch decodes with one Argon2id run
+/// per KDF salt.
+///
+/// The options are those the format can record, grouping on output and
+/// the chunk size, plus hardened decode failures. The cipher, KDF and mapping are fixed by the header
+/// version; options for them arrive with the header fields that record
+/// them.
+use std::io::{Cursor, Read, Seek, Write};
+use std::sync::{Arc, OnceLock};
+
+use crate::crypto::{derive_master_key, MasterKey, MasterKeyCache};
+use crate::encoding::{decode_stream_cached, encode_stream_chunked, harden, CHUNK_SIZE};
+use crate::error::Result;
+
+/// Settings for an Encoder.
+#[derive(Clone)]
+pub struct EncodeOptions {
+    key: EncodeKey,
+    group: Option<usize>,
+    chunk_size: usize,
+}
+
+/// Where an Encoder's master key comes from.
+#[derive(Clone)]
+enum EncodeKey {
+    Password(String),
+    Master(Arc<MasterKey>),
+}
+
+impl EncodeOptions {
+    /// Encode under `password`, with continuous output and 64 KiB chunks.
+    pub fn new(password: &str) -> Self {
+        Self { key: EncodeKey::Password(password.to_string()), group: None, chunk_size: CHUNK_SIZE }
+    }
+
+    /// Encode under an already derived master key, sharing its KDF salt
+    /// with whatever else was encoded under it.
+    pub fn with_master_key(master: Arc<MasterKey>) -> Self {
+        Self { key: EncodeKey::Master(master), group: None, chunk_size: CHUNK_SIZE }
+    }
+
+    /// Insert a space every `bases` bases of output; 0 for none.
+    pub fn group(mut self, bases: usize) -> Self {
+        self.group = (bases > 0).then_some(bases);
+        self
+    }
+
+    /// Seal the plaintext in chunks of `bytes`, 1 byte to 16 MiB. Smaller
+    /// chunks let a decoder start writing sooner; each costs a 16-byte
+    /// tag.
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes;
+        self
+    }
+}
+
+/// Encrypts plaintext into DNA.
+pub struct Encoder {
+    opts: EncodeOptions,
+    master: OnceLock<Arc<MasterKey>>,
+}
+
+impl Encoder {
+    pub fn new(opts: EncodeOptions) -> Self {
+        let master = OnceLock::new();
+        if let EncodeKey::Master(key) = &opts.key {
+            let _ = master.set(Arc::clone(key));
+        }
+        Self { opts, master }
+    }
+
+    /// The master key, derived from the password on first use.
+    pub fn master_key(&self) -> Result<Arc<MasterKey>> {
+        if let Some(master) = self.master.get() {
+            return Ok(Arc::clone(master));
+        }
+        let EncodeKey::Password(password) = &self.opts.key else { unreachable!("set in new") };
+        let master = Arc::new(derive_master_key(password)?);
+        Ok(Arc::clone(self.master.get_or_init(|| master)))
+    }
+
+    /// Read plaintext from `input` to its end and write the DNA to
+    /// `output`, which is returned. Memory use does not grow with the
+    /// input.
+    pub fn encode<R: Read, W: Write>(&self, input: R, output: W) -> Result<W> {
+        let master = self.master_key()?;
+        encode_stream_chunked(input, output, &master, self.opts.group, self.opts.chunk_size)
+    }
+
+    /// Encode `plaintext` held in memory.
+    pub fn encode_bytes(&self, plaintext: &[u8]) -> Result<String> {
+        let dna = self.encode(plaintext, Vec::new())?;
+        // DnaWriter only ever emits ASCII bases and spaces
+        Ok(String::from_utf8(dna).expect("DNA output is ASCII"))
+    }
+}
+
+/// Settings for a Decoder.
+#[derive(Clone)]
+pub struct DecodeOptions<'a> {
+    password: &'a str,
+    hardened: bool,
+}
+
+impl<'a> DecodeOptions<'a> {
+    /// Decode with `password`.
+    pub fn new(password: &'a str) -> Self {
+        Self { password, hardened: false }
+    }
+
+    /// Report every failure as DecodeFailed, each after one Argon2id
+    /// run, so errors and timing do not say where decoding stopped (see
+    /// `encoding::decode_raw_hardened`). A Decoder reused across
+    /// payloads still skips Argon2id for KDF salts it has seen.
+    pub fn hardened(mut self, on: bool) -> Self {
+        self.hardened = on;
+        self
+    }
+}
+
+/// Decrypts DNA back into plaintext.
+pub struct Decoder<'a> {
+    keys: MasterKeyCache<'a>,
+    hardened: bool,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(opts: DecodeOptions<'a>) -> Self {
+        Self { keys: MasterKeyCache::new(opts.password), hardened: opts.hardened }
+    }
+
+    /// Read DNA, grouped or not, from `input` and write the plaintext to
+    /// `output`, which is returned. Memory use is one chunk; on error
+    /// `output` may hold a prefix of the plaintext. `input` is rewound
+    /// only for pre-chunking (v1) payloads.
+    pub fn decode<R: Read + Seek, W: Write>(&self, input: R, output: W) -> Result<W> {
+        match self.hardened {
+            true => harden(&self.keys, |keys| decode_stream_cached(input, output, keys)),
+            false => decode_stream_cached(input, output, &self.keys),
+        }
+    }
+
+    /// Decode `dna` held in memory.
+    pub fn decode_bytes(&self, dna: &[u8]) -> Result<Vec<u8>> {
+        self.decode(Cursor::new(dna), Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::decode_raw;
+    use crate::error::DendecError;
+
+    #[test]
+    fn test_encoder_options_and_shared_key() {
+        let encoder = Encoder::new(EncodeOptions::new("pw").group(10).chunk_size(5));
+        let plaintext = b"twelve bytes";
+        let first = encoder.encode_bytes(plaintext).unwrap();
+        let second = encoder.encode_bytes(plaintext).unwrap();
+        assert!(first.split(' ').all(|g| g.len() <= 10));
+        // 52-byte header, then 5 + 5 + 2 bytes in three chunks with tags
+        let bases = first.chars().filter(|c| !c.is_whitespace()).count();
+        assert_eq!(bases, (52 + 12 + 3 * 16) * 4);
+        assert_ne!(first, second);
+
+        let decoder = Decoder::new(DecodeOptions::new("pw"));
+        assert_eq!(decoder.decode_bytes(first.as_bytes()).unwrap(), plaintext);
+        assert_eq!(decoder.decode_bytes(second.as_bytes()).unwrap(), plaintext);
+        // Both under one master key: one Argon2id run for the two
+        assert_eq!(decoder.keys.len(), 1);
+        assert_eq!(decode_raw(&first, "pw").unwrap(), plaintext);
+
+        let shared = Encoder::new(EncodeOptions::with_master_key(encoder.master_key().unwrap()));
+        let third = shared.encode_bytes(b"").unwrap();
+        assert_eq!(decoder.decode_bytes(third.as_bytes()).unwrap(), b"");
+        assert_eq!(decoder.keys.len(), 1);
+
+        let bad = Encoder::new(EncodeOptions::with_master_key(shared.master_key().unwrap()).chunk_size(0));
+        assert!(matches!(bad.encode_bytes(b"x"), Err(DendecError::ChunkSizeOutOfRange(0))));
+
+        let hardened = Decoder::new(DecodeOptions::new("wrong").hardened(true));
+        assert!(matches!(hardened.decode_bytes(first.as_bytes()), Err(DendecError::DecodeFailed)));
+        assert!(matches!(hardened.decode_bytes(b"not dna"), Err(DendecError::DecodeFailed)));
+    }
+}
@@ -13,6 +13,37 @@
 ///   (rainbow table) attacks and ensures that two users with the same
 ///   password cannot correlate their outputs.
 ///
+/// WHY a master key and per-file subkeys?
+///   Argon2id is deliberately slow, and wrap may touch thousands of
+///   files. Argon2id therefore produces a 256-bit master key from the
+///   password and a KDF salt, and each payload's cipher key and mapping
+///   seed come from HKDF-SHA256 over that master key with a fresh
+///   per-file salt. Both salts are stored in the header. A wrap batch
+///   shares one KDF salt, so it pays for Argon2id once, yet every file
+///   still has independent keys. A single encode simply uses a batch of
+///   one.
+///
+/// WHY a key hierarchy?
+///   Every key a feature needs is an HKDF-SHA256 subkey of the master
+///   key under its own info label, so features never share key bytes
+///   and a new one never slices more bytes off the Argon2id output:
+///
+///     password ─Argon2id(KDF salt)─► master key
+///       ├─HKDF(file salt, "dendec file keys")─► cipher key ‖ mapping seed
+///       ├─HKDF(file salt, "dendec key slot")──► key slot key
+///       ├─HKDF("dendec file names")──────────► file name key
+///       └─HKDF("dendec manifest mac")────────► manifest MAC key
+///
+///   A payload locked with several passwords has a random file key in
+///   place of the master key at the top of the file keys; each password
+///   seals it into its own key slot with its key slot key.
+///
+///   The per-file split into cipher key and mapping seed predates the
+///   hierarchy and is fixed by the v2 format. New purposes are added to
+///   `Purpose`; an existing label never changes, or old output stops
+///   decoding. Refer's password-only key has its own subkeys the same
+///   way (see `ReferKey`).
+///
 /// WHY ChaCha20-Poly1305?
 ///   ChaCha20-Poly1305 is an AEAD (Authenticated Encryption with
 ///   Associated Data) cipher. It provides:
@@ -24,27 +55,87 @@
 ///
 /// WHY a random nonce?
 ///   ChaCha20-Poly1305 is catastrophically broken if a nonce is ever
-///   reused with the same key. A 96-bit random nonce has a collision
-///   probability of ~2^-33 after 2^32 messages — safe for our use
-///   case. The nonce is stored in the header so decode can recover it.
+///   reused with the same key. Every key comes from a fresh random salt,
+///   and the payload is split into chunks whose nonces are
+///   prefix ‖ counter ‖ last-flag (the STREAM construction): a random
+///   56-bit prefix stored in the header, a 32-bit big-endian chunk
+///   counter, and one byte marking the final chunk. Nonces never repeat
+///   within a payload, and dropping, reordering, or truncating chunks
+///   fails authentication.
+///
+/// WHY a password verifier?
+///   Without one, a wrong password is only detected when the Poly1305
+///   tag fails — after the whole payload has been converted from DNA.
+///   A 4-byte truncated HMAC of a fixed label under the cipher key lets
+///   decode reject a wrong password right after the KDF. It reveals
+///   nothing an attacker could not learn by running the KDF themselves,
+///   and every guess still costs one full Argon2id run.
 use crate::error::{DendecError, Result};
+use crate::stats::{self, Stage};
 use argon2::{Algorithm, Argon2, Params, Version};
 use chacha20poly1305::{
     aead::{Aead, KeyInit},
     ChaCha20Poly1305, Key, Nonce,
 };
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use log::{debug, trace};
 use rand::{RngCore, SeedableRng};
 use rand::rngs::StdRng;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 /// Size constants
 pub const SALT_LEN: usize = 16; // 128-bit salt for Argon2
+pub const FILE_SALT_LEN: usize = 16; // 128-bit per-file HKDF salt
 pub const NONCE_LEN: usize = 12; // 96-bit nonce for ChaCha20-Poly1305
+pub const NONCE_PREFIX_LEN: usize = NONCE_LEN - 5; // STREAM prefix: nonce minus counter + flag
+pub const TAG_LEN: usize = 16; // Poly1305 tag appended to every chunk
 pub const KEY_LEN: usize = 32;  // 256-bit ChaCha20 key
 pub const MAPPING_SEED_LEN: usize = 8; // 64-bit seed for DNA mapping RNG
+pub const VERIFIER_LEN: usize = 4; // 32-bit truncated HMAC password check
+pub const FINGERPRINT_LEN: usize = 16; // 128-bit master key fingerprint
+pub const SLOT_LEN: usize = SALT_LEN + KEY_LEN + TAG_LEN; // KDF salt ‖ sealed file key
+
+/// Domain-separation label for the password verifier HMAC
+const VERIFIER_LABEL: &[u8] = b"dendec password verifier";
+
+/// Domain-separation label for the master key fingerprint HMAC
+const FINGERPRINT_LABEL: &[u8] = b"dendec master key fingerprint";
+
+/// HKDF info string for per-file key material
+const FILE_KEYS_INFO: &[u8] = b"dendec file keys";
+
+/// HKDF info string for the key that seals a file key into a key slot
+const KEY_SLOT_INFO: &[u8] = b"dendec key slot";
+
+/// HKDF info string for the file name encryption key
+const FILE_NAMES_INFO: &[u8] = b"dendec file names";
+
+/// HKDF info string for the manifest MAC key
+const MANIFEST_MAC_INFO: &[u8] = b"dendec manifest mac";
+
+/// Argon2 salt for refer keys. Fixed, not random: a refer key must come
+/// from the password alone, so the same password always gives the same key.
+const REFER_SALT: &[u8; SALT_LEN] = b"dendec refer key";
+
+/// HKDF info string for the refer coordinate selection seed
+const REFER_SELECTION_INFO: &[u8] = b"dendec refer selection";
+
+/// HKDF info string for the refer record order seed
+const REFER_ORDER_INFO: &[u8] = b"dendec refer order";
+
+/// HKDF info string for the refer bedGraph value mask
+const REFER_SIGNAL_INFO: &[u8] = b"dendec refer signal";
+
+/// HKDF info string for the key behind refer's keyed record names
+const REFER_NAMES_INFO: &[u8] = b"dendec refer names";
 
 /// Argon2id parameters (OWASP "interactive" tier, adjustable)
-const ARGON2_M_COST: u32 = 65536; // 64 MiB memory
-const ARGON2_T_COST: u32 = 3;     // 3 iterations
+pub const ARGON2_M_COST: u32 = 65536; // 64 MiB memory
+pub const ARGON2_T_COST: u32 = 3;     // 3 iterations
 const ARGON2_P_COST: u32 = 1;     // 1 thread (CLI context)
 
 /// Output of key derivation — everything needed for one session
@@ -53,33 +144,302 @@ pub struct DerivedKeys {
     pub cipher_key: [u8; KEY_LEN],
     /// 64-bit seed used to derive the DNA base mapping order
     pub mapping_seed: u64,
-    /// The salt used (must be stored in header for decode)
+}
+
+/// What a master key subkey is for. Each purpose has its own HKDF info
+/// label, so subkeys for different purposes are independent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    /// Encrypting file names in wrap output
+    FileNames,
+    /// Authenticating a wrap manifest
+    ManifestMac,
+}
+
+impl Purpose {
+    fn info(self) -> &'static [u8] {
+        match self {
+            Purpose::FileNames => FILE_NAMES_INFO,
+            Purpose::ManifestMac => MANIFEST_MAC_INFO,
+        }
+    }
+}
+
+/// Argon2id output for one password and KDF salt.
+///
+/// Never used as a cipher key directly — see `file_keys`.
+pub struct MasterKey {
+    key: [u8; KEY_LEN],
+    /// The Argon2 salt used (stored in every header of the batch)
     pub salt: [u8; SALT_LEN],
 }
 
-/// Derive all session keys from a password.
+impl MasterKey {
+    /// Derive the keys for one payload from its per-file salt.
+    ///
+    /// HKDF-SHA256 expands the master key into 40 bytes: 32 bytes for the
+    /// cipher key and 8 bytes for the DNA mapping seed.
+    pub fn file_keys(&self, file_salt: &[u8; FILE_SALT_LEN]) -> DerivedKeys {
+        split_key_material(&hkdf_expand(&self.key, Some(file_salt), FILE_KEYS_INFO))
+    }
+
+    /// The subkey for `purpose`. Same master key → same subkey; no two
+    /// purposes share one.
+    pub fn subkey(&self, purpose: Purpose) -> [u8; KEY_LEN] {
+        hkdf_expand(&self.key, None, purpose.info())
+    }
+
+    /// Seal `file_key` into a key slot for this key's password: the KDF
+    /// salt, then the file key encrypted under a key unique to this
+    /// master key and `file_salt`. That key seals nothing else, so the
+    /// nonce is fixed but for its last byte, which records `region`, the
+    /// part of the payload the file key opens (see encoding.rs).
+    pub fn seal_slot(&self, file_salt: &[u8; FILE_SALT_LEN], file_key: &FileKey, region: u8) -> [u8; SLOT_LEN] {
+        let sealed = ChaCha20Poly1305::new(Key::from_slice(&self.slot_key(file_salt)))
+            .encrypt(Nonce::from_slice(&slot_nonce(region)), &file_key.key[..])
+            .expect("ChaCha20-Poly1305 seals 32 bytes");
+        let mut slot = [0u8; SLOT_LEN];
+        slot[..SALT_LEN].copy_from_slice(&self.salt);
+        slot[SALT_LEN..].copy_from_slice(&sealed);
+        slot
+    }
+
+    /// Open a key slot sealed by `seal_slot` for any region below
+    /// `regions`, returning the file key and its region; `None` if the
+    /// slot was sealed for another password or has been altered.
+    pub fn open_slot(&self, file_salt: &[u8; FILE_SALT_LEN], slot: &[u8; SLOT_LEN], regions: u8) -> Option<(FileKey, u8)> {
+        let slot_key = self.slot_key(file_salt);
+        (0..regions).find_map(|region| {
+            let key = decrypt(&slot_key, &slot_nonce(region), &slot[SALT_LEN..]).ok()?;
+            Some((FileKey { key: key.try_into().ok()? }, region))
+        })
+    }
+
+    fn slot_key(&self, file_salt: &[u8; FILE_SALT_LEN]) -> [u8; KEY_LEN] {
+        hkdf_expand(&self.key, Some(file_salt), KEY_SLOT_INFO)
+    }
+
+    /// A value that identifies this key without revealing it: HMAC-SHA256
+    /// keyed with the master key over a fixed label, truncated to
+    /// `FINGERPRINT_LEN` bytes. Equal for the same password and salt.
+    pub fn fingerprint(&self) -> [u8; FINGERPRINT_LEN] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(FINGERPRINT_LABEL);
+        let tag = mac.finalize().into_bytes();
+        let mut fingerprint = [0u8; FINGERPRINT_LEN];
+        fingerprint.copy_from_slice(&tag[..FINGERPRINT_LEN]);
+        fingerprint
+    }
+}
+
+/// The nonce that seals a key slot for `region`.
+fn slot_nonce(region: u8) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - 1] = region;
+    nonce
+}
+
+/// The random key of a payload locked with several passwords.
+///
+/// Stands where the master key does for an ordinary payload: the file
+/// keys come from it. Each password's key slot holds a sealed copy, so
+/// slots can be added or removed without touching the payload.
+pub struct FileKey {
+    key: [u8; KEY_LEN],
+}
+
+impl FileKey {
+    /// A fresh random file key.
+    pub fn random() -> Self {
+        let mut key = [0u8; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self { key }
+    }
+
+    /// Derive the keys for the payload from its file salt, as
+    /// `MasterKey::file_keys` does.
+    pub fn file_keys(&self, file_salt: &[u8; FILE_SALT_LEN]) -> DerivedKeys {
+        split_key_material(&hkdf_expand(&self.key, Some(file_salt), FILE_KEYS_INFO))
+    }
+}
+
+/// Argon2id output for refer's keyed mode.
 ///
-/// Generates a fresh random salt, then runs Argon2id to produce
-/// 40 bytes of key material: 32 bytes for the cipher key and
-/// 8 bytes for the DNA mapping seed.
-pub fn derive_keys(password: &str) -> Result<DerivedKeys> {
+/// Refer output carries no header to store a salt in, and unrefer must
+/// work from the password alone, so this key is derived under a fixed
+/// salt. Each use draws its own HKDF subkey.
+pub struct ReferKey {
+    key: [u8; KEY_LEN],
+}
+
+impl ReferKey {
+    /// A CSPRNG (StdRng, seeded from an HKDF subkey) that picks among each
+    /// 8-mer's coordinate options. Same password + same .dna → same picks.
+    pub fn selection_rng(&self) -> StdRng {
+        self.subkey_rng(REFER_SELECTION_INFO)
+    }
+
+    /// A CSPRNG, independent of `selection_rng`, that shuffles the order
+    /// of BED records. Same password + same chunk count → same order.
+    pub fn order_rng(&self) -> StdRng {
+        self.subkey_rng(REFER_ORDER_INFO)
+    }
+
+    /// A CSPRNG, independent of the others, whose output masks the values
+    /// of a bedGraph, one draw per line in file order.
+    pub fn signal_rng(&self) -> StdRng {
+        self.subkey_rng(REFER_SIGNAL_INFO)
+    }
+
+    /// A key, independent of the RNGs, for the permutation that turns
+    /// chunk indices into `--name-style` record names.
+    pub fn name_key(&self) -> [u8; KEY_LEN] {
+        self.subkey(REFER_NAMES_INFO)
+    }
+
+    fn subkey_rng(&self, info: &[u8]) -> StdRng {
+        StdRng::from_seed(self.subkey(info))
+    }
+
+    fn subkey(&self, info: &[u8]) -> [u8; KEY_LEN] {
+        hkdf_expand(&self.key, None, info)
+    }
+}
+
+/// HKDF-SHA256 extract over `key` with `salt`, then expand under `info`
+/// into `N` bytes.
+fn hkdf_expand<const N: usize>(key: &[u8; KEY_LEN], salt: Option<&[u8]>, info: &[u8]) -> [u8; N] {
+    let mut output = [0u8; N];
+    Hkdf::<Sha256>::new(salt, key)
+        .expand(info, &mut output)
+        .expect("subkeys are far below the HKDF-SHA256 output limit");
+    output
+}
+
+/// Derive the refer key for a password.
+pub fn derive_refer_key(password: &str) -> Result<ReferKey> {
+    let mut key = [0u8; KEY_LEN];
+    argon2id(password, REFER_SALT, &mut key)?;
+    Ok(ReferKey { key })
+}
+
+/// Derive a master key from a password under a fresh random KDF salt.
+pub fn derive_master_key(password: &str) -> Result<MasterKey> {
     let mut salt = [0u8; SALT_LEN];
     rand::thread_rng().fill_bytes(&mut salt);
-    derive_keys_with_salt(password, &salt)
+    derive_master_key_with_salt(password, &salt)
 }
 
-/// Derive keys from a password and an existing salt (used during decode).
-pub fn derive_keys_with_salt(password: &str, salt: &[u8; SALT_LEN]) -> Result<DerivedKeys> {
-    let params = Params::new(ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST, Some(KEY_LEN + MAPPING_SEED_LEN))
-        .map_err(|e| DendecError::KeyDerivation(e.to_string()))?;
+/// Derive a master key from a password and an existing KDF salt.
+pub fn derive_master_key_with_salt(password: &str, salt: &[u8; SALT_LEN]) -> Result<MasterKey> {
+    let mut key = [0u8; KEY_LEN];
+    argon2id(password, salt, &mut key)?;
+    Ok(MasterKey { key, salt: *salt })
+}
+
+/// Generate a fresh random per-file HKDF salt.
+pub fn random_file_salt() -> [u8; FILE_SALT_LEN] {
+    let mut salt = [0u8; FILE_SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt
+}
+
+/// Master keys for one password, memoised by KDF salt.
+///
+/// Files encoded in the same wrap batch share a KDF salt, so decoding
+/// the batch runs Argon2id once per distinct salt instead of per file.
+/// The cache can be shared by parallel decoders; one that needs a salt
+/// another is deriving waits for it rather than deriving it again.
+pub struct MasterKeyCache<'a> {
+    password: &'a str,
+    keys: Mutex<HashMap<[u8; SALT_LEN], Arc<MasterKey>>>,
+    /// v1 derivations, which are not memoised
+    v1_runs: AtomicUsize,
+}
+
+impl<'a> MasterKeyCache<'a> {
+    pub fn new(password: &'a str) -> Self {
+        Self { password, keys: Mutex::new(HashMap::new()), v1_runs: AtomicUsize::new(0) }
+    }
+
+    /// The password every key in this cache is derived from.
+    pub fn password(&self) -> &'a str {
+        self.password
+    }
 
-    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    /// Return the master key for `salt`, running Argon2id on first use.
+    pub fn get(&self, salt: &[u8; SALT_LEN]) -> Result<Arc<MasterKey>> {
+        let mut keys = self.lock();
+        if !keys.contains_key(salt) {
+            debug!("Argon2id for KDF salt {}…", salt_id(salt));
+            let key = derive_master_key_with_salt(self.password, salt)?;
+            keys.insert(*salt, Arc::new(key));
+        } else {
+            trace!("master key for KDF salt {}… from cache", salt_id(salt));
+        }
+        Ok(Arc::clone(&keys[salt]))
+    }
+
+    /// Derive v1 session keys for `salt`. Never memoised: v1 has no
+    /// master key, and every v1 payload has its own salt.
+    pub fn v1_keys(&self, salt: &[u8; SALT_LEN]) -> Result<DerivedKeys> {
+        debug!("Argon2id for v1 salt {}…", salt_id(salt));
+        self.v1_runs.fetch_add(1, Ordering::Relaxed);
+        derive_keys_with_salt(self.password, salt)
+    }
+
+    /// Number of Argon2id runs started through this cache, v2 and v1.
+    pub fn derivations(&self) -> usize {
+        self.lock().len() + self.v1_runs.load(Ordering::Relaxed)
+    }
+
+    /// Some key already derived, if any. All share one password, so any
+    /// of them identifies it.
+    pub fn any(&self) -> Option<Arc<MasterKey>> {
+        self.lock().values().next().cloned()
+    }
+
+    /// Number of distinct KDF salts derived so far.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<[u8; SALT_LEN], Arc<MasterKey>>> {
+        // A panic mid-insert leaves nothing half-written; carry on
+        self.keys.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
 
+/// The first bytes of a KDF salt in hex, to tell salts apart in logs.
+fn salt_id(salt: &[u8; SALT_LEN]) -> String {
+    salt[..4].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Derive v1 session keys from a password and an existing salt.
+///
+/// v1 has no master key: Argon2id produces 40 bytes of key material: 32 bytes for the cipher
+/// key and 8 bytes for the DNA mapping seed.
+pub fn derive_keys_with_salt(password: &str, salt: &[u8; SALT_LEN]) -> Result<DerivedKeys> {
     let mut output = [0u8; KEY_LEN + MAPPING_SEED_LEN];
-    argon2
-        .hash_password_into(password.as_bytes(), salt, &mut output)
+    argon2id(password, salt, &mut output)?;
+    Ok(split_key_material(&output))
+}
+
+/// Run Argon2id with the production parameters, filling `output`.
+fn argon2id(password: &str, salt: &[u8; SALT_LEN], output: &mut [u8]) -> Result<()> {
+    let params = Params::new(ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST, Some(output.len()))
         .map_err(|e| DendecError::KeyDerivation(e.to_string()))?;
 
+    let _timer = stats::timer(Stage::Kdf);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, output)
+        .map_err(|e| DendecError::KeyDerivation(e.to_string()))
+}
+
+/// Split 40 bytes of key material into a cipher key and mapping seed.
+fn split_key_material(output: &[u8; KEY_LEN + MAPPING_SEED_LEN]) -> DerivedKeys {
     let mut cipher_key = [0u8; KEY_LEN];
     cipher_key.copy_from_slice(&output[..KEY_LEN]);
 
@@ -88,17 +448,88 @@ pub fn derive_keys_with_salt(password: &str, salt: &[u8; SALT_LEN]) -> Result<De
     seed_bytes.copy_from_slice(&output[KEY_LEN..]);
     let mapping_seed = u64::from_le_bytes(seed_bytes);
 
-    Ok(DerivedKeys {
+    DerivedKeys {
         cipher_key,
         mapping_seed,
-        salt: *salt,
-    })
+    }
+}
+
+/// Compute the password verifier stored in the header.
+///
+/// HMAC-SHA256 keyed with the cipher key over a fixed label, truncated
+/// to `VERIFIER_LEN` bytes.
+pub fn password_verifier(keys: &DerivedKeys) -> [u8; VERIFIER_LEN] {
+    let tag = verifier_mac(keys).finalize().into_bytes();
+    let mut verifier = [0u8; VERIFIER_LEN];
+    verifier.copy_from_slice(&tag[..VERIFIER_LEN]);
+    verifier
+}
+
+/// Check a stored verifier against freshly derived keys in constant time.
+pub fn check_password_verifier(keys: &DerivedKeys, stored: &[u8; VERIFIER_LEN]) -> Result<()> {
+    verifier_mac(keys)
+        .verify_truncated_left(stored)
+        .map_err(|_| DendecError::WrongPassword)
+}
+
+fn verifier_mac(keys: &DerivedKeys) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&keys.cipher_key)
+        .expect("HMAC accepts keys of any length");
+    mac.update(VERIFIER_LABEL);
+    mac
+}
+
+/// Generate a fresh random STREAM nonce prefix for one payload.
+pub fn random_nonce_prefix() -> [u8; NONCE_PREFIX_LEN] {
+    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+    rand::thread_rng().fill_bytes(&mut prefix);
+    prefix
+}
+
+/// Build the nonce for chunk `index`: prefix ‖ index (u32 BE) ‖ last flag.
+fn chunk_nonce(prefix: &[u8; NONCE_PREFIX_LEN], index: u32, last: bool) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&index.to_be_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    nonce
 }
 
-/// Encrypt plaintext bytes with ChaCha20-Poly1305.
+/// Encrypt one chunk of a payload with ChaCha20-Poly1305.
 ///
-/// Returns (nonce, ciphertext). The nonce is randomly generated and
-/// must be stored in the header for decryption.
+/// Returns the ciphertext with its 16-byte tag appended. `last` must be
+/// set on exactly the final chunk so truncation is detectable.
+pub fn encrypt_chunk(
+    key: &[u8; KEY_LEN],
+    prefix: &[u8; NONCE_PREFIX_LEN],
+    index: u32,
+    last: bool,
+    plaintext: &[u8],
+) -> Result<Vec<u8>> {
+    let nonce = chunk_nonce(prefix, index, last);
+    let _timer = stats::timer(Stage::Cipher);
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| DendecError::DecryptionFailed)
+}
+
+/// Decrypt one chunk of a payload, verifying its Poly1305 tag.
+pub fn decrypt_chunk(
+    key: &[u8; KEY_LEN],
+    prefix: &[u8; NONCE_PREFIX_LEN],
+    index: u32,
+    last: bool,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>> {
+    let nonce = chunk_nonce(prefix, index, last);
+    decrypt(key, &nonce, ciphertext)
+}
+
+/// Encrypt plaintext bytes with ChaCha20-Poly1305 under a random nonce.
+///
+/// This is the v1 whole-message construction. Current output is chunked
+/// (see encrypt_chunk); this stays only to build v1 fixtures in tests.
+#[cfg(test)]
 pub fn encrypt(key: &[u8; KEY_LEN], plaintext: &[u8]) -> Result<([u8; NONCE_LEN], Vec<u8>)> {
     let mut nonce_bytes = [0u8; NONCE_LEN];
     rand::thread_rng().fill_bytes(&mut nonce_bytes);
@@ -118,6 +549,7 @@ pub fn encrypt(key: &[u8; KEY_LEN], plaintext: &[u8]) -> Result<([u8; NONCE_LEN]
 /// The Poly1305 MAC is verified automatically — if the password is
 /// wrong or the data is corrupted, decryption returns an error.
 pub fn decrypt(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    let _timer = stats::timer(Stage::Cipher);
     let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
     let nonce = Nonce::from_slice(nonce);
 
@@ -150,3 +582,54 @@ pub fn derive_dna_mapping(mapping_seed: u64) -> [u8; 4] {
     bases
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subkeys_are_per_purpose_and_stable() {
+        let master = MasterKey { key: [7u8; KEY_LEN], salt: [0u8; SALT_LEN] };
+        let names = master.subkey(Purpose::FileNames);
+        let mac = master.subkey(Purpose::ManifestMac);
+        assert_ne!(names, mac);
+        assert_eq!(names, master.subkey(Purpose::FileNames));
+
+        // Fixed by the v2 format: file keys must not move with the hierarchy
+        let keys = master.file_keys(&[1u8; FILE_SALT_LEN]);
+        assert_ne!(keys.cipher_key, names);
+        assert_ne!(keys.cipher_key, mac);
+        let mut expected = [0u8; KEY_LEN + MAPPING_SEED_LEN];
+        Hkdf::<Sha256>::new(Some(&[1u8; FILE_SALT_LEN]), &[7u8; KEY_LEN])
+            .expand(FILE_KEYS_INFO, &mut expected)
+            .unwrap();
+        assert_eq!(keys.cipher_key[..], expected[..KEY_LEN]);
+
+        let other = MasterKey { key: [8u8; KEY_LEN], salt: [0u8; SALT_LEN] };
+        assert_ne!(other.subkey(Purpose::FileNames), names);
+    }
+
+    #[test]
+    fn test_key_slots_open_only_for_their_password() {
+        let alice = MasterKey { key: [1u8; KEY_LEN], salt: [1u8; SALT_LEN] };
+        let bob = MasterKey { key: [2u8; KEY_LEN], salt: [2u8; SALT_LEN] };
+        let file_key = FileKey::random();
+        let file_salt = random_file_salt();
+
+        let slot = alice.seal_slot(&file_salt, &file_key, 0);
+        assert_eq!(slot[..SALT_LEN], alice.salt);
+        let (opened, region) = alice.open_slot(&file_salt, &slot, 3).unwrap();
+        assert_eq!((opened.key, region), (file_key.key, 0));
+        assert!(bob.open_slot(&file_salt, &slot, 3).is_none());
+        // The slot key is bound to the payload's file salt
+        assert!(alice.open_slot(&random_file_salt(), &slot, 3).is_none());
+
+        let mut altered = slot;
+        altered[SLOT_LEN - 1] ^= 1;
+        assert!(alice.open_slot(&file_salt, &altered, 3).is_none());
+
+        // The region is recovered, and only regions asked for are tried
+        let second = alice.seal_slot(&file_salt, &file_key, 2);
+        assert_eq!(alice.open_slot(&file_salt, &second, 3).unwrap().1, 2);
+        assert!(alice.open_slot(&file_salt, &second, 2).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+/// daemon.rs — dendec daemon: JSON-RPC 2.0 over stdin and stdout
+///
+/// For editor plugins and GUIs that keep dendec as a long-lived child
+/// process instead of spawning it per call. One JSON object per line in
+/// each direction; requests are handled one at a time, in order:
+///
+///   → {"jsonrpc":"2.0","id":1,"method":"encode","params":{"text":"hi","password":"pw"}}
+///   ← {"jsonrpc":"2.0","id":1,"result":{"dna":"ATGC…"}}
+///
+/// METHODS
+///
+///   version   {}                                   → {"version"}
+///   encode    {password, text | file, group?, out?, force?}
+///                                                  → {"dna"} or {"out","bytes"}
+///   decode    {password, dna | file, refer_password?, out?, force?}
+///                                                  → {"text"} or {"out","bytes"}
+///   wrap      {mode: "encode" | "decode", path, password, keep?, verify?,
+///              include_binary?, no_ignore?, abort_on_error?, force?, jobs?}
+///                                                  → {"path"}
+///   shutdown  {}                                   → null, then exit
+///
+/// `file` may be a .dna file or, for decode, a refer file in any format.
+/// Binary plaintext cannot travel in JSON: decode answers {"text"} only
+/// for UTF-8, and binary needs `out`. `wrap` takes a directory, never a
+/// command, since a command's output could land on stdout.
+///
+/// While a wrap runs, every file it deals with is sent as a notification:
+///
+///   ← {"jsonrpc":"2.0","method":"progress","params":{"id":3,"path":"notes.md",
+///      "outcome":"ok","detail":"1.2 KB → 4.9 KB","done":1,"total":40}}
+///
+/// Failures are JSON-RPC errors: -32700 unparsable line, -32600 not a
+/// request, -32601 unknown method, -32602 bad params, -32001 wrong
+/// password, -32000 anything else, with dendec's message. Stdout carries
+/// nothing but the protocol; dendec's usual messages go to stderr.
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::encoding::{decode_raw, encode_raw};
+use crate::error::{DendecError, Result};
+use crate::output::{check_output, prepare_output, write_atomic, OverwritePolicy};
+use crate::refer::format::is_refer_file;
+use crate::refer::unrefer;
+use crate::wrap::progress::{set_observer, FileEvent};
+use crate::wrap::{run_wrap, FailurePolicy, WrapOptions};
+
+/// Where responses and notifications go, shared with the wrap observer.
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+/// A failed request: JSON-RPC error code and message.
+struct RpcError(i64, String);
+
+impl From<DendecError> for RpcError {
+    fn from(e: DendecError) -> Self {
+        let code = match e {
+            DendecError::DecryptionFailed | DendecError::WrongPassword | DendecError::DecodeFailed => -32001,
+            _ => -32000,
+        };
+        RpcError(code, e.to_string())
+    }
+}
+
+type RpcResult = std::result::Result<Value, RpcError>;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EncodeParams {
+    password: String,
+    text: Option<String>,
+    file: Option<PathBuf>,
+    group: Option<usize>,
+    out: Option<PathBuf>,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DecodeParams {
+    password: String,
+    dna: Option<String>,
+    file: Option<PathBuf>,
+    refer_password: Option<String>,
+    out: Option<PathBuf>,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WrapParams {
+    mode: String,
+    path: PathBuf,
+    password: String,
+    #[serde(default)]
+    keep: bool,
+    #[serde(default)]
+    verify: bool,
+    #[serde(default)]
+    include_binary: bool,
+    #[serde(default)]
+    no_ignore: bool,
+    #[serde(default)]
+    abort_on_error: bool,
+    #[serde(default)]
+    force: bool,
+    jobs: Option<usize>,
+}
+
+/// Serve requests from stdin until it closes or `shutdown` arrives.
+pub fn run_daemon() -> Result<()> {
+    let stdin = std::io::stdin();
+    run(stdin.lock(), Arc::new(Mutex::new(std::io::stdout())))
+}
+
+/// Serve requests read from `input`, answering on `output`.
+pub fn run(input: impl BufRead, output: Output) -> Result<()> {
+    for line in input.lines() {
+        let line = line.map_err(DendecError::Io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, method, response) = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                let id = request.get("id").cloned();
+                let method = request.get("method").and_then(Value::as_str).unwrap_or_default().to_string();
+                (id.clone(), method, dispatch(&request, id, &output))
+            }
+            Err(e) => (Some(Value::Null), String::new(), Err(RpcError(-32700, format!("Parse error: {e}")))),
+        };
+        // A request without an id is a notification: no answer
+        if let Some(id) = id {
+            send(&output, &match response {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                Err(RpcError(code, message)) => {
+                    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+                }
+            })?;
+        }
+        if method == "shutdown" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Write `message` as one line.
+fn send(output: &Output, message: &Value) -> Result<()> {
+    let mut out = output.lock().unwrap_or_else(|e| e.into_inner());
+    writeln!(out, "{message}").map_err(DendecError::Io)?;
+    out.flush().map_err(DendecError::Io)
+}
+
+fn dispatch(request: &Value, id: Option<Value>, output: &Output) -> RpcResult {
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return Err(RpcError(-32600, "Invalid request: no method".to_string()));
+    };
+    let params = request.get("params").cloned().unwrap_or(json!({}));
+    match method {
+        "version" => Ok(json!({"version": env!("CARGO_PKG_VERSION")})),
+        "encode" => encode(parse_params(params)?),
+        "decode" => decode(parse_params(params)?),
+        "wrap" => wrap(parse_params(params)?, id.unwrap_or(Value::Null), output),
+        "shutdown" => Ok(Value::Null),
+        _ => Err(RpcError(-32601, format!("Method not found: {method}"))),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError(-32602, format!("Invalid params: {e}")))
+}
+
+/// Exactly one of two alternative params.
+fn one_of<T>(a: Option<T>, b: Option<T>, names: &str) -> std::result::Result<T, RpcError> {
+    match (a, b) {
+        (Some(v), None) | (None, Some(v)) => Ok(v),
+        _ => Err(RpcError(-32602, format!("Invalid params: give exactly one of {names}"))),
+    }
+}
+
+/// Write `bytes` to `path`, answering with where and how much.
+fn write_out(path: &Path, bytes: &[u8], force: bool) -> RpcResult {
+    let overwrite = OverwritePolicy::from_flags(force, false);
+    check_output(path, overwrite)?;
+    prepare_output(path, overwrite)?;
+    write_atomic(path, bytes, None)?;
+    Ok(json!({"out": path, "bytes": bytes.len()}))
+}
+
+fn encode(p: EncodeParams) -> RpcResult {
+    let plaintext = match one_of(p.text.map(Ok), p.file.map(Err), "text, file")? {
+        Ok(text) => text.into_bytes(),
+        Err(path) => std::fs::read(&path).map_err(DendecError::Io)?,
+    };
+    if let Some(out) = &p.out {
+        check_output(out, OverwritePolicy::from_flags(p.force, false))?;
+    }
+    let dna = encode_raw(&plaintext, &p.password, p.group)?;
+    match &p.out {
+        Some(out) => write_out(out, dna.as_bytes(), p.force),
+        None => Ok(json!({"dna": dna})),
+    }
+}
+
+fn decode(p: DecodeParams) -> RpcResult {
+    let dna = match one_of(p.dna.map(Ok), p.file.map(Err), "dna, file")? {
+        Ok(dna) => dna,
+        Err(path) if is_refer_file(&path)? => unrefer(&path, None, p.refer_password.as_deref())?,
+        Err(path) => std::fs::read_to_string(&path).map_err(DendecError::Io)?,
+    };
+    if let Some(out) = &p.out {
+        check_output(out, OverwritePolicy::from_flags(p.force, false))?;
+    }
+    let plaintext = decode_raw(&dna, &p.password)?;
+    match &p.out {
+        Some(out) => write_out(out, &plaintext, p.force),
+        None => match String::from_utf8(plaintext) {
+            Ok(text) => Ok(json!({"text": text})),
+            Err(_) => Err(RpcError(-32000, "Decoded data is binary — give \"out\" to write it to a file".to_string())),
+        },
+    }
+}
+
+fn wrap(p: WrapParams, id: Value, output: &Output) -> RpcResult {
+    let encode_mode = match p.mode.as_str() {
+        "encode" => true,
+        "decode" => false,
+        other => return Err(RpcError(-32602, format!("Invalid params: mode '{other}', expected encode or decode"))),
+    };
+    if !p.path.is_dir() {
+        return Err(RpcError(-32602, format!("Invalid params: {} is not a directory", p.path.display())));
+    }
+    let opts = WrapOptions {
+        overwrite: OverwritePolicy::from_flags(p.force, false),
+        on_error: FailurePolicy::from_flags(p.abort_on_error),
+        keep: p.keep,
+        verify: p.verify,
+        no_ignore: p.no_ignore,
+        include_binary: p.include_binary,
+        jobs: p.jobs,
+        ..Default::default()
+    };
+
+    let observer_output = Arc::clone(output);
+    set_observer(Some(Box::new(move |event: &FileEvent| {
+        let params = json!({
+            "id": id,
+            "path": event.path,
+            "outcome": event.outcome,
+            "detail": event.detail,
+            "done": event.done,
+            "total": event.total,
+        });
+        let _ = send(&observer_output, &json!({"jsonrpc": "2.0", "method": "progress", "params": params}));
+    })));
+    let result = run_wrap(encode_mode, &[p.path.to_string_lossy().into_owned()], &p.password, &opts);
+    set_observer(None);
+    result?;
+    Ok(json!({"path": p.path}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Run the daemon over `requests`, one per line; returns every line
+    /// it wrote.
+    fn converse(requests: &[Value]) -> Vec<Value> {
+        let input: String = requests.iter().map(|r| format!("{r}\n")).collect();
+        converse_raw(&input)
+    }
+
+    fn converse_raw(input: &str) -> Vec<Value> {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        run(input.as_bytes(), buffer.clone()).unwrap();
+        let out = buffer.lock().unwrap();
+        out.split(|&b| b == b'\n').filter(|l| !l.is_empty()).map(|l| serde_json::from_slice(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_encode_decode_and_errors() {
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("note.dna");
+        let first = converse(&[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "encode", "params": {"text": "hello", "password": "pw"}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "encode",
+                   "params": {"text": "to file", "password": "pw", "out": out}}),
+        ]);
+        let dna = first[0]["result"]["dna"].as_str().unwrap();
+        assert_eq!(first[1]["result"]["out"], json!(out));
+
+        let replies = converse(&[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "decode", "params": {"dna": dna, "password": "pw"}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "decode", "params": {"file": out, "password": "pw"}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "decode", "params": {"dna": dna, "password": "nope"}}),
+            json!({"jsonrpc": "2.0", "method": "version"}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "frobnicate"}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "encode", "params": {"password": "pw"}}),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "id": 7, "method": "version"}),
+        ]);
+        assert_eq!(replies[0]["result"]["text"], "hello");
+        assert_eq!(replies[1]["result"]["text"], "to file");
+        assert_eq!(replies[2]["error"]["code"], -32001);
+        // The notification got no answer; nothing after shutdown either
+        assert_eq!(replies.len(), 6);
+        assert_eq!(replies[3]["error"]["code"], -32601);
+        assert_eq!(replies[4]["error"]["code"], -32602);
+        assert_eq!(replies[5], json!({"jsonrpc": "2.0", "id": 6, "result": null}));
+
+        let replies = converse_raw("{\"jsonrpc\": \"2.0\", \"id\": 1,\n");
+        assert_eq!(replies[0]["id"], Value::Null);
+        assert_eq!(replies[0]["error"]["code"], -32700);
+    }
+
+    #[test]
+    fn test_wrap_sends_progress() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "alpha\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "beta\n").unwrap();
+        let replies = converse(&[
+            json!({"jsonrpc": "2.0", "id": 9, "method": "wrap",
+                   "params": {"mode": "encode", "path": dir.path(), "password": "pw"}}),
+        ]);
+        // Other tests' wraps may run meanwhile; look at ours only
+        let ours: Vec<&Value> = replies
+            .iter()
+            .filter(|r| r["method"] == "progress")
+            .filter(|r| r["params"]["path"].as_str().is_some_and(|p| p.starts_with(dir.path().to_str().unwrap())))
+            .collect();
+        assert_eq!(ours.len(), 2);
+        assert!(ours.iter().all(|r| r["params"]["id"] == 9 && r["params"]["outcome"] == "ok"));
+        let answer = replies.iter().find(|r| r["id"] == 9).unwrap();
+        assert_eq!(answer["result"]["path"], json!(dir.path()));
+        assert!(dir.path().join("a.txt.dna").exists());
+    }
+}
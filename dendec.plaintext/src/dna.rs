@@ -4,76 +4,305 @@
 /// The mapping table itself is key-derived (see crypto::derive_dna_mapping).
 ///
 /// Encoding:
-///   bytes → iterate bits MSB-first → pair into 2-bit diits → lookup base
+///   bytes → iterate bits MSB-first → pair into 2-bit digits → lookup base
 ///
 /// Decoding:
-///   bases → reverse lookup → 2-bit digits → reassemble bytes
+///   bases → 256-entry reverse table → 2-bit digits → reassemble bytes
+use std::io::{self, Read, Write};
+
 use crate::error::{DendecError, Result};
+use crate::stats::{self, Stage};
+
+/// Marker for bytes that are not part of the active mapping.
+const INVALID: u8 = 0xFF;
 
 /// Convert a byte slice to a DNA string using the provided base mapping.
 ///
 /// `mapping[0b00]` = base for 00, `mapping[0b01]` = base for 01, etc.
 /// Bits are processed MSB-first within each byte, which ensures a
 /// deterministic, byte-aligned encoding.
+///
+/// Encode itself streams through DnaWriter; this one-shot form remains
+/// the reference the writer is tested against. Not dead code in spirit.
+#[allow(dead_code)]
 pub fn bytes_to_dna(bytes: &[u8], mapping: &[u8; 4]) -> String {
-    let mut dna = String::with_capacity(bytes.len() * 4);
+    let expand = expansion_table(mapping);
+    let mut dna = Vec::with_capacity(bytes.len() * 4);
     for &byte in bytes {
-        // Extract 4 pairs of 2 bits from MSB to LSB
-        for shift in [6u8, 4, 2, 0] {
-            let two_bits = ((byte >> shift) & 0b11) as usize;
-            dna.push(mapping[two_bits] as char);
+        dna.extend_from_slice(&expand[byte as usize]);
+    }
+    // Every byte written above comes from `mapping`, which holds ASCII bases
+    String::from_utf8(dna).expect("DNA mapping contains non-ASCII bases")
+}
+
+/// Precompute the 4-base expansion of every possible byte, so the hot
+/// loop is a single table read and a 4-byte copy per input byte.
+fn expansion_table(mapping: &[u8; 4]) -> [[u8; 4]; 256] {
+    let mut expand = [[0u8; 4]; 256];
+    for (byte, bases) in expand.iter_mut().enumerate() {
+        for (i, shift) in [6u8, 4, 2, 0].into_iter().enumerate() {
+            bases[i] = mapping[(byte >> shift) & 0b11];
+        }
+    }
+    expand
+}
+
+/// Incremental bytes → DNA writer.
+///
+/// Produces exactly what `bytes_to_dna` followed by `group_dna` would,
+/// without holding the whole sequence in memory. The mapping can be
+/// switched between writes (the header prefix uses a fixed mapping,
+/// the rest a key-derived one) and grouping carries across the switch.
+pub struct DnaWriter<W: Write> {
+    inner: W,
+    expand: [[u8; 4]; 256],
+    group: usize,
+    column: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> DnaWriter<W> {
+    /// Create a writer. `group` of `None` or `Some(0)` means no grouping.
+    pub fn new(inner: W, mapping: &[u8; 4], group: Option<usize>) -> Self {
+        Self {
+            inner,
+            expand: expansion_table(mapping),
+            group: group.unwrap_or(0),
+            column: 0,
+            buf: Vec::with_capacity(64 * 1024),
+        }
+    }
+
+    /// Use `mapping` for all subsequent writes.
+    pub fn set_mapping(&mut self, mapping: &[u8; 4]) {
+        self.expand = expansion_table(mapping);
+    }
+
+    /// Convert `bytes` to bases and write them.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let _timer = stats::timer(Stage::Bases);
+        for &byte in bytes {
+            let bases = self.expand[byte as usize];
+            if self.group == 0 {
+                self.buf.extend_from_slice(&bases);
+            } else {
+                for base in bases {
+                    if self.column == self.group {
+                        self.buf.push(b' ');
+                        self.column = 0;
+                    }
+                    self.buf.push(base);
+                    self.column += 1;
+                }
+            }
+            if self.buf.len() >= 64 * 1024 {
+                self.inner.write_all(&self.buf)?;
+                self.buf.clear();
+            }
         }
+        Ok(())
+    }
+
+    /// Flush buffered bases and return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let _timer = stats::timer(Stage::Bases);
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(self.inner)
     }
-    dna
+}
+
+/// Build the 256-entry reverse lookup table: input byte → 2-bit value.
+///
+/// Every byte not present in `mapping` resolves to `INVALID`, which lets
+/// the decoder validate and translate in the same table read.
+fn reverse_table(mapping: &[u8; 4]) -> [u8; 256] {
+    let mut lut = [INVALID; 256];
+    for (i, &base) in mapping.iter().enumerate() {
+        lut[base as usize] = i as u8;
+    }
+    lut
 }
 
 /// Convert a DNA string back to bytes using the provided base mapping.
 ///
-/// Validates each character and reassembles 8-bit bytes from 4 bases each.
+/// Operates directly on the UTF-8 bytes of `dna` — no per-character
+/// decoding — and reassembles 8-bit bytes from 4 bases each.
 pub fn dna_to_bytes(dna: &str, mapping: &[u8; 4]) -> Result<Vec<u8>> {
-    // Build reverse mapping: base char → 2-bit value
-    let mut reverse = [None::<u8>; 128];
-    for (i, &base) in mapping.iter().enumerate() {
-        if (base as usize) < 128 {
-            reverse[base as usize] = Some(i as u8);
+    let _timer = stats::timer(Stage::Bases);
+    let input = dna.as_bytes();
+
+    if !input.len().is_multiple_of(4) {
+        return Err(DendecError::InvalidDnaLength(input.len()));
+    }
+
+    let lut = reverse_table(mapping);
+    let mut bytes = Vec::with_capacity(input.len() / 4);
+
+    for (chunk_idx, chunk) in input.chunks_exact(4).enumerate() {
+        let a = lut[chunk[0] as usize];
+        let b = lut[chunk[1] as usize];
+        let c = lut[chunk[2] as usize];
+        let d = lut[chunk[3] as usize];
+
+        // Valid entries are 0..=3, so any INVALID marker sets a high bit
+        if (a | b | c | d) > 0b11 {
+            let offset = [a, b, c, d].iter().position(|&v| v == INVALID).unwrap_or(0);
+            return Err(invalid_char_at(dna, chunk_idx * 4 + offset));
         }
+
+        // MSB-first: first base goes to bits 7-6
+        bytes.push((a << 6) | (b << 4) | (c << 2) | d);
     }
 
-    let chars: Vec<char> = dna.chars().collect();
+    Ok(bytes)
+}
 
-    if chars.len() % 4 != 0 {
-        return Err(DendecError::InvalidDnaLength(chars.len()));
+/// Reduce DNA as people paste it to bare uppercase bases.
+///
+/// Accepts what arrives from emails, terminals and sequence viewers: a
+/// leading byte-order mark, FASTA `>` header and `;` comment lines, any
+/// whitespace or line ending, position numbers (`--color` rulers,
+/// GenBank-style listings), lowercase bases, and RNA `U` for `T`. Any
+/// other character is an `InvalidDnaChar` at its position among the
+/// bases, rather than a failed header further on.
+pub fn normalize_dna(text: &str) -> Result<String> {
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+    let mut bases = String::with_capacity(text.len());
+    for line in text.lines() {
+        if matches!(line.trim_start().chars().next(), Some('>' | ';')) {
+            continue;
+        }
+        for ch in line.chars() {
+            match ch.to_ascii_uppercase() {
+                base @ ('A' | 'T' | 'G' | 'C') => bases.push(base),
+                'U' => bases.push('T'),
+                c if c.is_whitespace() || c.is_ascii_digit() => {}
+                _ => return Err(DendecError::InvalidDnaChar(ch, bases.len())),
+            }
+        }
     }
+    Ok(bases)
+}
 
-    let mut bytes = Vec::with_capacity(chars.len() / 4);
+/// Incremental DNA → bytes reader.
+///
+/// The inverse of `DnaWriter`: reads bases from `inner`, skipping ASCII
+/// whitespace (grouping), and yields the bytes they spell under the
+/// current mapping, which can be switched between reads. Errors in the
+/// input (a foreign character, a dangling partial byte) surface as
+/// `io::Error`s carrying the `DendecError` that `dna_to_bytes` would
+/// give; `from_io` recovers it. Positions count bases, not whitespace.
+pub struct DnaReader<R: Read> {
+    inner: R,
+    lut: [u8; 256],
+    buf: Vec<u8>,
+    pos: usize,
+    len: usize,
+    /// Bases consumed so far
+    bases: usize,
+}
 
-    for (chunk_idx, chunk) in chars.chunks(4).enumerate() {
-        let mut byte = 0u8;
-        for (bit_idx, &ch) in chunk.iter().enumerate() {
-            let ascii = ch as usize;
-            let two_bits = if ascii < 128 {
-                reverse[ascii]
-            } else {
-                None
-            };
-            match two_bits {
-                Some(v) => {
-                    // MSB-first: first pair goes to bits 7-6
-                    byte |= v << (6 - bit_idx * 2);
+impl<R: Read> DnaReader<R> {
+    pub fn new(inner: R, mapping: &[u8; 4]) -> Self {
+        Self {
+            inner,
+            lut: reverse_table(mapping),
+            buf: vec![0; 64 * 1024],
+            pos: 0,
+            len: 0,
+            bases: 0,
+        }
+    }
+
+    /// Use `mapping` for all subsequent reads.
+    pub fn set_mapping(&mut self, mapping: &[u8; 4]) {
+        self.lut = reverse_table(mapping);
+    }
+
+    /// The 2-bit value of the next base, or `None` at end of input.
+    fn next_digit(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            if self.pos == self.len {
+                self.len = match self.inner.read(&mut self.buf) {
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                self.pos = 0;
+                if self.len == 0 {
+                    return Ok(None);
                 }
-                None => {
-                    return Err(DendecError::InvalidDnaChar(ch, chunk_idx * 4 + bit_idx));
+            }
+            let byte = self.buf[self.pos];
+            self.pos += 1;
+            if byte.is_ascii_whitespace() {
+                continue;
+            }
+            let digit = self.lut[byte as usize];
+            if digit == INVALID {
+                // Decode what is buffered of a multi-byte character
+                let rest = &self.buf[self.pos - 1..self.len.min(self.pos + 3)];
+                let ch = String::from_utf8_lossy(rest).chars().next().unwrap_or('\u{FFFD}');
+                return Err(to_io(DendecError::InvalidDnaChar(ch, self.bases)));
+            }
+            self.bases += 1;
+            return Ok(Some(digit));
+        }
+    }
+}
+
+impl<R: Read> Read for DnaReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let _timer = stats::timer(Stage::Bases);
+        for (n, slot) in out.iter_mut().enumerate() {
+            let mut byte = 0u8;
+            for i in 0..4 {
+                match self.next_digit()? {
+                    Some(d) => byte = (byte << 2) | d,
+                    None if i == 0 => return Ok(n),
+                    None => return Err(to_io(DendecError::InvalidDnaLength(self.bases))),
                 }
             }
+            *slot = byte;
         }
-        bytes.push(byte);
+        Ok(out.len())
     }
+}
 
-    Ok(bytes)
+fn to_io(e: DendecError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// The error behind `e`: a `DnaReader`'s own, or `e` itself as `Io`.
+pub fn from_io(e: io::Error) -> DendecError {
+    if !e.get_ref().is_some_and(|inner| inner.is::<DendecError>()) {
+        return DendecError::Io(e);
+    }
+    match e.into_inner().map(|inner| inner.downcast::<DendecError>()) {
+        Some(Ok(inner)) => *inner,
+        _ => unreachable!("checked above"),
+    }
+}
+
+/// Build an `InvalidDnaChar` error for the byte at `position`.
+///
+/// Every byte before `position` is a valid ASCII base, so `position` is
+/// always a character boundary and the offending character can be
+/// reported whole even when it is multi-byte UTF-8.
+fn invalid_char_at(dna: &str, position: usize) -> DendecError {
+    let ch = dna
+        .get(position..)
+        .and_then(|rest| rest.chars().next())
+        .unwrap_or('\u{FFFD}');
+    DendecError::InvalidDnaChar(ch, position)
 }
 
 /// Format a DNA string into groups of `n` bases separated by spaces.
 /// Example: group_dna("ATGCATGC", 4) → "ATGC ATGC"
+///
+/// DnaWriter groups while streaming; kept alongside bytes_to_dna.
+#[allow(dead_code)]
 pub fn group_dna(dna: &str, n: usize) -> String {
     if n == 0 {
         return dna.to_string();
@@ -124,6 +353,60 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_invalid_char_position_reported() {
+        match dna_to_bytes("ATGCATGX", &DEFAULT_MAPPING) {
+            Err(DendecError::InvalidDnaChar(ch, pos)) => {
+                assert_eq!(ch, 'X');
+                assert_eq!(pos, 7);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_char_rejected() {
+        // "ATG" + 'é' is 5 bytes — pad to a multiple of 4 with valid bases
+        match dna_to_bytes("ATGéATG", &DEFAULT_MAPPING) {
+            Err(DendecError::InvalidDnaChar(ch, pos)) => {
+                assert_eq!(ch, 'é');
+                assert_eq!(pos, 3);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_all_bytes_roundtrip() {
+        let original: Vec<u8> = (0u8..=255u8).collect();
+        let dna = bytes_to_dna(&original, &DEFAULT_MAPPING);
+        let decoded = dna_to_bytes(&dna, &DEFAULT_MAPPING).unwrap();
+        assert_eq!(original, decoded);
+    }
+
+    #[test]
+    fn test_reader_matches_dna_to_bytes() {
+        let original: Vec<u8> = (0u8..=255u8).cycle().take(70_000).collect();
+        let dna = group_dna(&bytes_to_dna(&original, &DEFAULT_MAPPING), 10);
+        let mut read = Vec::new();
+        DnaReader::new(dna.as_bytes(), &DEFAULT_MAPPING).read_to_end(&mut read).unwrap();
+        assert_eq!(read, original);
+
+        let mut sink = Vec::new();
+        let err = DnaReader::new(&b"ATGC AT\nGX"[..], &DEFAULT_MAPPING).read_to_end(&mut sink).unwrap_err();
+        assert!(matches!(from_io(err), DendecError::InvalidDnaChar('X', 7)));
+        let err = DnaReader::new(&b"ATGCA"[..], &DEFAULT_MAPPING).read_to_end(&mut sink).unwrap_err();
+        assert!(matches!(from_io(err), DendecError::InvalidDnaLength(5)));
+    }
+
+    #[test]
+    fn test_pasted_dna_normalized() {
+        let pasted = "\u{FEFF}>payload from dendec\r\n; exported\r\n        1 acgu ACGT\r\n       9 TTGA\r\n";
+        assert_eq!(normalize_dna(pasted).unwrap(), "ACGTACGTTTGA");
+        assert_eq!(normalize_dna("ATGC ATGC\n").unwrap(), "ATGCATGC");
+        assert!(matches!(normalize_dna("ACGT\nACNT"), Err(DendecError::InvalidDnaChar('N', 6))));
+    }
+
     #[test]
     fn test_odd_length_rejected() {
         let result = dna_to_bytes("ATG", &DEFAULT_MAPPING);
@@ -145,6 +428,22 @@ mod tests {
         assert_eq!(grouped, "ATGC ATGC");
     }
 
+    #[test]
+    fn test_writer_matches_bytes_to_dna() {
+        let mapping: [u8; 4] = [b'G', b'A', b'C', b'T'];
+        let data: Vec<u8> = (0u8..=255u8).collect();
+
+        let mut w = DnaWriter::new(Vec::new(), &DEFAULT_MAPPING, Some(7));
+        w.write_bytes(&data[..5]).unwrap();
+        w.set_mapping(&mapping);
+        w.write_bytes(&data[5..]).unwrap();
+        let written = String::from_utf8(w.finish().unwrap()).unwrap();
+
+        let mut expected = bytes_to_dna(&data[..5], &DEFAULT_MAPPING);
+        expected.push_str(&bytes_to_dna(&data[5..], &mapping));
+        assert_eq!(written, group_dna(&expected, 7));
+    }
+
     #[test]
     fn test_zero_byte() {
         let original = &[0u8];
@@ -6,100 +6,535 @@
 ///  Offset  Len   Field
 ///  ──────  ───   ─────────────────────────────────────────────────
 ///  0       4     Magic bytes: 0x44 0x4E 0x44 0x43  ("DNDC")
-///  4       1     Version: 0x01
-///  5       16    Argon2 salt  (random, 128 bits)
-///  21      12    ChaCha20 nonce  (random, 96 bits)
-///  33      8     Payload length (u64 little-endian, encrypted ciphertext size)
+///  4       1     Version: 0x02
+///  5       16    Argon2 salt  (random, 128 bits, shared by a wrap batch)
+///  21      16    File salt  (random, 128 bits, HKDF salt for this payload)
+///  37      4     Password verifier (truncated HMAC, see crypto.rs)
+///  41      7     STREAM nonce prefix  (random, 56 bits)
+///  48      4     Chunk size (u32 little-endian, plaintext bytes per chunk)
 ///  ──────  ───   ─────────────────────────────────────────────────
-///  41      N     Ciphertext (plaintext bytes encrypted with Poly1305 tag)
+///  52      N     Chunks: ciphertext + 16-byte Poly1305 tag each
 ///
-/// Total header: 41 bytes → 164 DNA bases
+/// Total header: 52 bytes → 208 DNA bases
+///
+/// Every chunk except the last carries exactly `chunk size` plaintext
+/// bytes; the last carries 0..=chunk size and is flagged in its nonce.
+/// There is always at least one chunk, so an empty payload is a single
+/// 16-byte tag. No total length is stored — encode can stream input of
+/// unknown size, and the final-chunk flag detects truncation.
+///
+/// A payload locked with several passwords (`encode --passwords`) has
+/// version 0x03 and key slots in place of the KDF salt and verifier:
+///
+///  Offset  Len   Field
+///  ──────  ───   ─────────────────────────────────────────────────
+///  0       4     Magic bytes: 0x44 0x4E 0x44 0x43  ("DNDC")
+///  4       1     Version: 0x03
+///  5       16    File salt
+///  21      1     Slot count n, 1..=16
+///  22      64n   Key slots: Argon2 salt ‖ file key sealed for one password
+///  22+64n  7     STREAM nonce prefix
+///  29+64n  4     Chunk size
+///  ──────  ───   ─────────────────────────────────────────────────
+///  33+64n  N     Chunks, as in v2
+///
+/// The file keys come from a random file key rather than a master key
+/// (see crypto.rs), and a slot's tag doubles as its password check. The
+/// chunks depend on the file key alone, so `rekey` rewrites the slots
+/// and copies the chunks unchanged.
+///
+/// A padded or hidden payload (`--pad`, `--hidden`) splits everything
+/// after the slots into two regions of equal length, each a nonce
+/// prefix, chunk size and chunks under its own file key. Each region's
+/// plaintext is its payload's length (u64 little-endian), the payload,
+/// and zeros up to the longer payload's length, so neither region's size
+/// says which payload is longer. `--pad` fills the second region with
+/// random bytes, so a hidden payload is indistinguishable from padding
+/// to anyone without its password. A slot's nonce records which region
+/// its file key opens: the only one, the first or the second.
+///
+/// The v1 header has no file salt, no verifier and no chunking: a 12-byte
+/// nonce at 21, the u64 ciphertext length at 33, and one ciphertext at 41
+/// (164 bases). Its keys come straight from Argon2id.
+///
+/// DNA MAPPING BY REGION
+/// ──────────────────────────────────────────────────────
+///
+///  v3: bytes up to the end of the key slots use the canonical mapping;
+///      the rest uses the mapping derived from the file key. Decode tries
+///      each slot in turn, one Argon2id run per slot until one opens.
+///
+///  v2: bytes 0..37 (magic, version, both salts) use the fixed canonical
+///      mapping A=00 T=01 G=10 C=11. Everything after the salts uses the
+///      key-derived mapping. Decode reads the salts directly, runs Argon2id
+///      at most once (never, if a MasterKeyCache already holds the KDF
+///      salt), and checks the verifier before converting the payload, so a
+///      wrong password fails immediately.
+///
+///  v1: the whole packet uses the key-derived mapping. Decode recovers the
+///      mapping by matching the magic bytes against all 24 permutations
+///      (no KDF involved), then runs Argon2id once to confirm it. v1 is
+///      still decoded; it is no longer produced.
+use std::collections::HashSet;
+use std::io::{Cursor, Read, Seek, Write};
+
+use log::debug;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use rayon::prelude::*;
+
 use crate::crypto::{
-    decrypt, derive_dna_mapping, derive_keys, derive_keys_with_salt, encrypt, NONCE_LEN, SALT_LEN,
+    check_password_verifier, decrypt, decrypt_chunk, derive_dna_mapping, derive_master_key,
+    derive_master_key_with_salt, encrypt_chunk, password_verifier, random_file_salt, random_nonce_prefix,
+    DerivedKeys, FileKey, MasterKey, MasterKeyCache, FILE_SALT_LEN, NONCE_LEN, NONCE_PREFIX_LEN,
+    SALT_LEN, SLOT_LEN, TAG_LEN, VERIFIER_LEN,
 };
-use crate::dna::{bytes_to_dna, dna_to_bytes, group_dna};
+use crate::dna::{dna_to_bytes, from_io, normalize_dna, DnaReader, DnaWriter};
 use crate::error::{DendecError, Result};
 
 const MAGIC: [u8; 4] = [0x44, 0x4E, 0x44, 0x43];
-const VERSION: u8 = 0x01;
+const VERSION: u8 = 0x02;
+const VERSION_V1: u8 = 0x01;
+const VERSION_SLOTS: u8 = 0x03;
+
+/// Most key slots one payload may carry.
+pub const MAX_SLOTS: usize = 16;
 
-struct Header {
-    salt: [u8; SALT_LEN],
-    nonce: [u8; NONCE_LEN],
-    payload_len: u64,
+/// Which part of a v3 body a key slot's file key opens.
+const REGION_ONLY: u8 = 0;
+const REGION_FIRST: u8 = 1;
+const REGION_SECOND: u8 = 2;
+const REGIONS: u8 = 3;
+
+/// Length prefix of each region's plaintext in a two-region payload.
+const REGION_LEN_PREFIX: usize = 8;
+
+/// Plaintext bytes per chunk written by encode.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest chunk size decode accepts — bounds per-chunk memory.
+pub const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Length of the canonically mapped v2 prefix: magic + version + salts.
+const PREFIX_LEN: usize = 4 + 1 + SALT_LEN + FILE_SALT_LEN;
+const PREFIX_DNA_LEN: usize = PREFIX_LEN * 4;
+
+/// Length of magic + version + Argon2 salt, common to v1 and v2.
+const SALT_END: usize = 4 + 1 + SALT_LEN;
+
+/// v2 header sizes in bytes and in DNA bases.
+const HEADER_LEN: usize = PREFIX_LEN + VERIFIER_LEN + NONCE_PREFIX_LEN + 4;
+const HEADER_DNA_LEN: usize = HEADER_LEN * 4;
+
+/// v1 header sizes in bytes and in DNA bases.
+const HEADER_LEN_V1: usize = SALT_END + NONCE_LEN + 8;
+const HEADER_DNA_LEN_V1: usize = HEADER_LEN_V1 * 4;
+
+/// Fixed mapping for the v2 prefix. Matches the refer table's base-4 order.
+const CANONICAL_MAPPING: [u8; 4] = [b'A', b'T', b'G', b'C'];
+
+/// Magic plus version in bases — all `is_dendec_payload` needs to see.
+const SIGNATURE_DNA_LEN: usize = 5 * 4;
+
+/// The keyed part of a v2 header after its verifier, and of a v3 header
+/// after its key slots.
+struct StreamHeader {
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    chunk_size: usize,
+}
+
+impl StreamHeader {
+    const LEN: usize = NONCE_PREFIX_LEN + 4;
+
+    /// Split the header bytes into fields. The chunk size is range
+    /// checked later, once the password is confirmed.
+    fn parse(bytes: &[u8]) -> Self {
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        nonce_prefix.copy_from_slice(&bytes[..NONCE_PREFIX_LEN]);
+
+        let mut size_bytes = [0u8; 4];
+        size_bytes.copy_from_slice(&bytes[NONCE_PREFIX_LEN..Self::LEN]);
+        let chunk_size = u32::from_le_bytes(size_bytes) as usize;
+
+        Self { nonce_prefix, chunk_size }
+    }
 }
 
-fn build_packet(header: &Header, ciphertext: &[u8]) -> Vec<u8> {
-    let mut packet = Vec::with_capacity(41 + ciphertext.len());
-    packet.extend_from_slice(&MAGIC);
-    packet.push(VERSION);
-    packet.extend_from_slice(&header.salt);
-    packet.extend_from_slice(&header.nonce);
-    packet.extend_from_slice(&header.payload_len.to_le_bytes());
-    packet.extend_from_slice(ciphertext);
-    packet
+/// The canonically mapped part of a v3 header after its signature.
+struct SlotHeader {
+    file_salt: [u8; FILE_SALT_LEN],
+    slots: Vec<[u8; SLOT_LEN]>,
 }
 
-fn parse_packet(packet: &[u8]) -> Result<(Header, &[u8])> {
-    if packet.len() < 42 {
-        return Err(DendecError::BadMagic);
+impl SlotHeader {
+    /// Read the file salt and key slots.
+    fn read<R: Read>(dna: &mut DnaReader<R>) -> Result<Self> {
+        let truncated = |e| match from_io(e) {
+            DendecError::Io(_) => DendecError::BadMagic,
+            e => e,
+        };
+        let mut file_salt = [0u8; FILE_SALT_LEN];
+        dna.read_exact(&mut file_salt).map_err(truncated)?;
+        let mut count = [0u8; 1];
+        dna.read_exact(&mut count).map_err(truncated)?;
+        if !(1..=MAX_SLOTS).contains(&(count[0] as usize)) {
+            return Err(DendecError::BadMagic);
+        }
+        let mut slots = vec![[0u8; SLOT_LEN]; count[0] as usize];
+        for slot in &mut slots {
+            dna.read_exact(slot).map_err(truncated)?;
+        }
+        Ok(Self { file_salt, slots })
     }
-    if &packet[0..4] != MAGIC {
-        return Err(DendecError::BadMagic);
+
+    /// The file key, and the region it opens, from the first slot `keys`'
+    /// password opens. Each slot tried costs an Argon2id run unless
+    /// `keys` holds its salt.
+    fn open(&self, keys: &MasterKeyCache) -> Result<(FileKey, u8)> {
+        for slot in &self.slots {
+            let mut salt = [0u8; SALT_LEN];
+            salt.copy_from_slice(&slot[..SALT_LEN]);
+            if let Some(opened) = keys.get(&salt)?.open_slot(&self.file_salt, slot, REGIONS) {
+                return Ok(opened);
+            }
+        }
+        Err(DendecError::WrongPassword)
     }
+}
 
-    let version = packet[4];
-    if version != VERSION {
-        return Err(DendecError::UnsupportedVersion {
-            expected: VERSION,
-            got: version,
-        });
+/// Streaming encode — reads plaintext from `input`, writes DNA to `output`.
+///
+/// This is the canonical implementation. Chunks are sealed on all cores
+/// via rayon, and memory use is bounded by one batch of chunks (four per
+/// worker thread) regardless of input size, so `input` can be a
+/// memory-mapped multi-GB file. `group` inserts a space every N bases.
+pub fn encode_stream<R: Read, W: Write>(
+    input: R,
+    output: W,
+    password: &str,
+    group: Option<usize>,
+) -> Result<W> {
+    let master = derive_master_key(password)?;
+    encode_stream_with_master(input, output, &master, group)
+}
+
+/// Streaming encode under an already derived master key.
+///
+/// Used by batch callers (wrap) that derive the master key once and
+/// encode many payloads under it. Each call draws a fresh file salt,
+/// so every payload still gets its own cipher key and mapping.
+pub fn encode_stream_with_master<R: Read, W: Write>(
+    input: R,
+    output: W,
+    master: &MasterKey,
+    group: Option<usize>,
+) -> Result<W> {
+    encode_stream_chunked(input, output, master, group, CHUNK_SIZE)
+}
+
+/// Streaming encode in chunks of `chunk_size` plaintext bytes, which
+/// must be 1..=MAX_CHUNK_SIZE (see codec.rs).
+pub(crate) fn encode_stream_chunked<R: Read, W: Write>(
+    input: R,
+    output: W,
+    master: &MasterKey,
+    group: Option<usize>,
+    chunk_size: usize,
+) -> Result<W> {
+    if !(1..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return Err(DendecError::ChunkSizeOutOfRange(chunk_size));
     }
+    let file_salt = random_file_salt();
+    let keys = master.file_keys(&file_salt);
+    let nonce_prefix = random_nonce_prefix();
 
-    let mut salt = [0u8; SALT_LEN];
-    salt.copy_from_slice(&packet[5..21]);
+    let mut dna = DnaWriter::new(output, &CANONICAL_MAPPING, group);
+    dna.write_bytes(&MAGIC)?;
+    dna.write_bytes(&[VERSION])?;
+    dna.write_bytes(&master.salt)?;
+    dna.write_bytes(&file_salt)?;
 
-    let mut nonce = [0u8; NONCE_LEN];
-    nonce.copy_from_slice(&packet[21..33]);
+    dna.set_mapping(&derive_dna_mapping(keys.mapping_seed));
+    dna.write_bytes(&password_verifier(&keys))?;
+    dna.write_bytes(&nonce_prefix)?;
+    dna.write_bytes(&(chunk_size as u32).to_le_bytes())?;
 
-    let mut len_bytes = [0u8; 8];
-    len_bytes.copy_from_slice(&packet[33..41]);
-    let payload_len = u64::from_le_bytes(len_bytes);
+    seal_chunks(input, &keys, &nonce_prefix, chunk_size, |chunk| Ok(dna.write_bytes(chunk)?))?;
+    Ok(dna.finish()?)
+}
 
-    let ciphertext = &packet[41..];
-    if ciphertext.len() != payload_len as usize {
-        return Err(DendecError::LengthMismatch {
-            header: payload_len as usize,
-            actual: ciphertext.len(),
-        });
+/// Streaming encode locked with several passwords, one key slot per
+/// master key in `masters`; any one of them decodes the result. Up to
+/// `MAX_SLOTS` keys, each with its own KDF salt.
+pub fn encode_stream_slots<R: Read, W: Write>(
+    input: R,
+    output: W,
+    masters: &[&MasterKey],
+    group: Option<usize>,
+) -> Result<W> {
+    let file_key = FileKey::random();
+    let file_salt = random_file_salt();
+    let keys = file_key.file_keys(&file_salt);
+    let nonce_prefix = random_nonce_prefix();
+
+    let mut dna = DnaWriter::new(output, &CANONICAL_MAPPING, group);
+    let slots = seal_slots(masters, &file_salt, &file_key, REGION_ONLY);
+    write_slot_header(&mut dna, &file_salt, &slots)?;
+    dna.set_mapping(&derive_dna_mapping(keys.mapping_seed));
+    dna.write_bytes(&nonce_prefix)?;
+    dna.write_bytes(&(CHUNK_SIZE as u32).to_le_bytes())?;
+
+    seal_chunks(input, &keys, &nonce_prefix, CHUNK_SIZE, |chunk| Ok(dna.write_bytes(chunk)?))?;
+    Ok(dna.finish()?)
+}
+
+/// In-memory encode locked with `masters`, as `encode_stream_slots`,
+/// followed by a second region of random bytes as long as the first.
+/// The result looks like the output of `encode_hidden`.
+pub fn encode_padded(plaintext: &[u8], masters: &[&MasterKey], group: Option<usize>) -> Result<String> {
+    encode_regions(plaintext, masters, None, group)
+}
+
+/// In-memory encode of two payloads in one sequence: `decoy` opens
+/// under the `decoy_masters` passwords, `hidden` under the
+/// `hidden_masters` ones. Both regions are padded to the same length.
+pub fn encode_hidden(
+    decoy: &[u8],
+    decoy_masters: &[&MasterKey],
+    hidden: &[u8],
+    hidden_masters: &[&MasterKey],
+    group: Option<usize>,
+) -> Result<String> {
+    encode_regions(decoy, decoy_masters, Some((hidden, hidden_masters)), group)
+}
+
+fn encode_regions(
+    first: &[u8],
+    first_masters: &[&MasterKey],
+    second: Option<(&[u8], &[&MasterKey])>,
+    group: Option<usize>,
+) -> Result<String> {
+    let file_salt = random_file_salt();
+    let padded_len = first.len().max(second.map_or(0, |(p, _)| p.len()));
+    let first_key = FileKey::random();
+    let mut slots = seal_slots(first_masters, &file_salt, &first_key, REGION_FIRST);
+    let second_key = second.map(|(_, masters)| {
+        let key = FileKey::random();
+        slots.extend(seal_slots(masters, &file_salt, &key, REGION_SECOND));
+        key
+    });
+    if slots.iter().map(|s| &s[..SALT_LEN]).collect::<HashSet<_>>().len() < slots.len() {
+        return Err(DendecError::HiddenPasswordReused);
     }
+    // Slot order would otherwise say which region a slot opens
+    slots.shuffle(&mut rand::thread_rng());
+
+    let mut dna = DnaWriter::new(Vec::new(), &CANONICAL_MAPPING, group);
+    write_slot_header(&mut dna, &file_salt, &slots)?;
+    let first_region = seal_region(first, padded_len, &first_key, &file_salt)?;
+    let second_region = match (second, &second_key) {
+        (Some((plaintext, _)), Some(key)) => seal_region(plaintext, padded_len, key, &file_salt)?,
+        _ => {
+            let mut random = vec![0u8; first_region.len()];
+            rand::thread_rng().fill_bytes(&mut random);
+            random
+        }
+    };
+    for (region, key) in [(&first_region, Some(&first_key)), (&second_region, second_key.as_ref())] {
+        // Random padding reads as bases under any mapping
+        if let Some(key) = key {
+            dna.set_mapping(&derive_dna_mapping(key.file_keys(&file_salt).mapping_seed));
+        }
+        dna.write_bytes(region)?;
+    }
+    // DnaWriter only ever emits ASCII bases and spaces
+    Ok(String::from_utf8(dna.finish()?).expect("DNA output is ASCII"))
+}
+
+/// One region of a two-region payload: nonce prefix, chunk size and the
+/// sealed chunks of `plaintext` framed and padded to `padded_len` bytes.
+fn seal_region(
+    plaintext: &[u8],
+    padded_len: usize,
+    file_key: &FileKey,
+    file_salt: &[u8; FILE_SALT_LEN],
+) -> Result<Vec<u8>> {
+    let mut framed = Vec::with_capacity(REGION_LEN_PREFIX + padded_len);
+    framed.extend_from_slice(&(plaintext.len() as u64).to_le_bytes());
+    framed.extend_from_slice(plaintext);
+    framed.resize(REGION_LEN_PREFIX + padded_len, 0);
 
-    Ok((Header { salt, nonce, payload_len }, ciphertext))
+    let keys = file_key.file_keys(file_salt);
+    let nonce_prefix = random_nonce_prefix();
+    let mut region = nonce_prefix.to_vec();
+    region.extend_from_slice(&(CHUNK_SIZE as u32).to_le_bytes());
+    seal_chunks(&framed[..], &keys, &nonce_prefix, CHUNK_SIZE, |chunk| {
+        region.extend_from_slice(chunk);
+        Ok(())
+    })?;
+    Ok(region)
 }
 
-/// Core encode — operates on raw bytes.
+/// Replace the key slots of a payload from `encode_stream_slots`.
 ///
-/// This is the canonical implementation. Both `encode` (text path)
-/// and file-mode encoding call this. Accepts any byte slice, so binary
-/// files, UTF-8 text, and partial content are all handled identically.
-pub fn encode_raw(plaintext: &[u8], password: &str, group: Option<usize>) -> Result<String> {
-    let keys = derive_keys(password)?;
-    let (nonce, ciphertext) = encrypt(&keys.cipher_key, plaintext)?;
+/// `unlock` opens the file key from the current slots; the new header
+/// has one slot per master key in `masters`, so a password left out can
+/// no longer decode this copy. The sealed chunks are copied unchanged,
+/// never decrypted. Memory use does not grow with the input. A padded
+/// or hidden payload is refused: slots for the region `unlock` cannot
+/// open would be lost.
+pub fn rekey_stream<R: Read, W: Write>(
+    input: R,
+    output: W,
+    unlock: &MasterKeyCache,
+    masters: &[&MasterKey],
+    group: Option<usize>,
+) -> Result<W> {
+    let mut dna = DnaReader::new(input, &CANONICAL_MAPPING);
+    if read_signature(&mut dna) != Some(VERSION_SLOTS) {
+        return Err(DendecError::NotSlotted);
+    }
+    let header = SlotHeader::read(&mut dna)?;
+    let (file_key, region) = header.open(unlock)?;
+    if region != REGION_ONLY {
+        return Err(DendecError::RekeyTwoRegions);
+    }
+    let mapping = derive_dna_mapping(file_key.file_keys(&header.file_salt).mapping_seed);
 
-    let header = Header {
-        salt: keys.salt,
-        nonce,
-        payload_len: ciphertext.len() as u64,
-    };
-    let packet = build_packet(&header, &ciphertext);
-    let mapping = derive_dna_mapping(keys.mapping_seed);
-    let dna = bytes_to_dna(&packet, &mapping);
+    let mut out = DnaWriter::new(output, &CANONICAL_MAPPING, group);
+    let slots = seal_slots(masters, &header.file_salt, &file_key, REGION_ONLY);
+    write_slot_header(&mut out, &header.file_salt, &slots)?;
+    dna.set_mapping(&mapping);
+    out.set_mapping(&mapping);
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = dna.read(&mut buf).map_err(from_io)?;
+        if n == 0 {
+            break;
+        }
+        out.write_bytes(&buf[..n])?;
+    }
+    Ok(out.finish()?)
+}
 
-    if let Some(n) = group {
-        Ok(group_dna(&dna, n))
-    } else {
-        Ok(dna)
+/// One key slot per master key in `masters`, all opening `region`. A
+/// key given twice gets one slot.
+fn seal_slots(
+    masters: &[&MasterKey],
+    file_salt: &[u8; FILE_SALT_LEN],
+    file_key: &FileKey,
+    region: u8,
+) -> Vec<[u8; SLOT_LEN]> {
+    let mut unique: Vec<&MasterKey> = Vec::with_capacity(masters.len());
+    for &master in masters {
+        if !unique.iter().any(|m| m.salt == master.salt) {
+            unique.push(master);
+        }
     }
+    unique.iter().map(|m| m.seal_slot(file_salt, file_key, region)).collect()
+}
+
+/// Write the canonically mapped v3 header: signature, file salt and
+/// key slots.
+fn write_slot_header<W: Write>(
+    dna: &mut DnaWriter<W>,
+    file_salt: &[u8; FILE_SALT_LEN],
+    slots: &[[u8; SLOT_LEN]],
+) -> Result<()> {
+    if !(1..=MAX_SLOTS).contains(&slots.len()) {
+        return Err(DendecError::SlotCount(slots.len()));
+    }
+    dna.write_bytes(&MAGIC)?;
+    dna.write_bytes(&[VERSION_SLOTS])?;
+    dna.write_bytes(file_salt)?;
+    dna.write_bytes(&[slots.len() as u8])?;
+    for slot in slots {
+        dna.write_bytes(slot)?;
+    }
+    Ok(())
+}
+
+/// Seal plaintext from `input` in chunks of `chunk_size`, passing each
+/// sealed chunk to `write` in order.
+fn seal_chunks<R: Read>(
+    mut input: R,
+    keys: &DerivedKeys,
+    nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+    chunk_size: usize,
+    mut write: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    // Chunks are sealed in parallel batches and written in order. One
+    // chunk beyond the batch is read ahead so the final chunk is known
+    // before it is sealed and can be flagged as last.
+    let batch_len = rayon::current_num_threads().max(1) * 4;
+    let mut batch: Vec<Vec<u8>> = Vec::with_capacity(batch_len + 1);
+    let mut first_index = 0u64;
+    let mut eof = false;
+    loop {
+        while !eof && batch.len() <= batch_len {
+            let chunk = read_chunk(&mut input, chunk_size)?;
+            eof = chunk.len() < chunk_size;
+            // An empty read only becomes a chunk when the input is empty
+            if !chunk.is_empty() || (batch.is_empty() && first_index == 0) {
+                batch.push(chunk);
+            }
+        }
+
+        let carry = if eof { None } else { batch.pop() };
+        let final_pos = batch.len() - 1;
+        if first_index + final_pos as u64 > u32::MAX as u64 {
+            return Err(DendecError::PayloadTooLarge);
+        }
+
+        let sealed: Vec<Vec<u8>> = batch
+            .par_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let index = (first_index + i as u64) as u32;
+                let last = eof && i == final_pos;
+                encrypt_chunk(&keys.cipher_key, nonce_prefix, index, last, chunk)
+            })
+            .collect::<Result<_>>()?;
+        for chunk in &sealed {
+            write(chunk)?;
+        }
+
+        if eof {
+            break;
+        }
+        first_index += batch.len() as u64;
+        batch.clear();
+        batch.extend(carry);
+    }
+    Ok(())
+}
+
+/// Fill a buffer of up to `size` bytes, stopping early only at EOF.
+fn read_chunk<R: Read>(input: &mut R, size: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(size);
+    input.take(size as u64).read_to_end(&mut buf).map_err(from_io)?;
+    Ok(buf)
+}
+
+/// In-memory encode — operates on raw bytes.
+///
+/// Convenience wrapper around encode_stream for callers that want the
+/// whole sequence as a String. Accepts any byte slice, so binary files,
+/// UTF-8 text, and partial content are all handled identically.
+pub fn encode_raw(plaintext: &[u8], password: &str, group: Option<usize>) -> Result<String> {
+    let master = derive_master_key(password)?;
+    encode_raw_with_master(plaintext, &master, group)
+}
+
+/// In-memory encode under an already derived master key.
+pub fn encode_raw_with_master(
+    plaintext: &[u8],
+    master: &MasterKey,
+    group: Option<usize>,
+) -> Result<String> {
+    let dna = encode_stream_with_master(plaintext, Vec::new(), master, group)?;
+    // DnaWriter only ever emits ASCII bases and spaces
+    Ok(String::from_utf8(dna).expect("DNA output is ASCII"))
 }
 
 /// Encode Unicode text into an encrypted DNA sequence.
@@ -116,45 +551,435 @@ pub fn encode(text: &str, password: &str, group: Option<usize>) -> Result<String
 /// This is the canonical implementation. Both `decode` (text path)
 /// and file-mode decoding call this. The caller decides whether to
 /// interpret the result as UTF-8 or write it verbatim to a file.
+///
+/// Argon2id runs exactly once per call, for both v2 and v1 input.
 pub fn decode_raw(dna: &str, password: &str) -> Result<Vec<u8>> {
-    let dna_clean: String = dna.chars().filter(|c| !c.is_whitespace()).collect();
+    decode_raw_cached(dna, &MasterKeyCache::new(password))
+}
 
-    let header_dna_len = 41 * 4;
-    if dna_clean.len() < header_dna_len {
-        return Err(DendecError::BadMagic);
+/// Core decode with master keys shared across calls.
+///
+/// Decoding a wrap batch through one cache runs Argon2id once for the
+/// whole batch. v1 input has no master key and always derives afresh.
+pub fn decode_raw_cached(dna: &str, keys: &MasterKeyCache) -> Result<Vec<u8>> {
+    let dna_clean = normalize_dna(dna)?;
+
+    // Slotted payloads decode as a stream over the cleaned input
+    let signature = dna_clean.get(..SIGNATURE_DNA_LEN).and_then(|s| dna_to_bytes(s, &CANONICAL_MAPPING).ok());
+    if signature.is_some_and(|s| s[0..4] == MAGIC && s[4] == VERSION_SLOTS) {
+        return decode_stream_cached(Cursor::new(dna_clean.as_bytes()), Vec::new(), keys);
+    }
+
+    // `get` rather than indexing: a non-ASCII character straddling a
+    // region boundary must be a clean error, not a slicing panic.
+    let prefix = dna_clean
+        .get(..PREFIX_DNA_LEN)
+        .and_then(|p| dna_to_bytes(p, &CANONICAL_MAPPING).ok())
+        .filter(|p| p[0..4] == MAGIC && p[4] == VERSION);
+
+    match prefix {
+        Some(prefix) => decode_v2(&dna_clean, &prefix, keys),
+        None => decode_v1(&dna_clean, keys),
     }
+}
+
+/// Core decode for adversarial settings: every failure looks the same.
+///
+/// The ordinary errors say where decoding stopped — BadMagic before the
+/// KDF, WrongPassword right after it, DecryptionFailed once the payload
+/// is converted — and those paths take visibly different time. Here
+/// any failure is DecodeFailed, and one that stopped before Argon2id
+/// runs it anyway, so every outcome pays for one derivation. DNA
+/// conversion and decryption stay linear in the input; Argon2id
+/// outweighs them for all but very large payloads.
+pub fn decode_raw_hardened(dna: &str, password: &str) -> Result<Vec<u8>> {
+    harden(&MasterKeyCache::new(password), |keys| decode_raw_cached(dna, keys))
+}
 
-    let header_dna = &dna_clean[..header_dna_len];
-    let bases = [b'A', b'T', b'G', b'C'];
-    let permutations = all_permutations(&bases);
+/// Run `decode` under `keys` with hardened failures: any error becomes
+/// DecodeFailed, after one Argon2id run if `decode` started none.
+pub fn harden<'a, T>(
+    keys: &MasterKeyCache<'a>,
+    decode: impl FnOnce(&MasterKeyCache<'a>) -> Result<T>,
+) -> Result<T> {
+    let before = keys.derivations();
+    decode(keys).map_err(|_| {
+        if keys.derivations() == before {
+            // Only the time matters; the key is dropped, not cached
+            let _ = derive_master_key_with_salt(keys.password(), &[0u8; SALT_LEN]);
+        }
+        DendecError::DecodeFailed
+    })
+}
+
+/// True if `sample`, the start of some input, opens with a dendec header:
+/// v2 or v3 magic and version under the canonical mapping, or v1 under
+/// any of the 24 mappings. Whitespace from grouped output is skipped.
+///
+/// No key material is involved, so wrap can classify files by content
+/// without a password. A match says nothing about whether the rest of
+/// the payload is intact; 20 bases that happen to match by chance are
+/// about one in 10^10.
+pub fn is_dendec_payload(sample: &[u8]) -> bool {
+    payload_suite(sample).is_some()
+}
+
+/// The algorithms behind one header version.
+///
+/// The version byte is the header's algorithm identifier: every change
+/// of KDF, key schedule or cipher has been a new version, so the byte
+/// names all three together and decode picks its pipeline from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Suite {
+    pub version: u8,
+    pub kdf: &'static str,
+    pub keys: &'static str,
+    pub cipher: &'static str,
+    /// Whether encode still writes this version. Payloads on a suite
+    /// that is not current are what `dendec migrate` upgrades.
+    pub current: bool,
+}
 
-    let mut found_mapping: Option<[u8; 4]> = None;
+/// Every version decode understands, oldest first.
+pub const SUITES: [Suite; 3] = [
+    Suite {
+        version: VERSION_V1,
+        kdf: "Argon2id (64 MiB, t=3, p=1)",
+        keys: "cipher key and mapping straight from Argon2id",
+        cipher: "ChaCha20-Poly1305, one message",
+        current: false,
+    },
+    Suite {
+        version: VERSION,
+        kdf: "Argon2id (64 MiB, t=3, p=1)",
+        keys: "HKDF-SHA256 per file from the master key",
+        cipher: "ChaCha20-Poly1305 STREAM",
+        current: true,
+    },
+    Suite {
+        version: VERSION_SLOTS,
+        kdf: "Argon2id (64 MiB, t=3, p=1) per key slot",
+        keys: "HKDF-SHA256 per file from a random file key",
+        cipher: "ChaCha20-Poly1305 STREAM",
+        current: true,
+    },
+];
 
-    'outer: for perm in &permutations {
-        if let Ok(header_bytes) = dna_to_bytes(header_dna, perm) {
-            if &header_bytes[0..4] == MAGIC && header_bytes[4] == VERSION {
-                let mut salt = [0u8; SALT_LEN];
-                salt.copy_from_slice(&header_bytes[5..21]);
-                let keys = derive_keys_with_salt(password, &salt)?;
-                let expected_mapping = derive_dna_mapping(keys.mapping_seed);
-                if expected_mapping == *perm {
-                    found_mapping = Some(*perm);
-                    break 'outer;
+/// The suite of the payload `sample` opens, as `is_dendec_payload`
+/// decides it; `None` if `sample` is not dendec output.
+pub fn payload_suite(sample: &[u8]) -> Option<Suite> {
+    let bases: String = sample
+        .iter()
+        .filter(|b| !b.is_ascii_whitespace())
+        .take(SIGNATURE_DNA_LEN)
+        .map(|&b| b as char)
+        .collect();
+    if bases.len() < SIGNATURE_DNA_LEN {
+        return None;
+    }
+
+    let version = all_permutations(&CANONICAL_MAPPING).iter().find_map(|mapping| {
+        let h = dna_to_bytes(&bases, mapping).ok()?;
+        let canonical = *mapping == CANONICAL_MAPPING;
+        (h[0..4] == MAGIC && (h[4] == VERSION_V1 || (matches!(h[4], VERSION | VERSION_SLOTS) && canonical)))
+            .then_some(h[4])
+    })?;
+    SUITES.into_iter().find(|s| s.version == version)
+}
+
+/// Streaming decode — reads DNA from `input`, writes plaintext to `output`.
+///
+/// The counterpart of `encode_stream`: memory use is one chunk whatever
+/// the payload size, so wrap can decode files larger than RAM. Plaintext
+/// is written as each chunk authenticates, so on error `output` holds a
+/// prefix of it; callers write to a temp file and discard it then.
+///
+/// v1 payloads are one ciphertext with no chunking and cannot be
+/// streamed; they are rewound and decoded in memory as before.
+pub fn decode_stream_cached<R: Read + Seek, W: Write>(
+    mut input: R,
+    mut output: W,
+    keys: &MasterKeyCache,
+) -> Result<W> {
+    let mut dna = DnaReader::new(&mut input, &CANONICAL_MAPPING);
+    match read_signature(&mut dna) {
+        Some(VERSION) => {
+            debug!("v2 header");
+            let prefix = read_v2_prefix_rest(&mut dna)?;
+            let mut salt = [0u8; SALT_LEN];
+            salt.copy_from_slice(&prefix[5..SALT_END]);
+            let master = keys.get(&salt)?;
+            decode_v2_stream(dna, &prefix, &master, &mut output)?;
+        }
+        Some(_) => {
+            let header = SlotHeader::read(&mut dna)?;
+            debug!("v3 header with {} key slot(s)", header.slots.len());
+            let (file_key, region) = header.open(keys)?;
+            let file_keys = file_key.file_keys(&header.file_salt);
+            dna.set_mapping(&derive_dna_mapping(file_keys.mapping_seed));
+            match region {
+                REGION_ONLY => {
+                    let stream_header = read_stream_header(&mut dna)?;
+                    decrypt_chunks(&file_keys, &stream_header, dna, &mut output)?;
                 }
+                _ => decrypt_region(dna, &file_keys, region, &mut output)?,
             }
         }
+        None => {
+            input.rewind()?;
+            let mut text = String::new();
+            input.read_to_string(&mut text).map_err(|_| DendecError::BadMagic)?;
+            output.write_all(&decode_raw_cached(&text, keys)?)?;
+        }
     }
+    Ok(output)
+}
+
+/// Streaming decode of v2 input under a master key already in hand, as
+/// `wrap --verify` does right after encoding. Input under another key
+/// fails the password check; v1 input is rejected, since it has no
+/// master key.
+pub fn decode_stream_with_master<R: Read, W: Write>(
+    input: R,
+    mut output: W,
+    master: &MasterKey,
+) -> Result<W> {
+    let mut dna = DnaReader::new(input, &CANONICAL_MAPPING);
+    if read_signature(&mut dna) != Some(VERSION) {
+        return Err(DendecError::BadMagic);
+    }
+    let prefix = read_v2_prefix_rest(&mut dna)?;
+    decode_v2_stream(dna, &prefix, master, &mut output)?;
+    Ok(output)
+}
+
+/// Decrypt one region of a two-region payload. The regions' boundary is
+/// the middle of the body, so the body is read whole; the other region
+/// reads as noise under this region's mapping and is ignored.
+fn decrypt_region<R: Read, W: Write>(
+    mut dna: DnaReader<R>,
+    keys: &DerivedKeys,
+    region: u8,
+    output: &mut W,
+) -> Result<()> {
+    let mut body = Vec::new();
+    dna.read_to_end(&mut body).map_err(from_io)?;
+    let half = body.len() / 2;
+    let bytes = if region == REGION_FIRST { &body[..half] } else { &body[half..] };
+    if body.len() % 2 != 0 || bytes.len() < StreamHeader::LEN {
+        return Err(DendecError::BadMagic);
+    }
+
+    let header = StreamHeader::parse(bytes);
+    let mut framed = Vec::with_capacity(bytes.len());
+    decrypt_chunks(keys, &header, &bytes[StreamHeader::LEN..], &mut framed)?;
+    let (len, padded) = framed.split_at_checked(REGION_LEN_PREFIX).ok_or(DendecError::BadMagic)?;
+    let len = u64::from_le_bytes(len.try_into().expect("split at 8")) as usize;
+    let plaintext = padded.get(..len).ok_or(DendecError::LengthMismatch { header: len, actual: padded.len() })?;
+    output.write_all(plaintext)?;
+    Ok(())
+}
 
-    let mapping = found_mapping.ok_or(DendecError::BadMagic)?;
+/// Read the canonically mapped magic and version; the version if the
+/// input opens with a v2 or v3 signature, `None` otherwise.
+fn read_signature<R: Read>(dna: &mut DnaReader<R>) -> Option<u8> {
+    let mut signature = [0u8; 5];
+    dna.read_exact(&mut signature).ok()?;
+    (signature[0..4] == MAGIC && matches!(signature[4], VERSION | VERSION_SLOTS)).then_some(signature[4])
+}
 
-    let packet = dna_to_bytes(&dna_clean, &mapping)?;
-    let (header, ciphertext) = parse_packet(&packet)?;
-    let keys = derive_keys_with_salt(password, &header.salt)?;
-    let plaintext = decrypt(&keys.cipher_key, &header.nonce, ciphertext)?;
+/// Read the rest of the canonically mapped v2 prefix after its signature.
+fn read_v2_prefix_rest<R: Read>(dna: &mut DnaReader<R>) -> Result<[u8; PREFIX_LEN]> {
+    let mut prefix = [0u8; PREFIX_LEN];
+    prefix[0..4].copy_from_slice(&MAGIC);
+    prefix[4] = VERSION;
+    dna.read_exact(&mut prefix[5..]).map_err(|_| DendecError::BadMagic)?;
+    Ok(prefix)
+}
+
+/// Read the nonce prefix and chunk size that precede the chunks.
+fn read_stream_header<R: Read>(dna: &mut DnaReader<R>) -> Result<StreamHeader> {
+    let mut bytes = [0u8; StreamHeader::LEN];
+    dna.read_exact(&mut bytes).map_err(|e| match from_io(e) {
+        DendecError::Io(_) => DendecError::BadMagic,
+        e => e,
+    })?;
+    Ok(StreamHeader::parse(&bytes))
+}
+
+fn decode_v2_stream<R: Read, W: Write>(
+    mut dna: DnaReader<R>,
+    prefix: &[u8],
+    master: &MasterKey,
+    output: &mut W,
+) -> Result<()> {
+    let mut file_salt = [0u8; FILE_SALT_LEN];
+    file_salt.copy_from_slice(&prefix[SALT_END..PREFIX_LEN]);
+    let keys = master.file_keys(&file_salt);
+    dna.set_mapping(&derive_dna_mapping(keys.mapping_seed));
+
+    // Check the verifier before touching the payload
+    let mut verifier = [0u8; VERIFIER_LEN];
+    dna.read_exact(&mut verifier).map_err(|e| match from_io(e) {
+        DendecError::Io(_) => DendecError::BadMagic,
+        e => e,
+    })?;
+    check_password_verifier(&keys, &verifier)?;
+
+    let header = read_stream_header(&mut dna)?;
+    decrypt_chunks(&keys, &header, dna, output)
+}
 
+fn decode_v2(dna_clean: &str, prefix: &[u8], cache: &MasterKeyCache) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&prefix[5..SALT_END]);
+    let master = cache.get(&salt)?;
+    decode_v2_under(dna_clean, prefix, &master)
+}
+
+fn decode_v2_under(dna_clean: &str, prefix: &[u8], master: &MasterKey) -> Result<Vec<u8>> {
+    // A valid sequence holds at least one chunk tag after the header
+    if dna_clean.len() < HEADER_DNA_LEN + TAG_LEN * 4 {
+        return Err(DendecError::BadMagic);
+    }
+
+    let mut file_salt = [0u8; FILE_SALT_LEN];
+    file_salt.copy_from_slice(&prefix[SALT_END..PREFIX_LEN]);
+    let keys = master.file_keys(&file_salt);
+    let mapping = derive_dna_mapping(keys.mapping_seed);
+
+    // Check the verifier before touching the payload
+    let header_dna = dna_clean
+        .get(PREFIX_DNA_LEN..HEADER_DNA_LEN)
+        .ok_or(DendecError::BadMagic)?;
+    let header_bytes = dna_to_bytes(header_dna, &mapping)?;
+    let mut verifier = [0u8; VERIFIER_LEN];
+    verifier.copy_from_slice(&header_bytes[..VERIFIER_LEN]);
+    check_password_verifier(&keys, &verifier)?;
+    let header = StreamHeader::parse(&header_bytes[VERIFIER_LEN..]);
+
+    let body = dna_to_bytes(&dna_clean[HEADER_DNA_LEN..], &mapping)?;
+    let mut plaintext = Vec::with_capacity(body.len());
+    decrypt_chunks(&keys, &header, &body[..], &mut plaintext)?;
     Ok(plaintext)
 }
 
+/// Decrypt a v2 chunk sequence from `body`, writing plaintext to `out`.
+///
+/// A chunk is final when fewer than a full chunk's bytes remain after it
+/// — either a short read or a full read followed by EOF.
+fn decrypt_chunks<R: Read, W: Write>(
+    keys: &DerivedKeys,
+    header: &StreamHeader,
+    mut body: R,
+    out: &mut W,
+) -> Result<()> {
+    if header.chunk_size == 0 || header.chunk_size > MAX_CHUNK_SIZE {
+        return Err(DendecError::BadChunkSize(header.chunk_size));
+    }
+
+    let sealed_size = header.chunk_size + TAG_LEN;
+    let mut current = read_chunk(&mut body, sealed_size)?;
+    // A valid sequence holds at least one chunk tag after the header
+    if current.len() < TAG_LEN {
+        return Err(DendecError::BadMagic);
+    }
+    let mut index = 0u32;
+    loop {
+        let next = if current.len() == sealed_size {
+            read_chunk(&mut body, sealed_size)?
+        } else {
+            Vec::new()
+        };
+        let last = next.is_empty();
+
+        let plain = decrypt_chunk(&keys.cipher_key, &header.nonce_prefix, index, last, &current)?;
+        out.write_all(&plain)?;
+
+        if last {
+            return Ok(());
+        }
+        current = next;
+        index = index.checked_add(1).ok_or(DendecError::PayloadTooLarge)?;
+    }
+}
+
+fn decode_v1(dna_clean: &str, cache: &MasterKeyCache) -> Result<Vec<u8>> {
+    let header_dna = dna_clean
+        .get(..HEADER_DNA_LEN_V1)
+        .ok_or(DendecError::BadMagic)?;
+    let (mapping, keys) = recover_v1_mapping(header_dna, cache)?;
+
+    let packet = dna_to_bytes(dna_clean, &mapping)?;
+    let (nonce, ciphertext) = parse_packet_v1(&packet)?;
+    decrypt(&keys.cipher_key, &nonce, ciphertext)
+}
+
+/// Parse a v1 packet into its nonce and ciphertext.
+fn parse_packet_v1(packet: &[u8]) -> Result<([u8; NONCE_LEN], &[u8])> {
+    if packet.len() < HEADER_LEN_V1 + 1 {
+        return Err(DendecError::BadMagic);
+    }
+    if packet[0..4] != MAGIC {
+        return Err(DendecError::BadMagic);
+    }
+
+    let version = packet[4];
+    if version != VERSION_V1 {
+        return Err(DendecError::UnsupportedVersion {
+            expected: VERSION,
+            got: version,
+        });
+    }
+
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&packet[SALT_END..SALT_END + NONCE_LEN]);
+
+    let mut len_bytes = [0u8; 8];
+    len_bytes.copy_from_slice(&packet[SALT_END + NONCE_LEN..HEADER_LEN_V1]);
+    let payload_len = u64::from_le_bytes(len_bytes);
+
+    let ciphertext = &packet[HEADER_LEN_V1..];
+    if ciphertext.len() != payload_len as usize {
+        return Err(DendecError::LengthMismatch {
+            header: payload_len as usize,
+            actual: ciphertext.len(),
+        });
+    }
+
+    Ok((nonce, ciphertext))
+}
+
+/// Recover the key-derived mapping of a v1 sequence.
+///
+/// The magic bytes contain all four 2-bit values, so at most one of the
+/// 24 permutations decodes them correctly — the search itself needs no
+/// key material. Argon2id then runs once to confirm the candidate, and the
+/// resulting keys are returned so decryption does not derive them again.
+fn recover_v1_mapping(header_dna: &str, cache: &MasterKeyCache) -> Result<([u8; 4], DerivedKeys)> {
+    let candidate = all_permutations(&CANONICAL_MAPPING)
+        .into_iter()
+        .find(|perm| {
+            dna_to_bytes(header_dna, perm)
+                .map(|h| h[0..4] == MAGIC && h[4] == VERSION_V1)
+                .unwrap_or(false)
+        })
+        .ok_or(DendecError::BadMagic)?;
+
+    let header_bytes = dna_to_bytes(header_dna, &candidate)?;
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&header_bytes[5..SALT_END]);
+
+    // The magic already matched, so a mapping mismatch means the
+    // password is wrong rather than the input not being dendec output
+    let keys = cache.v1_keys(&salt)?;
+    if derive_dna_mapping(keys.mapping_seed) != candidate {
+        return Err(DendecError::WrongPassword);
+    }
+
+    Ok((candidate, keys))
+}
+
 /// Decode an encrypted DNA sequence back to Unicode text.
 ///
 /// Convenience wrapper around decode_raw for inline text output.
@@ -190,9 +1015,27 @@ fn all_permutations(arr: &[u8; 4]) -> Vec<[u8; 4]> {
     result
 }
 
+/// Build a sequence in the legacy v1 layout (whole packet key-mapped),
+/// which encode no longer writes.
+#[cfg(test)]
+pub(crate) fn encode_v1(plaintext: &[u8], password: &str) -> String {
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let keys = crate::crypto::derive_keys_with_salt(password, &salt).unwrap();
+    let (nonce, ciphertext) = crate::crypto::encrypt(&keys.cipher_key, plaintext).unwrap();
+    let mut packet = MAGIC.to_vec();
+    packet.push(VERSION_V1);
+    packet.extend_from_slice(&salt);
+    packet.extend_from_slice(&nonce);
+    packet.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    packet.extend_from_slice(&ciphertext);
+    crate::dna::bytes_to_dna(&packet, &derive_dna_mapping(keys.mapping_seed))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dna::bytes_to_dna;
 
     #[test]
     fn test_encode_decode_ascii() {
@@ -237,7 +1080,18 @@ mod tests {
         let text = "Secret message";
         let dna = encode(text, "correct-password", None).unwrap();
         let result = decode(&dna, "wrong-password");
-        assert!(result.is_err());
+        assert!(matches!(result, Err(DendecError::WrongPassword)));
+    }
+
+    #[test]
+    fn test_wrong_password_detected_before_payload() {
+        // Replace the payload with junk bases: the verifier alone must reject
+        // the password, before decryption could fail on the junk
+        let dna = encode("payload never read", "correct-password", None).unwrap();
+        let mut corrupted = dna[..HEADER_DNA_LEN].to_string();
+        corrupted.push_str(&"A".repeat(TAG_LEN * 4));
+        let result = decode(&corrupted, "wrong-password");
+        assert!(matches!(result, Err(DendecError::WrongPassword)));
     }
 
     #[test]
@@ -249,6 +1103,14 @@ mod tests {
         assert_eq!(text, decoded);
     }
 
+    #[test]
+    fn test_pasted_output_decodes() {
+        let dna = encode("pasted", "testpass", Some(10)).unwrap();
+        let fasta = format!("\u{FEFF}>notes.dna\r\n{}\r\n", dna.to_lowercase().replace('t', "u"));
+        assert_eq!(decode(&fasta, "testpass").unwrap(), "pasted");
+        assert!(matches!(decode("hello", "testpass"), Err(DendecError::InvalidDnaChar('h', 0))));
+    }
+
     #[test]
     fn test_all_permutations_count() {
         let arr = [b'A', b'T', b'G', b'C'];
@@ -293,6 +1155,209 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_v1_sequence_still_decodes() {
+        let bytes = b"legacy v1 payload\n";
+        let dna = encode_v1(bytes, "v1-password");
+        let decoded = decode_raw(&dna, "v1-password").unwrap();
+        assert_eq!(bytes.as_ref(), decoded.as_slice());
+    }
+
+    #[test]
+    fn test_hardened_failures_look_alike() {
+        let dna = encode("hardened", "right", None).unwrap();
+        assert_eq!(decode_raw_hardened(&dna, "right").unwrap(), b"hardened");
+
+        // Not dendec output, the wrong password, and a tampered body fail alike
+        let mut tampered = dna.clone().into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'A' { b'C' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        for (input, password) in [("ACGT", "right"), (dna.as_str(), "wrong"), (tampered.as_str(), "right")] {
+            assert!(decode_raw(input, password).is_err());
+            assert!(matches!(decode_raw_hardened(input, password), Err(DendecError::DecodeFailed)));
+        }
+
+        // The run an early failure pays for is not cached; v1 runs count
+        let keys = MasterKeyCache::new("right");
+        assert!(harden(&keys, |keys| decode_raw_cached("ACGT", keys)).is_err());
+        assert_eq!(keys.derivations(), 0);
+        let v1 = encode_v1(b"old", "right");
+        assert!(harden(&keys, |keys| decode_raw_cached(&v1, keys)).is_ok());
+        assert_eq!(keys.derivations(), 1);
+    }
+
+    #[test]
+    fn test_any_slot_password_decodes_and_rekey_revokes() {
+        let alice = derive_master_key("alice").unwrap();
+        let bob = derive_master_key("bob").unwrap();
+        let plaintext = b"shared team archive";
+        let dna = encode_stream_slots(&plaintext[..], Vec::new(), &[&alice, &bob, &alice], Some(12)).unwrap();
+        let dna = String::from_utf8(dna).unwrap();
+        assert!(is_dendec_payload(dna.as_bytes()));
+        assert_eq!(decode_raw(&dna, "alice").unwrap(), plaintext);
+        assert_eq!(decode_raw(&dna, "bob").unwrap(), plaintext);
+        assert!(matches!(decode_raw(&dna, "carol"), Err(DendecError::WrongPassword)));
+        // The repeated key got one slot: 22-byte prefix, two 64-byte slots
+        let bases = dna.chars().filter(|c| !c.is_whitespace()).count();
+        assert_eq!(bases, (22 + 2 * SLOT_LEN + 11 + plaintext.len() + TAG_LEN) * 4);
+
+        // Bob unlocks and leaves himself out; the chunks are untouched
+        let carol = derive_master_key("carol").unwrap();
+        let rekeyed = rekey_stream(dna.as_bytes(), Vec::new(), &MasterKeyCache::new("bob"), &[&alice, &carol], None).unwrap();
+        let rekeyed = String::from_utf8(rekeyed).unwrap();
+        let clean: String = dna.chars().filter(|c| !c.is_whitespace()).collect();
+        let body = (22 + 2 * SLOT_LEN) * 4;
+        assert_eq!(rekeyed[body..], clean[body..]);
+        assert_eq!(decode_raw(&rekeyed, "carol").unwrap(), plaintext);
+        assert!(matches!(decode_raw(&rekeyed, "bob"), Err(DendecError::WrongPassword)));
+
+        let v2 = encode_raw(plaintext, "alice", None).unwrap();
+        let unlock = MasterKeyCache::new("alice");
+        assert!(matches!(rekey_stream(v2.as_bytes(), Vec::new(), &unlock, &[&alice], None), Err(DendecError::NotSlotted)));
+        assert!(matches!(encode_stream_slots(&b""[..], Vec::new(), &[], None), Err(DendecError::SlotCount(0))));
+    }
+
+    #[test]
+    fn test_hidden_payload_looks_like_padding() {
+        let decoy = derive_master_key("decoy").unwrap();
+        let real = derive_master_key("real").unwrap();
+        let dna = encode_hidden(b"grocery list", &[&decoy], b"the real notes, longer", &[&real], None).unwrap();
+        assert_eq!(decode_raw(&dna, "decoy").unwrap(), b"grocery list");
+        assert_eq!(decode_raw(&dna, "real").unwrap(), b"the real notes, longer");
+        assert!(matches!(decode_raw(&dna, "other"), Err(DendecError::WrongPassword)));
+
+        // A padded payload of the longer length has exactly the same size
+        let padded = encode_padded(b"the real notes, longer", &[&decoy, &real], Some(10)).unwrap();
+        let bases = padded.chars().filter(|c| !c.is_whitespace()).count();
+        assert_eq!(bases, dna.len());
+        assert_eq!(decode_raw(&padded, "real").unwrap(), b"the real notes, longer");
+
+        let unlock = MasterKeyCache::new("decoy");
+        let rekeyed = rekey_stream(dna.as_bytes(), Vec::new(), &unlock, &[&decoy], None);
+        assert!(matches!(rekeyed, Err(DendecError::RekeyTwoRegions)));
+        let reused = encode_hidden(b"a", &[&decoy], b"b", &[&decoy], None);
+        assert!(matches!(reused, Err(DendecError::HiddenPasswordReused)));
+    }
+
+    #[test]
+    fn test_v2_prefix_uses_canonical_mapping() {
+        let dna = encode("prefix", "prefix-password", None).unwrap();
+        let mut expected = MAGIC.to_vec();
+        expected.push(VERSION);
+        let expected_dna = bytes_to_dna(&expected, &CANONICAL_MAPPING);
+        assert!(dna.starts_with(&expected_dna));
+    }
+
+    #[test]
+    fn test_payload_detection() {
+        let grouped = encode_raw(b"sniff me", "sniff", Some(8)).unwrap();
+        assert!(is_dendec_payload(grouped.as_bytes()));
+        assert!(is_dendec_payload(encode_v1(b"old", "sniff").as_bytes()));
+
+        // Plain DNA, ordinary text, and a truncated header are not payloads
+        assert!(!is_dendec_payload("ACGT".repeat(64).as_bytes()));
+        assert!(!is_dendec_payload(b"fn main() {}\n"));
+        assert!(!is_dendec_payload(&grouped.as_bytes()[..12]));
+    }
+
+    #[test]
+    fn test_non_ascii_header_rejected_cleanly() {
+        // A multi-byte character straddling the header boundary must not panic
+        let dna = format!("{}é{}", "A".repeat(HEADER_DNA_LEN_V1 - 1), "A".repeat(8));
+        assert!(decode_raw(&dna, "whatever").is_err());
+    }
+
+    #[test]
+    fn test_multi_chunk_roundtrip_and_truncation() {
+        // Two full chunks plus a partial one
+        let bytes: Vec<u8> = (0..CHUNK_SIZE * 2 + 7).map(|i| (i % 251) as u8).collect();
+        let password = "chunked";
+        let dna = encode_raw(&bytes, password, Some(64)).unwrap();
+        assert_eq!(decode_raw(&dna, password).unwrap(), bytes);
+
+        // Dropping the final chunk must fail authentication, not return a prefix
+        let clean: String = dna.chars().filter(|c| !c.is_whitespace()).collect();
+        let truncated = &clean[..clean.len() - (7 + TAG_LEN) * 4];
+        assert!(matches!(
+            decode_raw(truncated, password),
+            Err(DendecError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_stream_decode() {
+        use std::io::Cursor;
+        let bytes: Vec<u8> = (0..CHUNK_SIZE * 2 + 11).map(|i| (i % 239) as u8).collect();
+        let master = derive_master_key("streamed").unwrap();
+        let dna = encode_raw_with_master(&bytes, &master, Some(10)).unwrap();
+        let keys = MasterKeyCache::new("streamed");
+        let out = decode_stream_cached(Cursor::new(&dna), Vec::new(), &keys).unwrap();
+        assert_eq!(out, bytes);
+        let out = decode_stream_with_master(dna.as_bytes(), Vec::new(), &master).unwrap();
+        assert_eq!(out, bytes);
+
+        // v1 falls back to the in-memory path
+        let v1 = encode_v1(b"legacy", "streamed");
+        assert_eq!(decode_stream_cached(Cursor::new(&v1), Vec::new(), &keys).unwrap(), b"legacy");
+        assert!(decode_stream_with_master(v1.as_bytes(), Vec::new(), &master).is_err());
+
+        // Truncation and stray characters fail as they do in memory
+        let clean: String = dna.chars().filter(|c| !c.is_whitespace()).collect();
+        let truncated = &clean[..clean.len() - (11 + TAG_LEN) * 4];
+        assert!(matches!(
+            decode_stream_cached(Cursor::new(truncated), Vec::new(), &keys),
+            Err(DendecError::DecryptionFailed)
+        ));
+        let stray = format!("{}X{}", &clean[..HEADER_DNA_LEN + 8], &clean[HEADER_DNA_LEN + 9..]);
+        assert!(matches!(
+            decode_stream_cached(Cursor::new(stray), Vec::new(), &keys),
+            Err(DendecError::InvalidDnaChar('X', _))
+        ));
+    }
+
+    #[test]
+    fn test_parallel_batches_roundtrip() {
+        // One worker thread → batches of 4 chunks; 9 full chunks plus a tail
+        // crosses several batch boundaries, including the read-ahead carry.
+        let bytes: Vec<u8> = (0..CHUNK_SIZE * 9 + 3).map(|i| (i % 241) as u8).collect();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let dna = pool.install(|| encode_raw(&bytes, "batched", None)).unwrap();
+        assert_eq!(decode_raw(&dna, "batched").unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_exact_chunk_boundary_roundtrip() {
+        // A full final chunk is flagged last, with no empty chunk after it
+        let bytes = vec![0xA5u8; CHUNK_SIZE];
+        let dna = encode_raw(&bytes, "boundary", None).unwrap();
+        let expected_len = (HEADER_LEN + CHUNK_SIZE + TAG_LEN) * 4;
+        assert_eq!(dna.len(), expected_len);
+        assert_eq!(decode_raw(&dna, "boundary").unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_batch_shares_kdf_salt_not_file_keys() {
+        let master = derive_master_key("batch").unwrap();
+        let a = encode_raw_with_master(b"same", &master, None).unwrap();
+        let b = encode_raw_with_master(b"same", &master, None).unwrap();
+
+        // Same KDF salt, different file salts → different ciphertexts
+        let kdf_salt_dna = SALT_END * 4;
+        assert_eq!(a[..kdf_salt_dna], b[..kdf_salt_dna]);
+        assert_ne!(a[kdf_salt_dna..PREFIX_DNA_LEN], b[kdf_salt_dna..PREFIX_DNA_LEN]);
+        assert_ne!(a, b);
+
+        // Decoding both through one cache derives the master key once
+        let cache = MasterKeyCache::new("batch");
+        assert_eq!(decode_raw_cached(&a, &cache).unwrap(), b"same");
+        assert_eq!(decode_raw_cached(&b, &cache).unwrap(), b"same");
+        assert_eq!(cache.len(), 1);
+
+        // A plain decode, with no cache, works on batch output too
+        assert_eq!(decode_raw(&a, "batch").unwrap(), b"same");
+    }
+
     #[test]
     fn test_unicode_newlines_tabs() {
         let text = "Line one\n\tTabbed line\nLine three 日本語";
@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 use thiserror::Error;
 
+use crate::refer::assembly::Assembly;
+use crate::refer::naming::NameStyle;
+
 #[derive(Debug, Error)]
 pub enum DendecError {
     #[error("Password mismatch: confirmation did not match")]
@@ -21,18 +24,61 @@ pub enum DendecError {
     #[error("Decryption failed: wrong password or corrupted data")]
     DecryptionFailed,
 
+    #[error("Wrong password")]
+    WrongPassword,
+
+    /// Any decode failure in hardened mode, which does not say which.
+    #[error("Decode failed: wrong password or not intact dendec output")]
+    DecodeFailed,
+
     #[error("Key derivation failed: {0}")]
     KeyDerivation(String),
 
     #[error("Data is not valid UTF-8: {0}")]
     Utf8(#[from] std::string::FromUtf8Error),
 
+    #[error("Decoded data is binary — use --as <PATH>, --as -, or redirect stdout")]
+    BinaryToTerminal,
+
+    #[error("Output file already exists: {0} — use --force to overwrite or --backup to keep a copy")]
+    OutputExists(PathBuf),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("Payload length mismatch: header says {header}, actual {actual}")]
     LengthMismatch { header: usize, actual: usize },
 
+    #[error("Corrupted header: chunk size {0} is out of range")]
+    BadChunkSize(usize),
+
+    #[error("Chunk size {0} is out of range: expected 1 byte to 16 MiB")]
+    ChunkSizeOutOfRange(usize),
+
+    #[error("Payload too large: exceeds the maximum chunk count")]
+    PayloadTooLarge,
+
+    #[error("{0} key slots requested: a payload holds 1 to 16 passwords")]
+    SlotCount(usize),
+
+    #[error("Not locked with --passwords: only such a payload has key slots to rekey")]
+    NotSlotted,
+
+    #[error("A padded or hidden payload cannot be rekeyed — decode it and encode it again")]
+    RekeyTwoRegions,
+
+    #[error("The decoy and hidden (or duress) payloads need different passwords")]
+    HiddenPasswordReused,
+
+    #[error("Two inputs would both be encoded to {0} — rename one or encode them separately")]
+    DuplicateOutput(PathBuf),
+
+    #[error("BLAKE2b-256 mismatch: nothing here hashes to {0} — no output was written")]
+    ChecksumMismatch(String),
+
+    #[error("No manual page for '{0}' — plain `dendec man` lists the commands")]
+    UnknownManPage(String),
+
     // ── wrap errors ───────────────────────────────────────────────────
     #[error("Wrap command failed with exit code {code}: {cmd}")]
     WrapCommandFailed { cmd: String, code: i32 },
@@ -49,12 +95,51 @@ pub enum DendecError {
     #[error("Wrap requires either -e or -d flag")]
     WrapMissingFlag,
 
+    #[error("No wrap manifest at {0} — nothing to undo")]
+    WrapManifestMissing(PathBuf),
+
+    #[error("Wrap manifest is corrupt at line {line}: {reason}")]
+    WrapManifestCorrupt { line: usize, reason: String },
+
+    #[error("--pty is only supported on Unix")]
+    #[allow(dead_code)]
+    // Only constructed on platforms without pseudo-terminal support
+    WrapPtyUnsupported,
+
+    #[error("Cannot watch directory: {0}")]
+    WrapWatch(String),
+
+    #[error("Verification of {path} failed: {reason} — original kept")]
+    WrapVerifyFailed { path: PathBuf, reason: String },
+
+    #[error("Wrap aborted after {failed} failure(s), {not_attempted} file(s) not attempted; {rolled_back} transformed file(s) rolled back")]
+    WrapAborted { failed: usize, not_attempted: usize, rolled_back: usize },
+
+    #[error("{hook}-hook failed: {reason}")]
+    WrapHookFailed { hook: &'static str, reason: String },
+
+    #[error("Wrap archive: {0}")]
+    WrapArchive(String),
+
+    #[error("An earlier wrap did not finish ({0} exists); rerun with --resume to continue it, or delete that file to start over")]
+    WrapInterrupted(PathBuf),
+
+    #[error("Cannot resume wrap: {0}")]
+    WrapResume(String),
+
+    #[error("Invalid wrap config {path}: {reason}")]
+    WrapConfig { path: PathBuf, reason: String },
+
     // ── refer errors ──────────────────────────────────────────────────
 
-    /// The embedded table.bin failed magic/version checks or was truncated.
+    /// A loaded table refers to a chromosome it does not list.
     #[error("Reference table is corrupt or incompatible — reinstall dendec")]
     ReferTableCorrupt,
 
+    /// A table failed to parse; the reason says what and where.
+    #[error("Invalid reference table: {0}")]
+    ReferTableInvalid(String),
+
     /// A BED file line could not be parsed.
     #[error("Invalid BED file: {0}")]
     ReferInvalidBed(String),
@@ -70,6 +155,51 @@ pub enum DendecError {
     /// A BED file references an accession not present in the embedded table.
     #[error("Assembly mismatch: expected {expected}, got '{got}' — BED file may be from a different genome build")]
     ReferAssemblyMismatch { expected: String, got: String },
+
+    /// The requested assembly's table was not compiled into this binary.
+    #[error("Assembly {0} is not built into this dendec — rebuild with `--features {0}`")]
+    ReferAssemblyUnavailable(&'static str),
+
+    /// A refer file was cut into chunks of a different size than the
+    /// table it is being unreferred with.
+    #[error("Refer file uses {file}-base chunks but the {assembly} table has {table}-base chunks")]
+    ReferChunkSizeMismatch { assembly: Assembly, file: usize, table: usize },
+
+    /// A password-dependent refer format was used without a password.
+    #[error("{0} needs a password")]
+    ReferPasswordRequired(&'static str),
+
+    /// A --name-style has fewer distinct names than the payload has
+    /// chunks.
+    #[error("--name-style {style} has names for at most {limit} chunks")]
+    ReferTooManyChunks { style: NameStyle, limit: u64 },
+
+    /// The genome FASTA behind --format fasta is missing or does not
+    /// match the table.
+    #[error("Reference FASTA: {0}")]
+    ReferReference(String),
+
+    /// Unrefer reassembled DNA whose SHA-256 differs from the header's.
+    #[error("Recovered DNA does not match the payload_sha256 in the refer file — it was truncated or edited, or the --shuffle password is wrong")]
+    ReferChecksumMismatch,
+
+    /// dendec table build could not produce a table from its inputs.
+    #[error("Cannot build table: {0}")]
+    TableBuild(String),
+
+    /// dendec table verify found problems; each was reported as it ran.
+    #[error("Table verification failed: {0} problem(s) found")]
+    TableVerifyFailed(usize),
+
+    /// dendec refer verify found problems; each was reported as it ran.
+    #[error("Refer file verification failed: {0} problem(s) found")]
+    ReferVerifyFailed(usize),
+
+    // ── serve errors ──────────────────────────────────────────────────
+
+    /// dendec serve could not start.
+    #[error("Cannot serve: {0}")]
+    Serve(String),
 }
 
 pub type Result<T> = std::result::Result<T, DendecError>;
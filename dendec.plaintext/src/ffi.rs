@@ -0,0 +1,318 @@
+/// ffi.rs — C ABI for libdendec
+///
+/// Encode, decode, refer and unrefer for C and C++ callers, exported
+/// unmangled from libdendec.so / libdendec.a and declared in
+/// include/dendec.h. The header is generated from this file by cbindgen
+/// (see cbindgen.toml); regenerate it after changing anything here:
+///
+///   cbindgen --config cbindgen.toml --output include/dendec.h
+///
+/// The ABI is stable: functions and status codes are only ever added, and
+/// a changed signature gets a new name.
+///
+/// CONVENTIONS
+///
+///   - Every function but the last four returns a DendecStatus,
+///     DENDEC_STATUS_OK (0) on success.
+///   - Strings passed in are NUL-terminated UTF-8; byte buffers are a
+///     pointer and a length.
+///   - Results come back through out-pointers, allocated by dendec. Free
+///     them with dendec_string_free or dendec_bytes_free, never free().
+///   - After a failure, dendec_last_error() describes it until the next
+///     call on the same thread.
+///   - Nothing unwinds into C: a panic is caught and returned as
+///     DENDEC_STATUS_PANIC.
+///   - Nothing is printed and nothing is prompted for.
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::encoding::{decode_raw, encode_raw};
+use crate::error::DendecError;
+use crate::refer::assembly::Assembly;
+use crate::refer::{refer_bytes, unrefer_quiet};
+use crate::scratch::ScratchFile;
+
+/// Result of a libdendec call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DendecStatus {
+    /// Success; the out-pointers are set.
+    Ok = 0,
+    /// A required pointer was NULL, or a string not valid UTF-8.
+    InvalidArgument = 1,
+    /// Decryption failed: wrong password or corrupted data.
+    WrongPassword = 2,
+    /// Any other failure; see dendec_last_error.
+    Failed = 3,
+    /// dendec panicked. A bug; please report it with dendec_last_error.
+    Panic = 4,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// A failed call: the status to return and the message for
+/// dendec_last_error.
+struct Failure(DendecStatus, String);
+
+impl From<DendecError> for Failure {
+    fn from(e: DendecError) -> Self {
+        let status = match e {
+            DendecError::DecryptionFailed | DendecError::WrongPassword | DendecError::DecodeFailed => DendecStatus::WrongPassword,
+            _ => DendecStatus::Failed,
+        };
+        Failure(status, e.to_string())
+    }
+}
+
+/// Run `body`, catching any panic, and record its error for
+/// dendec_last_error.
+fn call(body: impl FnOnce() -> Result<(), Failure>) -> DendecStatus {
+    let Failure(status, message) = match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => return DendecStatus::Ok,
+        Ok(Err(failure)) => failure,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Failure(DendecStatus::Panic, format!("dendec panicked: {message}"))
+        }
+    };
+    // Messages never hold NUL; drop any rather than lose the message
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    status
+}
+
+/// The string at `ptr`, or InvalidArgument naming `what`.
+///
+/// # Safety
+/// `ptr` must be NULL or point to a NUL-terminated string that outlives
+/// the call.
+unsafe fn str_arg<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err(Failure(DendecStatus::InvalidArgument, format!("{what} is NULL")));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| Failure(DendecStatus::InvalidArgument, format!("{what} is not valid UTF-8")))
+}
+
+/// Check an out-pointer is not NULL.
+fn out_arg<T>(ptr: *mut T, what: &str) -> Result<(), Failure> {
+    match ptr.is_null() {
+        true => Err(Failure(DendecStatus::InvalidArgument, format!("{what} is NULL"))),
+        false => Ok(()),
+    }
+}
+
+/// Hand `text`, which holds no NUL, to C.
+fn into_c_string(text: String) -> *mut c_char {
+    CString::new(text).expect("dendec output holds no NUL").into_raw()
+}
+
+/// Encrypt `len` bytes at `plaintext` under `password` into a DNA
+/// string, stored in `*dna_out`.
+///
+/// # Safety
+/// `plaintext` must point to `len` readable bytes (it may be NULL when
+/// `len` is 0), `password` to a NUL-terminated string, and `dna_out` to
+/// writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn dendec_encode(
+    plaintext: *const u8,
+    len: usize,
+    password: *const c_char,
+    dna_out: *mut *mut c_char,
+) -> DendecStatus {
+    call(|| {
+        let plaintext = match (plaintext.is_null(), len) {
+            (_, 0) => &[][..],
+            (true, _) => return Err(Failure(DendecStatus::InvalidArgument, "plaintext is NULL".to_string())),
+            (false, _) => std::slice::from_raw_parts(plaintext, len),
+        };
+        let password = str_arg(password, "password")?;
+        out_arg(dna_out, "dna_out")?;
+        *dna_out = into_c_string(encode_raw(plaintext, password, None)?);
+        Ok(())
+    })
+}
+
+/// Decrypt the DNA string `dna` with `password`. The plaintext is stored
+/// in `*plaintext_out` and its length in `*len_out`; it is bytes, not a
+/// NUL-terminated string.
+///
+/// # Safety
+/// `dna` and `password` must point to NUL-terminated strings, and
+/// `plaintext_out` and `len_out` to writable storage.
+#[no_mangle]
+pub unsafe extern "C" fn dendec_decode(
+    dna: *const c_char,
+    password: *const c_char,
+    plaintext_out: *mut *mut u8,
+    len_out: *mut usize,
+) -> DendecStatus {
+    call(|| {
+        let dna = str_arg(dna, "dna")?;
+        let password = str_arg(password, "password")?;
+        out_arg(plaintext_out, "plaintext_out")?;
+        out_arg(len_out, "len_out")?;
+        let plaintext = decode_raw(dna, password)?.into_boxed_slice();
+        *len_out = plaintext.len();
+        *plaintext_out = Box::into_raw(plaintext) as *mut u8;
+        Ok(())
+    })
+}
+
+/// Translate the DNA string `dna`, as dendec_encode returns it, into a
+/// BED file of genome coordinates under `assembly` ("hg38", "mm39", …;
+/// NULL for hg38), stored in `*bed_out`. Like `dendec refer` with no
+/// options: chunk names, one random coordinate per chunk.
+///
+/// # Safety
+/// `dna` must point to a NUL-terminated string, `assembly` to one or be
+/// NULL, and `bed_out` to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn dendec_refer(
+    dna: *const c_char,
+    assembly: *const c_char,
+    bed_out: *mut *mut c_char,
+) -> DendecStatus {
+    call(|| {
+        let dna = str_arg(dna, "dna")?;
+        let assembly = match assembly.is_null() {
+            true => Assembly::default(),
+            false => str_arg(assembly, "assembly")?
+                .parse()
+                .map_err(|e| Failure(DendecStatus::InvalidArgument, e))?,
+        };
+        out_arg(bed_out, "bed_out")?;
+        let bases: Vec<u8> = dna.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        let mut bed = Vec::new();
+        refer_bytes(&bases, assembly, &mut bed)?;
+        *bed_out = into_c_string(String::from_utf8(bed).map_err(DendecError::from)?);
+        Ok(())
+    })
+}
+
+/// Translate `refer_text`, a file dendec_refer or `dendec refer` wrote in
+/// any format, back into the DNA string, stored in `*dna_out`. Files
+/// written with --shuffle, --keyed names or as bedGraph need a password
+/// and are refused here.
+///
+/// # Safety
+/// `refer_text` must point to a NUL-terminated string and `dna_out` to
+/// writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn dendec_unrefer(refer_text: *const c_char, dna_out: *mut *mut c_char) -> DendecStatus {
+    call(|| {
+        let text = str_arg(refer_text, "refer_text")?;
+        out_arg(dna_out, "dna_out")?;
+        // The readers work on files, telling formats apart by content
+        let file = ScratchFile::with_contents(text.as_bytes())?;
+        *dna_out = into_c_string(unrefer_quiet(file.path())?);
+        Ok(())
+    })
+}
+
+/// Free a string from dendec_encode, dendec_refer or dendec_unrefer.
+/// NULL is ignored.
+///
+/// # Safety
+/// `s` must be NULL or a string returned by libdendec, not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn dendec_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Free the plaintext from dendec_decode, given its length. NULL is
+/// ignored.
+///
+/// # Safety
+/// `bytes` must be NULL or a buffer returned by dendec_decode, not yet
+/// freed, and `len` the length returned with it.
+#[no_mangle]
+pub unsafe extern "C" fn dendec_bytes_free(bytes: *mut u8, len: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(bytes, len)));
+    }
+}
+
+/// What the last failed call on this thread went wrong with; empty if
+/// none has. Valid until the next call on the same thread. Do not free.
+#[no_mangle]
+pub extern "C" fn dendec_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// The libdendec version, e.g. "0.1.0". Do not free.
+#[no_mangle]
+pub extern "C" fn dendec_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(dendec_last_error()) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_encode_decode_refer_roundtrip() {
+        let password = c"correct horse";
+        let plaintext = b"lab notebook \x00\xff page 12";
+        unsafe {
+            let mut dna = ptr::null_mut();
+            let status = dendec_encode(plaintext.as_ptr(), plaintext.len(), password.as_ptr(), &mut dna);
+            assert_eq!(status, DendecStatus::Ok, "{}", last_error());
+
+            let mut bed = ptr::null_mut();
+            assert_eq!(dendec_refer(dna, ptr::null(), &mut bed), DendecStatus::Ok, "{}", last_error());
+            assert!(CStr::from_ptr(bed).to_str().unwrap().starts_with("##dendec-refer"));
+            let mut back = ptr::null_mut();
+            assert_eq!(dendec_unrefer(bed, &mut back), DendecStatus::Ok, "{}", last_error());
+            assert_eq!(CStr::from_ptr(back), CStr::from_ptr(dna));
+
+            let (mut out, mut len) = (ptr::null_mut(), 0);
+            assert_eq!(dendec_decode(back, password.as_ptr(), &mut out, &mut len), DendecStatus::Ok);
+            assert_eq!(std::slice::from_raw_parts(out, len), plaintext);
+
+            let status = dendec_decode(back, c"wrong".as_ptr(), &mut out, &mut len);
+            assert_eq!(status, DendecStatus::WrongPassword);
+            assert!(!last_error().is_empty());
+
+            dendec_bytes_free(out, len);
+            for s in [dna, bed, back] {
+                dendec_string_free(s);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bad_arguments_reported() {
+        unsafe {
+            let mut out = ptr::null_mut();
+            let status = dendec_encode(b"x".as_ptr(), 1, ptr::null(), &mut out);
+            assert_eq!(status, DendecStatus::InvalidArgument);
+            assert_eq!(last_error(), "password is NULL");
+            assert!(out.is_null());
+
+            let status = dendec_refer(c"ACGT".as_ptr(), c"panTro6".as_ptr(), &mut out);
+            assert_eq!(status, DendecStatus::InvalidArgument);
+            assert!(last_error().contains("unknown assembly"));
+
+            assert_eq!(dendec_unrefer(c"not a refer file".as_ptr(), &mut out), DendecStatus::Failed);
+            dendec_string_free(ptr::null_mut());
+            assert_eq!(CStr::from_ptr(dendec_version()).to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+        }
+    }
+}
@@ -0,0 +1,28 @@
+/// lib.rs — dendec as a library
+///
+/// Everything the CLI does is here; main.rs only parses arguments and
+/// prompts for passwords. Rust callers start from codec's Encoder and
+/// Decoder; C and C++ callers link libdendec and call the ffi module's
+/// functions, declared in include/dendec.h.
+pub mod bench;
+pub mod checksum;
+pub mod codec;
+pub mod crypto;
+pub mod daemon;
+pub mod dna;
+pub mod encoding;
+pub mod error;
+pub mod migrate;
+pub mod ffi;
+pub mod logging;
+pub mod man;
+pub mod mmap;
+pub mod multi;
+pub mod output;
+pub mod refer;
+pub mod scratch;
+pub mod serve;
+pub mod stats;
+pub mod view;
+pub mod watch;
+pub mod wrap;
@@ -0,0 +1,171 @@
+/// logging.rs — Where dendec's messages go
+///
+/// Status lines, warnings and debug detail are `log` records, so the
+/// binary's global flags decide what reaches stderr and how it looks:
+///
+///   -q / --quiet        warnings and errors only
+///   (default)           status lines too
+///   -v                  debug detail: key derivations, skip decisions
+///   -vv                 trace: per-chunk and per-record detail
+///
+///   --log-format text   what a person reads; status lines as they are,
+///                       warnings and errors prefixed
+///   --log-format json   one object per line with time, level, target
+///                       (the module) and message, for log pipelines
+///
+/// Library callers get no output until they install a logger of their
+/// own, as with any crate using `log`. Password prompts and the wrap
+/// progress bar talk to the terminal directly and are not records; the
+/// bar is drawn only when text status lines would be shown.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// How records are written to stderr (`--log-format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub const ALL: [LogFormat; 2] = [LogFormat::Text, LogFormat::Json];
+
+    /// Name as given to `--log-format`.
+    pub fn name(self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        LogFormat::ALL
+            .into_iter()
+            .find(|f| f.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown log format '{s}' (expected text or json)"))
+    }
+}
+
+/// Set by `init` when status lines go to stderr as text.
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The level `-q` and `-v` counts select: `verbosity` is -1 for quiet,
+/// 0 by default, and the number of `-v` flags otherwise.
+pub fn level_for(verbosity: i8) -> LevelFilter {
+    match verbosity {
+        i8::MIN..=-1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Install the stderr logger. Later calls do nothing.
+pub fn init(verbosity: i8, format: LogFormat) {
+    let level = level_for(verbosity);
+    if log::set_boxed_logger(Box::new(Logger { format })).is_ok() {
+        log::set_max_level(level);
+        INTERACTIVE.store(format == LogFormat::Text && level >= LevelFilter::Info, Ordering::Relaxed);
+    }
+}
+
+/// True if status lines reach stderr as text, so drawing a progress bar
+/// between them makes sense.
+pub fn interactive() -> bool {
+    INTERACTIVE.load(Ordering::Relaxed)
+}
+
+struct Logger {
+    format: LogFormat,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let line = match self.format {
+            LogFormat::Text => text_line(record.level(), &message),
+            LogFormat::Json if message.trim().is_empty() => return,
+            LogFormat::Json => json_line(record, message.trim()),
+        };
+        let mut err = std::io::stderr().lock();
+        let _ = writeln!(err, "{line}");
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// A record as text: status lines as they are, anything else prefixed
+/// after the message's own indentation.
+fn text_line(level: Level, message: &str) -> String {
+    let rest = message.trim_start();
+    let indent = &message[..message.len() - rest.len()];
+    match level {
+        Level::Error => format!("{indent}Error: {rest}"),
+        Level::Warn => format!("{indent}Warning: {rest}"),
+        Level::Info => message.to_string(),
+        Level::Debug => format!("{indent}debug: {rest}"),
+        Level::Trace => format!("{indent}trace: {rest}"),
+    }
+}
+
+fn json_line(record: &Record, message: &str) -> String {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+    serde_json::json!({
+        "time": time,
+        "level": record.level().as_str().to_ascii_lowercase(),
+        "target": record.target(),
+        "message": message,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_and_formats() {
+        assert_eq!(level_for(-1), LevelFilter::Warn);
+        assert_eq!(level_for(0), LevelFilter::Info);
+        assert_eq!(level_for(2), LevelFilter::Trace);
+
+        assert_eq!(text_line(Level::Info, "  Skipping a.png"), "  Skipping a.png");
+        assert_eq!(text_line(Level::Warn, "  could not remove a"), "  Warning: could not remove a");
+
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("dendec::wrap")
+            .args(format_args!("could not remove a"))
+            .build();
+        let value: serde_json::Value = serde_json::from_str(&json_line(&record, "could not remove a")).unwrap();
+        assert_eq!(value["level"], "warn");
+        assert_eq!(value["target"], "dendec::wrap");
+        assert_eq!(value["message"], "could not remove a");
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+    }
+}
@@ -1,95 +1,324 @@
 /// main.rs — dendec entry point
-
 mod cli;
-mod crypto;
-mod dna;
-mod encoding;
-mod error;
-mod refer;
-mod wrap;
+
+use dendec::{
+    bench, checksum, crypto, daemon, encoding, error, logging, man, migrate, mmap, multi, output, refer, scratch, serve, stats, view, wrap,
+};
 
 use std::fs;
-use std::io::Write;
-use clap::Parser;
-use cli::{Cli, Command};
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::time::Instant;
+use clap::{CommandFactory, Parser};
+use log::{error, info, warn};
+use cli::{Cli, Command, ReferCommand, TableCommand};
 use error::DendecError;
+use output::OverwritePolicy;
+use wrap::FailurePolicy;
 
 fn main() {
     if let Err(e) = run() {
-        eprintln!("Error: {e}");
+        error!("{e}");
         std::process::exit(1);
     }
 }
 
 fn run() -> error::Result<()> {
     let cli = Cli::parse();
+    let verbosity = if cli.quiet { -1 } else { cli.verbose.min(2) as i8 };
+    logging::init(verbosity, cli.log_format);
+    scratch::cleanup_on_signal();
 
     match cli.command {
-        Command::Encode { text, file, save_as, group } => {
-            let plaintext: Vec<u8> = match (&file, &text) {
-                (Some(path), _) => fs::read(path).map_err(DendecError::Io)?,
-                (None, Some(t)) => t.as_bytes().to_vec(),
+        Command::Encode {
+            text, file, save_as, as_dir, group, color, force, backup, watch, checksum, expect, stats, passwords, pad, hidden, duress,
+        } => {
+            let overwrite = OverwritePolicy::from_flags(force, backup);
+            if let Some(path) = &save_as {
+                output::check_output(path, overwrite)?;
+            }
+
+            if let Some(source) = watch {
+                let target = save_as.expect("clap requires --as with --watch");
+                let password = prompt_new_password("password")?;
+                info!("Encoding… (Argon2id key derivation may take a moment)");
+                let master = crypto::derive_master_key(&password)?;
+                report_backup(output::prepare_output(&target, overwrite)?, &target);
+                return dendec::watch::watch_encode(&source, &target, &master, group);
+            }
+
+            // Every output is checked before the password is asked for
+            if let Some(dir) = as_dir {
+                multi::check_outputs(&file, &dir, overwrite)?;
+                let entered = match passwords {
+                    Some(n) => prompt_new_passwords(n)?,
+                    None => vec![prompt_new_password("password")?],
+                };
+                info!("Encoding {} file(s)… (Argon2id key derivation may take a moment)", file.len());
+                let masters = entered
+                    .iter()
+                    .map(|p| crypto::derive_master_key(p))
+                    .collect::<error::Result<Vec<_>>>()?;
+                let masters: Vec<_> = masters.iter().collect();
+                let written = multi::encode_to_dir(&file, &dir, &masters, group, overwrite)?;
+                info!("Written {} file(s) to {}", written.len(), dir.display());
+                return Ok(());
+            }
+            if file.len() > 1 {
+                error!("several --file inputs need --as-dir <DIR>");
+                std::process::exit(1);
+            }
+            let file = file.into_iter().next();
+
+            // Files are memory-mapped and streamed, never read whole
+            let mapped;
+            let plaintext: &[u8] = match (&file, &text) {
+                (Some(path), _) => {
+                    mapped = mmap::map_file(path)?;
+                    &mapped
+                }
+                (None, Some(t)) => t.as_bytes(),
                 (None, None) => {
-                    eprintln!("Error: provide text as an argument or use --file <PATH>");
+                    error!("provide text as an argument or use --file <PATH>");
                     std::process::exit(1);
                 }
             };
 
-            let password = rpassword::prompt_password("Enter password: ")
-                .map_err(DendecError::Io)?;
-            let confirm = rpassword::prompt_password("Confirm password: ")
-                .map_err(DendecError::Io)?;
+            // Checked before any password is asked for
+            let plaintext_sum = (checksum || expect.is_some()).then(|| checksum::checksum(plaintext));
+            checksum::check_expected(expect, &[plaintext_sum])?;
 
-            if password != confirm {
-                return Err(DendecError::PasswordMismatch);
-            }
-            if password.is_empty() {
-                eprintln!("Warning: using an empty password provides no security.");
-            }
+            let hidden = hidden.as_deref().map(mmap::map_file).transpose()?;
+            let duress = duress.as_deref().map(mmap::map_file).transpose()?;
 
-            eprintln!("Encoding… (Argon2id key derivation may take a moment)");
-            let dna = encoding::encode_raw(&plaintext, &password, group)?;
+            // Each password gets its own KDF salt and Argon2id run
+            // The first password opens the input, the second the other file
+            let entered = match (passwords, &hidden, &duress) {
+                (Some(n), _, _) => prompt_new_passwords(n)?,
+                (None, Some(_), _) | (None, _, Some(_)) => {
+                    let (first, second) = match hidden {
+                        Some(_) => ("decoy password", "hidden password"),
+                        None => ("password", "duress password"),
+                    };
+                    let first = prompt_new_password(first)?;
+                    let second = prompt_new_password(second)?;
+                    if first == second {
+                        return Err(DendecError::HiddenPasswordReused);
+                    }
+                    vec![first, second]
+                }
+                (None, None, None) => vec![prompt_new_password("password")?],
+            };
+            let start = Instant::now();
+            if stats {
+                stats::enable();
+            }
+            info!("Encoding… (Argon2id key derivation may take a moment)");
+            let masters = entered
+                .iter()
+                .map(|p| crypto::derive_master_key(p))
+                .collect::<error::Result<Vec<_>>>()?;
+            let masters: Vec<_> = masters.iter().collect();
 
+            let out: Box<dyn Write> = match &save_as {
+                Some(path) => {
+                    report_backup(output::prepare_output(path, overwrite)?, path);
+                    Box::new(BufWriter::new(fs::File::create(path).map_err(DendecError::Io)?))
+                }
+                None if color.enabled(io::stdout().is_terminal()) => {
+                    let width = view::terminal_width();
+                    let block = group.unwrap_or(view::DEFAULT_BLOCK);
+                    Box::new(view::DnaView::new(BufWriter::new(io::stdout().lock()), block, width, true))
+                }
+                None => Box::new(BufWriter::new(io::stdout().lock())),
+            };
+            // Hashes and counts the DNA as written, before any --color layout
+            let mut out = checksum::HashWriter::new(stats::CountingWriter::new(out), checksum);
+            let mut out = match (hidden.as_ref().or(duress.as_ref()), passwords) {
+                (Some(other), _) => {
+                    let dna = encoding::encode_hidden(plaintext, &masters[..1], other, &masters[1..], group)?;
+                    out.write_all(dna.as_bytes()).map_err(DendecError::Io)?;
+                    out
+                }
+                (None, Some(_)) if pad => {
+                    let dna = encoding::encode_padded(plaintext, &masters, group)?;
+                    out.write_all(dna.as_bytes()).map_err(DendecError::Io)?;
+                    out
+                }
+                (None, Some(_)) => encoding::encode_stream_slots(plaintext, out, &masters, group)?,
+                (None, None) => encoding::encode_stream_with_master(plaintext, out, masters[0], group)?,
+            };
             match &save_as {
                 Some(path) => {
-                    fs::write(path, dna.as_bytes()).map_err(DendecError::Io)?;
-                    eprintln!("Written to {}", path.display());
+                    out.flush().map_err(DendecError::Io)?;
+                    info!("Written to {}", path.display());
+                }
+                None => {
+                    writeln!(out).map_err(DendecError::Io)?;
+                    out.flush().map_err(DendecError::Io)?;
+                }
+            }
+            let (out, dna_sum) = out.finish();
+            if let (Some(plaintext_sum), Some(dna_sum)) = (plaintext_sum, dna_sum) {
+                let sums = [(plaintext_sum, file.as_deref()), (dna_sum, save_as.as_deref())];
+                checksum::report(&sums, save_as.is_none()).map_err(DendecError::Io)?;
+            }
+            if stats {
+                let (_, dna_len) = out.finish();
+                stats::Report {
+                    plaintext: plaintext.len() as u64,
+                    dna: dna_len,
+                    elapsed: start.elapsed(),
+                    timings: stats::timings(),
                 }
-                None => println!("{dna}"),
+                .print();
+            }
+        }
+
+        Command::Rekey { file, passwords, save_as, group, force, backup } => {
+            let target = save_as.unwrap_or_else(|| file.clone());
+            if target != file {
+                output::check_output(&target, OverwritePolicy::from_flags(force, backup))?;
+            }
+            let current = rpassword::prompt_password("Enter a current password: ")
+                .map_err(DendecError::Io)?;
+            let passwords = prompt_new_passwords(passwords)?;
+
+            info!("Rekeying… (Argon2id runs once per password)");
+            let unlock = crypto::MasterKeyCache::new(&current);
+            let masters = passwords
+                .iter()
+                .map(|p| crypto::derive_master_key(p))
+                .collect::<error::Result<Vec<_>>>()?;
+            let masters: Vec<_> = masters.iter().collect();
+
+            if target != file {
+                report_backup(output::prepare_output(&target, OverwritePolicy::from_flags(force, backup))?, &target);
             }
+            let input = io::BufReader::new(fs::File::open(&file).map_err(DendecError::Io)?);
+            output::write_atomic_with(&target, None, None, |out| {
+                encoding::rekey_stream(input, out, &unlock, &masters, group)?;
+                Ok(())
+            })?;
+            info!("Written to {} with {} password(s)", target.display(), masters.len());
+        }
+
+        Command::Migrate { paths, dry_run, group } => {
+            let password = match dry_run {
+                true => None,
+                false => Some(rpassword::prompt_password("Enter password: ").map_err(DendecError::Io)?),
+            };
+            let opts = migrate::MigrateOptions { dry_run, group };
+            migrate::run_migrate(&paths, password.as_deref(), &opts)?;
         }
 
-        Command::Decode { dna, file, save_as } => {
+        Command::Decode { dna, file, save_as, force, backup, checksum, expect, stats, hardened } => {
+            let overwrite = OverwritePolicy::from_flags(force, backup);
+            if let Some(path) = save_as.as_deref().filter(|p| !output::is_stdout(p)) {
+                output::check_output(path, overwrite)?;
+            }
+
             let dna_string: String = match (&file, &dna) {
+                // A refer file is unreferred in memory first
+                (Some(path), _) if refer::format::is_refer_file(path)? => {
+                    let password = if refer::needs_password(path)? {
+                        Some(rpassword::prompt_password("Enter refer password: ").map_err(DendecError::Io)?)
+                    } else {
+                        None
+                    };
+                    info!("Unreferring {}…", path.display());
+                    refer::unrefer(path, None, password.as_deref())?
+                }
                 (Some(path), _) => fs::read_to_string(path).map_err(DendecError::Io)?,
                 (None, Some(d)) => d.clone(),
                 (None, None) => {
-                    eprintln!("Error: provide a DNA sequence as an argument or use --file <PATH>");
+                    error!("provide a DNA sequence as an argument or use --file <PATH>");
                     std::process::exit(1);
                 }
             };
+            // The input as it arrived: a refer file's own bytes, not its DNA
+            let input_sum = match (&file, checksum || expect.is_some()) {
+                (Some(path), true) => Some(checksum::checksum(&fs::read(path).map_err(DendecError::Io)?)),
+                (None, true) => Some(checksum::checksum(dna_string.as_bytes())),
+                (_, false) => None,
+            };
 
             let password = rpassword::prompt_password("Enter password: ")
                 .map_err(DendecError::Io)?;
 
-            eprintln!("Decoding… (Argon2id key derivation may take a moment)");
-            let decoded_bytes = encoding::decode_raw(&dna_string, &password)?;
+            let start = Instant::now();
+            if stats {
+                stats::enable();
+            }
+            info!("Decoding… (Argon2id key derivation may take a moment)");
+            let decoded_bytes = match hardened {
+                true => encoding::decode_raw_hardened(&dna_string, &password)?,
+                false => encoding::decode_raw(&dna_string, &password)?,
+            };
+            let plaintext_len = decoded_bytes.len() as u64;
+            let plaintext_sum = input_sum.map(|_| checksum::checksum(&decoded_bytes));
+            checksum::check_expected(expect, &[plaintext_sum, input_sum])?;
 
             match &save_as {
+                Some(path) if output::is_stdout(path) => {
+                    output::write_plaintext_stdout(decoded_bytes, true)?;
+                }
                 Some(path) => {
+                    report_backup(output::prepare_output(path, overwrite)?, path);
                     let mut f = fs::File::create(path).map_err(DendecError::Io)?;
                     f.write_all(&decoded_bytes).map_err(DendecError::Io)?;
-                    eprintln!("Written to {}", path.display());
+                    info!("Written to {}", path.display());
                 }
-                None => {
-                    let text = String::from_utf8(decoded_bytes)
-                        .map_err(DendecError::Utf8)?;
-                    print!("{text}");
+                None => output::write_plaintext_stdout(decoded_bytes, false)?,
+            }
+            if let (true, Some(input_sum), Some(plaintext_sum)) = (checksum, input_sum, plaintext_sum) {
+                let to_file = save_as.as_deref().filter(|p| !output::is_stdout(p));
+                let sums = [(input_sum, file.as_deref()), (plaintext_sum, to_file)];
+                checksum::report(&sums, to_file.is_none()).map_err(DendecError::Io)?;
+            }
+            if stats {
+                stats::Report {
+                    plaintext: plaintext_len,
+                    dna: dna_string.len() as u64,
+                    elapsed: start.elapsed(),
+                    timings: stats::timings(),
                 }
+                .print();
             }
         }
 
-        Command::Wrap { encode, decode, command } => {
+        Command::Wrap {
+            encode,
+            decode,
+            force,
+            backup,
+            abort_on_error,
+            continue_on_error: _,
+            keep,
+            verify,
+            resume,
+            no_ignore,
+            scan_root,
+            shell,
+            env,
+            clean_env,
+            pty,
+            include_binary,
+            binary_max_size,
+            sniff,
+            max_size,
+            exclude_dirs,
+            binary_extensions,
+            archive,
+            refer,
+            assembly,
+            pre_hook,
+            post_hook,
+            jobs,
+            throttle,
+            report,
+            watch,
+            command,
+        } => {
             if encode && decode {
                 return Err(DendecError::WrapConflictingFlags);
             }
@@ -108,29 +337,251 @@ fn run() -> error::Result<()> {
                 }
             }
 
-            wrap::run_wrap(encode, &command, &password)?;
+            let opts = wrap::WrapOptions {
+                overwrite: OverwritePolicy::from_flags(force, backup),
+                on_error: FailurePolicy::from_flags(abort_on_error),
+                keep,
+                verify,
+                resume,
+                no_ignore,
+                scan_root,
+                shell,
+                env,
+                clean_env,
+                pty,
+                include_binary,
+                binary_max_size,
+                sniff,
+                max_size,
+                exclude_dirs,
+                binary_extensions,
+                archive,
+                refer: refer.then(|| assembly.unwrap_or_default()),
+                pre_hook,
+                post_hook,
+                jobs,
+                throttle,
+                report,
+                ..Default::default()
+            };
+            match watch {
+                Some(dir) => wrap::run_watch(encode, &dir, &password, &opts)?,
+                None => wrap::run_wrap(encode, &command, &password, &opts)?,
+            }
         }
 
-        Command::Refer { refer, unrefer, from, to } => {
+        Command::Unwrap { undo: _, dir, force } => {
+            let root = dir.unwrap_or_else(|| std::path::PathBuf::from("."));
+            let manifest = wrap::manifest::Manifest::load(&root)?;
+
+            // Only prompt when a file actually has to be transformed back
+            let password = if wrap::undo::needs_password(&manifest) {
+                let password = rpassword::prompt_password("Enter password: ")
+                    .map_err(DendecError::Io)?;
+                // Undoing a decode re-encodes, so confirm as encode does
+                if !manifest.encode_mode {
+                    let confirm = rpassword::prompt_password("Confirm password: ")
+                        .map_err(DendecError::Io)?;
+                    if password != confirm {
+                        return Err(DendecError::PasswordMismatch);
+                    }
+                }
+                password
+            } else {
+                String::new()
+            };
+
+            wrap::undo::run_undo(&root, &manifest, &password, force)?;
+        }
+
+        Command::Refer {
+            action,
+            refer,
+            unrefer,
+            from,
+            to,
+            assembly,
+            keyed,
+            shuffle,
+            format,
+            bed12,
+            reference,
+            both_strands,
+            chrom_style,
+            name_style,
+            audit,
+            force,
+            backup,
+        } => {
+            if let Some(ReferCommand::Verify { bed, dna, shuffle }) = action {
+                let bedgraph = refer::format::detect(&bed)? == refer::format::Format::BedGraph;
+                let password = match shuffle || bedgraph || refer::has_keyed_names(&bed)? {
+                    true => Some(rpassword::prompt_password("Enter password: ").map_err(DendecError::Io)?),
+                    false => None,
+                };
+                refer::audit::run_audit(&bed, dna.as_deref(), password.as_deref())?;
+                return Ok(());
+            }
+            // clap requires both unless a subcommand was given
+            let (Some(from), Some(to)) = (from, to) else {
+                error!("--from and --to are required");
+                std::process::exit(1);
+            };
             if refer && unrefer {
-                eprintln!("Error: -r and -u are mutually exclusive");
+                error!("-r and -u are mutually exclusive");
                 std::process::exit(1);
             }
             if !refer && !unrefer {
-                eprintln!("Error: provide either -r (refer) or -u (unrefer)");
+                error!("provide either -r (refer) or -u (unrefer)");
                 std::process::exit(1);
             }
 
+            let overwrite = OverwritePolicy::from_flags(force, backup);
+            // A bedGraph carries chunk order in keyed values, and
+            // --name-style records in keyed names, either way
+            let keyed_order = match refer {
+                true => {
+                    format == Some(refer::format::Format::BedGraph)
+                        || name_style.is_some_and(|style| style.is_keyed())
+                }
+                false => {
+                    refer::format::detect(&from)? == refer::format::Format::BedGraph
+                        || refer::has_keyed_names(&from)?
+                }
+            };
+            let password = if keyed || shuffle || keyed_order {
+                output::check_output(&to, overwrite)?;
+                let password = rpassword::prompt_password("Enter password: ")
+                    .map_err(DendecError::Io)?;
+                if refer {
+                    let confirm = rpassword::prompt_password("Confirm password: ")
+                        .map_err(DendecError::Io)?;
+                    if password != confirm {
+                        return Err(DendecError::PasswordMismatch);
+                    }
+                }
+                Some(password)
+            } else {
+                None
+            };
+
             if refer {
-                eprintln!("Referring {} → {}", from.display(), to.display());
-                crate::refer::refer_encode(from, to)?;
+                let keying = password
+                    .as_deref()
+                    .map(|password| refer::Keying { password, select: keyed, shuffle });
+                info!("Referring {} → {}", from.display(), to.display());
+                let opts = refer::ReferOptions {
+                    assembly: assembly.unwrap_or_default(),
+                    format: if bed12 { refer::format::Format::Bed12 } else { format.unwrap_or_default() },
+                    reference,
+                    both_strands,
+                    chrom_style: chrom_style.unwrap_or_default(),
+                    name_style: name_style.unwrap_or_default(),
+                };
+                refer::refer_encode(from, to.clone(), keying, &opts, overwrite)?;
+                if audit {
+                    refer::tells::run_tells(&to, password.as_deref())?;
+                }
             } else {
-                eprintln!("Unreferring {} → {}", from.display(), to.display());
-                crate::refer::refer_decode(from, to)?;
+                info!("Unreferring {} → {}", from.display(), to.display());
+                refer::refer_decode(from, to, assembly, password.as_deref(), overwrite)?;
             }
         }
+
+        Command::Bench { size, kdf_runs } => {
+            bench::run_bench(size, kdf_runs)?;
+        }
+
+        Command::Daemon => daemon::run_daemon()?,
+
+        Command::Man { command, dir } => {
+            let cli = Cli::command();
+            match dir {
+                Some(dir) => {
+                    let written = man::write_pages(&cli, &dir)?;
+                    info!("Written {} page(s) to {}", written.len(), dir.display());
+                }
+                None => {
+                    let Some(page) = man::page(&cli, &command) else {
+                        return Err(DendecError::UnknownManPage(command.join(" ")));
+                    };
+                    io::stdout().write_all(page.as_bytes()).map_err(DendecError::Io)?;
+                }
+            }
+        }
+
+        Command::Serve { listen, token_file, max_body, jobs, hardened } => {
+            serve::serve(&serve::ServeOptions { listen, max_body, jobs, token_file, hardened })?;
+        }
+
+        Command::Table { action } => match action {
+            TableCommand::Build {
+                fasta,
+                out,
+                max_coords,
+                chunk_size,
+                forward_only,
+                primary_only,
+                accession_map,
+                exclude,
+                parts,
+                report,
+                force,
+                backup,
+            } => {
+                let overwrite = OverwritePolicy::from_flags(force, backup);
+                let opts = crate::refer::build::BuildOptions {
+                    max_coords,
+                    chunk_size: chunk_size as usize,
+                    forward_only,
+                    primary_only,
+                    accession_map,
+                    exclude,
+                    parts,
+                    report,
+                };
+                crate::refer::build::build_table(&fasta, &out, &opts, overwrite)?;
+            }
+            TableCommand::Merge { parts, out, report, force, backup } => {
+                let overwrite = OverwritePolicy::from_flags(force, backup);
+                crate::refer::part::merge_parts(&parts, &out, report.as_deref(), overwrite)?;
+            }
+            TableCommand::Stats { file, assembly } => {
+                crate::refer::stats::run_stats(file.as_deref(), assembly.unwrap_or_default())?;
+            }
+            TableCommand::Verify { file, assembly } => {
+                crate::refer::verify::run_verify(file.as_deref(), assembly.unwrap_or_default())?;
+            }
+        },
     }
 
     Ok(())
 }
 
+
+/// Prompt for `n` new passwords, each confirmed.
+fn prompt_new_passwords(n: u8) -> error::Result<Vec<String>> {
+    (1..=n).map(|i| prompt_new_password(&format!("password {i} of {n}"))).collect()
+}
+
+/// Prompt for a new password, named `what` in the prompts, and confirm it.
+fn prompt_new_password(what: &str) -> error::Result<String> {
+    let password = rpassword::prompt_password(format!("Enter {what}: "))
+        .map_err(DendecError::Io)?;
+    let confirm = rpassword::prompt_password(format!("Confirm {what}: "))
+        .map_err(DendecError::Io)?;
+    if password != confirm {
+        return Err(DendecError::PasswordMismatch);
+    }
+    if password.is_empty() {
+        warn!("using an empty password provides no security.");
+    }
+    Ok(password)
+}
+
+/// Tell the user where `--backup` moved the previous output file.
+fn report_backup(backup: Option<std::path::PathBuf>, path: &std::path::Path) {
+    if let Some(backup) = backup {
+        info!("Existing {} moved to {}", path.display(), backup.display());
+    }
+}
@@ -0,0 +1,275 @@
+/// man.rs — Manual pages from the clap definitions
+///
+/// `dendec man` renders roff (man(7)) pages from the same clap `Command`
+/// tree that parses the command line, so the pages cannot drift from
+/// the flags: one page for dendec itself and one per subcommand, nested
+/// ones included, named the way man-db expects:
+///
+///   dendec.1   dendec-encode.1   dendec-wrap.1   dendec-table-build.1 ...
+///
+/// Each page has NAME, SYNOPSIS, DESCRIPTION (the long help), OPTIONS
+/// with every visible argument and its possible and default values,
+/// COMMANDS linking the subcommands' pages, and VERSION. Hidden
+/// arguments and clap's generated `help` subcommand are left out.
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{Arg, Command};
+
+use crate::error::{DendecError, Result};
+
+/// Manual section for user commands.
+const SECTION: &str = "1";
+
+/// Every page under `cmd`, root first, as (file name, roff source).
+pub fn pages(cmd: &Command) -> Vec<(String, String)> {
+    let mut cmd = cmd.clone();
+    // Propagates global arguments and fills in subcommand bin names
+    cmd.build();
+    let root = Root { name: cmd.get_name(), version: cmd.get_version().unwrap_or_default() };
+    let mut out = Vec::new();
+    collect(&cmd, &root, &[], &mut out);
+    out
+}
+
+/// The page for the subcommand of `cmd` at `path` (empty for the root).
+pub fn page(cmd: &Command, path: &[String]) -> Option<String> {
+    let name = page_name(cmd.get_name(), path);
+    pages(cmd).into_iter().find(|(file, _)| *file == format!("{name}.{SECTION}")).map(|(_, roff)| roff)
+}
+
+/// Write every page under `cmd` into `dir`, returning their paths.
+pub fn write_pages(cmd: &Command, dir: &Path) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir).map_err(DendecError::Io)?;
+    pages(cmd)
+        .into_iter()
+        .map(|(file, roff)| {
+            let path = dir.join(file);
+            fs::write(&path, roff).map_err(DendecError::Io)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// The top-level command, whose name and version every page carries.
+struct Root<'a> {
+    name: &'a str,
+    version: &'a str,
+}
+
+/// Add the page for `cmd`, at `path` under `root`, and its subcommands'.
+fn collect(cmd: &Command, root: &Root, path: &[String], out: &mut Vec<(String, String)>) {
+    out.push((format!("{}.{SECTION}", page_name(root.name, path)), render(cmd, root, path)));
+    for sub in visible_subcommands(cmd) {
+        let mut sub_path = path.to_vec();
+        sub_path.push(sub.get_name().to_string());
+        collect(sub, root, &sub_path, out);
+    }
+}
+
+/// `dendec-table-build` for root `dendec` and path `table build`.
+fn page_name(root: &str, path: &[String]) -> String {
+    std::iter::once(root).chain(path.iter().map(String::as_str)).collect::<Vec<_>>().join("-")
+}
+
+fn visible_subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands().filter(|s| !s.is_hide_set() && s.get_name() != "help")
+}
+
+/// The roff source of one page.
+fn render(cmd: &Command, root: &Root, path: &[String]) -> String {
+    let name = page_name(root.name, path);
+    let invocation = std::iter::once(root.name).chain(path.iter().map(String::as_str)).collect::<Vec<_>>().join(" ");
+    let mut roff = String::new();
+
+    let _ = writeln!(
+        roff,
+        ".TH {} {SECTION} \"\" \"{} {}\" \"User Commands\"",
+        escape(&name.to_uppercase()),
+        escape(root.name),
+        escape(root.version)
+    );
+
+    roff.push_str(".SH NAME\n");
+    let about = cmd.get_about().map(|a| a.to_string()).unwrap_or_default();
+    let _ = writeln!(roff, "{} \\- {}", escape(&name), escape(about.trim()));
+
+    roff.push_str(".SH SYNOPSIS\n");
+    let _ = write!(roff, "\\fB{}\\fR", escape(&invocation));
+    if options(cmd).next().is_some() {
+        roff.push_str(" [\\fIOPTIONS\\fR]");
+    }
+    for arg in cmd.get_positionals().filter(|a| !a.is_hide_set()) {
+        let value = value_name(arg);
+        let _ = match arg.is_required_set() {
+            true => write!(roff, " \\fI{}\\fR", escape(&value)),
+            false => write!(roff, " [\\fI{}\\fR]", escape(&value)),
+        };
+    }
+    if visible_subcommands(cmd).next().is_some() {
+        roff.push_str(" \\fICOMMAND\\fR");
+    }
+    roff.push('\n');
+
+    let description = cmd.get_long_about().or(cmd.get_about()).map(|a| a.to_string()).unwrap_or_default();
+    if !description.trim().is_empty() {
+        roff.push_str(".SH DESCRIPTION\n");
+        paragraphs(&mut roff, &description);
+    }
+
+    let args: Vec<&Arg> = cmd.get_positionals().chain(options(cmd)).filter(|a| !a.is_hide_set()).collect();
+    if !args.is_empty() {
+        roff.push_str(".SH OPTIONS\n");
+        for arg in args {
+            roff.push_str(".TP\n");
+            roff.push_str(&arg_heading(arg));
+            roff.push('\n');
+            let help = arg.get_long_help().or(arg.get_help()).map(|h| h.to_string()).unwrap_or_default();
+            paragraphs(&mut roff, &help);
+            let possible: Vec<String> = arg
+                .get_possible_values()
+                .iter()
+                .filter(|v| !v.is_hide_set())
+                .map(|v| v.get_name().to_string())
+                .collect();
+            if !possible.is_empty() && arg.get_action().takes_values() {
+                let _ = writeln!(roff, ".br\nPossible values: {}", escape(&possible.join(", ")));
+            }
+            let defaults: Vec<String> = arg.get_default_values().iter().map(|v| v.to_string_lossy().into_owned()).collect();
+            if !defaults.is_empty() && !arg.is_hide_default_value_set() && arg.get_action().takes_values() {
+                let _ = writeln!(roff, ".br\nDefault: {}", escape(&defaults.join(", ")));
+            }
+        }
+    }
+
+    let subs: Vec<&Command> = visible_subcommands(cmd).collect();
+    if !subs.is_empty() {
+        roff.push_str(".SH COMMANDS\n");
+        for sub in subs {
+            let mut sub_path = path.to_vec();
+            sub_path.push(sub.get_name().to_string());
+            let _ = writeln!(roff, ".TP\n\\fB{}\\fR({SECTION})", escape(&page_name(root.name, &sub_path)));
+            let about = sub.get_about().map(|a| a.to_string()).unwrap_or_default();
+            let _ = writeln!(roff, "{}", escape(about.trim()));
+        }
+    }
+
+    if !root.version.is_empty() {
+        let _ = writeln!(roff, ".SH VERSION\nv{}", escape(root.version));
+    }
+    roff
+}
+
+/// Flags and options: every argument that is not positional.
+fn options(cmd: &Command) -> impl Iterator<Item = &Arg> {
+    cmd.get_arguments().filter(|a| !a.is_positional() && !a.is_hide_set())
+}
+
+/// `\fB\-g\fR, \fB\-\-group\fR \fIN\fR`, or `\fIPATH\fR` for a positional.
+fn arg_heading(arg: &Arg) -> String {
+    let value = format!("\\fI{}\\fR", escape(&value_name(arg)));
+    if arg.is_positional() {
+        return value;
+    }
+    let mut names = Vec::new();
+    if let Some(short) = arg.get_short() {
+        names.push(format!("\\fB\\-{}\\fR", escape(&short.to_string())));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+    }
+    let mut heading = names.join(", ");
+    if arg.get_action().takes_values() {
+        heading.push(' ');
+        heading.push_str(&value);
+    }
+    heading
+}
+
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .and_then(|names| names.first())
+        .map(|n| n.to_string())
+        .unwrap_or_else(|| arg.get_id().as_str().to_uppercase())
+}
+
+/// Help text as roff: blank lines start paragraphs, indented lines are
+/// kept as they are (examples).
+fn paragraphs(roff: &mut String, text: &str) {
+    let mut literal = false;
+    let mut first = true;
+    for block in text.trim().split("\n\n") {
+        if !first {
+            roff.push_str(".PP\n");
+        }
+        first = false;
+        for line in block.lines() {
+            let indented = line.starts_with(' ');
+            if indented != literal {
+                roff.push_str(if indented { ".nf\n" } else { ".fi\n" });
+                literal = indented;
+            }
+            roff.push_str(&escape(if indented { line } else { line.trim() }));
+            roff.push('\n');
+        }
+        if literal {
+            roff.push_str(".fi\n");
+            literal = false;
+        }
+    }
+}
+
+/// Escape text for roff: backslashes and hyphens, and a leading `.` or
+/// `'` that would be read as a request.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    match escaped.chars().next() {
+        Some('.') | Some('\'') => format!("\\&{escaped}"),
+        _ => escaped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ArgAction;
+
+    fn demo() -> Command {
+        Command::new("demo")
+            .version("1.2.3")
+            .about("Demo tool")
+            .arg(Arg::new("verbose").short('v').long("verbose").action(ArgAction::Count).global(true).help("More output"))
+            .subcommand(
+                Command::new("table").about("Table tools").subcommand(
+                    Command::new("build")
+                        .about("Build a table")
+                        .long_about("Build a table\n\nExamples:\n  demo table build --out t.bin")
+                        .arg(Arg::new("out").long("out").value_name("PATH").required(true).help("Where to write"))
+                        .arg(Arg::new("secret").long("secret").hide(true)),
+                ),
+            )
+    }
+
+    #[test]
+    fn test_pages_follow_the_command_tree() {
+        let pages = pages(&demo());
+        let names: Vec<&str> = pages.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["demo.1", "demo-table.1", "demo-table-build.1"]);
+
+        let root = &pages[0].1;
+        assert!(root.starts_with(".TH DEMO 1 \"\" \"demo 1.2.3\""));
+        assert!(root.contains("\\fBdemo\\-table\\fR(1)\nTable tools"));
+
+        let build = page(&demo(), &["table".into(), "build".into()]).unwrap();
+        assert!(build.starts_with(".TH DEMO\\-TABLE\\-BUILD 1 \"\" \"demo 1.2.3\""));
+        assert!(build.contains("\\fBdemo table build\\fR [\\fIOPTIONS\\fR]"));
+        assert!(build.contains("\\fB\\-\\-out\\fR \\fIPATH\\fR\nWhere to write"));
+        // Global flags reach every page; hidden ones none
+        assert!(build.contains("\\fB\\-v\\fR, \\fB\\-\\-verbose\\fR\nMore output"));
+        assert!(!build.contains("secret"));
+        // Indented example lines stay as they are
+        assert!(build.contains(".nf\n  demo table build \\-\\-out t.bin\n.fi"));
+        assert!(page(&demo(), &["nope".into()]).is_none());
+    }
+}
@@ -0,0 +1,170 @@
+/// migrate.rs — Re-encode payloads written under an outdated suite
+///
+/// Every header carries a version byte, and the version names the whole
+/// algorithm suite (see `encoding::SUITES`): KDF, key schedule and
+/// cipher. Decode keeps every old suite working, so nothing forces a
+/// payload forward; migrate does it on purpose, for the files under a
+/// tree that an older dendec wrote.
+///
+/// Each outdated payload is decoded, re-encoded under one master key
+/// with the current suite, decoded again to check the round trip, and
+/// only then replaces the original atomically, keeping its permissions
+/// and modification time. Current payloads and files that are not
+/// payloads are left alone, so running migrate twice is harmless.
+///
+/// A password only opens the payloads written under it; the others fail
+/// and are listed at the end, untouched. Refer BEDs are not looked at:
+/// unrefer them first.
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use walkdir::WalkDir;
+
+use crate::crypto::{derive_master_key, MasterKey, MasterKeyCache};
+use crate::encoding::{decode_raw_cached, decode_stream_with_master, encode_raw_with_master, payload_suite, Suite};
+use crate::error::{DendecError, Result};
+use crate::output::write_atomic_with;
+use crate::wrap::classify::read_sample;
+use crate::wrap::transform::{print_summary, TransformSummary};
+
+/// Options for `run_migrate`.
+#[derive(Debug, Default, Clone)]
+pub struct MigrateOptions {
+    /// List the outdated payloads without touching them
+    pub dry_run: bool,
+    /// Bases per output line; None writes one line
+    pub group: Option<usize>,
+}
+
+/// Every payload under `paths` whose suite is no longer current, with
+/// that suite. Directories are walked; links are not followed.
+pub fn find_outdated(paths: &[PathBuf]) -> Vec<(PathBuf, Suite)> {
+    paths
+        .iter()
+        .flat_map(|p| WalkDir::new(p).follow_links(false))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let suite = payload_suite(&read_sample(e.path(), 512).ok()?)?;
+            (!suite.current).then(|| (e.into_path(), suite))
+        })
+        .collect()
+}
+
+/// Migrate every outdated payload under `paths`. `password` may be None
+/// for a dry run only.
+pub fn run_migrate(paths: &[PathBuf], password: Option<&str>, opts: &MigrateOptions) -> Result<TransformSummary> {
+    let outdated = find_outdated(paths);
+    let mut summary = TransformSummary::default();
+    if outdated.is_empty() {
+        info!("  No outdated payloads found.");
+        return Ok(summary);
+    }
+
+    if opts.dry_run {
+        for (path, suite) in &outdated {
+            info!("  Would migrate {} (v{})", path.display(), suite.version);
+        }
+        summary.skipped = outdated.len();
+        return Ok(summary);
+    }
+
+    let password = password.expect("run_migrate needs a password unless dry_run");
+    let keys = MasterKeyCache::new(password);
+    let mut master: Option<MasterKey> = None;
+
+    for (path, suite) in &outdated {
+        match migrate_file(path, &keys, &mut master, opts.group) {
+            Ok(()) => {
+                info!("  Migrated {} (v{})", path.display(), suite.version);
+                summary.transformed += 1;
+            }
+            Err(e) => {
+                warn!("  Migrating {} failed: {e}", path.display());
+                summary.failed += 1;
+                summary.failures.push((path.clone(), e.to_string()));
+            }
+        }
+    }
+
+    print_summary(&summary, "migrate");
+
+    if summary.failed > 0 {
+        return Err(DendecError::WrapFileFailed {
+            path: PathBuf::from("<multiple>"),
+            reason: format!("{} file(s) could not be migrated and were left as they were", summary.failed),
+        });
+    }
+    Ok(summary)
+}
+
+/// Re-encode one payload in place, deriving the shared master key on
+/// first use.
+fn migrate_file(path: &Path, keys: &MasterKeyCache, master: &mut Option<MasterKey>, group: Option<usize>) -> Result<()> {
+    let dna = fs::read_to_string(path).map_err(DendecError::Io)?;
+    let plaintext = decode_raw_cached(&dna, keys)?;
+
+    if master.is_none() {
+        *master = Some(derive_master_key(keys.password())?);
+    }
+    let master = master.as_ref().expect("set above");
+    let migrated = encode_raw_with_master(&plaintext, master, group)?;
+
+    let roundtrip = decode_stream_with_master(Cursor::new(migrated.as_bytes()), Vec::new(), master)?;
+    if roundtrip != plaintext {
+        return Err(DendecError::WrapFileFailed {
+            path: path.to_path_buf(),
+            reason: "re-encoded payload does not decode to the original".into(),
+        });
+    }
+
+    let meta = fs::metadata(path).map_err(DendecError::Io)?;
+    write_atomic_with(path, Some(meta.permissions()), meta.modified().ok(), |file| {
+        Ok(file.write_all(migrated.as_bytes())?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{decode_raw, encode_v1};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_migrate_upgrades_only_outdated_payloads() {
+        let dir = tempdir().unwrap();
+        let old = dir.path().join("old.dna");
+        let new = dir.path().join("new.dna");
+        let plain = dir.path().join("notes.txt");
+        fs::write(&old, encode_v1(b"legacy secret", "migrate")).unwrap();
+        let master = derive_master_key("migrate").unwrap();
+        let current = encode_raw_with_master(b"fresh", &master, None).unwrap();
+        fs::write(&new, &current).unwrap();
+        fs::write(&plain, b"not a payload").unwrap();
+
+        let paths = [dir.path().to_path_buf()];
+        let found = find_outdated(&paths);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, old);
+
+        // A dry run changes nothing
+        let dry = MigrateOptions { dry_run: true, ..Default::default() };
+        let before = fs::read(&old).unwrap();
+        run_migrate(&paths, None, &dry).unwrap();
+        assert_eq!(fs::read(&old).unwrap(), before);
+
+        // The wrong password leaves the file as it was
+        assert!(run_migrate(&paths, Some("wrong"), &MigrateOptions::default()).is_err());
+        assert_eq!(fs::read(&old).unwrap(), before);
+
+        let summary = run_migrate(&paths, Some("migrate"), &MigrateOptions::default()).unwrap();
+        assert_eq!(summary.transformed, 1);
+        let migrated = fs::read_to_string(&old).unwrap();
+        assert!(payload_suite(migrated.as_bytes()).unwrap().current);
+        assert_eq!(decode_raw(&migrated, "migrate").unwrap(), b"legacy secret");
+        assert_eq!(fs::read_to_string(&new).unwrap(), current);
+        assert!(find_outdated(&paths).is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+/// mmap.rs — Memory-mapped file input
+///
+/// Large inputs are mapped rather than read so the streaming encoder can
+/// walk them chunk by chunk without first copying the whole file into
+/// RAM. Pages are faulted in on demand and dropped by the kernel under
+/// memory pressure, so encoding a multi-GB disk image needs roughly one
+/// chunk of resident memory plus output buffering.
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::error::{DendecError, Result};
+
+/// Map `path` read-only into memory.
+///
+/// The returned map derefs to `&[u8]` and implements nothing else, so
+/// callers treat it exactly like the `Vec<u8>` from `fs::read`.
+pub fn map_file(path: &Path) -> Result<Mmap> {
+    let file = File::open(path).map_err(DendecError::Io)?;
+    // SAFETY: the mapping is read-only and private to this process. If
+    // another process truncates the file while we read it, the worst case
+    // is a SIGBUS — the same class of failure as the file vanishing
+    // mid-read. Concurrent modification changes what is encoded, which is
+    // equally true of a plain read racing a writer.
+    unsafe { Mmap::map(&file) }.map_err(DendecError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_map_matches_read() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let content: Vec<u8> = (0u8..=255u8).cycle().take(10_000).collect();
+        std::fs::write(&path, &content).unwrap();
+        assert_eq!(&map_file(&path).unwrap()[..], content.as_slice());
+    }
+
+    #[test]
+    fn test_map_empty_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("empty");
+        std::fs::write(&path, b"").unwrap();
+        assert!(map_file(&path).unwrap().is_empty());
+    }
+}
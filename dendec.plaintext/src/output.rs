@@ -0,0 +1,276 @@
+/// output.rs — Where output bytes go
+///
+/// Decoded payloads are arbitrary bytes. Printing them to a terminal is
+/// only safe when they are text, but a pipe or redirect wants the exact
+/// bytes whatever they are. This module owns that decision so decode and
+/// wrap behave the same way.
+///
+/// It also owns what happens when an output path already exists. Every
+/// command that writes files (encode, decode, wrap, refer) goes through
+/// `prepare_output`, so the refuse / --force / --backup rules are the
+/// same everywhere. `write_atomic` replaces a file so a crash leaves
+/// either the old contents or the new, never a torn file.
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::{DendecError, Result};
+
+/// Path value that means "stdout" for `--as`.
+pub const STDOUT_PATH: &str = "-";
+
+/// True if `path` is the `-` placeholder for stdout.
+pub fn is_stdout(path: &Path) -> bool {
+    path.as_os_str() == STDOUT_PATH
+}
+
+/// What to do when an output file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    /// Fail with `OutputExists` and leave the file untouched (default).
+    #[default]
+    Refuse,
+    /// Replace the existing file (`--force`).
+    Force,
+    /// Rename the existing file to `<name>.~N~` first (`--backup`).
+    Backup,
+}
+
+impl OverwritePolicy {
+    /// Build a policy from the `--force` / `--backup` flag pair.
+    /// clap rejects both together, so `force` wins only defensively.
+    pub fn from_flags(force: bool, backup: bool) -> Self {
+        match (force, backup) {
+            (true, _) => OverwritePolicy::Force,
+            (false, true) => OverwritePolicy::Backup,
+            (false, false) => OverwritePolicy::Refuse,
+        }
+    }
+}
+
+/// Fail early if `path` exists and the policy refuses to replace it.
+///
+/// Used before prompting for a password or running the KDF, so a refused
+/// write costs nothing. Does not modify the filesystem.
+pub fn check_output(path: &Path, policy: OverwritePolicy) -> Result<()> {
+    if policy == OverwritePolicy::Refuse && exists(path) {
+        return Err(DendecError::OutputExists(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Make `path` ready to be written under `policy`.
+///
+/// Call immediately before creating the file, once the output is known
+/// to be good — a backup is only taken when something will replace it.
+/// Returns the backup path if one was made.
+pub fn prepare_output(path: &Path, policy: OverwritePolicy) -> Result<Option<PathBuf>> {
+    if !exists(path) {
+        return Ok(None);
+    }
+    match policy {
+        OverwritePolicy::Refuse => Err(DendecError::OutputExists(path.to_path_buf())),
+        OverwritePolicy::Force => Ok(None),
+        OverwritePolicy::Backup => {
+            let backup = next_backup_path(path);
+            std::fs::rename(path, &backup).map_err(DendecError::Io)?;
+            Ok(Some(backup))
+        }
+    }
+}
+
+/// Existence check that also sees dangling symlinks.
+fn exists(path: &Path) -> bool {
+    path.symlink_metadata().is_ok()
+}
+
+/// First unused numbered backup name: `file.txt` → `file.txt.~1~`, `~2~`, …
+fn next_backup_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (1u32..)
+        .map(|n| path.with_file_name(format!("{name}.~{n}~")))
+        .find(|candidate| !exists(candidate))
+        .expect("numbered backup names are unbounded")
+}
+
+/// Name prefix and suffix of `write_atomic`'s temp files.
+const TEMP_PREFIX: &str = ".dendec-";
+const TEMP_SUFFIX: &str = ".tmp";
+
+/// True if `path` looks like one of `write_atomic`'s temp files, which
+/// exist only until they are renamed into place.
+pub fn is_atomic_temp(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.starts_with(TEMP_PREFIX) && n.ends_with(TEMP_SUFFIX))
+        .unwrap_or(false)
+}
+
+/// Write `data` to `path` atomically.
+///
+/// The bytes go to a temp file in the same directory, which is fsynced
+/// and then renamed over `path`. Rename within one filesystem is atomic,
+/// so readers see the old file or the complete new one. The directory is
+/// synced afterwards so the rename itself survives a power loss. On any
+/// error the temp file is removed and `path` is untouched.
+///
+/// The new file gets `perms` if given, else the permissions of the file
+/// it replaces, else the temp file's owner-only default.
+pub fn write_atomic(path: &Path, data: &[u8], perms: Option<std::fs::Permissions>) -> Result<()> {
+    write_atomic_with(path, perms, None, |file| Ok(file.write_all(data)?))
+}
+
+/// Like `write_atomic`, but `fill` writes the contents, so output larger
+/// than memory can be streamed in. An error from `fill` discards the
+/// temp file and leaves `path` untouched. The new file's modification
+/// time is set to `modified` if given.
+pub fn write_atomic_with<T>(
+    path: &Path,
+    perms: Option<std::fs::Permissions>,
+    modified: Option<SystemTime>,
+    fill: impl FnOnce(&mut io::BufWriter<&std::fs::File>) -> Result<T>,
+) -> Result<T> {
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    let tmp = tempfile::Builder::new()
+        .prefix(TEMP_PREFIX)
+        .suffix(TEMP_SUFFIX)
+        .tempfile_in(dir)?;
+    let perms = perms.or_else(|| std::fs::metadata(path).ok().map(|m| m.permissions()));
+    if let Some(perms) = perms {
+        tmp.as_file().set_permissions(perms)?;
+    }
+    let mut writer = io::BufWriter::new(tmp.as_file());
+    let value = fill(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    if let Some(modified) = modified {
+        tmp.as_file().set_modified(modified)?;
+    }
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| DendecError::Io(e.error))?;
+
+    sync_dir(dir);
+    Ok(value)
+}
+
+/// Flush a directory entry change to disk. Best effort: not every
+/// platform or filesystem lets a directory be opened and synced.
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(d) = std::fs::File::open(dir) {
+        let _ = d.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+/// Write decoded plaintext to stdout.
+///
+/// Raw bytes are written verbatim when `force_raw` is set (`--as -`) or
+/// when stdout is not a terminal, so `dendec decode ... | tar x` works
+/// for binary payloads. On a terminal the bytes must be valid UTF-8;
+/// binary output is refused rather than dumped onto the screen.
+pub fn write_plaintext_stdout(bytes: Vec<u8>, force_raw: bool) -> Result<()> {
+    let stdout = io::stdout();
+    let raw = force_raw || !stdout.is_terminal();
+
+    let bytes = if raw {
+        bytes
+    } else {
+        String::from_utf8(bytes)
+            .map_err(|_| DendecError::BinaryToTerminal)?
+            .into_bytes()
+    };
+
+    let mut out = stdout.lock();
+    out.write_all(&bytes).map_err(DendecError::Io)?;
+    out.flush().map_err(DendecError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_refuse_existing_output() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        assert!(prepare_output(&path, OverwritePolicy::Refuse).unwrap().is_none());
+
+        fs::write(&path, b"keep me").unwrap();
+        assert!(check_output(&path, OverwritePolicy::Refuse).is_err());
+        assert!(matches!(
+            prepare_output(&path, OverwritePolicy::Refuse),
+            Err(DendecError::OutputExists(_))
+        ));
+        assert_eq!(fs::read(&path).unwrap(), b"keep me");
+    }
+
+    #[test]
+    fn test_force_leaves_file_in_place() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        fs::write(&path, b"old").unwrap();
+        check_output(&path, OverwritePolicy::Force).unwrap();
+        assert!(prepare_output(&path, OverwritePolicy::Force).unwrap().is_none());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_numbered_backups() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+
+        fs::write(&path, b"first").unwrap();
+        let b1 = prepare_output(&path, OverwritePolicy::Backup).unwrap().unwrap();
+        assert_eq!(b1, dir.path().join("out.txt.~1~"));
+        assert!(!path.exists());
+
+        fs::write(&path, b"second").unwrap();
+        let b2 = prepare_output(&path, OverwritePolicy::Backup).unwrap().unwrap();
+        assert_eq!(b2, dir.path().join("out.txt.~2~"));
+        assert_eq!(fs::read(&b1).unwrap(), b"first");
+        assert_eq!(fs::read(&b2).unwrap(), b"second");
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_and_cleans_up() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+
+        write_atomic(&path, b"first", None).unwrap();
+        write_atomic(&path, b"second", None).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+
+        // Only the target remains — no stray temp files
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("out.txt")]);
+    }
+
+    #[test]
+    fn test_write_atomic_failure_leaves_target() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("no-such-dir").join("out.txt");
+        assert!(write_atomic(&missing, b"data", None).is_err());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_dash_is_stdout() {
+        assert!(is_stdout(Path::new("-")));
+        assert!(!is_stdout(Path::new("./-")));
+        assert!(!is_stdout(Path::new("out.bin")));
+    }
+}
@@ -0,0 +1,170 @@
+/// refer/assembly.rs — Genome assemblies refer can map onto
+///
+/// Each assembly is a separate coordinate universe with its own embedded
+/// lookup table. Tables are large, so each one sits behind a cargo feature
+/// of the same name; only hg38 is built by default:
+///
+///   cargo build --features hg19,mm39
+///   cargo build --features sacCer3,ecoli
+///
+/// Besides human and mouse there are two model organisms of microbiology,
+/// budding yeast and E. coli K-12. Their genomes are small (12 Mb and
+/// 4.6 Mb), but still hold every 8-mer, so refer maps any payload onto
+/// them; each 8-mer just has fewer distinct places to choose from.
+///
+/// build.rs embeds them zstd-compressed; each is decompressed once, on
+/// first use, and kept for the rest of the run.
+///
+/// The assembly chosen on refer is written into the BED `##assembly`
+/// header (RefSeq assembly accession, then the short name). Unrefer reads
+/// it back to pick the matching table, so a BED file is never resolved
+/// against coordinates from a different build.
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::error::{DendecError, Result};
+use super::table::unpack;
+
+/// A genome build with a refer lookup table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Assembly {
+    /// Human, GRCh37
+    Hg19,
+    /// Human, GRCh38
+    #[default]
+    Hg38,
+    /// Mouse, GRCm39
+    Mm39,
+    /// Budding yeast (Saccharomyces cerevisiae S288C), R64
+    SacCer3,
+    /// Escherichia coli K-12 MG1655, ASM584v2
+    Ecoli,
+}
+
+impl Assembly {
+    pub const ALL: [Assembly; 5] =
+        [Assembly::Hg19, Assembly::Hg38, Assembly::Mm39, Assembly::SacCer3, Assembly::Ecoli];
+
+    /// Short UCSC-style name, as given to `--assembly`. UCSC has no
+    /// current E. coli assembly, so that one is just "ecoli".
+    pub fn name(self) -> &'static str {
+        match self {
+            Assembly::Hg19 => "hg19",
+            Assembly::Hg38 => "hg38",
+            Assembly::Mm39 => "mm39",
+            Assembly::SacCer3 => "sacCer3",
+            Assembly::Ecoli => "ecoli",
+        }
+    }
+
+    /// RefSeq assembly accession written into the BED header.
+    pub fn refseq(self) -> &'static str {
+        match self {
+            Assembly::Hg19 => "GCF_000001405.25",
+            Assembly::Hg38 => "GCF_000001405.40",
+            Assembly::Mm39 => "GCF_000001635.27",
+            Assembly::SacCer3 => "GCF_000146045.2",
+            Assembly::Ecoli => "GCF_000005845.2",
+        }
+    }
+
+    /// The embedded table for this assembly, decompressed on first call.
+    /// Fails with ReferAssemblyUnavailable when its cargo feature was not
+    /// enabled at build time.
+    pub fn table_bytes(self) -> Result<&'static [u8]> {
+        static TABLES: [OnceLock<Vec<u8>>; Assembly::ALL.len()] = [const { OnceLock::new() }; Assembly::ALL.len()];
+
+        let cell = &TABLES[self as usize];
+        if let Some(bytes) = cell.get() {
+            return Ok(bytes);
+        }
+        let packed = self
+            .compressed_table()
+            .ok_or(DendecError::ReferAssemblyUnavailable(self.name()))?;
+        let bytes = unpack(packed)?.into_owned();
+        Ok(cell.get_or_init(|| bytes))
+    }
+
+    /// The table as embedded by build.rs (zstd-compressed), or None when
+    /// its cargo feature was not enabled.
+    pub fn compressed_table(self) -> Option<&'static [u8]> {
+        match self {
+            #[cfg(feature = "hg19")]
+            Assembly::Hg19 => Some(include_bytes!(concat!(env!("OUT_DIR"), "/hg19.bin.zst"))),
+            #[cfg(feature = "hg38")]
+            Assembly::Hg38 => Some(include_bytes!(concat!(env!("OUT_DIR"), "/hg38.bin.zst"))),
+            #[cfg(feature = "mm39")]
+            Assembly::Mm39 => Some(include_bytes!(concat!(env!("OUT_DIR"), "/mm39.bin.zst"))),
+            #[cfg(feature = "sacCer3")]
+            Assembly::SacCer3 => Some(include_bytes!(concat!(env!("OUT_DIR"), "/sacCer3.bin.zst"))),
+            #[cfg(feature = "ecoli")]
+            Assembly::Ecoli => Some(include_bytes!(concat!(env!("OUT_DIR"), "/ecoli.bin.zst"))),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    /// Value of the BED `##assembly` header line.
+    pub fn header_value(self) -> String {
+        format!("{} {}", self.refseq(), self.name())
+    }
+
+    /// Parse the value of a `##assembly` header line.
+    ///
+    /// Either field identifies the assembly; when both are present they
+    /// must agree.
+    pub fn from_header_value(value: &str) -> Result<Self> {
+        let invalid = || DendecError::ReferInvalidBed(format!("unknown assembly '{}'", value.trim()));
+        let mut found = None;
+        for field in value.split_whitespace() {
+            let assembly = Assembly::ALL
+                .into_iter()
+                .find(|a| a.refseq() == field || a.name() == field)
+                .ok_or_else(invalid)?;
+            if found.is_some_and(|f| f != assembly) {
+                return Err(invalid());
+            }
+            found = Some(assembly);
+        }
+        found.ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for Assembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Assembly {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Assembly::ALL
+            .into_iter()
+            .find(|a| a.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Assembly::ALL.iter().map(|a| a.name()).collect();
+                format!("unknown assembly '{s}' (expected one of: {})", names.join(", "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_value_roundtrip() {
+        for assembly in Assembly::ALL {
+            assert_eq!(Assembly::from_header_value(&assembly.header_value()).unwrap(), assembly);
+        }
+        assert_eq!(Assembly::from_header_value("hg19").unwrap(), Assembly::Hg19);
+        assert!(Assembly::from_header_value("GCF_000001405.40 mm39").is_err());
+        assert!(Assembly::from_header_value("panTro6").is_err());
+        assert_eq!("HG38".parse::<Assembly>().unwrap(), Assembly::Hg38);
+        assert_eq!("saccer3".parse::<Assembly>().unwrap(), Assembly::SacCer3);
+        assert_eq!(Assembly::from_header_value("GCF_000005845.2").unwrap(), Assembly::Ecoli);
+    }
+}
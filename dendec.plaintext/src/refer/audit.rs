@@ -0,0 +1,319 @@
+/// refer/audit.rs — dendec refer verify
+///
+/// Checks that a refer file still decodes, and optionally that it holds
+/// a given .dna, before the sources are deleted. Each check is reported
+/// on its own line, as `table verify` does for tables:
+///
+///   read         format recognised, every record parses
+///   count        records, chunk count and DNA length agree
+///   chunks       chunk names run 0..n, none missing or repeated; keyed
+///                `--name-style` names are decoded with the password first
+///   table        chunk size, table checksum and accessions match the table
+///   bounds       every record lies inside its chromosome
+///   coordinates  every coordinate is one the table lists
+///   payload      unrefer succeeds and matches payload_sha256
+///   dna          the unreferred DNA equals the .dna given with --dna
+///
+/// A file that cannot be read stops the run, as does one the table
+/// cannot be loaded for. The payload is only unreferred when every
+/// structural check passed, since it would fail on the same problem.
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::crypto::derive_refer_key;
+use crate::error::{DendecError, Result};
+use super::chunk::{reassemble, KmerReader};
+use super::coordinate::BedRecord;
+use super::format::{self, read_records, Format};
+use super::naming::NameCipher;
+use super::table::{CoordKey, ReferTable};
+use super::verify::{Check, Status};
+use super::{check_table, decode_names, recover, READ_BUFFER};
+
+/// Audit the refer file `bed`, against `dna` if given, and print the
+/// report to stdout. `password` unlocks shuffled files, bedGraphs and
+/// `--name-style` names.
+/// Fails with ReferVerifyFailed if any check failed.
+pub fn run_audit(bed: &Path, dna: Option<&Path>, password: Option<&str>) -> Result<()> {
+    println!("Verifying {}", bed.display());
+    println!();
+    let checks = audit(bed, dna, password);
+    for check in &checks {
+        println!("  {:<5} {:<12} {}", check.status, check.name, check.detail);
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failed > 0 {
+        return Err(DendecError::ReferVerifyFailed(failed));
+    }
+    println!();
+    println!("  {} decodes{}.", bed.display(), if dna.is_some() { " to the .dna given" } else { "" });
+    Ok(())
+}
+
+/// Run every check on a refer file.
+pub fn audit(bed: &Path, dna: Option<&Path>, password: Option<&str>) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut push = |status, name, detail: String| checks.push(Check { status, name, detail });
+
+    // ── Read ──────────────────────────────────────────────────────────
+    let read = format::detect(bed).and_then(|format| {
+        let key = match (format, password) {
+            (Format::BedGraph, Some(password)) => Some(derive_refer_key(password)?),
+            _ => None,
+        };
+        Ok((format, read_records(bed, key.as_ref())?))
+    });
+    let (format, (header, mut records)) = match read {
+        Ok(read) => read,
+        Err(e) => {
+            push(Status::Fail, "read", e.to_string());
+            return checks;
+        }
+    };
+    let minus = records.iter().filter(|r| r.strand == 1).count();
+    push(
+        Status::Ok,
+        "read",
+        format!("{} file, {} records ({} + / {} −)", format, records.len(), records.len() - minus, minus),
+    );
+
+    // ── Counts ────────────────────────────────────────────────────────
+    let k = header.chunk_size();
+    let chunks_for_length = header.dna_length.div_ceil(k);
+    if records.len() != header.chunk_count {
+        push(
+            Status::Fail,
+            "count",
+            format!("header lists {} chunks but the file has {} records", header.chunk_count, records.len()),
+        );
+    } else if header.dna_length > 0 && chunks_for_length != header.chunk_count {
+        push(
+            Status::Fail,
+            "count",
+            format!(
+                "{} bases make {} {}-base chunks, but the header lists {}",
+                header.dna_length, chunks_for_length, k, header.chunk_count
+            ),
+        );
+    } else {
+        push(Status::Ok, "count", format!("{} chunks of {} bases, {} bases in all", records.len(), k, header.dna_length));
+    }
+
+    // ── Chunk names ───────────────────────────────────────────────────
+    // Readers sort named records, so a gap or repeat shows as the first
+    // name that differs from its position. Keyed names are decoded and
+    // sorted here first
+    let keyed = records.first().is_some_and(|r| r.keyed_name.is_some());
+    let undecoded = match (keyed, password) {
+        (false, _) => None,
+        (true, None) => Some("--name-style names — give the password to check them".to_string()),
+        (true, Some(password)) => derive_refer_key(password)
+            .and_then(|key| decode_names(&mut records, &NameCipher::new(&key), header.chunk_count))
+            .err()
+            .map(|e| format!("--name-style names do not decode: {}", e)),
+    };
+    let shuffled = records.first().is_some_and(|r| r.chunk_idx.is_none());
+    let misnamed = records.iter().enumerate().find(|(i, r)| !shuffled && r.chunk_idx != Some(*i))
+        .map(|(i, r)| (i, r.chunk_idx));
+    match (&undecoded, shuffled) {
+        (Some(problem), _) => push(Status::Fail, "chunks", problem.clone()),
+        (None, true) => push(Status::Ok, "chunks", "unnamed (shuffled) — the password gives the order".to_string()),
+        (None, false) => match misnamed {
+            Some((i, idx)) => push(
+                Status::Fail,
+                "chunks",
+                format!(
+                    "chunk {} is {} — names must run 0 to {} once each",
+                    i,
+                    match idx {
+                        Some(idx) if idx < i => "repeated".to_string(),
+                        _ => "missing".to_string(),
+                    },
+                    records.len().saturating_sub(1)
+                ),
+            ),
+            None if keyed => push(
+                Status::Ok,
+                "chunks",
+                format!("--name-style names decode to 0 to {}", records.len().saturating_sub(1)),
+            ),
+            None => push(Status::Ok, "chunks", format!("named 0 to {}", records.len().saturating_sub(1))),
+        },
+    }
+
+    // ── Table ─────────────────────────────────────────────────────────
+    let assembly = header.assembly.unwrap_or_default();
+    let table = match ReferTable::load(assembly) {
+        Ok(table) => table,
+        Err(e) => {
+            push(Status::Fail, "table", e.to_string());
+            return checks;
+        }
+    };
+    match check_table(&header, &mut records, &table, assembly) {
+        Err(e) => {
+            push(Status::Fail, "table", e.to_string());
+            return checks;
+        }
+        Ok(()) if header.table_sha256.is_none() => push(
+            Status::Warn,
+            "table",
+            format!("{} table, {}-base chunks; no table_sha256 recorded to compare", assembly, k),
+        ),
+        Ok(()) => push(
+            Status::Ok,
+            "table",
+            format!("{} table, {}-base chunks, sha256 {}… matches", assembly, k, &table.sha256()[..16]),
+        ),
+    }
+
+    // ── Bounds and coordinates ────────────────────────────────────────
+    let extents = table.chrom_extents();
+    let mut outside = Vec::new();
+    let mut unlisted = Vec::new();
+    for (line, record) in records.iter().enumerate() {
+        let chunk = record.chunk_idx.unwrap_or(line);
+        let Some(chrom_idx) = table.chrom_idx_for(&record.accession) else {
+            continue;
+        };
+        if record.start + k as u64 > extents[chrom_idx as usize] {
+            outside.push((chunk, record));
+            continue;
+        }
+        let key = CoordKey { chrom_idx, start: record.start, strand: record.strand };
+        if table.resolve(&key).is_none() {
+            unlisted.push((chunk, record));
+        }
+    }
+    let place = |(chunk, record): &(usize, &BedRecord)| {
+        format!(
+            "chunk {} at {}:{} {}",
+            chunk,
+            record.accession,
+            record.start,
+            if record.strand == 0 { '+' } else { '-' }
+        )
+    };
+    match outside.first() {
+        Some(first) => push(
+            Status::Fail,
+            "bounds",
+            format!("{} record(s) past their chromosome's end (first: {})", outside.len(), place(first)),
+        ),
+        None => push(Status::Ok, "bounds", "every record inside its chromosome".to_string()),
+    }
+    match unlisted.first() {
+        Some(first) => push(
+            Status::Fail,
+            "coordinates",
+            format!("{} coordinate(s) the {} table does not list (first: {})", unlisted.len(), assembly, place(first)),
+        ),
+        None => push(Status::Ok, "coordinates", format!("all {} listed in the {} table", records.len(), assembly)),
+    }
+
+    let counted = records.len() == header.chunk_count
+        && (header.dna_length == 0 || chunks_for_length == header.chunk_count);
+    if !counted || undecoded.is_some() || misnamed.is_some() || !outside.is_empty() || !unlisted.is_empty() {
+        return checks;
+    }
+
+    // ── Payload ───────────────────────────────────────────────────────
+    let payload = match recover(bed, Some(assembly), password, false) {
+        Ok(payload) => payload,
+        Err(e) => {
+            push(Status::Fail, "payload", e.to_string());
+            return checks;
+        }
+    };
+    match header.payload_sha256 {
+        Some(_) => push(Status::Ok, "payload", format!("{} bases, payload_sha256 matches", payload.len())),
+        None => push(
+            Status::Warn,
+            "payload",
+            format!("{} bases; no payload_sha256 recorded to compare", payload.len()),
+        ),
+    }
+
+    // ── Against the .dna ──────────────────────────────────────────────
+    if let Some(dna) = dna {
+        match compare_dna(dna, payload.as_bytes(), k) {
+            Ok(None) => push(Status::Ok, "dna", format!("matches {}", dna.display())),
+            Ok(Some(detail)) => push(Status::Fail, "dna", detail),
+            Err(e) => push(Status::Fail, "dna", e.to_string()),
+        }
+    }
+
+    checks
+}
+
+/// Compare the bases of the .dna file at `path` with `payload`. None if
+/// they are equal, else where they first differ.
+fn compare_dna(path: &Path, payload: &[u8], chunk_size: usize) -> Result<Option<String>> {
+    let mut reader = KmerReader::new(BufReader::with_capacity(READ_BUFFER, File::open(path)?), chunk_size);
+    let kmers = reader.by_ref().collect::<Result<Vec<_>>>()?;
+    let bases = reassemble(&kmers);
+    if let Some(i) = bases.bytes().zip(payload.iter().copied()).position(|(a, b)| a != b) {
+        return Ok(Some(format!("differs from {} at base {} (chunk {})", path.display(), i, i / chunk_size)));
+    }
+    if bases.len() != payload.len() {
+        return Ok(Some(format!(
+            "{} has {} bases, the refer file {}",
+            path.display(),
+            bases.len(),
+            payload.len()
+        )));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OverwritePolicy;
+    use crate::refer::{refer_encode, ReferOptions};
+    use tempfile::tempdir;
+
+    fn statuses(bed: &Path, dna: Option<&Path>) -> Vec<(&'static str, Status)> {
+        audit(bed, dna, None).into_iter().map(|c| (c.name, c.status)).collect()
+    }
+
+    #[test]
+    fn test_audit_passes_and_pinpoints() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        std::fs::write(&dna, "ATGCGATCGGCTAATCCGTA\n").unwrap();
+        let bed = dir.path().join("out.bed");
+        refer_encode(dna.clone(), bed.clone(), None, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+
+        let checks = statuses(&bed, Some(&dna));
+        assert_eq!(checks.len(), 8);
+        assert!(checks.iter().all(|(_, s)| *s == Status::Ok), "{:?}", checks);
+
+        // Another .dna fails only the comparison
+        let other = dir.path().join("other.dna");
+        std::fs::write(&other, "ATGCGATCGGCTAATCCGTT").unwrap();
+        let checks = audit(&bed, Some(&other), None);
+        assert_eq!(checks.last().unwrap().status, Status::Fail);
+        assert!(checks.last().unwrap().detail.contains("at base 19 (chunk 2)"), "{}", checks.last().unwrap().detail);
+
+        // A repeated chunk name and a coordinate nudged off the table
+        let text = std::fs::read_to_string(&bed).unwrap();
+        std::fs::write(&bed, text.replace("chunk_00000001", "chunk_00000000")).unwrap();
+        assert!(statuses(&bed, None).contains(&("chunks", Status::Fail)));
+
+        let last = text.lines().last().unwrap();
+        let cols: Vec<&str> = last.split('\t').collect();
+        let table = ReferTable::load(crate::refer::assembly::Assembly::Hg38).unwrap();
+        let chrom_idx = table.chrom_idx_for(cols[0]).unwrap();
+        let start = (cols[1].parse::<u64>().unwrap() + 1..)
+            .find(|&start| table.resolve(&CoordKey { chrom_idx, start, strand: 0 }).is_none())
+            .unwrap();
+        let moved = format!("{}\t{}\t{}\t{}", cols[0], start, start + 8, cols[3..].join("\t"));
+        std::fs::write(&bed, text.replace(last, &moved)).unwrap();
+        let checks = statuses(&bed, None);
+        assert!(checks.contains(&("coordinates", Status::Fail)));
+        assert!(!checks.iter().any(|(name, _)| *name == "payload"));
+    }
+}
@@ -0,0 +1,248 @@
+/// refer/bedgraph.rs — bedGraph read and write
+///
+/// `refer --format bedgraph` writes the chunks as a coverage track, for
+/// labs where signal tracks are the routine artifact:
+///
+///  track type=bedGraph name=signal description="hg38 signal"
+///  ##dendec-refer v0.1.0
+///  ##assembly GCF_000001405.40 hg38
+///  ##chunk_size 8
+///  ##dna_length 168432
+///  ##chunk_count 21054
+///  ##payload_sha256 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///  ##table_sha256 a415178627e2c5200c58944182e5d2feecc55543bfc7f0cbd994385be46efc71
+///  NC_000001.11  106588  106596  1283.507261
+///  NC_000001.11  112878  112886  3517.044932
+///
+/// bedGraph has no name or strand column, so those travel in the value:
+/// chunk index × 2 + strand, XORed with a 32-bit draw from the refer key's
+/// signal CSPRNG for that line, printed as millionths. The intervals are
+/// sorted by position like any real track, and nothing in the file shows
+/// chunk order without the password. The format therefore always needs
+/// one, and a wrong one is caught when the values fail to unmask into
+/// each chunk exactly once.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::RngCore;
+
+use crate::crypto::ReferKey;
+use crate::error::{DendecError, Result};
+use super::coordinate::{read_header_line, write_header, BedHeader, BedRecord, HEADER_PREFIX};
+use super::format::{Chunk, Meta, RecordWriter};
+
+/// Start of the first line of every refer bedGraph; unrefer recognises
+/// the format by it.
+pub const BEDGRAPH_SIGNATURE: &str = "track type=bedGraph";
+
+/// Values are printed with this many decimal places.
+const VALUE_SCALE: u64 = 1_000_000;
+
+/// Writes a dendec-refer bedGraph. Records are held until finish, which
+/// sorts them by position.
+pub struct BedGraphWriter<W: Write> {
+    w: W,
+    rng: StdRng,
+    accessions: Vec<String>,
+    chunk_size: usize,
+    /// (chrom_idx, start, chunk index × 2 + strand) per chunk pushed.
+    held: Vec<(usize, u64, u32)>,
+}
+
+impl<W: Write> BedGraphWriter<W> {
+    /// Write the track line and the ## header lines.
+    pub fn new(mut w: W, meta: &Meta, key: &ReferKey) -> Result<Self> {
+        // Chunk index and strand must fit one 32-bit value
+        if meta.chunk_count > (u32::MAX >> 1) as usize + 1 {
+            return Err(DendecError::PayloadTooLarge);
+        }
+        writeln!(
+            w,
+            "{} name=signal description=\"{} signal\"",
+            BEDGRAPH_SIGNATURE,
+            meta.assembly.name()
+        )
+        .map_err(DendecError::Io)?;
+        write_header(&mut w, meta, HEADER_PREFIX)?;
+        Ok(BedGraphWriter {
+            w,
+            rng: key.signal_rng(),
+            accessions: meta.accessions.to_vec(),
+            chunk_size: meta.chunk_size,
+            held: Vec::with_capacity(meta.chunk_count),
+        })
+    }
+}
+
+impl<W: Write> RecordWriter for BedGraphWriter<W> {
+    fn push(&mut self, chunk: &Chunk) -> Result<()> {
+        let chrom_idx = self
+            .accessions
+            .iter()
+            .position(|a| a == chunk.accession)
+            .ok_or(DendecError::ReferTableCorrupt)?;
+        let word = (self.held.len() as u32) << 1 | chunk.strand as u32;
+        self.held.push((chrom_idx, chunk.start, word));
+        Ok(())
+    }
+
+    /// Write every record, sorted by position, with its masked value.
+    fn finish(&mut self) -> Result<()> {
+        self.held.sort_by_key(|&(chrom_idx, start, _)| (chrom_idx, start));
+        for &(chrom_idx, start, word) in &self.held {
+            let value = (word ^ self.rng.next_u32()) as u64;
+            writeln!(
+                self.w,
+                "{}\t{}\t{}\t{}.{:06}",
+                self.accessions[chrom_idx],
+                start,
+                start + self.chunk_size as u64,
+                value / VALUE_SCALE,
+                value % VALUE_SCALE
+            )
+            .map_err(DendecError::Io)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a dendec-refer bedGraph from `path`, unmasking its values with
+/// `key`.
+///
+/// Returns the header metadata and the records, sorted by chunk index.
+pub fn read_bedgraph(path: &Path, key: &ReferKey) -> Result<(BedHeader, Vec<BedRecord>)> {
+    let reader = BufReader::new(File::open(path).map_err(DendecError::Io)?);
+    let invalid = |reason: String| DendecError::ReferInvalidBed(format!("bedGraph: {}", reason));
+
+    let mut rng = key.signal_rng();
+    let mut header = BedHeader::default();
+    let mut records = Vec::new();
+
+    for raw in reader.lines() {
+        let line = raw.map_err(DendecError::Io)?;
+        let line = line.trim();
+
+        // ── Header lines ──────────────────────────────────────────────
+        if read_header_line(line, HEADER_PREFIX, &mut header)?
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+            || line.is_empty()
+        {
+            continue;
+        }
+
+        // ── Data lines ────────────────────────────────────────────────
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() != 4 {
+            return Err(invalid(format!("expected 4 tab-separated columns, got {}: {:?}", cols.len(), line)));
+        }
+
+        let start: u64 = cols[1]
+            .parse()
+            .map_err(|_| invalid(format!("invalid start '{}' in line: {}", cols[1], line)))?;
+        let value = parse_value(cols[3])
+            .ok_or_else(|| invalid(format!("invalid value '{}' in line: {}", cols[3], line)))?;
+        let word = value ^ rng.next_u32();
+
+        records.push(BedRecord {
+            accession: cols[0].to_string(),
+            start,
+            strand: (word & 1) as u8,
+            chunk_idx: Some((word >> 1) as usize),
+            keyed_name: None,
+        });
+    }
+
+    // Every line's mask depends on the lines before it
+    if header.chunk_count != records.len() {
+        return Err(invalid(format!(
+            "header lists {} chunks but {} were read — a bedGraph cannot be unmasked with lines missing",
+            header.chunk_count,
+            records.len()
+        )));
+    }
+    // Under the right password the values unmask to each chunk once
+    records.sort_by_key(|r| r.chunk_idx);
+    if records.iter().enumerate().any(|(i, r)| r.chunk_idx != Some(i)) {
+        return Err(DendecError::WrongPassword);
+    }
+    Ok((header, records))
+}
+
+/// Parse a value written by BedGraphWriter: whole part, '.', six digits.
+fn parse_value(s: &str) -> Option<u32> {
+    let (whole, frac) = s.split_once('.')?;
+    if frac.len() != 6 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let value = whole.parse::<u64>().ok()?.checked_mul(VALUE_SCALE)? + frac.parse::<u64>().ok()?;
+    u32::try_from(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::chunk::Kmer;
+    use crate::crypto::derive_refer_key;
+    use crate::refer::assembly::Assembly;
+    use crate::refer::naming::Naming;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_and_read_roundtrip() {
+        let key = derive_refer_key("hunter2").unwrap();
+        let accessions = ["NC_000001.11".to_string(), "NC_000007.14".to_string()];
+        let meta = Meta {
+            assembly: Assembly::Hg38,
+            chunk_size: 8,
+            dna_length: 24,
+            payload_sha256: "",
+            table_sha256: "",
+            chunk_count: 3,
+            naming: Naming::Chunk,
+            accessions: &accessions,
+            contig_lengths: &[],
+            key: Some(&key),
+            reference: None,
+        };
+        let mut graph = BedGraphWriter::new(Vec::new(), &meta, &key).unwrap();
+        let chunks = [("NC_000007.14", 553084, 1), ("NC_000001.11", 883401, 0), ("NC_000001.11", 100, 1)];
+        for (idx, (accession, start, strand)) in chunks.into_iter().enumerate() {
+            graph.push(&Chunk { idx, accession, start, strand, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        }
+        graph.finish().unwrap();
+
+        let text = String::from_utf8(graph.w).unwrap();
+        assert!(text.starts_with("track type=bedGraph name=signal description=\"hg38 signal\"\n##dendec-refer"));
+        // Sorted by position, whatever the chunk order
+        let starts: Vec<&str> = text
+            .lines()
+            .filter(|l| !l.starts_with('#') && !l.starts_with("track"))
+            .map(|l| l.split('\t').nth(1).unwrap())
+            .collect();
+        assert_eq!(starts, ["100", "883401", "553084"]);
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.bedgraph");
+        std::fs::write(&path, &text).unwrap();
+        let (header, records) = read_bedgraph(&path, &key).unwrap();
+        assert_eq!((header.assembly, header.chunk_count), (Some(Assembly::Hg38), 3));
+        let got: Vec<_> = records.iter().map(|r| (r.accession.as_str(), r.start, r.strand)).collect();
+        assert_eq!(got, chunks);
+
+        let wrong = derive_refer_key("hunter3").unwrap();
+        assert!(matches!(read_bedgraph(&path, &wrong), Err(DendecError::WrongPassword)));
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("0.000001"), Some(1));
+        assert_eq!(parse_value("4294.967295"), Some(u32::MAX));
+        for bad in ["4294.967296", "12.5", "12", "-1.000000", "1.00000x"] {
+            assert_eq!(parse_value(bad), None, "{bad}");
+        }
+    }
+}
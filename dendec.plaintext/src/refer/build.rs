@@ -0,0 +1,980 @@
+/// refer/build.rs — dendec table build
+///
+/// Generates a refer lookup table from FASTA files. Slides a k-mer window
+/// across every base of every record, on both strands, and keeps up to
+/// `max_coords` genome coordinates for each of the 4^k possible k-mers —
+/// 65,536 for the default chunk size of 8. The result is written in the
+/// binary format documented in table.rs.
+///
+/// The coordinates kept for a k-mer are a uniform sample of all its
+/// occurrences in every input (reservoir sampling), not the first ones
+/// found, so a whole-genome table spreads its options across every
+/// chromosome instead of filling up on chr1. The sampling RNG has a fixed
+/// seed: the same inputs always give the same table, and so the same
+/// table_sha256.
+///
+/// With `forward_only` just the + strand is kept: each k-mer's options
+/// are then all places it occurs as read, and `refer --both-strands`
+/// finds the − strand ones through the reverse complement's entry. The
+/// coordinates go twice as far before an entry is saturated.
+///
+/// Each record's accession is the first word of its `>` header, unless
+/// an accession map (`--accession-map`) renames it: a tab-separated file
+/// of FASTA name and accession per line, such as the chr1 → NC_000001.11
+/// pairs of an assembly report.
+///
+/// With `exclude`, BED files of regions to leave out — assembly gaps,
+/// centromeres, telomeres — no k-mer overlapping one of them is kept, so
+/// refer never points into them. A region names its chromosome as the
+/// FASTA does or by its accession.
+///
+/// With `parts` each chromosome is sampled on its own and saved to a part
+/// file in that directory once read, and the table is merged from the
+/// parts; a rerun skips chromosomes whose part is there (see part.rs).
+///
+/// Larger chunk sizes mean fewer records per payload but a bigger table:
+/// 4 bytes of offsets per possible k-mer (64 MB at 12), and a genome must
+/// hold every k-mer somewhere for refer to map any payload.
+///
+/// Each FASTA record becomes one chromosome; its accession is the first
+/// word of the `>` header line (`>NC_000001.11 Homo sapiens chromosome 1`
+/// → `NC_000001.11`). Inputs may be plain, gzipped or bgzipped FASTA, or
+/// UCSC .2bit — detected by magic bytes, not extension (see open_fasta).
+/// Sequence is streamed in blocks of BLOCK_LEN bases, so a whole genome
+/// never has to fit in memory. Each block is cut into k-mers on every
+/// core, and the k-mers are sampled in parallel by shard (see
+/// TableBuilder::flush). Lowercase (soft-masked) bases count;
+/// N and any other symbol break the window.
+///
+/// A table indexes up to 65,535 chromosomes, so the scaffolds, alternate
+/// loci and patches of a full RefSeq or UCSC assembly all fit. With
+/// `primary_only` those records are skipped and only the assembled
+/// chromosomes are read (see is_primary).
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::MultiGzDecoder;
+use log::{info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::error::{DendecError, Result};
+use crate::output::{check_output, prepare_output, write_atomic_with, OverwritePolicy};
+use super::chunk::{DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE};
+use super::coverage::write_coverage_report;
+use super::part::{merge_parts, part_path, Part};
+use super::table::{write_table, Coord};
+use super::twobit::{is_twobit, TwoBitFasta};
+
+/// Coordinate options kept per k-mer unless `--max-coords` says otherwise.
+pub const DEFAULT_MAX_COORDS: u8 = 8;
+
+/// Report progress every this many bases.
+const PROGRESS_EVERY: u64 = 10_000_000;
+
+/// Bases of a chromosome read before they are cut into k-mers.
+const BLOCK_LEN: usize = 1 << 20;
+/// Bases per slice of a block, cut into k-mers on one thread.
+const SLICE_LEN: usize = 1 << 16;
+/// K-mer shards, each sampled by one thread with its own RNG. Fixed, not
+/// the thread count, so the table is the same on any machine.
+const SHARDS: usize = 64;
+
+/// Seed of the RNG that samples each k-mer's coordinates. Fixed, so
+/// builds are reproducible.
+const SAMPLE_SEED: u64 = 0x6465_6e64_6563_0001;
+
+/// Table build options, set from CLI flags.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// Coordinate options kept per k-mer (`--max-coords`)
+    pub max_coords: u8,
+    /// Bases per k-mer (`--chunk-size`), MIN_CHUNK_SIZE to MAX_CHUNK_SIZE
+    pub chunk_size: usize,
+    /// Keep + strand coordinates only (`--forward-only`)
+    pub forward_only: bool,
+    /// Skip records that are not primary chromosomes (`--primary-only`)
+    pub primary_only: bool,
+    /// FASTA name to accession pairs (`--accession-map`)
+    pub accession_map: Option<PathBuf>,
+    /// BED files of regions no coordinate may overlap (`--exclude`)
+    pub exclude: Vec<PathBuf>,
+    /// Directory of per-chromosome parts to resume from (`--parts`)
+    pub parts: Option<PathBuf>,
+    /// Where to write a coverage report of the table (`--report`)
+    pub report: Option<PathBuf>,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            max_coords: DEFAULT_MAX_COORDS,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            forward_only: false,
+            primary_only: false,
+            accession_map: None,
+            exclude: Vec::new(),
+            parts: None,
+            report: None,
+        }
+    }
+}
+
+/// Build a table from `fastas` and write it atomically to `out`.
+pub fn build_table(fastas: &[PathBuf], out: &Path, opts: &BuildOptions, overwrite: OverwritePolicy) -> Result<()> {
+    check_output(out, overwrite)?;
+    let chunk_size = opts.chunk_size;
+
+    let mut builder = TableBuilder::new(opts.max_coords, chunk_size, opts.forward_only, opts.primary_only);
+    if let Some(path) = &opts.accession_map {
+        builder.rename(read_accession_map(path)?);
+    }
+    if !opts.exclude.is_empty() {
+        builder.exclude(read_mask(&opts.exclude)?);
+    }
+    if let Some(dir) = &opts.parts {
+        std::fs::create_dir_all(dir)?;
+        builder.parts = Some(dir.clone());
+    }
+    let table_size = builder.entry_count();
+    for path in fastas {
+        info!("Reading {}...", path.display());
+        builder.read_fasta(open_fasta(path)?)?;
+    }
+
+    // ── Coverage report ───────────────────────────────────────────────
+    if builder.renamed > 0 {
+        info!("  {} records renamed by the accession map", builder.renamed);
+    }
+    if builder.skipped > 0 {
+        info!("  {} records that are not primary chromosomes skipped", builder.skipped);
+    }
+    if builder.excluded > 0 {
+        info!("  {} bases excluded by --exclude regions", builder.excluded);
+    }
+    if let Some(dir) = &builder.parts {
+        if builder.resumed > 0 {
+            info!("  {} chromosomes resumed from parts already built", builder.resumed);
+        }
+        info!("Merging parts...");
+        let parts: Vec<PathBuf> = builder.accessions.iter().map(|a| part_path(dir, a)).collect();
+        return merge_parts(&parts, out, opts.report.as_deref(), overwrite);
+    }
+    report_coverage(builder.accessions.len(), builder.filled(), builder.saturated(), table_size, chunk_size);
+
+    // ── Write output ──────────────────────────────────────────────────
+    if let Some(backup) = prepare_output(out, overwrite)? {
+        info!("  Existing output moved to {}", backup.display());
+    }
+    let size = write_atomic_with(out, None, None, |w| builder.write(w))?;
+    info!("Written {}  ({:.1} KB)", out.display(), size as f64 / 1024.0);
+    if let Some(report) = &opts.report {
+        write_coverage_report(report, out, builder.max_coords, builder.forward_only, &builder.occurrences())?;
+    }
+    Ok(())
+}
+
+/// Print how many of the `table_size` k-mers a table of `chromosomes`
+/// covers, warning about any it misses.
+pub(super) fn report_coverage(chromosomes: usize, filled: usize, saturated: usize, table_size: usize, chunk_size: usize) {
+    info!(
+        "  {} chromosomes  filled {}/{}  saturated {}/{}",
+        chromosomes, filled, table_size, saturated, table_size
+    );
+    if filled < table_size {
+        warn!(
+            "  {} {}-mers have no coverage — refer fails on any .dna containing them",
+            table_size - filled,
+            chunk_size
+        );
+    }
+}
+
+/// Open a genome as FASTA text: plain, gzipped or bgzipped FASTA (a
+/// bgzip file is a series of gzip members), or UCSC .2bit. The format is
+/// told by magic bytes, not extension.
+pub(super) fn open_fasta(path: &Path) -> Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let sample = reader.fill_buf()?;
+    Ok(if sample.starts_with(&[0x1f, 0x8b]) {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else if is_twobit(sample) {
+        Box::new(TwoBitFasta::open(path)?)
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Read an accession map: per line, a FASTA record name and the
+/// accession to record for it, separated by a tab. Blank lines and lines
+/// starting with '#' are skipped; columns past the second are ignored.
+pub fn read_accession_map(path: &Path) -> Result<HashMap<String, String>> {
+    let mut names = HashMap::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut cols = line.split('\t').map(str::trim);
+        let (Some(name), Some(accession)) = (cols.next(), cols.next()) else {
+            return Err(DendecError::TableBuild(format!(
+                "{} line {}: expected a FASTA name and an accession, tab-separated",
+                path.display(),
+                i + 1
+            )));
+        };
+        if name.is_empty() || accession.is_empty() {
+            return Err(DendecError::TableBuild(format!("{} line {}: empty name", path.display(), i + 1)));
+        }
+        if names.insert(name.to_string(), accession.to_string()).is_some() {
+            return Err(DendecError::TableBuild(format!(
+                "{} line {}: '{}' is mapped twice",
+                path.display(),
+                i + 1,
+                name
+            )));
+        }
+    }
+    Ok(names)
+}
+
+/// Read BED files of regions to exclude, as sorted, merged [start, end)
+/// intervals by chromosome name. Blank, '#', track and browser lines are
+/// skipped; columns past the third are ignored.
+pub fn read_mask(paths: &[PathBuf]) -> Result<HashMap<String, Vec<(u64, u64)>>> {
+    let mut mask: HashMap<String, Vec<(u64, u64)>> = HashMap::new();
+    for path in paths {
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+                continue;
+            }
+            let mut cols = line.split('\t');
+            let (Some(chrom), Some(Ok(start)), Some(Ok(end))) = (
+                cols.next(),
+                cols.next().map(|c| c.trim().parse::<u64>()),
+                cols.next().map(|c| c.trim().parse::<u64>()),
+            ) else {
+                return Err(DendecError::TableBuild(format!(
+                    "{} line {}: expected a BED region: chromosome, start and end, tab-separated",
+                    path.display(),
+                    i + 1
+                )));
+            };
+            if start > end {
+                return Err(DendecError::TableBuild(format!(
+                    "{} line {}: region ends before it starts",
+                    path.display(),
+                    i + 1
+                )));
+            }
+            mask.entry(chrom.trim().to_string()).or_default().push((start, end));
+        }
+    }
+    for regions in mask.values_mut() {
+        regions.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(regions.len());
+        for &(start, end) in regions.iter() {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        *regions = merged;
+    }
+    Ok(mask)
+}
+
+// ── Builder ───────────────────────────────────────────────────────────
+
+/// Accumulates coordinates for all k-mers across any number of FASTA inputs.
+pub struct TableBuilder {
+    max_coords: usize,
+    chunk_size: usize,
+    /// Skip − strand coordinates.
+    forward_only: bool,
+    /// Skip records that are not primary chromosomes.
+    primary_only: bool,
+    /// Accession to record for a FASTA name, where it differs.
+    names: HashMap<String, String>,
+    accessions: Vec<String>,
+    /// K-mer idx is sampled by shard idx % SHARDS, as its entry
+    /// idx / SHARDS.
+    shards: Vec<Shard>,
+    /// Records left out by `primary_only`.
+    skipped: usize,
+    /// Records named by the accession map.
+    renamed: usize,
+    /// Regions to leave out, by chromosome name (see read_mask).
+    mask: HashMap<String, Vec<(u64, u64)>>,
+    /// Bases the mask left out.
+    excluded: u64,
+    /// Write each chromosome to a part in this directory, and skip those
+    /// with one already.
+    parts: Option<PathBuf>,
+    /// Records skipped because their part was there.
+    resumed: usize,
+}
+
+impl TableBuilder {
+    /// `chunk_size` must be within MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE; the
+    /// CLI checks it.
+    pub fn new(max_coords: u8, chunk_size: usize, forward_only: bool, primary_only: bool) -> Self {
+        TableBuilder {
+            max_coords: max_coords.max(1) as usize,
+            chunk_size,
+            forward_only,
+            primary_only,
+            names: HashMap::new(),
+            accessions: Vec::new(),
+            shards: new_shards(chunk_size),
+            skipped: 0,
+            renamed: 0,
+            mask: HashMap::new(),
+            excluded: 0,
+            parts: None,
+            resumed: 0,
+        }
+    }
+
+    /// Record the accession `names` maps a FASTA name to in its place.
+    pub fn rename(&mut self, names: HashMap<String, String>) {
+        self.names = names;
+    }
+
+    /// Keep no k-mer that overlaps a region of `mask`, from read_mask.
+    pub fn exclude(&mut self, mask: HashMap<String, Vec<(u64, u64)>>) {
+        self.mask = mask;
+    }
+
+    /// Count of k-mers, 4^k.
+    fn entry_count(&self) -> usize {
+        self.shards.iter().map(|s| s.entries.len()).sum()
+    }
+
+    /// Entries with at least one coordinate.
+    fn filled(&self) -> usize {
+        self.shards.iter().map(|s| s.filled).sum()
+    }
+
+    /// Entries with `max_coords` coordinates.
+    fn saturated(&self) -> usize {
+        self.shards.iter().map(|s| s.saturated).sum()
+    }
+
+    /// Occurrences of each k-mer so far, in k-mer index order.
+    fn occurrences(&self) -> Vec<u64> {
+        (0..self.entry_count()).map(|idx| self.shards[idx % SHARDS].seen[idx / SHARDS] as u64).collect()
+    }
+
+    /// Scan every record in a FASTA stream.
+    pub fn read_fasta(&mut self, mut reader: impl BufRead) -> Result<()> {
+        let mut line = Vec::new();
+        let mut record: Option<Record> = None;
+        let mut skipping = false;
+
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            if let Some(header) = line.strip_prefix(b">") {
+                if let Some(done) = record.as_mut() {
+                    self.end_record(done)?;
+                }
+                let header = String::from_utf8_lossy(header);
+                let name = header.split_whitespace().next().unwrap_or("");
+                let accession = match self.names.get(name) {
+                    Some(accession) => {
+                        self.renamed += 1;
+                        accession.clone()
+                    }
+                    None => name.to_string(),
+                };
+                skipping = self.primary_only && !is_primary(&accession);
+                record = match skipping {
+                    true => {
+                        self.skipped += 1;
+                        None
+                    }
+                    false => Some(self.start_record(&accession)?),
+                };
+                if let Some(r) = record.as_mut() {
+                    r.mask = self.mask.get(name).or_else(|| self.mask.get(&accession)).cloned().unwrap_or_default();
+                }
+                if record.is_some() && self.has_part(&accession)? {
+                    info!("    part already built, skipped");
+                    self.resumed += 1;
+                    skipping = true;
+                    record = None;
+                }
+                continue;
+            }
+            if skipping {
+                continue;
+            }
+            let Some(r) = record.as_mut() else {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return Err(DendecError::TableBuild(
+                    "sequence before the first '>' header — not a FASTA file?".to_string(),
+                ));
+            };
+            r.block.extend(line.iter().filter(|b| !b.is_ascii_whitespace()));
+            if r.block.len() - r.carry >= BLOCK_LEN {
+                self.flush(r);
+            }
+        }
+        if let Some(done) = record.as_mut() {
+            self.end_record(done)?;
+        }
+        Ok(())
+    }
+
+    /// Whether, building parts, `accession` has its part already. A part
+    /// that is damaged is built again; one built with other settings is
+    /// an error, as merging it would fail.
+    fn has_part(&self, accession: &str) -> Result<bool> {
+        let Some(path) = self.parts.as_ref().map(|dir| part_path(dir, accession)) else {
+            return Ok(false);
+        };
+        if !path.exists() {
+            return Ok(false);
+        }
+        let part = match Part::read(&path) {
+            Ok(part) => part,
+            Err(e) => {
+                info!("    {e}");
+                return Ok(false);
+            }
+        };
+        let wanted = Part { accession: accession.to_string(), entries: Vec::new(), ..self.part_settings() };
+        if part.accession != accession {
+            return Err(DendecError::TableBuild(format!(
+                "{} holds '{}', not '{}' — keep parts of differently named chromosomes in separate --parts directories",
+                path.display(),
+                part.accession,
+                accession
+            )));
+        }
+        if !part.same_settings(&wanted) {
+            return Err(DendecError::TableBuild(format!(
+                "{} was built with {}, not {} — use another --parts directory",
+                path.display(),
+                part.settings(),
+                wanted.settings()
+            )));
+        }
+        Ok(true)
+    }
+
+    /// An empty part with this build's settings.
+    fn part_settings(&self) -> Part {
+        Part {
+            accession: String::new(),
+            chunk_size: self.chunk_size,
+            max_coords: self.max_coords,
+            forward_only: self.forward_only,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Sample the rest of `r`. Building parts, write its part and start
+    /// the next chromosome with empty entries.
+    fn end_record(&mut self, r: &mut Record) -> Result<()> {
+        self.flush(r);
+        let Some(dir) = &self.parts else {
+            return Ok(());
+        };
+        let accession = &self.accessions[r.chrom_idx as usize];
+        let path = part_path(dir, accession);
+        let shards = std::mem::replace(&mut self.shards, new_shards(self.chunk_size));
+        let mut part = Part { accession: accession.clone(), ..self.part_settings() };
+        for idx in 0..shards.len() * shards[0].entries.len() {
+            let (shard, i) = (&shards[idx % SHARDS], idx / SHARDS);
+            if shard.seen[i] > 0 {
+                part.entries.push((idx as u32, shard.seen[i], shard.entries[i].clone()));
+            }
+        }
+        write_atomic_with(&path, None, None, |w| part.write(w))?;
+        info!("    part written to {}", path.display());
+        Ok(())
+    }
+
+    /// Register a new chromosome and return an empty record for it.
+    fn start_record(&mut self, accession: &str) -> Result<Record> {
+        if accession.is_empty() || accession.len() > u8::MAX as usize {
+            return Err(DendecError::TableBuild(format!(
+                "FASTA header name must be 1–255 bytes, got '{}'",
+                accession
+            )));
+        }
+        if self.accessions.iter().any(|a| a == accession) {
+            return Err(DendecError::TableBuild(format!(
+                "chromosome '{}' appears more than once",
+                accession
+            )));
+        }
+        if self.accessions.len() >= u16::MAX as usize {
+            return Err(DendecError::TableBuild(format!(
+                "too many chromosomes — a table holds at most {}; \
+                 --primary-only skips scaffolds, alternate loci and patches",
+                u16::MAX
+            )));
+        }
+        info!("  Processing {}...", accession);
+        self.accessions.push(accession.to_string());
+        Ok(Record {
+            chrom_idx: (self.accessions.len() - 1) as u16,
+            pos: 0,
+            carry: 0,
+            block: Vec::with_capacity(BLOCK_LEN + MAX_CHUNK_SIZE),
+            mask: Vec::new(),
+        })
+    }
+
+    /// Sample every k-mer ending in the bases `r` holds past its carry,
+    /// then keep the last k − 1 bases as the next block's carry.
+    ///
+    /// Slices of the block are cut into k-mers on all threads, and each
+    /// k-mer shard then takes its own k-mers from every slice in genome
+    /// order, also in parallel. Neither step depends on the thread count.
+    fn flush(&mut self, r: &mut Record) {
+        self.excluded += r.apply_mask();
+        let k = self.chunk_size;
+        let origin = r.pos - r.carry as u64;
+        let block = &r.block;
+        let ends: Vec<usize> = (k - 1..block.len()).step_by(SLICE_LEN).collect();
+        let forward_only = self.forward_only;
+        let slices: Vec<Vec<Vec<(u32, Coord)>>> = ends
+            .par_iter()
+            .map(|&first| {
+                let last = (first + SLICE_LEN).min(block.len());
+                cut_kmers(&block[first + 1 - k..last], k, r.chrom_idx, origin + (first + 1 - k) as u64, forward_only)
+            })
+            .collect();
+        let max_coords = self.max_coords;
+        self.shards.par_iter_mut().enumerate().for_each(|(s, shard)| {
+            for slice in &slices {
+                for &(idx, ref coord) in &slice[s] {
+                    shard.record(idx as usize / SHARDS, coord.clone(), max_coords);
+                }
+            }
+        });
+
+        let before = r.pos;
+        r.pos += (r.block.len() - r.carry) as u64;
+        if before / PROGRESS_EVERY != r.pos / PROGRESS_EVERY {
+            info!(
+                "  position {:>12}  filled {}/{}  saturated {}/{}",
+                r.pos,
+                self.filled(),
+                self.entry_count(),
+                self.saturated(),
+                self.entry_count()
+            );
+        }
+        r.carry = r.block.len().min(k - 1);
+        r.block.drain(..r.block.len() - r.carry);
+    }
+
+    /// Serialise in the table.rs binary format, checksum trailer
+    /// included. Returns the byte count.
+    pub fn write(&self, w: &mut impl Write) -> Result<u64> {
+        let entries: Vec<&[Coord]> = (0..self.entry_count())
+            .map(|idx| self.shards[idx % SHARDS].entries[idx / SHARDS].as_slice())
+            .collect();
+        write_table(w, self.chunk_size, &self.accessions, &entries)
+    }
+}
+
+/// The k-mers of `bases`, a stretch of chromosome `chrom_idx` starting
+/// at `start`, each as its index and coordinate, bucketed by shard. Both
+/// strands unless `forward_only`; N and any other symbol break the
+/// window.
+fn cut_kmers(bases: &[u8], k: usize, chrom_idx: u16, start: u64, forward_only: bool) -> Vec<Vec<(u32, Coord)>> {
+    let mut shards = vec![Vec::new(); SHARDS];
+    let mask = (1u32 << (2 * k)) - 1;
+    let (mut forward, mut reverse, mut run) = (0u32, 0u32, 0usize);
+    for (i, &base) in bases.iter().enumerate() {
+        // Same fixed mapping as table.rs: A=0, T=1, G=2, C=3
+        let code = match base.to_ascii_uppercase() {
+            b'A' => 0u32,
+            b'T' => 1,
+            b'G' => 2,
+            b'C' => 3,
+            _ => {
+                run = 0;
+                continue;
+            }
+        };
+        forward = ((forward << 2) | code) & mask;
+        // Complement is code ^ 1 (A↔T, G↔C); it enters at the front
+        reverse = (reverse >> 2) | ((code ^ 1) << (2 * (k - 1)));
+        run += 1;
+        if run < k {
+            continue;
+        }
+        let start = start + (i + 1 - k) as u64;
+        shards[forward as usize % SHARDS].push((forward, Coord { chrom_idx, start, strand: 0 }));
+        if !forward_only {
+            shards[reverse as usize % SHARDS].push((reverse, Coord { chrom_idx, start, strand: 1 }));
+        }
+    }
+    shards
+}
+
+/// Empty shards for a table of `chunk_size`-mers, each with its RNG
+/// freshly seeded.
+fn new_shards(chunk_size: usize) -> Vec<Shard> {
+    let per_shard = (1 << (2 * chunk_size)) / SHARDS;
+    (0..SHARDS)
+        .map(|s| Shard {
+            entries: vec![Vec::new(); per_shard],
+            seen: vec![0; per_shard],
+            rng: StdRng::seed_from_u64(SAMPLE_SEED ^ s as u64),
+            filled: 0,
+            saturated: 0,
+        })
+        .collect()
+}
+
+/// The entries of the k-mers one shard samples.
+struct Shard {
+    entries: Vec<Vec<Coord>>,
+    /// Occurrences of each k-mer so far, kept or not.
+    seen: Vec<u32>,
+    rng: StdRng,
+    filled: usize,
+    saturated: usize,
+}
+
+impl Shard {
+    /// Offer a coordinate to entry `i`. Past `max_coords`, the n-th
+    /// occurrence replaces a kept one with probability max_coords/n,
+    /// which keeps every occurrence equally likely to be in the sample.
+    fn record(&mut self, i: usize, coord: Coord, max_coords: usize) {
+        let seen = &mut self.seen[i];
+        *seen = seen.saturating_add(1);
+        let entry = &mut self.entries[i];
+        if entry.len() >= max_coords {
+            let slot = self.rng.gen_range(0..*seen as usize);
+            if let Some(kept) = entry.get_mut(slot) {
+                *kept = coord;
+            }
+            return;
+        }
+        entry.push(coord);
+        if entry.len() == 1 {
+            self.filled += 1;
+        }
+        if entry.len() == max_coords {
+            self.saturated += 1;
+        }
+    }
+}
+
+/// Whether a FASTA record is an assembled chromosome rather than a
+/// scaffold, alternate locus or patch: a RefSeq NC_ accession, or a
+/// chromosome number, X, Y, M or MT, with or without a chr prefix. Yeast
+/// numbers its chromosomes in Roman numerals, chrI to chrXVI.
+fn is_primary(accession: &str) -> bool {
+    let name = accession.strip_prefix("chr").unwrap_or(accession);
+    accession.starts_with("NC_")
+        || matches!(name, "X" | "Y" | "M" | "MT")
+        || (!name.is_empty() && name.len() <= 2 && name.bytes().all(|b| b.is_ascii_digit()))
+        || (!name.is_empty() && name.len() <= 5 && name.bytes().all(|b| b"IVX".contains(&b)))
+}
+
+/// The chromosome being read: bases buffered since the last flush,
+/// after the carry of the k − 1 before them.
+struct Record {
+    chrom_idx: u16,
+    /// Bases flushed so far, including N.
+    pos: u64,
+    /// Bases at the front of `block` already flushed.
+    carry: usize,
+    block: Vec<u8>,
+    /// Regions of this chromosome to leave out, sorted and merged.
+    mask: Vec<(u64, u64)>,
+}
+
+impl Record {
+    /// Overwrite the bases past the carry that fall in a masked region
+    /// with N, which breaks every window over them. Returns how many.
+    fn apply_mask(&mut self) -> u64 {
+        let (from, to) = (self.pos, self.pos + (self.block.len() - self.carry) as u64);
+        let first = self.mask.partition_point(|&(_, end)| end <= from);
+        let mut masked = 0;
+        for &(start, end) in self.mask[first..].iter().take_while(|&&(start, _)| start < to) {
+            let (start, end) = (start.max(from), end.min(to));
+            let at = |p: u64| self.carry + (p - from) as usize;
+            self.block[at(start)..at(end)].fill(b'N');
+            masked += end - start;
+        }
+        masked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::reverse::reverse_complement_seq;
+    use crate::refer::table::{CoordKey, ReferTable};
+    use flate2::write::GzEncoder;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_build_covers_every_kmer() {
+        // Every 8-mer once, separated by N so no windows span two of them
+        let mut seq = Vec::new();
+        for idx in 0..65_536 {
+            seq.extend_from_slice(&ReferTable::index_to_kmer(idx, 8));
+            seq.push(b'N');
+        }
+        let (first, second) = seq.split_at(seq.len() / 2);
+        let fasta = |name: &str, seq: &[u8]| {
+            let mut text = format!(">{name} test chromosome\n").into_bytes();
+            for line in seq.chunks(60) {
+                text.extend_from_slice(&line.to_ascii_lowercase());
+                text.push(b'\n');
+            }
+            text
+        };
+
+        let dir = tempdir().unwrap();
+        let plain = dir.path().join("chrA.fa");
+        std::fs::write(&plain, fasta("chrA", first)).unwrap();
+        let gz = dir.path().join("chrB.fa.gz");
+        let mut enc = GzEncoder::new(File::create(&gz).unwrap(), Default::default());
+        enc.write_all(&fasta("chrB", second)).unwrap();
+        enc.finish().unwrap();
+
+        let out = dir.path().join("custom.bin");
+        let opts = BuildOptions { max_coords: 2, ..Default::default() };
+        build_table(&[plain, gz], &out, &opts, OverwritePolicy::Refuse).unwrap();
+
+        let bytes = std::fs::read(&out).unwrap();
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+        assert_eq!(table.accessions, ["chrA", "chrB"]);
+        for idx in (0..65_536).step_by(97) {
+            let kmer = ReferTable::index_to_kmer(idx, 8);
+            let coord = table.lookup(&kmer, &mut rand::thread_rng()).expect("8-mer not covered");
+            assert_eq!(table.reverse_lookup(&CoordKey::from(&coord)), Some(kmer));
+        }
+
+        // Forward hit for AAAAAAAA is the very first window of chrA
+        let key = CoordKey { chrom_idx: 0, start: 0, strand: 0 };
+        assert_eq!(&table.reverse_lookup(&key).unwrap()[..], b"AAAAAAAA");
+        // …whose reverse complement TTTTTTTT is recorded at the same start
+        let key = CoordKey { chrom_idx: 0, start: 0, strand: 1 };
+        assert_eq!(&table.reverse_lookup(&key).unwrap()[..], b"TTTTTTTT");
+    }
+
+    #[test]
+    fn test_bgzip_and_twobit_inputs() {
+        use crate::refer::twobit::tests::twobit;
+        let seq: Vec<u8> = b"ACGTTGCAacgtNNNNGGATCCTA".repeat(40);
+        let dir = tempdir().unwrap();
+        let build = |name: &str, bytes: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            let out = dir.path().join(format!("{name}.bin"));
+            build_table(&[path], &out, &BuildOptions::default(), OverwritePolicy::Refuse).unwrap();
+            std::fs::read(out).unwrap()
+        };
+
+        let fasta = [b">chrA\n".as_slice(), &seq, b"\n"].concat();
+        let plain = build("chrA.fa", &fasta);
+        // bgzip writes a series of gzip members
+        let mut bgzip = Vec::new();
+        for part in fasta.chunks(100) {
+            let mut enc = GzEncoder::new(Vec::new(), Default::default());
+            enc.write_all(part).unwrap();
+            bgzip.extend(enc.finish().unwrap());
+        }
+        assert_eq!(build("chrA.fa.bgz", &bgzip), plain);
+        assert_eq!(build("chrA.2bit", &twobit(&[("chrA", &seq)], false)), plain);
+    }
+
+    #[test]
+    fn test_build_larger_chunk_size() {
+        // Random bases cover only part of 4^10, but every 10-mer looked up
+        // below is taken from the sequence itself
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(10);
+        let seq: Vec<u8> = (0..200_000).map(|_| b"ATGC"[rng.gen_range(0..4)]).collect();
+        let mut builder = TableBuilder::new(2, 10, false, false);
+        builder.read_fasta(&[b">chrA\n".as_slice(), &seq, b"\n"].concat()[..]).unwrap();
+        let mut bytes = Vec::new();
+        builder.write(&mut bytes).unwrap();
+
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+        table.validate().unwrap();
+        assert_eq!((table.chunk_size(), table.entry_count()), (10, 1 << 20));
+        let kmer = &seq[1234..1244];
+        let coord = table.lookup(kmer, &mut rng).expect("10-mer not covered");
+        assert_eq!(&table.reverse_lookup(&CoordKey::from(&coord)).unwrap()[..], kmer);
+        // An 8-mer is not a key of a 10-mer table
+        assert!(table.lookup(&kmer[..8], &mut rng).is_none());
+        // Both strands of the first window
+        let key = CoordKey { chrom_idx: 0, start: 0, strand: 1 };
+        assert_eq!(&table.reverse_lookup(&key).unwrap()[..], &*reverse_complement_seq(&seq[..10]));
+    }
+
+    #[test]
+    fn test_coordinates_sampled_across_chromosomes() {
+        // The same 8-mers on four chromosomes: the first-found would all
+        // be on chr1
+        let seq = b"ACGTTGCAACGTTGCAACGTTGCA".repeat(50);
+        let fasta: Vec<u8> = ["chr1", "chr2", "chr3", "chrX"]
+            .iter()
+            .flat_map(|name| [format!(">{name}\n").into_bytes(), seq.clone(), b"\n".to_vec()].concat())
+            .collect();
+        let build = || {
+            let mut builder = TableBuilder::new(16, 8, false, false);
+            builder.read_fasta(&fasta[..]).unwrap();
+            let mut bytes = Vec::new();
+            builder.write(&mut bytes).unwrap();
+            bytes
+        };
+        let bytes = build();
+        assert_eq!(bytes, build(), "same inputs, same table");
+
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+        let options = table.options(b"ACGTTGCA", false);
+        assert_eq!(options.len(), 16);
+        let chroms: std::collections::HashSet<u16> = options.iter().map(|c| c.chrom_idx).collect();
+        assert!(chroms.len() >= 3, "{:?}", chroms);
+    }
+
+    #[test]
+    fn test_blocks_keep_every_kmer_once() {
+        // Past one block, in uneven lines, with N runs across the block
+        // and slice boundaries: 10-mers occur about once, so every window
+        // is kept and each must be where it was found
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(40);
+        let mut seq: Vec<u8> = (0..BLOCK_LEN + 70_001).map(|_| b"ATGC"[rng.gen_range(0..4)]).collect();
+        seq[BLOCK_LEN - 3..BLOCK_LEN + 2].fill(b'N');
+        seq[SLICE_LEN * 3 + 4] = b'n';
+        let mut fasta = b">chrA\n".to_vec();
+        for line in seq.chunks(61) {
+            fasta.extend_from_slice(line);
+            fasta.push(b'\n');
+        }
+        let build = || {
+            let mut builder = TableBuilder::new(255, 10, false, false);
+            builder.read_fasta(&fasta[..]).unwrap();
+            let mut bytes = Vec::new();
+            builder.write(&mut bytes).unwrap();
+            bytes
+        };
+        let bytes = build();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        assert_eq!(pool.install(build), bytes, "table depends on the thread count");
+
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+        let windows: Vec<usize> = (0..=seq.len() - 10)
+            .filter(|&i| seq[i..i + 10].iter().all(|b| b"ATGC".contains(b)))
+            .collect();
+        assert_eq!(table.coord_count(), 2 * windows.len());
+        for &i in windows.iter().filter(|&&i| i % 997 == 0 || (i as i64 - BLOCK_LEN as i64).abs() < 20) {
+            let key = CoordKey { chrom_idx: 0, start: i as u64, strand: 0 };
+            assert_eq!(&table.reverse_lookup(&key).unwrap()[..], &seq[i..i + 10], "window at {i}");
+        }
+    }
+
+    #[test]
+    fn test_primary_only_skips_scaffolds() {
+        let fasta = b">NC_000001.11 chromosome 1\nACGTACGTAC\n>NT_187361.1 unlocalized scaffold\nACGTACGTAC\n\
+                      >chr1_KI270706v1_random\nACGT\n>chrM\nACGTACGTAC\n>chrUn_GL000220v1\nACGT\n>MT\nACGTACGTAC\n";
+        let mut builder = TableBuilder::new(4, 8, false, true);
+        builder.read_fasta(&fasta[..]).unwrap();
+        assert_eq!(builder.accessions, ["NC_000001.11", "chrM", "MT"]);
+        assert_eq!(builder.skipped, 3);
+        assert!(!is_primary("chr23_alt") && is_primary("chr22") && is_primary("7"));
+        assert!(is_primary("chrXIV") && is_primary("chrM") && !is_primary("chrIVa"));
+    }
+
+    #[test]
+    fn test_accession_map_renames_records() {
+        let dir = tempdir().unwrap();
+        let map = dir.path().join("names.tsv");
+        std::fs::write(&map, "# UCSC\tRefSeq\nchr1\tNC_000001.11\textra column\n\nchrM\tNC_012920.1\n").unwrap();
+        let names = read_accession_map(&map).unwrap();
+        assert_eq!(names.len(), 2);
+
+        let mut builder = TableBuilder::new(4, 8, false, true);
+        builder.rename(names);
+        builder.read_fasta(&b">chr1\nACGTACGTAC\n>chrUn_GL000220v1\nACGT\n>chrM\nACGTACGTAC\n"[..]).unwrap();
+        assert_eq!(builder.accessions, ["NC_000001.11", "NC_012920.1"]);
+        assert_eq!((builder.renamed, builder.skipped), (2, 1));
+
+        for bad in ["chr1 NC_000001.11\n", "chr1\tNC_000001.11\nchr1\tNC_000002.12\n", "chr1\t\n"] {
+            std::fs::write(&map, bad).unwrap();
+            assert!(read_accession_map(&map).is_err(), "accepted {bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_exclude_masks_regions() {
+        // Random 10-mers occur about once each; the mask covers a stretch
+        // across the first block boundary and one named by accession
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(47);
+        let seq: Vec<u8> = (0..BLOCK_LEN + 5_000).map(|_| b"ATGC"[rng.gen_range(0..4)]).collect();
+        let dir = tempdir().unwrap();
+        let bed = dir.path().join("gaps.bed");
+        let regions = format!(
+            "track name=gaps\n#chrom\tstart\tend\nchr1\t100\t200\tgap\nchr1\t150\t300\n\
+             chr1\t{}\t{}\nNC_000002.12\t0\t50\n",
+            BLOCK_LEN - 10,
+            BLOCK_LEN + 10
+        );
+        std::fs::write(&bed, regions).unwrap();
+        let mask = read_mask(std::slice::from_ref(&bed)).unwrap();
+        assert_eq!(mask["chr1"], [(100, 300), (BLOCK_LEN as u64 - 10, BLOCK_LEN as u64 + 10)]);
+
+        let mut builder = TableBuilder::new(255, 10, true, false);
+        builder.rename(HashMap::from([("chr2".to_string(), "NC_000002.12".to_string())]));
+        builder.exclude(mask);
+        let fasta = [b">chr1\n".as_slice(), &seq, b"\n>chr2\n", &seq[..1000], b"\n"].concat();
+        builder.read_fasta(&fasta[..]).unwrap();
+        assert_eq!(builder.excluded, 200 + 20 + 50);
+        let mut bytes = Vec::new();
+        builder.write(&mut bytes).unwrap();
+
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+        let overlaps = |c: &Coord, (start, end): (u64, u64)| c.start < end && c.start + 10 > start;
+        let coords: Vec<Coord> = table.entries().flat_map(|e| e.iter()).collect();
+        for coord in &coords {
+            let regions: &[(u64, u64)] = match coord.chrom_idx {
+                0 => &[(100, 300), (BLOCK_LEN as u64 - 10, BLOCK_LEN as u64 + 10)],
+                _ => &[(0, 50)],
+            };
+            assert!(!regions.iter().any(|&r| overlaps(coord, r)), "{coord:?}");
+        }
+        // Up to the edges, windows are still kept
+        for (chrom_idx, start) in [(0, 90), (0, 300), (0, BLOCK_LEN as u64 + 10), (1, 50)] {
+            assert!(coords.iter().any(|c| c.chrom_idx == chrom_idx && c.start == start), "{chrom_idx}:{start}");
+        }
+
+        std::fs::write(&bed, "chr1\t300\t100\n").unwrap();
+        assert!(read_mask(&[bed]).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_chromosome_rejected() {
+        let mut builder = TableBuilder::new(DEFAULT_MAX_COORDS, 8, false, false);
+        let err = builder
+            .read_fasta(&b">chr1\nACGTACGTAC\n>chr1\nACGT\n"[..])
+            .unwrap_err();
+        assert!(err.to_string().contains("more than once"));
+        assert!(TableBuilder::new(1, 8, false, false).read_fasta(&b"ACGT\n"[..]).is_err());
+    }
+}
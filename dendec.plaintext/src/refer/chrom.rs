@@ -0,0 +1,218 @@
+/// refer/chrom.rs — RefSeq and UCSC chromosome names
+///
+/// The embedded tables name chromosomes by RefSeq accession
+/// (NC_000001.11), but much of the genomics ecosystem uses UCSC names
+/// (chr1). Refer writes either, picked with `--chrom-style`; unrefer
+/// accepts both, mapping a name its table does not list to the other
+/// style's name for the same chromosome.
+///
+/// Only primary chromosomes have a counterpart here. Any other name —
+/// a scaffold, or a chromosome of a custom table — is written and read
+/// as it is.
+use std::fmt;
+use std::str::FromStr;
+
+use super::assembly::Assembly;
+
+/// How refer names chromosomes in its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChromStyle {
+    /// RefSeq accessions, as the tables list them: NC_000001.11
+    #[default]
+    Refseq,
+    /// UCSC names: chr1
+    Ucsc,
+}
+
+impl ChromStyle {
+    pub const ALL: [ChromStyle; 2] = [ChromStyle::Refseq, ChromStyle::Ucsc];
+
+    /// Name as given to `--chrom-style`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ChromStyle::Refseq => "refseq",
+            ChromStyle::Ucsc => "ucsc",
+        }
+    }
+
+    /// What this style calls the chromosome a table lists as `name`, or
+    /// `name` itself when it has no counterpart or is already in style.
+    pub fn apply(self, assembly: Assembly, name: &str) -> &str {
+        let found = names(assembly).iter().find(|(refseq, ucsc)| match self {
+            ChromStyle::Refseq => *ucsc == name,
+            ChromStyle::Ucsc => *refseq == name,
+        });
+        match (self, found) {
+            (ChromStyle::Refseq, Some((refseq, _))) => refseq,
+            (ChromStyle::Ucsc, Some((_, ucsc))) => ucsc,
+            (_, None) => name,
+        }
+    }
+}
+
+impl fmt::Display for ChromStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ChromStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        ChromStyle::ALL
+            .into_iter()
+            .find(|c| c.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown chromosome style '{s}' (expected refseq or ucsc)"))
+    }
+}
+
+/// The other style's name for the chromosome `name` in `assembly`:
+/// chr1 for NC_000001.11 in hg38, and back.
+pub fn counterpart(assembly: Assembly, name: &str) -> Option<&'static str> {
+    names(assembly).iter().find_map(|&(refseq, ucsc)| match name {
+        n if n == refseq => Some(ucsc),
+        n if n == ucsc => Some(refseq),
+        _ => None,
+    })
+}
+
+/// Primary chromosomes of `assembly` as (RefSeq accession, UCSC name).
+fn names(assembly: Assembly) -> &'static [(&'static str, &'static str)] {
+    match assembly {
+        Assembly::Hg19 => HG19,
+        Assembly::Hg38 => HG38,
+        Assembly::Mm39 => MM39,
+        Assembly::SacCer3 => SACCER3,
+        Assembly::Ecoli => ECOLI,
+    }
+}
+
+// UCSC's hg19 chrM is the older NC_001807.4 sequence, not GRCh37's
+// NC_012920.1, so the two are left unmapped there
+const HG19: &[(&str, &str)] = &[
+    ("NC_000001.10", "chr1"),
+    ("NC_000002.11", "chr2"),
+    ("NC_000003.11", "chr3"),
+    ("NC_000004.11", "chr4"),
+    ("NC_000005.9", "chr5"),
+    ("NC_000006.11", "chr6"),
+    ("NC_000007.13", "chr7"),
+    ("NC_000008.10", "chr8"),
+    ("NC_000009.11", "chr9"),
+    ("NC_000010.10", "chr10"),
+    ("NC_000011.9", "chr11"),
+    ("NC_000012.11", "chr12"),
+    ("NC_000013.10", "chr13"),
+    ("NC_000014.8", "chr14"),
+    ("NC_000015.9", "chr15"),
+    ("NC_000016.9", "chr16"),
+    ("NC_000017.10", "chr17"),
+    ("NC_000018.9", "chr18"),
+    ("NC_000019.9", "chr19"),
+    ("NC_000020.10", "chr20"),
+    ("NC_000021.8", "chr21"),
+    ("NC_000022.10", "chr22"),
+    ("NC_000023.10", "chrX"),
+    ("NC_000024.9", "chrY"),
+];
+
+const HG38: &[(&str, &str)] = &[
+    ("NC_000001.11", "chr1"),
+    ("NC_000002.12", "chr2"),
+    ("NC_000003.12", "chr3"),
+    ("NC_000004.12", "chr4"),
+    ("NC_000005.10", "chr5"),
+    ("NC_000006.12", "chr6"),
+    ("NC_000007.14", "chr7"),
+    ("NC_000008.11", "chr8"),
+    ("NC_000009.12", "chr9"),
+    ("NC_000010.11", "chr10"),
+    ("NC_000011.10", "chr11"),
+    ("NC_000012.12", "chr12"),
+    ("NC_000013.11", "chr13"),
+    ("NC_000014.9", "chr14"),
+    ("NC_000015.10", "chr15"),
+    ("NC_000016.10", "chr16"),
+    ("NC_000017.11", "chr17"),
+    ("NC_000018.10", "chr18"),
+    ("NC_000019.10", "chr19"),
+    ("NC_000020.11", "chr20"),
+    ("NC_000021.9", "chr21"),
+    ("NC_000022.11", "chr22"),
+    ("NC_000023.11", "chrX"),
+    ("NC_000024.10", "chrY"),
+    ("NC_012920.1", "chrM"),
+];
+
+const MM39: &[(&str, &str)] = &[
+    ("NC_000067.7", "chr1"),
+    ("NC_000068.8", "chr2"),
+    ("NC_000069.7", "chr3"),
+    ("NC_000070.7", "chr4"),
+    ("NC_000071.7", "chr5"),
+    ("NC_000072.7", "chr6"),
+    ("NC_000073.7", "chr7"),
+    ("NC_000074.7", "chr8"),
+    ("NC_000075.7", "chr9"),
+    ("NC_000076.7", "chr10"),
+    ("NC_000077.7", "chr11"),
+    ("NC_000078.7", "chr12"),
+    ("NC_000079.7", "chr13"),
+    ("NC_000080.7", "chr14"),
+    ("NC_000081.7", "chr15"),
+    ("NC_000082.7", "chr16"),
+    ("NC_000083.7", "chr17"),
+    ("NC_000084.7", "chr18"),
+    ("NC_000085.7", "chr19"),
+    ("NC_000086.8", "chrX"),
+    ("NC_000087.8", "chrY"),
+    ("NC_005089.1", "chrM"),
+];
+
+const SACCER3: &[(&str, &str)] = &[
+    ("NC_001133.9", "chrI"),
+    ("NC_001134.8", "chrII"),
+    ("NC_001135.5", "chrIII"),
+    ("NC_001136.10", "chrIV"),
+    ("NC_001137.3", "chrV"),
+    ("NC_001138.5", "chrVI"),
+    ("NC_001139.9", "chrVII"),
+    ("NC_001140.6", "chrVIII"),
+    ("NC_001141.2", "chrIX"),
+    ("NC_001142.9", "chrX"),
+    ("NC_001143.9", "chrXI"),
+    ("NC_001144.5", "chrXII"),
+    ("NC_001145.3", "chrXIII"),
+    ("NC_001146.8", "chrXIV"),
+    ("NC_001147.6", "chrXV"),
+    ("NC_001148.4", "chrXVI"),
+    ("NC_001224.1", "chrM"),
+];
+
+// One chromosome, and no UCSC assembly to name it
+const ECOLI: &[(&str, &str)] = &[];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styles_and_counterparts() {
+        assert_eq!(ChromStyle::Ucsc.apply(Assembly::Hg38, "NC_000023.11"), "chrX");
+        assert_eq!(ChromStyle::Refseq.apply(Assembly::Hg38, "chrX"), "NC_000023.11");
+        assert_eq!(ChromStyle::Refseq.apply(Assembly::Hg38, "NC_000023.11"), "NC_000023.11");
+        assert_eq!(ChromStyle::Ucsc.apply(Assembly::Hg38, "NT_187361.1"), "NT_187361.1");
+        assert_eq!(ChromStyle::Ucsc.apply(Assembly::Mm39, "NC_000067.7"), "chr1");
+
+        assert_eq!(counterpart(Assembly::Hg38, "chr7"), Some("NC_000007.14"));
+        assert_eq!(counterpart(Assembly::Hg19, "NC_000007.13"), Some("chr7"));
+        assert_eq!(counterpart(Assembly::Hg19, "chrM"), None);
+        assert_eq!(counterpart(Assembly::Hg38, "chr23"), None);
+        assert_eq!(counterpart(Assembly::SacCer3, "chrXVI"), Some("NC_001148.4"));
+        assert_eq!(ChromStyle::Ucsc.apply(Assembly::Ecoli, "NC_000913.3"), "NC_000913.3");
+
+        assert_eq!("UCSC".parse::<ChromStyle>(), Ok(ChromStyle::Ucsc));
+        assert!("ensembl".parse::<ChromStyle>().is_err());
+    }
+}
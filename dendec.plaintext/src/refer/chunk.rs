@@ -1,57 +1,169 @@
-/// refer/chunk.rs — 8-mer splitting and reassembly
+/// refer/chunk.rs — Chunk splitting and reassembly
 ///
-/// Every valid dendec DNA string is a multiple of 4 bytes (4 bases per
-/// encrypted byte). Since 8 is a multiple of 4, every valid dendec output
-/// is automatically refer-compatible — no padding, no remainder, no edge cases.
+/// Refer cuts the DNA string into successive chunks of the table's chunk
+/// size, 8 bases unless the table was built otherwise (see table.rs).
+/// Every valid dendec DNA string is a multiple of 4 bases (4 per
+/// encrypted byte), so with 8-mers only a payload of an odd byte count
+/// ends on half a chunk, and with 10- or 12-mers most do. That last
+/// chunk is read short; refer completes it with filler (Kmer::padded)
+/// and the header's dna_length tells unrefer where to cut it off.
 ///
-/// This module is pure: no I/O, no network, no crypto. Fully testable
-/// in isolation.
+/// The DNA need not come from dendec encode. Any A/T/G/C sequence can be
+/// referred, plain or as FASTA: `>` header lines are skipped, records
+/// are joined into one sequence, and soft-masked (lowercase) bases are
+/// read as uppercase. Unrefer gives back that bare uppercase sequence.
+///
+/// This module is pure: no files, no network, no crypto. Chunks are read
+/// from any BufRead, so it is fully testable in isolation on byte slices.
+use std::io::BufRead;
+use std::ops::{Deref, DerefMut};
 
 use crate::error::{DendecError, Result};
 
-pub const KMER_LEN: usize = 8;
+/// Chunk size of every table before version 4, and of `table build`
+/// unless `--chunk-size` says otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 8;
+/// Chunk sizes a table may have. A table holds an offset for each of the
+/// 4^k possible chunks, which is 64 MB at the largest size.
+pub const MIN_CHUNK_SIZE: usize = 8;
+pub const MAX_CHUNK_SIZE: usize = 12;
 
-/// Split a flat DNA byte slice into successive 8-mer arrays.
-///
-/// Validates two invariants before returning:
-///   1. Length is a multiple of 8.
-///   2. Every character is A, T, G, or C.
+/// Base a short final chunk is completed with.
+const FILLER: u8 = b'A';
+
+/// One chunk of bases, held inline so chunks stay `Copy` whatever the
+/// table's chunk size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Kmer {
+    bases: [u8; MAX_CHUNK_SIZE],
+    len: u8,
+}
+
+impl Kmer {
+    /// Copy `bases`, at most MAX_CHUNK_SIZE of them.
+    pub fn new(bases: &[u8]) -> Self {
+        let mut kmer = Kmer { bases: [0; MAX_CHUNK_SIZE], len: bases.len() as u8 };
+        kmer.bases[..bases.len()].copy_from_slice(bases);
+        kmer
+    }
+
+    /// This chunk completed to `size` bases with filler, if it is short.
+    pub fn padded(mut self, size: usize) -> Self {
+        let len = self.len as usize;
+        if len < size {
+            self.bases[len..size].fill(FILLER);
+            self.len = size as u8;
+        }
+        self
+    }
+}
+
+impl Deref for Kmer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bases[..self.len as usize]
+    }
+}
+
+impl DerefMut for Kmer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bases[..self.len as usize]
+    }
+}
+
+/// Successive chunks of `size` bases read from a DNA stream, one buffer
+/// at a time; the last may be shorter.
 ///
-/// Any violation returns `ReferInvalidBases` with the position of the
-/// first offending byte.
-pub fn split_into_kmers(dna: &[u8]) -> Result<Vec<[u8; KMER_LEN]>> {
-    if dna.len() % KMER_LEN != 0 {
-        return Err(DendecError::ReferInvalidBases { position: dna.len() });
+/// Whitespace (grouping spaces, newlines) is skipped, the same defensive
+/// strip dendec decode applies, and so are FASTA `>` header lines;
+/// positions count bases only. Lowercase bases are uppercased. Any
+/// other character yields `ReferInvalidBases` with its position, after
+/// which the iterator ends.
+pub struct KmerReader<R> {
+    reader: R,
+    size: usize,
+    /// Bases consumed so far.
+    bases: usize,
+    /// Whether the next byte starts a line, and whether the current line
+    /// is a FASTA header.
+    line_start: bool,
+    in_header: bool,
+    done: bool,
+}
+
+impl<R: BufRead> KmerReader<R> {
+    pub fn new(reader: R, size: usize) -> Self {
+        KmerReader { reader, size, bases: 0, line_start: true, in_header: false, done: false }
     }
 
-    let mut kmers = Vec::with_capacity(dna.len() / KMER_LEN);
+    /// Bases consumed so far — the whole sequence once the iterator ends.
+    pub fn bases(&self) -> usize {
+        self.bases
+    }
 
-    for (chunk_idx, chunk) in dna.chunks(KMER_LEN).enumerate() {
-        for (i, &b) in chunk.iter().enumerate() {
-            if !matches!(b, b'A' | b'T' | b'G' | b'C') {
-                return Err(DendecError::ReferInvalidBases {
-                    position: chunk_idx * KMER_LEN + i,
-                });
+    fn next_kmer(&mut self) -> Result<Option<Kmer>> {
+        let mut kmer = [0u8; MAX_CHUNK_SIZE];
+        let mut filled = 0;
+        while filled < self.size {
+            let buf = self.reader.fill_buf().map_err(DendecError::Io)?;
+            if buf.is_empty() {
+                break;
+            }
+            let mut used = 0;
+            for &b in buf {
+                used += 1;
+                let line_start = std::mem::replace(&mut self.line_start, b == b'\n');
+                if line_start && b == b'>' {
+                    self.in_header = true;
+                }
+                if self.in_header {
+                    self.in_header = b != b'\n';
+                    continue;
+                }
+                if b.is_ascii_whitespace() {
+                    continue;
+                }
+                let b = b.to_ascii_uppercase();
+                if !matches!(b, b'A' | b'T' | b'G' | b'C') {
+                    return Err(DendecError::ReferInvalidBases { position: self.bases });
+                }
+                kmer[filled] = b;
+                filled += 1;
+                self.bases += 1;
+                if filled == self.size {
+                    break;
+                }
             }
+            self.reader.consume(used);
         }
-        let mut kmer = [0u8; KMER_LEN];
-        kmer.copy_from_slice(chunk);
-        kmers.push(kmer);
+        Ok((filled > 0).then(|| Kmer::new(&kmer[..filled])))
     }
+}
 
-    Ok(kmers)
+impl<R: BufRead> Iterator for KmerReader<R> {
+    type Item = Result<Kmer>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_kmer().transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
 }
 
-/// Concatenate a sequence of 8-mer arrays back into a flat DNA string.
+/// Concatenate a sequence of chunks back into a flat DNA string.
 ///
 /// The caller guarantees all bytes are valid A/T/G/C — this function
-/// does not re-validate, mirroring the guarantee from split_into_kmers.
-pub fn reassemble(kmers: &[[u8; KMER_LEN]]) -> String {
-    let mut result = String::with_capacity(kmers.len() * KMER_LEN);
+/// does not re-validate, mirroring the guarantee from KmerReader.
+pub fn reassemble(kmers: &[Kmer]) -> String {
+    let mut result = String::with_capacity(kmers.iter().map(|k| k.len()).sum());
     for kmer in kmers {
         // Safety: all bytes are guaranteed to be ASCII (A/T/G/C)
         result.push_str(
-            std::str::from_utf8(kmer).expect("8-mer contains non-UTF8 bytes"),
+            std::str::from_utf8(kmer).expect("chunk contains non-UTF8 bytes"),
         );
     }
     result
@@ -61,20 +173,35 @@ pub fn reassemble(kmers: &[[u8; KMER_LEN]]) -> String {
 mod tests {
     use super::*;
 
+    fn split_into_kmers(dna: &[u8]) -> Result<Vec<Kmer>> {
+        KmerReader::new(dna, DEFAULT_CHUNK_SIZE).collect()
+    }
+
     #[test]
     fn test_split_two_kmers() {
         let dna = b"ATGCGATCGGCTAGCA";
         let kmers = split_into_kmers(dna).unwrap();
         assert_eq!(kmers.len(), 2);
-        assert_eq!(&kmers[0], b"ATGCGATC");
-        assert_eq!(&kmers[1], b"GGCTAGCA");
+        assert_eq!(&kmers[0][..], b"ATGCGATC");
+        assert_eq!(&kmers[1][..], b"GGCTAGCA");
     }
 
     #[test]
-    fn test_split_invalid_length_rejected() {
-        // 7 bases — not a multiple of 8
-        let result = split_into_kmers(b"ATGCGAT");
-        assert!(result.is_err());
+    fn test_short_final_chunk() {
+        // 12 bases — half a chunk left over, completed only when asked
+        let kmers = split_into_kmers(b"ATGCGATCGGCT").unwrap();
+        assert_eq!(&kmers[1][..], b"GGCT");
+        assert_eq!(&kmers[1].padded(DEFAULT_CHUNK_SIZE)[..], b"GGCTAAAA");
+        assert_eq!(kmers[0].padded(DEFAULT_CHUNK_SIZE), kmers[0]);
+    }
+
+    #[test]
+    fn test_larger_chunk_size() {
+        let dna = b"ATGCGATCGGCTAGCATCGA";
+        let kmers: Vec<Kmer> = KmerReader::new(&dna[..], 12).collect::<Result<_>>().unwrap();
+        assert_eq!(&kmers[0][..], b"ATGCGATCGGCT");
+        assert_eq!(&kmers[1][..], b"AGCATCGA");
+        assert_eq!(reassemble(&kmers).as_bytes(), dna);
     }
 
     #[test]
@@ -96,6 +223,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_fasta_input() {
+        // Headers skipped, records joined, soft-masked bases uppercased;
+        // a '>' inside a sequence line is still an invalid base
+        let fasta = b">seq1 from another tool\nATGCga\ntc\n>seq2\r\nGGCTa\r\n";
+        let kmers = split_into_kmers(fasta).unwrap();
+        assert_eq!(reassemble(&kmers), "ATGCGATCGGCTA");
+        let err = split_into_kmers(b">seq1\nATGC>GA\n").unwrap_err();
+        assert!(matches!(err, DendecError::ReferInvalidBases { position: 4 }));
+    }
+
     #[test]
     fn test_reassemble_roundtrip() {
         let dna = b"ATGCGATCGGCTAGCATCGATCGG";
@@ -109,12 +247,33 @@ mod tests {
         let dna = b"ATGCGATC";
         let kmers = split_into_kmers(dna).unwrap();
         assert_eq!(kmers.len(), 1);
-        assert_eq!(&kmers[0], b"ATGCGATC");
+        assert_eq!(&kmers[0][..], b"ATGCGATC");
+    }
+
+    #[test]
+    fn test_whitespace_and_small_buffers() {
+        // Grouped and wrapped input, read through a 3-byte buffer so
+        // 8-mers straddle refills
+        let dna = &b"ATGC GATC\nGGCT AGCA\n"[..];
+        let mut reader = KmerReader::new(std::io::BufReader::with_capacity(3, dna), DEFAULT_CHUNK_SIZE);
+        assert_eq!(&reader.next().unwrap().unwrap()[..], b"ATGCGATC");
+        assert_eq!(&reader.next().unwrap().unwrap()[..], b"GGCTAGCA");
+        assert!(reader.next().is_none());
+        assert_eq!(reader.bases(), 16);
+
+        // Positions skip whitespace; the iterator stops after an error
+        let mut reader = KmerReader::new(&b"ATGC GATC ATN"[..], DEFAULT_CHUNK_SIZE);
+        reader.next();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(DendecError::ReferInvalidBases { position: 10 }))
+        ));
+        assert!(reader.next().is_none());
     }
 
     #[test]
     fn test_empty_input_rejected() {
-        // Empty string: length 0 is a multiple of 8 but produces no kmers
+        // Empty string: no bases, so no chunks, not even a short one
         // This is technically valid — encode of empty DNA → empty BED
         let kmers = split_into_kmers(b"").unwrap();
         assert_eq!(kmers.len(), 0);
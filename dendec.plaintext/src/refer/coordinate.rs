@@ -11,93 +11,154 @@
 ///  ##chunk_size 8
 ///  ##dna_length 168432
 ///  ##chunk_count 21054
+///  ##payload_sha256 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///  ##table_sha256 a415178627e2c5200c58944182e5d2feecc55543bfc7f0cbd994385be46efc71
 ///  NC_000001.11  883401  883409  chunk_00000000  0  +
 ///  NC_000007.14  553084  553092  chunk_00000001  0  -
 ///
 /// Column layout:
 ///   1  Chromosome accession (RefSeq format)
 ///   2  Start position (0-based, BED convention)
-///   3  End position (start + 8, exclusive)
-///   4  Chunk name (chunk_ + zero-padded 8-digit index)
+///   3  End position (start + chunk size, exclusive)
+///   4  Chunk name (chunk_ + zero-padded 8-digit index), or "." when the
+///      records are shuffled and their order comes from the password
 ///   5  Score (always 0 — unused, present for BED compliance)
 ///   6  Strand (+ or -)
-
+///
+/// With `--bed12` six more columns follow, filled the way a single-exon
+/// feature would be, since many pipelines reject 6-column files:
+///   7  thickStart (= start)
+///   8  thickEnd (= end)
+///   9  itemRgb (0)
+///  10  blockCount (1)
+///  11  blockSizes (chunk size,)
+///  12  blockStarts (0,)
+///
+/// Unrefer reads either layout; only columns 1, 2, 4 and 6 carry data.
+/// The ##chunk_size line gives the bases per record, 8 in files written
+/// before it could vary; unrefer checks it against the table's.
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use crate::error::{DendecError, Result};
+use super::assembly::Assembly;
+use super::chunk::DEFAULT_CHUNK_SIZE;
+use super::format::{Chunk, Meta, RecordWriter};
+use super::naming::{keyed_name, Naming};
+
+pub(super) const REFER_VERSION: &str = "0.1.0";
+
+/// First line of every BED refer writes, version aside.
+pub const BED_SIGNATURE: &str = "##dendec-refer";
+
+/// Column layout of the data lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BedLayout {
+    /// chrom, start, end, name, score, strand
+    #[default]
+    Bed6,
+    /// BED6 plus thick range, colour and one block spanning the record
+    Bed12,
+}
 
-const REFER_VERSION: &str = "0.1.0";
-const ASSEMBLY: &str = "GCF_000001405.40 hg38";
-const CHUNK_SIZE: usize = 8;
-
-/// A single parsed record from a dendec-refer BED file.
+/// A single parsed record from a dendec-refer file (BED or any other
+/// format in format.rs).
 pub struct BedRecord {
     /// RefSeq accession string for the chromosome.
     pub accession: String,
-    /// 0-based start position of the 8-mer in the chromosome.
-    pub start: u32,
+    /// 0-based start position of the chunk in the chromosome.
+    pub start: u64,
     /// Strand: 0 = forward (+), 1 = reverse (-).
     pub strand: u8,
-    /// Chunk index — determines reassembly order.
-    pub chunk_idx: usize,
+    /// Chunk index — determines reassembly order. None for a "." name:
+    /// the order of a shuffled file is recovered from the password.
+    pub chunk_idx: Option<usize>,
+    /// A `--name-style` name, which the password turns into the chunk
+    /// index (chunk_idx is then None).
+    pub keyed_name: Option<String>,
 }
 
-/// Metadata recovered from the ## header lines of a BED file.
+/// Metadata recovered from the ## header lines of a refer file.
+#[derive(Default)]
 pub struct BedHeader {
+    /// Assembly named by the ##assembly line, if the file has one.
+    pub assembly: Option<Assembly>,
+    /// Bases per chunk from the chunk size line; 0 if the file has none.
+    pub chunk_size: usize,
     /// Total length of the original DNA string in bases.
     pub dna_length: usize,
     /// Number of chunks (BED data lines) expected.
-    /// This chunk_count is preserved for future rollouts and testing purposes only. dead code 
-    #[allow(dead_code)]
     pub chunk_count: usize,
+    /// SHA-256 of the original DNA string, lowercase hex. None in files
+    /// from before it was recorded.
+    pub payload_sha256: Option<String>,
+    /// Checksum of the table the file was made with, lowercase hex. None
+    /// in files from before it was recorded.
+    pub table_sha256: Option<String>,
+}
+
+impl BedHeader {
+    /// Bases per chunk, defaulting to 8 for files without the line.
+    pub fn chunk_size(&self) -> usize {
+        match self.chunk_size {
+            0 => DEFAULT_CHUNK_SIZE,
+            n => n,
+        }
+    }
 }
 
-/// Write a dendec-refer BED file to `path`.
+/// Streams a dendec-refer BED file, one record at a time.
 ///
-/// `records` is a slice of (accession, start, strand) tuples in chunk
-/// order. `dna_length` is the total character count of the source DNA
-/// string, stored in the header for defensive trimming on decode.
-pub fn write_bed(
-    path: &Path,
-    records: &[(String, u32, u8)],
-    dna_length: usize,
-) -> Result<()> {
-    let file = File::create(path).map_err(DendecError::Io)?;
-    let mut w = BufWriter::new(file);
+/// The header carries the DNA length and chunk count, so both are given
+/// up front; records are then written as they are mapped. The assembly
+/// is recorded so unrefer can pick the matching table.
+pub struct BedWriter<W: Write> {
+    w: W,
+    layout: BedLayout,
+    chunk_size: usize,
+    /// Names records chunk_NNNNNNNN or in a keyed style; "." when
+    /// they are shuffled.
+    naming: Naming,
+}
 
-    // Standard ## comment headers — identical in style to VCF/GFF
-    writeln!(w, "##dendec-refer v{}", REFER_VERSION).map_err(DendecError::Io)?;
-    writeln!(w, "##assembly {}", ASSEMBLY).map_err(DendecError::Io)?;
-    writeln!(w, "##chunk_size {}", CHUNK_SIZE).map_err(DendecError::Io)?;
-    writeln!(w, "##dna_length {}", dna_length).map_err(DendecError::Io)?;
-    writeln!(w, "##chunk_count {}", records.len()).map_err(DendecError::Io)?;
-
-    for (i, (accession, start, strand)) in records.iter().enumerate() {
-        let end = start + CHUNK_SIZE as u32;
-        let strand_char = if *strand == 0 { '+' } else { '-' };
-        writeln!(
-            w,
-            "{}\t{}\t{}\tchunk_{:08}\t0\t{}",
-            accession, start, end, i, strand_char
+impl<W: Write> BedWriter<W> {
+    /// Write the ## header lines (see write_header).
+    pub fn new(mut w: W, meta: &Meta, layout: BedLayout) -> Result<Self> {
+        write_header(&mut w, meta, HEADER_PREFIX)?;
+        Ok(BedWriter { w, layout, chunk_size: meta.chunk_size, naming: meta.naming.clone() })
+    }
+}
+
+impl<W: Write> RecordWriter for BedWriter<W> {
+    /// Append a record, named for its chunk unless records are
+    /// unnamed.
+    fn push(&mut self, chunk: &Chunk) -> Result<()> {
+        let Chunk { idx, accession, start, strand, .. } = *chunk;
+        let end = start + self.chunk_size as u64;
+        let strand_char = if strand == 0 { '+' } else { '-' };
+        write!(
+            self.w,
+            "{}\t{}\t{}\t{}\t0\t{}",
+            accession, start, end, chunk_name(&self.naming, idx), strand_char
         )
         .map_err(DendecError::Io)?;
+        if self.layout == BedLayout::Bed12 {
+            write!(self.w, "\t{}\t{}\t0\t1\t{},\t0,", start, end, self.chunk_size).map_err(DendecError::Io)?;
+        }
+        writeln!(self.w).map_err(DendecError::Io)?;
+        Ok(())
     }
-
-    Ok(())
 }
 
 /// Parse a dendec-refer BED file from `path`.
 ///
-/// Returns the parsed header metadata and a list of records sorted by
-/// chunk index. Sorting is defensive — file order should already be
-/// correct, but an out-of-order BED file will still decode correctly.
+/// Returns the parsed header metadata and the records, sorted by chunk
+/// index (see order_records).
 pub fn read_bed(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
     let file = File::open(path).map_err(DendecError::Io)?;
     let reader = BufReader::new(file);
 
-    let mut dna_length = 0usize;
-    let mut chunk_count = 0usize;
+    let mut header = BedHeader::default();
     let mut records: Vec<BedRecord> = Vec::new();
 
     for raw in reader.lines() {
@@ -105,31 +166,15 @@ pub fn read_bed(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
         let line = line.trim();
 
         // ── Header lines ──────────────────────────────────────────────
-        if line.starts_with("##dna_length") {
-            dna_length = line
-                .split_whitespace()
-                .nth(1)
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0);
-            continue;
-        }
-        if line.starts_with("##chunk_count") {
-            chunk_count = line
-                .split_whitespace()
-                .nth(1)
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0);
-            continue;
-        }
-        if line.starts_with('#') || line.is_empty() {
+        if read_header_line(line, HEADER_PREFIX, &mut header)? || line.starts_with('#') || line.is_empty() {
             continue;
         }
 
         // ── Data lines ────────────────────────────────────────────────
         let cols: Vec<&str> = line.split('\t').collect();
-        if cols.len() < 6 {
+        if cols.len() != 6 && cols.len() != 12 {
             return Err(DendecError::ReferInvalidBed(format!(
-                "expected 6 tab-separated columns, got {}: {:?}",
+                "expected 6 (BED6) or 12 (BED12) tab-separated columns, got {}: {:?}",
                 cols.len(),
                 line
             )));
@@ -137,7 +182,7 @@ pub fn read_bed(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
 
         let accession = cols[0].to_string();
 
-        let start: u32 = cols[1].parse().map_err(|_| {
+        let start: u64 = cols[1].parse().map_err(|_| {
             DendecError::ReferInvalidBed(format!(
                 "invalid start coordinate '{}' in line: {}",
                 cols[1], line
@@ -155,49 +200,155 @@ pub fn read_bed(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
             }
         };
 
-        let chunk_idx: usize = cols[3]
-            .strip_prefix("chunk_")
-            .and_then(|s| s.parse().ok())
-            .ok_or_else(|| {
-                DendecError::ReferInvalidBed(format!(
-                    "invalid chunk name '{}': expected chunk_NNNNNNNN",
-                    cols[3]
-                ))
-            })?;
-
         records.push(BedRecord {
             accession,
             start,
             strand,
-            chunk_idx,
+            chunk_idx: parse_chunk_name(cols[3])?,
+            keyed_name: keyed_name(cols[3]),
         });
     }
 
-    // Defensive sort by chunk index
-    records.sort_by_key(|r| r.chunk_idx);
+    order_records(&mut records)?;
+    Ok((header, records))
+}
+
+// ── Shared with the other formats ─────────────────────────────────────
 
-    Ok((BedHeader { dna_length, chunk_count }, records))
+/// Comment marker of the header lines in BED and bedGraph.
+pub(super) const HEADER_PREFIX: &str = "##";
+
+/// Write the header lines, each starting with `prefix`. The DNA length
+/// is stored for defensive trimming on decode.
+pub(super) fn write_header(w: &mut impl Write, meta: &Meta, prefix: &str) -> Result<()> {
+    // Standard ## comment headers — identical in style to VCF/GFF
+    writeln!(w, "{}dendec-refer v{}", prefix, REFER_VERSION).map_err(DendecError::Io)?;
+    writeln!(w, "{}assembly {}", prefix, meta.assembly.header_value()).map_err(DendecError::Io)?;
+    writeln!(w, "{}chunk_size {}", prefix, meta.chunk_size).map_err(DendecError::Io)?;
+    writeln!(w, "{}dna_length {}", prefix, meta.dna_length).map_err(DendecError::Io)?;
+    writeln!(w, "{}chunk_count {}", prefix, meta.chunk_count).map_err(DendecError::Io)?;
+    writeln!(w, "{}payload_sha256 {}", prefix, meta.payload_sha256).map_err(DendecError::Io)?;
+    writeln!(w, "{}table_sha256 {}", prefix, meta.table_sha256).map_err(DendecError::Io)?;
+    Ok(())
+}
+
+/// Apply a header line written by write_header with `prefix` to
+/// `header`. False for any other line.
+pub(super) fn read_header_line(line: &str, prefix: &str, header: &mut BedHeader) -> Result<bool> {
+    let Some(line) = line.strip_prefix(prefix) else {
+        return Ok(false);
+    };
+    let number = || line.split_whitespace().nth(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+    if let Some(value) = line.strip_prefix("assembly") {
+        header.assembly = Some(Assembly::from_header_value(value)?);
+    } else if line.starts_with("chunk_size") {
+        header.chunk_size = number();
+    } else if line.starts_with("dna_length") {
+        header.dna_length = number();
+    } else if line.starts_with("chunk_count") {
+        header.chunk_count = number();
+    } else if let Some(value) = line.strip_prefix("payload_sha256") {
+        header.payload_sha256 = Some(value.trim().to_string());
+    } else if let Some(value) = line.strip_prefix("table_sha256") {
+        header.table_sha256 = Some(value.trim().to_string());
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Name of the chunk at `idx`: chunk_NNNNNNNN or a keyed style's
+/// name, or "." when shuffled.
+pub(super) fn chunk_name(naming: &Naming, idx: usize) -> String {
+    naming.name(idx).unwrap_or_else(|| ".".to_string())
+}
+
+/// Parse a name written by chunk_name. A keyed style's name gives None
+/// here; keyed_name keeps it for the password to decode.
+pub(super) fn parse_chunk_name(name: &str) -> Result<Option<usize>> {
+    if name == "." || keyed_name(name).is_some() {
+        return Ok(None);
+    }
+    let idx = name.strip_prefix("chunk_").and_then(|s| s.parse().ok()).ok_or_else(|| {
+        DendecError::ReferInvalidBed(format!(
+            "invalid chunk name '{}': expected chunk_NNNNNNNN or .",
+            name
+        ))
+    })?;
+    Ok(Some(idx))
+}
+
+/// Sort named records by chunk index, leaving shuffled ones in file order.
+///
+/// Sorting is defensive — file order should already be correct, but an
+/// out-of-order file will still decode correctly. A file mixing named
+/// and unnamed records is rejected.
+pub(super) fn order_records(records: &mut [BedRecord]) -> Result<()> {
+    let named = records.iter().filter(|r| r.chunk_idx.is_some()).count();
+    if named != 0 && named != records.len() {
+        return Err(DendecError::ReferInvalidBed(
+            "some chunks are named and some are '.' — records from two files mixed?".to_string(),
+        ));
+    }
+    records.sort_by_key(|r| r.chunk_idx);
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::refer::chunk::Kmer;
     use tempfile::tempdir;
 
+    const ACCESSIONS: &[String] = &[];
+
+    fn writer(assembly: Assembly, chunks: usize, layout: BedLayout, chunk_names: bool) -> BedWriter<Vec<u8>> {
+        sized_writer(8, assembly, chunks, layout, chunk_names)
+    }
+
+    fn sized_writer(
+        chunk_size: usize,
+        assembly: Assembly,
+        chunks: usize,
+        layout: BedLayout,
+        chunk_names: bool,
+    ) -> BedWriter<Vec<u8>> {
+        let meta = Meta {
+            assembly,
+            chunk_size,
+            dna_length: chunks * chunk_size,
+            payload_sha256: "",
+            table_sha256: "",
+            chunk_count: chunks,
+            naming: match chunk_names {
+                true => Naming::Chunk,
+                false => Naming::Hidden,
+            },
+            accessions: ACCESSIONS,
+            contig_lengths: &[],
+            key: None,
+            reference: None,
+        };
+        BedWriter::new(Vec::new(), &meta, layout).unwrap()
+    }
+
+    fn push(bed: &mut BedWriter<Vec<u8>>, idx: usize, accession: &str, start: u64, strand: u8) {
+        bed.push(&Chunk { idx, accession, start, strand, kmer: Kmer::new(b"AAAAAAAA") }).unwrap();
+    }
+
     #[test]
     fn test_write_and_read_roundtrip() {
         let dir = tempdir().unwrap();
         let path = dir.path().join("test.bed");
 
-        let records = vec![
-            ("NC_000001.11".to_string(), 883401u32, 0u8),
-            ("NC_000001.11".to_string(), 19823u32,  1u8),
-            ("NC_000001.11".to_string(), 28401u32,  0u8),
-        ];
-
-        write_bed(&path, &records, 24).unwrap();
+        let mut bed = writer(Assembly::Mm39, 3, BedLayout::Bed6, true);
+        push(&mut bed, 0, "NC_000001.11", 883401, 0);
+        push(&mut bed, 1, "NC_000001.11", 19823, 1);
+        push(&mut bed, 2, "NC_000001.11", 28401, 0);
+        std::fs::write(&path, bed.w).unwrap();
 
         let (header, parsed) = read_bed(&path).unwrap();
+        assert_eq!(header.assembly, Some(Assembly::Mm39));
         assert_eq!(header.dna_length, 24);
         assert_eq!(header.chunk_count, 3);
         assert_eq!(parsed.len(), 3);
@@ -205,10 +356,43 @@ mod tests {
         assert_eq!(parsed[0].accession, "NC_000001.11");
         assert_eq!(parsed[0].start, 883401);
         assert_eq!(parsed[0].strand, 0);
-        assert_eq!(parsed[0].chunk_idx, 0);
+        assert_eq!(parsed[0].chunk_idx, Some(0));
 
         assert_eq!(parsed[1].strand, 1);
-        assert_eq!(parsed[1].chunk_idx, 1);
+        assert_eq!(parsed[1].chunk_idx, Some(1));
+    }
+
+    #[test]
+    fn test_bed12_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test12.bed");
+        let mut bed = writer(Assembly::Hg38, 2, BedLayout::Bed12, true);
+        push(&mut bed, 0, "NC_000001.11", 883401, 0);
+        push(&mut bed, 1, "NC_000007.14", 553084, 1);
+        let text = String::from_utf8(bed.w).unwrap();
+        assert!(text.contains(
+            "NC_000007.14\t553084\t553092\tchunk_00000001\t0\t-\t553084\t553092\t0\t1\t8,\t0,\n"
+        ));
+        std::fs::write(&path, text).unwrap();
+
+        let (_, records) = read_bed(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[1].accession.as_str(), records[1].start, records[1].strand), ("NC_000007.14", 553084, 1));
+    }
+
+    #[test]
+    fn test_chunk_size_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("k10.bed");
+        let mut bed = sized_writer(10, Assembly::Hg38, 1, BedLayout::Bed12, true);
+        push(&mut bed, 0, "NC_000001.11", 883401, 0);
+        let text = String::from_utf8(bed.w).unwrap();
+        assert!(text.contains("##chunk_size 10\n"));
+        assert!(text.contains("\t883401\t883411\tchunk_00000000\t0\t+\t883401\t883411\t0\t1\t10,\t0,\n"));
+        std::fs::write(&path, text).unwrap();
+
+        let (header, _) = read_bed(&path).unwrap();
+        assert_eq!((header.chunk_size, header.chunk_size()), (10, 10));
     }
 
     #[test]
@@ -258,9 +442,31 @@ mod tests {
         )
         .unwrap();
 
+        let (header, records) = read_bed(&path).unwrap();
+        assert_eq!(header.assembly, None);
+        // No ##chunk_size line: the 8-base chunks of older files
+        assert_eq!(header.chunk_size(), 8);
+        assert_eq!(records[0].chunk_idx, Some(0));
+        assert_eq!(records[1].chunk_idx, Some(1));
+    }
+
+    #[test]
+    fn test_unnamed_records_keep_file_order() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("shuffled.bed");
+        let mut bed = writer(Assembly::Hg38, 2, BedLayout::Bed6, false);
+        push(&mut bed, 0, "NC_000001.11", 883401, 0);
+        push(&mut bed, 1, "NC_000001.11", 19823, 1);
+        std::fs::write(&path, &bed.w).unwrap();
+
         let (_, records) = read_bed(&path).unwrap();
-        assert_eq!(records[0].chunk_idx, 0);
-        assert_eq!(records[1].chunk_idx, 1);
+        assert_eq!(records[0].start, 883401);
+        assert!(records.iter().all(|r| r.chunk_idx.is_none()));
+
+        // Named and unnamed records do not mix
+        let mut mixed = bed.w;
+        mixed.extend_from_slice(b"NC_000001.11\t19823\t19831\tchunk_00000002\t0\t-\n");
+        std::fs::write(&path, mixed).unwrap();
+        assert!(read_bed(&path).is_err());
     }
 }
-
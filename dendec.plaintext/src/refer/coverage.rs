@@ -0,0 +1,231 @@
+/// refer/coverage.rs — Machine-readable coverage report of a table build
+///
+/// `table build --report PATH` (and `table merge --report PATH`) writes
+/// what the new table covers, so its quality can be tracked from build to
+/// build and a pipeline change that loses coverage shows up in a diff:
+///
+///   {
+///     "dendec_version": "0.1.0",
+///     "table": "data/table.bin",
+///     "table_sha256": "9f2c…",
+///     "chunk_size": 8, "max_coords": 8, "forward_only": false,
+///     "summary": { "chromosomes": 25, "kmers": 65536, "covered": 65536,
+///                  "saturated": 65401, "missing": 0, "coords": 523153 },
+///     "options": [0, 12, 9, …],
+///     "chromosomes": [
+///       { "accession": "NC_000001.11", "coords": 41893, "share": 0.0801,
+///         "forward": 20950, "span": [10468, 248945637] }, …
+///     ],
+///     "kmers": { "options": [8, 8, …], "occurrences": [1843202, …] },
+///     "missing": []
+///   }
+///
+/// `options[n]` is how many k-mers have n coordinate options (the
+/// saturation histogram); `kmers` holds each k-mer's options and its
+/// occurrences in the inputs, both in k-mer index order (A=0, T=1, G=2,
+/// C=3, as in table.rs). A PATH ending in .tsv gets the same figures as
+/// tab-separated section, key and value rows instead:
+///
+///   summary      covered       65536
+///   options      8             65401
+///   chromosome   NC_000001.11  41893
+///   kmer         AAAAAAAA      8       1843202
+///   missing      CGCGCGCG      0       0
+///
+/// with a fourth column, occurrences, on kmer and missing rows.
+use std::fmt::Write as _;
+use std::path::Path;
+
+use log::info;
+use serde::Serialize;
+
+use crate::error::{DendecError, Result};
+use crate::output::write_atomic;
+use super::stats::TableStats;
+use super::table::{kmer_name, read_table_file, ReferTable};
+
+#[derive(Serialize)]
+struct Report<'a> {
+    dendec_version: &'static str,
+    table: String,
+    table_sha256: String,
+    chunk_size: usize,
+    max_coords: usize,
+    forward_only: bool,
+    summary: Summary,
+    options: &'a [usize],
+    chromosomes: Vec<Chromosome<'a>>,
+    kmers: Kmers<'a>,
+    missing: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    chromosomes: usize,
+    kmers: usize,
+    covered: usize,
+    saturated: usize,
+    missing: usize,
+    coords: usize,
+}
+
+#[derive(Serialize)]
+struct Chromosome<'a> {
+    accession: &'a str,
+    coords: usize,
+    share: f64,
+    forward: usize,
+    span: Option<(u64, u64)>,
+}
+
+#[derive(Serialize)]
+struct Kmers<'a> {
+    options: Vec<usize>,
+    occurrences: &'a [u64],
+}
+
+/// Write the coverage report of the table just written to `table_path`
+/// to `path`. `occurrences` is how often each k-mer occurred in the
+/// inputs, in k-mer index order; `max_coords` and `forward_only` are the
+/// build's settings, which the table does not record.
+pub fn write_coverage_report(
+    path: &Path,
+    table_path: &Path,
+    max_coords: usize,
+    forward_only: bool,
+    occurrences: &[u64],
+) -> Result<()> {
+    let bytes = read_table_file(table_path)?;
+    let table = ReferTable::from_bytes(&bytes)?;
+    let stats = TableStats::collect(&table, bytes.len());
+    let k = stats.chunk_size;
+    let options: Vec<usize> = table.entries().map(|e| e.len()).collect();
+    let missing: Vec<String> = (0..options.len()).filter(|&i| options[i] == 0).map(|i| kmer_name(i, k)).collect();
+
+    let report = Report {
+        dendec_version: env!("CARGO_PKG_VERSION"),
+        table: table_path.to_string_lossy().into_owned(),
+        table_sha256: table.sha256(),
+        chunk_size: k,
+        max_coords,
+        forward_only,
+        summary: Summary {
+            chromosomes: stats.chromosomes.len(),
+            kmers: stats.entries,
+            covered: stats.covered,
+            saturated: stats.histogram.get(max_coords).copied().unwrap_or(0),
+            missing: missing.len(),
+            coords: stats.coords,
+        },
+        options: &stats.histogram,
+        chromosomes: stats
+            .chromosomes
+            .iter()
+            .map(|c| Chromosome {
+                accession: &c.accession,
+                coords: c.coords,
+                share: c.coords as f64 / stats.coords.max(1) as f64,
+                forward: c.forward,
+                span: c.span,
+            })
+            .collect(),
+        kmers: Kmers { options, occurrences },
+        missing,
+    };
+
+    let is_tsv = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("tsv"));
+    let bytes = match is_tsv {
+        true => tsv(&report).into_bytes(),
+        false => {
+            let mut json = serde_json::to_vec_pretty(&report).map_err(|e| DendecError::Io(e.into()))?;
+            json.push(b'\n');
+            json
+        }
+    };
+    write_atomic(path, &bytes, None)?;
+    info!("Coverage report written to {}", path.display());
+    Ok(())
+}
+
+/// The report as section, key, value rows.
+fn tsv(report: &Report) -> String {
+    let mut out = String::from("section\tkey\tvalue\toccurrences\n");
+    let mut row = |section: &str, key: &dyn std::fmt::Display, value: &dyn std::fmt::Display| {
+        let _ = writeln!(out, "{section}\t{key}\t{value}");
+    };
+    row("summary", &"table", &report.table);
+    row("summary", &"table_sha256", &report.table_sha256);
+    row("summary", &"chunk_size", &report.chunk_size);
+    row("summary", &"max_coords", &report.max_coords);
+    row("summary", &"forward_only", &report.forward_only);
+    let s = &report.summary;
+    for (key, value) in [
+        ("chromosomes", s.chromosomes),
+        ("kmers", s.kmers),
+        ("covered", s.covered),
+        ("saturated", s.saturated),
+        ("missing", s.missing),
+        ("coords", s.coords),
+    ] {
+        row("summary", &key, &value);
+    }
+    for (n, count) in report.options.iter().enumerate() {
+        row("options", &n, count);
+    }
+    for chrom in &report.chromosomes {
+        row("chromosome", &chrom.accession, &chrom.coords);
+    }
+    let kmers = &report.kmers;
+    for (i, (options, occurrences)) in kmers.options.iter().zip(kmers.occurrences).enumerate() {
+        let section = if *options == 0 { "missing" } else { "kmer" };
+        let _ = writeln!(out, "{section}\t{}\t{options}\t{occurrences}", kmer_name(i, report.chunk_size));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::OverwritePolicy;
+    use crate::refer::build::{build_table, BuildOptions};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_coverage_report_json_and_tsv() {
+        // AAAAAAAA at 0 and 1, AAAAAAAC at 2, plus the reverse
+        // complements TTTTTTTT (twice) and GTTTTTTT
+        let dir = tempdir().unwrap();
+        let fasta = dir.path().join("genome.fa");
+        std::fs::write(&fasta, ">chr1\nAAAAAAAAAC\n>chr2\nNNNN\n").unwrap();
+        let out = dir.path().join("table.bin");
+        let json_path = dir.path().join("coverage.json");
+        let opts = BuildOptions { max_coords: 2, report: Some(json_path.clone()), ..Default::default() };
+        build_table(std::slice::from_ref(&fasta), &out, &opts, OverwritePolicy::Refuse).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&std::fs::read(&json_path).unwrap()).unwrap();
+        assert_eq!(json["chunk_size"], 8);
+        assert_eq!(json["max_coords"], 2);
+        assert_eq!(json["summary"]["covered"], 4);
+        assert_eq!(json["summary"]["saturated"], 2);
+        assert_eq!(json["summary"]["missing"], 65_536 - 4);
+        assert_eq!(json["options"][1], 2);
+        assert_eq!(json["chromosomes"][0]["coords"], 6);
+        assert_eq!(json["chromosomes"][1]["accession"], "chr2");
+        // AAAAAAAA is k-mer 0; TTTTTTTT is 0x5555
+        assert_eq!(json["kmers"]["options"][0], 2);
+        assert_eq!(json["kmers"]["occurrences"][0], 2);
+        assert_eq!(json["kmers"]["occurrences"][0x5555], 2);
+        assert_eq!(json["missing"].as_array().unwrap().len(), 65_536 - 4);
+
+        let tsv_path = dir.path().join("coverage.tsv");
+        let opts = BuildOptions { max_coords: 2, report: Some(tsv_path.clone()), ..Default::default() };
+        build_table(&[fasta], &out, &opts, OverwritePolicy::Force).unwrap();
+        let tsv = std::fs::read_to_string(&tsv_path).unwrap();
+        assert!(tsv.starts_with("section\tkey\tvalue\toccurrences\n"));
+        assert!(tsv.contains("\nsummary\tcovered\t4\n"));
+        assert!(tsv.contains("\nchromosome\tchr1\t6\n"));
+        assert!(tsv.contains("\nkmer\tAAAAAAAA\t2\t2\n"));
+        assert!(tsv.contains("\nkmer\tAAAAAAAC\t1\t1\n"));
+        assert!(tsv.contains("\nmissing\tCCCCCCCC\t0\t0\n"));
+        assert_eq!(tsv.lines().filter(|l| l.starts_with("missing\t")).count(), 65_536 - 4);
+    }
+}
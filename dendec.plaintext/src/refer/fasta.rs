@@ -0,0 +1,359 @@
+/// refer/fasta.rs — FASTA read and write
+///
+/// `refer --format fasta` writes each chunk as a captured region: its
+/// bases in the middle of a window of the real genome sequence around
+/// it, the way `bedtools getfasta -s` would cut it:
+///
+///     ;dendec-refer v0.1.0
+///     ;assembly GCF_000001405.40 hg38
+///     ;chunk_size 8
+///     ;dna_length 168432
+///     ;chunk_count 21054
+///     ;payload_sha256 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///     ;table_sha256 a415178627e2c5200c58944182e5d2feecc55543bfc7f0cbd994385be46efc71
+///     >NC_000001.11:883305-883505(+) chunk_00000000
+///     tcagGCTTACCTGA…  (200 bases, 60 per line)
+///
+/// Tables hold coordinates, not sequence, so the flanks are read from the
+/// genome FASTA the table was built from, given with `--reference`. Each
+/// payload chunk is checked against it, so a FASTA of the wrong build is
+/// caught. Case and Ns are kept as the reference has them.
+///
+/// The header names the window in BED convention (0-based, end
+/// exclusive), then the strand; a − strand region is the reverse
+/// complement of the window. Windows are FLANK bases either side of the
+/// chunk, narrowed equally on both sides near a chromosome end, so the
+/// chunk always sits in the middle. The chunk name follows the region;
+/// shuffled records have none. Unrefer reads the headers only.
+use std::io::{BufRead, BufReader, Write};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::error::{DendecError, Result};
+use super::assembly::Assembly;
+use super::build::open_fasta;
+use super::chrom::counterpart;
+use super::coordinate::{
+    order_records, parse_chunk_name, read_header_line, write_header, BedHeader, BedRecord,
+};
+use super::chunk::Kmer;
+use super::format::{Chunk, Meta, RecordWriter};
+use super::naming::{keyed_name, Naming};
+use super::reverse::{reverse_complement, reverse_complement_seq};
+
+/// Comment marker of the header lines, from the original FASTA format.
+pub const FASTA_PREFIX: &str = ";";
+
+/// Start of the first line of every refer FASTA; unrefer recognises the
+/// format by it.
+pub const FASTA_SIGNATURE: &str = ";dendec-refer";
+
+/// Genome bases either side of the chunk.
+const FLANK: usize = 96;
+
+/// Sequence line width.
+const LINE_WIDTH: usize = 60;
+
+/// One chunk waiting for its flanks.
+struct Region {
+    idx: usize,
+    chrom_idx: usize,
+    start: u64,
+    strand: u8,
+    kmer: Kmer,
+    /// Window start and sequence, once read from the reference.
+    window: Option<(usize, Vec<u8>)>,
+}
+
+/// Writes a dendec-refer FASTA. Records are held until finish, which
+/// reads the reference once for every flank.
+pub struct FastaWriter<W: Write> {
+    w: W,
+    reference: PathBuf,
+    assembly: Assembly,
+    accessions: Vec<String>,
+    naming: Naming,
+    held: Vec<Region>,
+}
+
+impl<W: Write> FastaWriter<W> {
+    /// Write the ; header lines.
+    pub fn new(mut w: W, meta: &Meta, reference: &Path) -> Result<Self> {
+        write_header(&mut w, meta, FASTA_PREFIX)?;
+        Ok(FastaWriter {
+            w,
+            reference: reference.to_path_buf(),
+            assembly: meta.assembly,
+            accessions: meta.accessions.to_vec(),
+            naming: meta.naming.clone(),
+            held: Vec::with_capacity(meta.chunk_count),
+        })
+    }
+
+    /// Cut the window of every held region on the chromosome `seq`.
+    fn cut(&mut self, chrom_idx: usize, seq: &[u8]) -> Result<()> {
+        let accession = &self.accessions[chrom_idx];
+        for region in self.held.iter_mut().filter(|r| r.chrom_idx == chrom_idx) {
+            let start = region.start as usize;
+            let end = start + region.kmer.len();
+            let expected = match region.strand {
+                0 => region.kmer,
+                _ => reverse_complement(&region.kmer),
+            };
+            if !seq.get(start..end).is_some_and(|s| s.eq_ignore_ascii_case(&expected)) {
+                return Err(DendecError::ReferReference(format!(
+                    "{}:{} does not hold the table's k-mer — is it the FASTA the table was built from?",
+                    accession, start
+                )));
+            }
+
+            let flank = FLANK.min(start).min(seq.len() - end);
+            let window = &seq[start - flank..end + flank];
+            let window = match region.strand {
+                0 => window.to_vec(),
+                _ => reverse_complement_seq(window),
+            };
+            region.window = Some((start - flank, window));
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for FastaWriter<W> {
+    fn push(&mut self, chunk: &Chunk) -> Result<()> {
+        let chrom_idx = self
+            .accessions
+            .iter()
+            .position(|a| a == chunk.accession)
+            .ok_or(DendecError::ReferTableCorrupt)?;
+        self.held.push(Region {
+            idx: chunk.idx,
+            chrom_idx,
+            start: chunk.start,
+            strand: chunk.strand,
+            kmer: chunk.kmer,
+            window: None,
+        });
+        Ok(())
+    }
+
+    /// Read every needed chromosome from the reference, one at a time,
+    /// then write the regions in the order pushed.
+    fn finish(&mut self) -> Result<()> {
+        let mut needed = vec![false; self.accessions.len()];
+        for region in &self.held {
+            needed[region.chrom_idx] = true;
+        }
+
+        let mut reader = open_fasta(&self.reference)?;
+        let mut line = Vec::new();
+        let mut current: Option<usize> = None;
+        let mut seq = Vec::new();
+        loop {
+            line.clear();
+            let done = reader.read_until(b'\n', &mut line)? == 0;
+            if done || line.starts_with(b">") {
+                if let Some(chrom_idx) = current.take() {
+                    self.cut(chrom_idx, &seq)?;
+                    needed[chrom_idx] = false;
+                }
+                if done || !needed.contains(&true) {
+                    break;
+                }
+                let name = String::from_utf8_lossy(&line[1..]);
+                let name = name.split_whitespace().next().unwrap_or("");
+                // The reference may use the other naming style
+                current = self
+                    .accessions
+                    .iter()
+                    .position(|a| a == name || counterpart(self.assembly, a) == Some(name))
+                    .filter(|&i| needed[i]);
+                seq.clear();
+                continue;
+            }
+            if current.is_some() {
+                seq.extend(line.iter().filter(|b| !b.is_ascii_whitespace()));
+            }
+        }
+        if let Some(missing) = needed.iter().position(|&n| n) {
+            return Err(DendecError::ReferReference(format!(
+                "{} has no record {}",
+                self.reference.display(),
+                self.accessions[missing]
+            )));
+        }
+
+        for region in &self.held {
+            let Some((window_start, window)) = &region.window else {
+                return Err(DendecError::ReferTableCorrupt);
+            };
+            write!(
+                self.w,
+                ">{}:{}-{}({})",
+                self.accessions[region.chrom_idx],
+                window_start,
+                window_start + window.len(),
+                if region.strand == 0 { '+' } else { '-' }
+            )
+            .map_err(DendecError::Io)?;
+            if let Some(name) = self.naming.name(region.idx) {
+                write!(self.w, " {}", name).map_err(DendecError::Io)?;
+            }
+            writeln!(self.w).map_err(DendecError::Io)?;
+            for row in window.chunks(LINE_WIDTH) {
+                self.w.write_all(row).map_err(DendecError::Io)?;
+                writeln!(self.w).map_err(DendecError::Io)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a dendec-refer FASTA from `path`.
+///
+/// Returns the header metadata and the records, sorted by chunk index
+/// unless they are shuffled.
+pub fn read_fasta_regions(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
+    let reader = BufReader::new(File::open(path).map_err(DendecError::Io)?);
+    let invalid = |line: &str| {
+        DendecError::ReferInvalidBed(format!(
+            "FASTA: expected >ACCESSION:START-END(STRAND) [chunk name], got: {}",
+            line
+        ))
+    };
+
+    let mut header = BedHeader::default();
+    let mut records = Vec::new();
+
+    for raw in reader.lines() {
+        let line = raw.map_err(DendecError::Io)?;
+        let line = line.trim();
+        if read_header_line(line, FASTA_PREFIX, &mut header)? {
+            continue;
+        }
+        // Comments and sequence lines carry nothing unrefer needs
+        let Some(name) = line.strip_prefix('>') else {
+            continue;
+        };
+
+        let mut fields = name.split_whitespace();
+        let region = fields.next().unwrap_or("");
+        let (accession, range) = region.rsplit_once(':').ok_or_else(|| invalid(line))?;
+        let (range, strand) = range
+            .strip_suffix(')')
+            .and_then(|r| r.split_once('('))
+            .ok_or_else(|| invalid(line))?;
+        let (start, end) = range.split_once('-').ok_or_else(|| invalid(line))?;
+        let (start, end): (u64, u64) = match (start.parse(), end.parse()) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => return Err(invalid(line)),
+        };
+        // The chunk sits in the middle, an equal flank either side
+        let flanks = end
+            .checked_sub(start)
+            .and_then(|len| len.checked_sub(header.chunk_size() as u64))
+            .filter(|f| f % 2 == 0)
+            .ok_or_else(|| invalid(line))?;
+        let strand = match strand {
+            "+" => 0,
+            "-" => 1,
+            _ => return Err(invalid(line)),
+        };
+
+        let name = fields.next();
+        records.push(BedRecord {
+            accession: accession.to_string(),
+            start: start + flanks / 2,
+            strand,
+            chunk_idx: name.map(parse_chunk_name).transpose()?.flatten(),
+            keyed_name: name.and_then(keyed_name),
+        });
+    }
+
+    order_records(&mut records)?;
+    Ok((header, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::assembly::Assembly;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_and_read_roundtrip() {
+        let dir = tempdir().unwrap();
+        let reference = dir.path().join("ref.fa");
+        // chr7 is skipped; chrA is 300 bases with ATGCGATC at 150 and
+        // near the start at 10, GATCGCAT (its reverse complement) at 280
+        let mut chr = "acgt".repeat(75).into_bytes();
+        chr[150..158].copy_from_slice(b"ATGCGATC");
+        chr[10..18].copy_from_slice(b"ATGCGATC");
+        chr[280..288].copy_from_slice(b"GATCGCAT");
+        let chr = String::from_utf8(chr).unwrap();
+        std::fs::write(&reference, format!(">chr7 other\nAAAA\n>chrA test\n{}\n{}\n", &chr[..200], &chr[200..])).unwrap();
+
+        let accessions = ["chr7".to_string(), "chrA".to_string()];
+        let meta = Meta {
+            assembly: Assembly::Hg38,
+            chunk_size: 8,
+            dna_length: 24,
+            payload_sha256: "",
+            table_sha256: "",
+            chunk_count: 3,
+            naming: Naming::Chunk,
+            accessions: &accessions,
+            contig_lengths: &[],
+            key: None,
+            reference: None,
+        };
+        let mut fasta = FastaWriter::new(Vec::new(), &meta, &reference).unwrap();
+        for (idx, (start, strand)) in [(150, 0), (10, 0), (280, 1)].into_iter().enumerate() {
+            fasta.push(&Chunk { idx, accession: "chrA", start, strand, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        }
+        fasta.finish().unwrap();
+
+        let text = String::from_utf8(fasta.w).unwrap();
+        assert!(text.starts_with(";dendec-refer v"));
+        assert!(text.contains("\n>chrA:54-254(+) chunk_00000000\n"));
+        assert!(text.contains("\n>chrA:0-28(+) chunk_00000001\nacgtacgtacATGCGATCgtacgtacgt\n"));
+        // 12 bases to the chromosome end, so 12 either side; reverse strand
+        assert!(text.contains("\n>chrA:268-300(-) chunk_00000002\nacgtacgtacgtATGCGATCacgtacgtacgt\n"));
+        assert!(text.lines().all(|l| l.len() <= LINE_WIDTH || l.starts_with(['>', ';'])));
+
+        let path = dir.path().join("out.fa");
+        std::fs::write(&path, &text).unwrap();
+        let (header, records) = read_fasta_regions(&path).unwrap();
+        assert_eq!((header.assembly, header.dna_length, header.chunk_count), (Some(Assembly::Hg38), 24, 3));
+        let got: Vec<_> = records.iter().map(|r| (r.start, r.strand, r.chunk_idx)).collect();
+        assert_eq!(got, [(150, 0, Some(0)), (10, 0, Some(1)), (280, 1, Some(2))]);
+    }
+
+    #[test]
+    fn test_wrong_reference_rejected() {
+        let dir = tempdir().unwrap();
+        let reference = dir.path().join("ref.fa");
+        std::fs::write(&reference, format!(">chrA\n{}\n", "A".repeat(100))).unwrap();
+        let accessions = ["chrA".to_string(), "chrB".to_string()];
+        let meta = Meta {
+            assembly: Assembly::Hg38,
+            chunk_size: 8,
+            dna_length: 8,
+            payload_sha256: "",
+            table_sha256: "",
+            chunk_count: 1,
+            naming: Naming::Chunk,
+            accessions: &accessions,
+            contig_lengths: &[],
+            key: None,
+            reference: None,
+        };
+        let refer = |accession| {
+            let mut fasta = FastaWriter::new(Vec::new(), &meta, &reference).unwrap();
+            fasta.push(&Chunk { idx: 0, accession, start: 40, strand: 0, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+            fasta.finish().unwrap_err().to_string()
+        };
+        assert!(refer("chrA").contains("chrA:40 does not hold"));
+        assert!(refer("chrB").contains("has no record chrB"));
+    }
+}
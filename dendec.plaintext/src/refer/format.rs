@@ -0,0 +1,262 @@
+/// refer/format.rs — File formats refer writes and reads
+///
+/// Every format carries the same thing, one record per chunk giving
+/// an accession, a start and a strand, dressed as a different kind of
+/// genomics file:
+///
+///   bed       6-column BED (coordinate.rs) — the default
+///   bed12     12-column BED (coordinate.rs)
+///   vcf       variant calls (vcf.rs)
+///   gff3      feature annotations (gff.rs)
+///   sam       aligned reads (sam.rs)
+///   bedgraph  a coverage track, order in keyed values (bedgraph.rs)
+///   fasta     chunks inside real flanking sequence (fasta.rs)
+///
+/// Refer picks one with `--format`. Unrefer recognises the format from the
+/// file's first line, so it needs no flag.
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::crypto::ReferKey;
+use crate::error::{DendecError, Result};
+use super::assembly::Assembly;
+use super::bedgraph::{read_bedgraph, BedGraphWriter, BEDGRAPH_SIGNATURE};
+use super::chunk::Kmer;
+use super::coordinate::{read_bed, BedHeader, BedLayout, BedRecord, BedWriter, BED_SIGNATURE};
+use super::fasta::{read_fasta_regions, FastaWriter, FASTA_SIGNATURE};
+use super::naming::Naming;
+use super::gff::{read_gff, GffWriter, GFF3_SIGNATURE};
+use super::gtf::{read_gtf, GtfWriter, GTF_SIGNATURE};
+use super::sam::{read_sam, SamWriter, SAM_SIGNATURE};
+use super::vcf::{read_vcf, VcfWriter, VCF_SIGNATURE};
+
+/// Bytes of a file's first line read to recognise its format.
+const SNIFF_LEN: u64 = 256;
+
+/// An output format for refer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Bed,
+    Bed12,
+    Vcf,
+    Gff3,
+    Gtf,
+    Sam,
+    BedGraph,
+    Fasta,
+}
+
+impl Format {
+    pub const ALL: [Format; 8] = [
+        Format::Bed,
+        Format::Bed12,
+        Format::Vcf,
+        Format::Gff3,
+        Format::Gtf,
+        Format::Sam,
+        Format::BedGraph,
+        Format::Fasta,
+    ];
+
+    /// Name as given to `--format`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Bed => "bed",
+            Format::Bed12 => "bed12",
+            Format::Vcf => "vcf",
+            Format::Gff3 => "gff3",
+            Format::Gtf => "gtf",
+            Format::Sam => "sam",
+            Format::BedGraph => "bedgraph",
+            Format::Fasta => "fasta",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Format::ALL
+            .into_iter()
+            .find(|f| f.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Format::ALL.iter().map(|f| f.name()).collect();
+                format!("unknown format '{s}' (expected one of: {})", names.join(", "))
+            })
+    }
+}
+
+/// What every format records in its header.
+pub struct Meta<'a> {
+    pub assembly: Assembly,
+    /// Bases per chunk, the table's chunk size.
+    pub chunk_size: usize,
+    /// Total base count of the source DNA string.
+    pub dna_length: usize,
+    /// SHA-256 of the source DNA string, lowercase hex.
+    pub payload_sha256: &'a str,
+    /// SHA-256 checksum of the table that mapped the chunks, lowercase hex.
+    pub table_sha256: &'a str,
+    pub chunk_count: usize,
+    /// How records are named: for their chunk, in a keyed
+    /// `--name-style`, or not at all when they are shuffled.
+    pub naming: Naming,
+    /// Every accession in the table, for formats that declare them.
+    pub accessions: &'a [String],
+    /// Length of each accession as far as the table knows, likewise.
+    pub contig_lengths: &'a [u64],
+    /// The refer key, when there is a password.
+    pub key: Option<&'a ReferKey>,
+    /// The genome FASTA behind the table, for formats that show flanks.
+    pub reference: Option<&'a Path>,
+}
+
+/// One chunk on its way out.
+pub struct Chunk<'a> {
+    /// Index of the chunk in the payload, which names its record.
+    pub idx: usize,
+    pub accession: &'a str,
+    pub start: u64,
+    /// 0 = forward (+), 1 = reverse (-).
+    pub strand: u8,
+    /// The chunk's bases, for formats that show reference bases.
+    pub kmer: Kmer,
+}
+
+/// Writes one record per chunk, in the order pushed.
+pub trait RecordWriter {
+    fn push(&mut self, chunk: &Chunk) -> Result<()>;
+
+    /// Called after the last push, for formats that hold records back.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Write the header for `format` and return a writer for its records.
+pub fn writer<'w, W: Write + 'w>(format: Format, w: W, meta: &Meta) -> Result<Box<dyn RecordWriter + 'w>> {
+    Ok(match format {
+        Format::Bed => Box::new(BedWriter::new(w, meta, BedLayout::Bed6)?),
+        Format::Bed12 => Box::new(BedWriter::new(w, meta, BedLayout::Bed12)?),
+        Format::Vcf => Box::new(VcfWriter::new(w, meta)?),
+        Format::Gff3 => Box::new(GffWriter::new(w, meta)?),
+        Format::Gtf => Box::new(GtfWriter::new(w, meta)?),
+        Format::Sam => Box::new(SamWriter::new(w, meta)?),
+        Format::BedGraph => {
+            let key = meta.key.ok_or(DendecError::ReferPasswordRequired("--format bedgraph"))?;
+            Box::new(BedGraphWriter::new(w, meta, key)?)
+        }
+        Format::Fasta => {
+            let reference = meta.reference.ok_or_else(|| {
+                DendecError::ReferReference("--format fasta needs --reference, the FASTA the table was built from".to_string())
+            })?;
+            Box::new(FastaWriter::new(w, meta, reference)?)
+        }
+    })
+}
+
+/// The format of the refer file at `path`, told by its first line.
+/// BED6 and BED12 both come back as `Format::Bed`, as does anything
+/// unrecognised, for read_bed to report on.
+pub fn detect(path: &Path) -> Result<Format> {
+    Ok(sniff(path)?.unwrap_or(Format::Bed))
+}
+
+/// Whether `path` starts the way every file refer writes does.
+pub fn is_refer_file(path: &Path) -> Result<bool> {
+    Ok(sniff(path)?.is_some())
+}
+
+/// Whether `sample`, the start of a file, opens like a refer BED (BED6
+/// or BED12), the format `wrap --refer` writes. Unlike the VCF or GFF3
+/// signatures, no file from another tool starts this way.
+pub fn is_refer_bed(sample: &[u8]) -> bool {
+    sample.starts_with(BED_SIGNATURE.as_bytes())
+}
+
+fn sniff(path: &Path) -> Result<Option<Format>> {
+    // A .dna is one long line; the signatures are all near the start
+    let mut first = Vec::new();
+    BufReader::new(File::open(path).map_err(DendecError::Io)?)
+        .take(SNIFF_LEN)
+        .read_until(b'\n', &mut first)
+        .map_err(DendecError::Io)?;
+    let first = String::from_utf8_lossy(&first);
+    Ok(if first.starts_with(BED_SIGNATURE) {
+        Some(Format::Bed)
+    } else if first.starts_with(VCF_SIGNATURE) {
+        Some(Format::Vcf)
+    } else if first.starts_with(GFF3_SIGNATURE) {
+        Some(Format::Gff3)
+    } else if first.starts_with(GTF_SIGNATURE) {
+        Some(Format::Gtf)
+    } else if first.starts_with(SAM_SIGNATURE) {
+        Some(Format::Sam)
+    } else if first.starts_with(BEDGRAPH_SIGNATURE) {
+        Some(Format::BedGraph)
+    } else if first.starts_with(FASTA_SIGNATURE) {
+        Some(Format::Fasta)
+    } else {
+        None
+    })
+}
+
+/// Parse a refer file of any format from `path` (see detect). Records
+/// come back in chunk order where they are named, else in file order.
+/// A bedGraph needs `key` to unmask its values.
+pub fn read_records(path: &Path, key: Option<&ReferKey>) -> Result<(BedHeader, Vec<BedRecord>)> {
+    match detect(path)? {
+        Format::Bed | Format::Bed12 => read_bed(path),
+        Format::Vcf => read_vcf(path),
+        Format::Gff3 => read_gff(path),
+        Format::Gtf => read_gtf(path),
+        Format::Sam => read_sam(path),
+        Format::Fasta => read_fasta_regions(path),
+        Format::BedGraph => {
+            let key = key.ok_or(DendecError::ReferPasswordRequired("unrefer of a bedGraph"))?;
+            read_bedgraph(path, key)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sniff_first_line() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("f");
+        let cases = [
+            ("##dendec-refer v0.1.0\n", Some(Format::Bed)),
+            ("##fileformat=VCFv4.2\n", Some(Format::Vcf)),
+            ("##gff-version 3\n", Some(Format::Gff3)),
+            ("#!genome-build GCF_000001405.40 hg38\n", Some(Format::Gtf)),
+            ("@HD\tVN:1.6\tSO:unsorted\n", Some(Format::Sam)),
+            ("track type=bedGraph name=signal\n", Some(Format::BedGraph)),
+            (";dendec-refer v0.1.0\n", Some(Format::Fasta)),
+            ("ATGC", None),
+        ];
+        for (first, format) in cases {
+            std::fs::write(&path, format!("{first}NC_000001.11\t0\t8\n")).unwrap();
+            assert_eq!(sniff(&path).unwrap(), format, "{first:?}");
+        }
+
+        // A long .dna is not read whole, and is no refer file
+        std::fs::write(&path, "ATGC".repeat(1 << 16)).unwrap();
+        assert!(!is_refer_file(&path).unwrap());
+        assert_eq!(detect(&path).unwrap(), Format::Bed);
+    }
+}
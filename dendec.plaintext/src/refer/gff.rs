@@ -0,0 +1,222 @@
+/// refer/gff.rs — GFF3 read and write
+///
+/// `refer --format gff3` writes each chunk as a feature annotation, for
+/// labs that exchange GFF rather than BED:
+///
+///  ##gff-version 3
+///  #!processor dendec-refer v0.1.0
+///  #!genome-build GCF_000001405.40 hg38
+///  #!chunk_size 8
+///  #!dna_length 168432
+///  #!chunk_count 21054
+///  #!payload_sha256 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///  #!table_sha256 a415178627e2c5200c58944182e5d2feecc55543bfc7f0cbd994385be46efc71
+///  NC_000001.11  dendec  sequence_feature  883402  883409  .  +  .  ID=chunk_00000000
+///  NC_000007.14  dendec  sequence_feature  553085  553092  .  -  .  ID=chunk_00000001
+///
+/// Columns 4 and 5 are 1-based and inclusive, so they are the table's
+/// start + 1 and start + chunk size. The chunk index travels in the ID attribute;
+/// shuffled records have no ID and their attributes column is ".".
+/// Unrefer reads columns 1, 4, 7 and 9.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::error::{DendecError, Result};
+use super::assembly::Assembly;
+use super::coordinate::{
+    order_records, parse_chunk_name, BedHeader, BedRecord, REFER_VERSION,
+};
+use super::format::{Chunk, Meta, RecordWriter};
+use super::naming::{keyed_name, Naming};
+
+/// First line of every GFF3 file; unrefer recognises the format by it.
+pub const GFF3_SIGNATURE: &str = "##gff-version 3";
+
+/// Source and type columns of every record.
+const SOURCE: &str = "dendec";
+const FEATURE_TYPE: &str = "sequence_feature";
+
+/// Streams a dendec-refer GFF3 file, one record at a time.
+pub struct GffWriter<W: Write> {
+    w: W,
+    chunk_size: usize,
+    /// Give each record an ID attribute; none when records are shuffled.
+    naming: Naming,
+}
+
+impl<W: Write> GffWriter<W> {
+    /// Write the version pragma and the #! metadata lines.
+    pub fn new(mut w: W, meta: &Meta) -> Result<Self> {
+        writeln!(w, "{}", GFF3_SIGNATURE).map_err(DendecError::Io)?;
+        writeln!(w, "#!processor dendec-refer v{}", REFER_VERSION).map_err(DendecError::Io)?;
+        writeln!(w, "#!genome-build {}", meta.assembly.header_value()).map_err(DendecError::Io)?;
+        writeln!(w, "#!chunk_size {}", meta.chunk_size).map_err(DendecError::Io)?;
+        writeln!(w, "#!dna_length {}", meta.dna_length).map_err(DendecError::Io)?;
+        writeln!(w, "#!chunk_count {}", meta.chunk_count).map_err(DendecError::Io)?;
+        writeln!(w, "#!payload_sha256 {}", meta.payload_sha256).map_err(DendecError::Io)?;
+        writeln!(w, "#!table_sha256 {}", meta.table_sha256).map_err(DendecError::Io)?;
+        Ok(GffWriter { w, chunk_size: meta.chunk_size, naming: meta.naming.clone() })
+    }
+}
+
+impl<W: Write> RecordWriter for GffWriter<W> {
+    fn push(&mut self, chunk: &Chunk) -> Result<()> {
+        let attributes = match self.naming.name(chunk.idx) {
+            Some(name) => format!("ID={}", name),
+            None => ".".to_string(),
+        };
+        writeln!(
+            self.w,
+            "{}\t{}\t{}\t{}\t{}\t.\t{}\t.\t{}",
+            chunk.accession,
+            SOURCE,
+            FEATURE_TYPE,
+            chunk.start + 1,
+            chunk.start + self.chunk_size as u64,
+            if chunk.strand == 0 { "+" } else { "-" },
+            attributes
+        )
+        .map_err(DendecError::Io)?;
+        Ok(())
+    }
+}
+
+/// Parse a dendec-refer GFF3 file from `path`.
+///
+/// Returns the header metadata and the records, sorted by chunk index
+/// unless they are shuffled.
+pub fn read_gff(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
+    let reader = BufReader::new(File::open(path).map_err(DendecError::Io)?);
+    let invalid = |reason: String| DendecError::ReferInvalidBed(format!("GFF3: {}", reason));
+
+    let mut header = BedHeader::default();
+    let mut records = Vec::new();
+
+    for raw in reader.lines() {
+        let line = raw.map_err(DendecError::Io)?;
+        let line = line.trim();
+
+        // ── Metadata lines ────────────────────────────────────────────
+        if let Some(meta) = line.strip_prefix("#!") {
+            let (key, value) = meta.split_once(' ').unwrap_or((meta, ""));
+            match key {
+                "genome-build" => header.assembly = Some(Assembly::from_header_value(value)?),
+                "chunk_size" => header.chunk_size = value.trim().parse().unwrap_or(0),
+                "dna_length" => header.dna_length = value.trim().parse().unwrap_or(0),
+                "chunk_count" => header.chunk_count = value.trim().parse().unwrap_or(0),
+                "payload_sha256" => header.payload_sha256 = Some(value.trim().to_string()),
+                "table_sha256" => header.table_sha256 = Some(value.trim().to_string()),
+                _ => {}
+            }
+            continue;
+        }
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        // ── Data lines ────────────────────────────────────────────────
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() != 9 {
+            return Err(invalid(format!("expected 9 tab-separated columns, got {}: {:?}", cols.len(), line)));
+        }
+
+        let start: u64 = cols[3]
+            .parse()
+            .ok()
+            .filter(|&p| p > 0)
+            .ok_or_else(|| invalid(format!("invalid start '{}' in line: {}", cols[3], line)))?;
+
+        let strand = match cols[6] {
+            "+" => 0,
+            "-" => 1,
+            other => return Err(invalid(format!("invalid strand '{}' in line: {}", other, line))),
+        };
+
+        let id = match cols[8] {
+            "." => ".",
+            attributes => attributes
+                .split(';')
+                .find_map(|a| a.strip_prefix("ID="))
+                .ok_or_else(|| invalid(format!("no ID attribute in line: {}", line)))?,
+        };
+
+        records.push(BedRecord {
+            accession: cols[0].to_string(),
+            start: start - 1,
+            strand,
+            chunk_idx: parse_chunk_name(id)?,
+            keyed_name: keyed_name(id),
+        });
+    }
+
+    order_records(&mut records)?;
+    Ok((header, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::chunk::Kmer;
+    use tempfile::tempdir;
+
+    fn write(chunk_names: bool) -> String {
+        let meta = Meta {
+            assembly: Assembly::Hg38,
+            chunk_size: 8,
+            dna_length: 16,
+            payload_sha256: "",
+            table_sha256: "",
+            chunk_count: 2,
+            naming: match chunk_names {
+                true => Naming::Chunk,
+                false => Naming::Hidden,
+            },
+            accessions: &[],
+            contig_lengths: &[],
+            key: None,
+            reference: None,
+        };
+        let mut gff = GffWriter::new(Vec::new(), &meta).unwrap();
+        gff.push(&Chunk { idx: 0, accession: "NC_000001.11", start: 883401, strand: 0, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        gff.push(&Chunk { idx: 1, accession: "NC_000007.14", start: 553084, strand: 1, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        String::from_utf8(gff.w).unwrap()
+    }
+
+    #[test]
+    fn test_write_and_read_roundtrip() {
+        let text = write(true);
+        assert!(text.starts_with("##gff-version 3\n"));
+        assert!(text.contains("NC_000001.11\tdendec\tsequence_feature\t883402\t883409\t.\t+\t.\tID=chunk_00000000\n"));
+        assert!(text.contains("NC_000007.14\tdendec\tsequence_feature\t553085\t553092\t.\t-\t.\tID=chunk_00000001\n"));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.gff3");
+        std::fs::write(&path, text).unwrap();
+        let (header, records) = read_gff(&path).unwrap();
+        assert_eq!((header.assembly, header.dna_length, header.chunk_count), (Some(Assembly::Hg38), 16, 2));
+        assert_eq!((records[0].start, records[0].strand, records[0].chunk_idx), (883401, 0, Some(0)));
+        assert_eq!((records[1].start, records[1].strand, records[1].chunk_idx), (553084, 1, Some(1)));
+
+        // Shuffled records carry no ID and keep their file order
+        std::fs::write(&path, write(false)).unwrap();
+        let (_, records) = read_gff(&path).unwrap();
+        assert!(records.iter().all(|r| r.chunk_idx.is_none()));
+        assert_eq!(records[1].start, 553084);
+    }
+
+    #[test]
+    fn test_bad_records_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bad.gff3");
+        for line in [
+            "NC_000001.11\tdendec\tsequence_feature\t0\t8\t.\t+\t.\tID=chunk_00000000",
+            "NC_000001.11\tdendec\tsequence_feature\t10\t17\t.\t*\t.\tID=chunk_00000000",
+            "NC_000001.11\tdendec\tsequence_feature\t10\t17\t.\t+\t.\tName=chunk_00000000",
+            "NC_000001.11\tdendec\tsequence_feature\t10\t17\t.\t+",
+        ] {
+            std::fs::write(&path, format!("##gff-version 3\n{line}\n")).unwrap();
+            assert!(read_gff(&path).is_err(), "accepted {line:?}");
+        }
+    }
+}
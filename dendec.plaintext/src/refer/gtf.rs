@@ -0,0 +1,222 @@
+/// refer/gtf.rs — GTF read and write
+///
+/// `refer --format gtf` writes each chunk as an exon, for collaborators
+/// whose tools take GTF (GFF2) annotation only. The metadata lines follow
+/// Ensembl's #! convention:
+///
+///  #!genome-build GCF_000001405.40 hg38
+///  #!processor dendec-refer v0.1.0
+///  #!chunk_size 8
+///  #!dna_length 168432
+///  #!chunk_count 21054
+///  #!payload_sha256 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///  #!table_sha256 a415178627e2c5200c58944182e5d2feecc55543bfc7f0cbd994385be46efc71
+///  NC_000001.11  dendec  exon  883402  883409  .  +  .  gene_id "chunk_00000000"; transcript_id "chunk_00000000";
+///  NC_000007.14  dendec  exon  553085  553092  .  -  .  gene_id "chunk_00000001"; transcript_id "chunk_00000001";
+///
+/// Columns 4 and 5 are 1-based and inclusive, as in GFF3. GTF requires
+/// gene_id and transcript_id on every record; both carry the chunk name,
+/// or "." when records are shuffled. Unrefer reads columns 1, 4, 7 and
+/// the transcript_id attribute.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::error::{DendecError, Result};
+use super::assembly::Assembly;
+use super::coordinate::{order_records, parse_chunk_name, BedHeader, BedRecord, REFER_VERSION};
+use super::format::{Chunk, Meta, RecordWriter};
+use super::naming::{keyed_name, Naming};
+
+/// First line of every refer GTF; unrefer recognises the format by it.
+pub const GTF_SIGNATURE: &str = "#!genome-build ";
+
+/// Source and feature columns of every record.
+const SOURCE: &str = "dendec";
+const FEATURE_TYPE: &str = "exon";
+
+/// Streams a dendec-refer GTF file, one record at a time.
+pub struct GtfWriter<W: Write> {
+    w: W,
+    chunk_size: usize,
+    /// Name for gene_id and transcript_id; "." when records are shuffled.
+    naming: Naming,
+}
+
+impl<W: Write> GtfWriter<W> {
+    /// Write the #! metadata lines.
+    pub fn new(mut w: W, meta: &Meta) -> Result<Self> {
+        writeln!(w, "{}{}", GTF_SIGNATURE, meta.assembly.header_value()).map_err(DendecError::Io)?;
+        writeln!(w, "#!processor dendec-refer v{}", REFER_VERSION).map_err(DendecError::Io)?;
+        writeln!(w, "#!chunk_size {}", meta.chunk_size).map_err(DendecError::Io)?;
+        writeln!(w, "#!dna_length {}", meta.dna_length).map_err(DendecError::Io)?;
+        writeln!(w, "#!chunk_count {}", meta.chunk_count).map_err(DendecError::Io)?;
+        writeln!(w, "#!payload_sha256 {}", meta.payload_sha256).map_err(DendecError::Io)?;
+        writeln!(w, "#!table_sha256 {}", meta.table_sha256).map_err(DendecError::Io)?;
+        Ok(GtfWriter { w, chunk_size: meta.chunk_size, naming: meta.naming.clone() })
+    }
+}
+
+impl<W: Write> RecordWriter for GtfWriter<W> {
+    fn push(&mut self, chunk: &Chunk) -> Result<()> {
+        let name = self.naming.name(chunk.idx).unwrap_or_else(|| ".".to_string());
+        writeln!(
+            self.w,
+            "{}\t{}\t{}\t{}\t{}\t.\t{}\t.\tgene_id \"{}\"; transcript_id \"{}\";",
+            chunk.accession,
+            SOURCE,
+            FEATURE_TYPE,
+            chunk.start + 1,
+            chunk.start + self.chunk_size as u64,
+            if chunk.strand == 0 { "+" } else { "-" },
+            name,
+            name
+        )
+        .map_err(DendecError::Io)?;
+        Ok(())
+    }
+}
+
+/// Parse a dendec-refer GTF file from `path`.
+///
+/// Returns the header metadata and the records, sorted by chunk index
+/// unless they are shuffled.
+pub fn read_gtf(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
+    let reader = BufReader::new(File::open(path).map_err(DendecError::Io)?);
+    let invalid = |reason: String| DendecError::ReferInvalidBed(format!("GTF: {}", reason));
+
+    let mut header = BedHeader::default();
+    let mut records = Vec::new();
+
+    for raw in reader.lines() {
+        let line = raw.map_err(DendecError::Io)?;
+        let line = line.trim();
+
+        // ── Metadata lines ────────────────────────────────────────────
+        if let Some(meta) = line.strip_prefix("#!") {
+            let (key, value) = meta.split_once(' ').unwrap_or((meta, ""));
+            match key {
+                "genome-build" => header.assembly = Some(Assembly::from_header_value(value)?),
+                "chunk_size" => header.chunk_size = value.trim().parse().unwrap_or(0),
+                "dna_length" => header.dna_length = value.trim().parse().unwrap_or(0),
+                "chunk_count" => header.chunk_count = value.trim().parse().unwrap_or(0),
+                "payload_sha256" => header.payload_sha256 = Some(value.trim().to_string()),
+                "table_sha256" => header.table_sha256 = Some(value.trim().to_string()),
+                _ => {}
+            }
+            continue;
+        }
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        // ── Data lines ────────────────────────────────────────────────
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() != 9 {
+            return Err(invalid(format!("expected 9 tab-separated columns, got {}: {:?}", cols.len(), line)));
+        }
+
+        let start: u64 = cols[3]
+            .parse()
+            .ok()
+            .filter(|&p| p > 0)
+            .ok_or_else(|| invalid(format!("invalid start '{}' in line: {}", cols[3], line)))?;
+
+        let strand = match cols[6] {
+            "+" => 0,
+            "-" => 1,
+            other => return Err(invalid(format!("invalid strand '{}' in line: {}", other, line))),
+        };
+
+        let id = attribute(cols[8], "transcript_id")
+            .ok_or_else(|| invalid(format!("no transcript_id attribute in line: {}", line)))?;
+
+        records.push(BedRecord {
+            accession: cols[0].to_string(),
+            start: start - 1,
+            strand,
+            chunk_idx: parse_chunk_name(id)?,
+            keyed_name: keyed_name(id),
+        });
+    }
+
+    order_records(&mut records)?;
+    Ok((header, records))
+}
+
+/// The value of `key` in a GTF attributes column (`key "value"; …`),
+/// unquoted.
+fn attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    attributes.split(';').find_map(|a| {
+        let (k, v) = a.trim().split_once(' ')?;
+        (k == key).then(|| v.trim().trim_matches('"'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::chunk::Kmer;
+    use tempfile::tempdir;
+
+    fn write(chunk_names: bool) -> String {
+        let meta = Meta {
+            assembly: Assembly::Hg38,
+            chunk_size: 8,
+            dna_length: 16,
+            payload_sha256: "",
+            table_sha256: "",
+            chunk_count: 2,
+            naming: match chunk_names {
+                true => Naming::Chunk,
+                false => Naming::Hidden,
+            },
+            accessions: &[],
+            contig_lengths: &[],
+            key: None,
+            reference: None,
+        };
+        let mut gtf = GtfWriter::new(Vec::new(), &meta).unwrap();
+        gtf.push(&Chunk { idx: 0, accession: "NC_000001.11", start: 883401, strand: 0, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        gtf.push(&Chunk { idx: 1, accession: "NC_000007.14", start: 553084, strand: 1, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        String::from_utf8(gtf.w).unwrap()
+    }
+
+    #[test]
+    fn test_write_and_read_roundtrip() {
+        let text = write(true);
+        assert!(text.starts_with("#!genome-build GCF_000001405.40 hg38\n"), "{text}");
+        assert!(text.contains(
+            "NC_000007.14\tdendec\texon\t553085\t553092\t.\t-\t.\tgene_id \"chunk_00000001\"; transcript_id \"chunk_00000001\";\n"
+        ));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.gtf");
+        std::fs::write(&path, text).unwrap();
+        let (header, records) = read_gtf(&path).unwrap();
+        assert_eq!((header.assembly, header.dna_length, header.chunk_count), (Some(Assembly::Hg38), 16, 2));
+        assert_eq!((records[0].start, records[0].strand, records[0].chunk_idx), (883401, 0, Some(0)));
+        assert_eq!((records[1].start, records[1].strand, records[1].chunk_idx), (553084, 1, Some(1)));
+
+        // Shuffled records are named "." and keep their file order
+        std::fs::write(&path, write(false)).unwrap();
+        let (_, records) = read_gtf(&path).unwrap();
+        assert!(records.iter().all(|r| r.chunk_idx.is_none()));
+        assert_eq!(records[1].start, 553084);
+    }
+
+    #[test]
+    fn test_bad_records_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bad.gtf");
+        for line in [
+            "NC_000001.11\tdendec\texon\t0\t8\t.\t+\t.\tgene_id \"chunk_00000000\"; transcript_id \"chunk_00000000\";",
+            "NC_000001.11\tdendec\texon\t10\t17\t.\t*\t.\tgene_id \"chunk_00000000\"; transcript_id \"chunk_00000000\";",
+            "NC_000001.11\tdendec\texon\t10\t17\t.\t+\t.\tgene_id \"chunk_00000000\";",
+            "NC_000001.11\tdendec\texon\t10\t17\t.\t+",
+        ] {
+            std::fs::write(&path, format!("#!genome-build GCF_000001405.40 hg38\n{line}\n")).unwrap();
+            assert!(read_gtf(&path).is_err(), "accepted {line:?}");
+        }
+    }
+}
@@ -2,107 +2,454 @@
 ///
 /// Exposes two public functions that main.rs calls directly:
 ///
-///   refer_encode(from, to) — .dna file → .bed file
-///   refer_decode(from, to) — .bed file → .dna file
+///   refer_encode(from, to, keying, opts, overwrite)       — .dna file → .bed file
+///   refer_decode(from, to, assembly, password, overwrite) — .bed file → .dna file
 ///
-/// Both are fully offline. The embedded lookup table handles all
-/// coordinate translation without any network access.
-
+/// plus refer_bytes and unrefer_quiet, the in-memory pair behind
+/// `wrap --refer`.
+///
+/// Both are fully offline. The embedded lookup table for the chosen
+/// assembly handles all coordinate translation without any network access.
+/// `dendec refer verify` (audit.rs) checks a refer file decodes, and to
+/// which .dna; `refer --audit` (tells.rs) rates how much it looks made.
+/// Tables themselves are generated by `dendec table build` (build.rs),
+/// inspected by `dendec table stats` (stats.rs) and checked by
+/// `dendec table verify` (verify.rs).
+pub mod assembly;
+pub mod audit;
+pub mod build;
+pub mod coverage;
+pub mod stats;
+pub mod tells;
+pub mod verify;
 pub mod table;
-mod chunk;
+pub mod format;
+pub mod chunk;
+pub mod chrom;
+pub mod naming;
+pub mod part;
 mod coordinate;
+mod fasta;
 mod reverse;
+mod bedgraph;
+mod gff;
+mod gtf;
+mod sam;
+mod twobit;
+mod vcf;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
 
-use std::path::PathBuf;
+use log::info;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
 
+use crate::crypto::{derive_refer_key, ReferKey};
 use crate::error::{DendecError, Result};
-use chunk::{split_into_kmers, reassemble};
-use coordinate::{read_bed, write_bed};
-use table::{CoordKey, ReferTable};
+use crate::output::{check_output, prepare_output, write_atomic_with, OverwritePolicy};
+use assembly::Assembly;
+use chrom::ChromStyle;
+use chunk::{reassemble, Kmer, KmerReader};
+use coordinate::{order_records, BedHeader, BedRecord};
+use format::{read_records, Chunk, Format, Meta};
+use naming::{NameCipher, NameStyle, Naming};
+use table::{Coord, CoordKey, ReferTable};
+
+/// Buffer size for streaming a .dna file through refer encode.
+const READ_BUFFER: usize = 1 << 16;
+
+/// Password-dependent refer options, each opted into separately.
+#[derive(Clone, Copy)]
+pub struct Keying<'a> {
+    pub password: &'a str,
+    /// Pick coordinates with a CSPRNG seeded by the password (`--keyed`).
+    pub select: bool,
+    /// Write records in an order derived from the password (`--shuffle`).
+    pub shuffle: bool,
+}
+
+/// Refer options that need no password, set from CLI flags.
+#[derive(Debug, Clone, Default)]
+pub struct ReferOptions {
+    /// Assembly whose table maps the chunks (`--assembly`)
+    pub assembly: Assembly,
+    /// Output format (`--format`, `--bed12`)
+    pub format: Format,
+    /// Genome FASTA for the flanks of `Format::Fasta` (`--reference`)
+    pub reference: Option<PathBuf>,
+    /// Also pick − strand coordinates where a chunk's reverse complement
+    /// lies on the + strand (`--both-strands`)
+    pub both_strands: bool,
+    /// Chromosome names to write (`--chrom-style`)
+    pub chrom_style: ChromStyle,
+    /// Record names to write (`--name-style`); a keyed style needs a
+    /// password
+    pub name_style: NameStyle,
+}
 
-/// Convert a .dna file into a genomic coordinate BED file.
+/// Convert a .dna file into a genomic coordinate BED file, or another
+/// `opts.format` from format.rs.
 ///
 /// Reads the flat ATGC string produced by `dendec encode`, chunks it
-/// into successive 8-mers, maps each to a real hg38 coordinate via the
-/// embedded lookup table, and writes a standard BED file. Fully offline.
-pub fn refer_encode(from: PathBuf, to: PathBuf) -> Result<()> {
+/// into successive k-mers of the table's chunk size, maps each to a real
+/// coordinate in `opts.assembly` via its embedded lookup table, and writes one
+/// record per k-mer. A short last chunk is completed with filler; the
+/// header's dna_length tells unrefer where to cut it off. Fully offline.
+///
+/// The .dna is streamed twice: once to validate it, count its bases and
+/// hash them for the BED header, then again to map each k-mer and write its line.
+/// Memory stays constant however long the sequence is, and the output is
+/// written atomically, so a failure part-way leaves no partial BED.
+///
+/// Each k-mer's coordinate is picked at random from its options, one
+/// the file has not used yet while there is one (see Picker), unless
+/// `keying.select`: then the picks come from a CSPRNG seeded by the
+/// password's refer key, so the same password and .dna always give the
+/// same BED, and nobody without the password can reproduce it. Unrefer
+/// does not need the password for that.
+///
+/// With `keying.shuffle` the records are written in a password-derived
+/// order and named "." instead of chunk_NNNNNNNN, so the file no longer
+/// shows which chunk comes first. Unrefer needs the same password to
+/// put them back. Shuffling holds every coordinate in memory until the
+/// last one is mapped.
+///
+/// With `opts.both_strands` a chunk may also land where its reverse
+/// complement lies on the + strand, written as a − strand record; the
+/// table lists such places under the reverse complement, so this
+/// widens the options of every chunk. Unrefer resolves them either way.
+///
+/// With a keyed `opts.name_style` the records carry RefSeq- or
+/// probe-like names that only the password turns back into chunk
+/// indices (see naming.rs); with `keying.shuffle` too, they are written
+/// in the shuffled order under those names.
+///
+/// `Format::BedGraph` always needs `keying`, whose key masks the values
+/// that carry chunk order; `keying.shuffle` then changes nothing.
+/// `Format::Fasta` needs the `opts.reference` genome FASTA for its flanks.
+pub fn refer_encode(
+    from: PathBuf,
+    to: PathBuf,
+    keying: Option<Keying<'_>>,
+    opts: &ReferOptions,
+    overwrite: OverwritePolicy,
+) -> Result<()> {
+    check_output(&to, overwrite)?;
+    let (assembly, format) = (opts.assembly, opts.format);
+
+    let key = match keying {
+        Some(keying) => {
+            info!("Deriving refer key… (Argon2id key derivation may take a moment)");
+            Some(derive_refer_key(keying.password)?)
+        }
+        None => None,
+    };
+    let mut rng = match (&key, keying.is_some_and(|k| k.select)) {
+        (Some(key), true) => key.selection_rng(),
+        _ => StdRng::from_entropy(),
+    };
+
     // ── Load table ────────────────────────────────────────────────────
-    eprintln!("Loading reference table...");
-    let table = ReferTable::load()?;
+    info!("Loading {} reference table...", assembly);
+    let table = ReferTable::load(assembly)?;
+    let contig_lengths = table.chrom_extents();
+    let chunk_size = table.chunk_size();
+    let chroms: Vec<String> = table
+        .accessions
+        .iter()
+        .map(|a| opts.chrom_style.apply(assembly, a).to_string())
+        .collect();
 
-    // ── Read DNA file ─────────────────────────────────────────────────
-    let raw = std::fs::read_to_string(&from).map_err(DendecError::Io)?;
+    // ── Validate DNA file ─────────────────────────────────────────────
+    let open = || -> Result<KmerReader<BufReader<File>>> {
+        Ok(KmerReader::new(BufReader::with_capacity(READ_BUFFER, File::open(&from)?), chunk_size))
+    };
+    let mut scan = open()?;
+    let mut hasher = Sha256::new();
+    let chunk_count = scan.by_ref().try_fold(0usize, |n, kmer| {
+        hasher.update(&*kmer?);
+        Ok::<_, DendecError>(n + 1)
+    })?;
+    let dna_length = scan.bases();
+    let payload_sha256 = hex_digest(hasher);
 
-    // Strip any whitespace (grouping spaces, newlines) the encode step
-    // may have introduced — same defensive strip as dendec decode uses.
-    let dna: String = raw.chars().filter(|c| !c.is_whitespace()).collect();
-    let dna_bytes = dna.as_bytes();
-    let dna_length = dna_bytes.len();
+    info!("  Read {} bases from {}", dna_length, from.display());
+    info!("  Mapping {} {}-mers to genome coordinates...", chunk_count, chunk_size);
 
-    eprintln!("  Read {} bases from {}", dna_length, from.display());
+    // A bedGraph is sorted by position, which hides the order already
+    let order = match (&key, keying.is_some_and(|k| k.shuffle)) {
+        (Some(key), true) if format != Format::BedGraph => Some(record_order(key, chunk_count)),
+        _ => None,
+    };
 
-    // ── Split into 8-mers ─────────────────────────────────────────────
-    let kmers = split_into_kmers(dna_bytes)?;
-    let chunk_count = kmers.len();
+    let naming = match Naming::new(opts.name_style, key.as_ref(), chunk_count)? {
+        Naming::Chunk if order.is_some() => Naming::Hidden,
+        naming => naming,
+    };
 
-    eprintln!("  Mapping {} 8-mers to genome coordinates...", chunk_count);
+    // ── Lookup each k-mer and write records ───────────────────────────
+    report_backup(prepare_output(&to, overwrite)?);
+    write_atomic_with(&to, None, None, |w| {
+        let meta = Meta {
+            assembly,
+            chunk_size,
+            dna_length,
+            payload_sha256: &payload_sha256,
+            table_sha256: &table.sha256(),
+            chunk_count,
+            naming: naming.clone(),
+            accessions: &chroms,
+            contig_lengths: &contig_lengths,
+            key: key.as_ref(),
+            reference: opts.reference.as_deref(),
+        };
+        let mut out = format::writer(format, w, &meta)?;
+        let mut push = |idx: usize, coord: &Coord, kmer: Kmer| {
+            let accession = chroms
+                .get(coord.chrom_idx as usize)
+                .ok_or(DendecError::ReferTableCorrupt)?;
+            out.push(&Chunk { idx, accession, start: coord.start, strand: coord.strand, kmer })
+        };
 
-    // ── Lookup each 8-mer ─────────────────────────────────────────────
-    let mut records: Vec<(String, u32, u8)> = Vec::with_capacity(chunk_count);
+        let mut picker = Picker::new(&table, opts.both_strands);
+        let mut held = Vec::with_capacity(if order.is_some() { chunk_count } else { 0 });
+        let mut mapped = 0;
+        for (i, kmer) in open()?.enumerate() {
+            let kmer = kmer?.padded(chunk_size);
+            let coord = picker
+                .pick(&kmer, &mut rng)
+                .ok_or(DendecError::ReferChunkNotFound { chunk: i })?;
+            mapped += 1;
+            match order {
+                Some(_) => held.push((coord, kmer)),
+                None => push(i, &coord, kmer)?,
+            }
+        }
+        // The header promised chunk_count records; a file that changed
+        // between the two passes must not leave it lying
+        if mapped != chunk_count {
+            return Err(DendecError::Io(std::io::Error::other(format!(
+                "{} changed while being read",
+                from.display()
+            ))));
+        }
+        for &chunk in order.iter().flatten() {
+            let (coord, kmer) = &held[chunk];
+            push(chunk, coord, *kmer)?;
+        }
+        out.finish()
+    })?;
 
-    for (i, kmer) in kmers.iter().enumerate() {
-        let coord = table
-            .lookup(kmer)
-            .ok_or(DendecError::ReferChunkNotFound { chunk: i })?;
+    info!(
+        "  Written {} chunks → {}",
+        chunk_count,
+        to.display()
+    );
+
+    Ok(())
+}
+
+/// Write `dna`, a payload already in memory, to `w` as a plain BED under
+/// `assembly`, each coordinate picked at random — refer_encode for
+/// `wrap --refer`, which has one payload per file and reports its own
+/// progress, so nothing is printed here.
+///
+/// `dna` is bases only, no grouping. As in refer_encode, a short last
+/// chunk is completed with filler.
+pub fn refer_bytes<W: Write>(dna: &[u8], assembly: Assembly, w: W) -> Result<()> {
+    let table = ReferTable::load(assembly)?;
+    let chunk_size = table.chunk_size();
 
+    let meta = Meta {
+        assembly,
+        chunk_size,
+        dna_length: dna.len(),
+        payload_sha256: &hex_digest(Sha256::new_with_prefix(dna)),
+        table_sha256: &table.sha256(),
+        chunk_count: dna.len().div_ceil(chunk_size),
+        naming: Naming::Chunk,
+        accessions: &table.accessions,
+        contig_lengths: &[],
+        key: None,
+        reference: None,
+    };
+
+    let mut out = format::writer(Format::Bed, w, &meta)?;
+    let mut rng = StdRng::from_entropy();
+    let mut picker = Picker::new(&table, false);
+    for (i, kmer) in KmerReader::new(dna, chunk_size).enumerate() {
+        let kmer = kmer?.padded(chunk_size);
+        let coord = picker
+            .pick(&kmer, &mut rng)
+            .ok_or(DendecError::ReferChunkNotFound { chunk: i })?;
         let accession = table
             .accession_for(coord.chrom_idx)
-            .ok_or(DendecError::ReferTableCorrupt)?
-            .to_string();
-
-        records.push((accession, coord.start, coord.strand));
+            .ok_or(DendecError::ReferTableCorrupt)?;
+        out.push(&Chunk { idx: i, accession, start: coord.start, strand: coord.strand, kmer })?;
     }
+    out.finish()
+}
 
-    // ── Write BED file ────────────────────────────────────────────────
-    write_bed(&to, &records, dna_length)?;
+/// Reconstruct a .dna file from a genomic coordinate BED file, or any
+/// other format refer writes (see unrefer).
+pub fn refer_decode(
+    from: PathBuf,
+    to: PathBuf,
+    assembly: Option<Assembly>,
+    password: Option<&str>,
+    overwrite: OverwritePolicy,
+) -> Result<()> {
+    check_output(&to, overwrite)?;
 
-    eprintln!(
-        "  Written {} chunks → {}",
-        chunk_count,
+    let dna = unrefer(&from, assembly, password)?;
+
+    report_backup(prepare_output(&to, overwrite)?);
+    std::fs::write(&to, dna.as_bytes()).map_err(DendecError::Io)?;
+
+    info!(
+        "  Recovered {} bases → {}",
+        dna.len(),
         to.display()
     );
 
     Ok(())
 }
 
-/// Reconstruct a .dna file from a genomic coordinate BED file.
+/// Reconstruct the DNA string from a refer file of any format
+/// (recognised by its first line), in memory.
 ///
-/// Parses the BED file, resolves each coordinate to its original 8-mer
-/// via the embedded reverse index, reassembles the 8-mers in chunk
-/// order, and writes the flat ATGC string. Fully offline.
-pub fn refer_decode(from: PathBuf, to: PathBuf) -> Result<()> {
-    // ── Load table ────────────────────────────────────────────────────
-    eprintln!("Loading reference table...");
-    let table = ReferTable::load()?;
+/// Parses the file, resolves each coordinate to its original k-mer
+/// via the embedded reverse index, and reassembles the k-mers in chunk
+/// order into the flat ATGC string. Fully offline.
+///
+/// The table is chosen by the BED `##assembly` header. `assembly`, when
+/// given, must agree with it; files without the header are taken to be
+/// hg38, the only assembly earlier versions wrote. The file's chunk size
+/// must match the table's; files without one used 8-base chunks. So
+/// must the table checksum it records, and every record's accession,
+/// all checked before the first lookup.
+///
+/// The reassembled DNA must match the header's payload_sha256, so a
+/// truncated or edited file fails here rather than at dendec decode.
+///
+/// A shuffled BED (records named ".") needs the `password` it was
+/// written with to restore chunk order. A wrong one yields scrambled
+/// DNA, which fails the checksum.
+/// A bedGraph needs the password to unmask its values, and a wrong one
+/// is detected.
+pub fn unrefer(from: &Path, assembly: Option<Assembly>, password: Option<&str>) -> Result<String> {
+    recover(from, assembly, password, true)
+}
+
+/// Unrefer a file that needs no password, reporting nothing — for
+/// `wrap -d`, which shows its own progress per file.
+pub fn unrefer_quiet(from: &Path) -> Result<String> {
+    recover(from, None, None, false)
+}
+
+/// unrefer, with progress on stderr only if `log`.
+fn recover(from: &Path, assembly: Option<Assembly>, password: Option<&str>, log: bool) -> Result<String> {
+    let derive = |password| {
+        if log {
+            info!("Deriving refer key… (Argon2id key derivation may take a moment)");
+        }
+        derive_refer_key(password)
+    };
 
     // ── Read BED file ─────────────────────────────────────────────────
-    let (header, records) = read_bed(&from)?;
+    // Only a bedGraph needs the key to be read at all
+    let format = format::detect(from)?;
+    let key = match (format, password) {
+        (Format::BedGraph, Some(password)) => Some(derive(password)?),
+        _ => None,
+    };
+    let (header, mut records) = read_records(from, key.as_ref())?;
 
-    eprintln!(
-        "  Read {} chunks from {}",
-        records.len(),
-        from.display()
-    );
+    if log {
+        info!(
+            "  Read {} chunks from {}",
+            records.len(),
+            from.display()
+        );
+    }
+
+    let assembly = match (assembly, header.assembly) {
+        (Some(expected), Some(got)) if expected != got => {
+            return Err(DendecError::ReferAssemblyMismatch {
+                expected: expected.header_value(),
+                got: got.header_value(),
+            });
+        }
+        (requested, recorded) => recorded.or(requested).unwrap_or_default(),
+    };
+
+    // ── Decode keyed names ────────────────────────────────────────────
+    let keyed = records.first().is_some_and(|r| r.keyed_name.is_some());
+    if keyed {
+        let password = password.ok_or(DendecError::ReferPasswordRequired("unrefer of --name-style records"))?;
+        decode_names(&mut records, &NameCipher::new(&derive(password)?), header.chunk_count)?;
+    }
+
+    // ── Recover chunk order ───────────────────────────────────────────
+    let shuffled = records.first().is_some_and(|r| r.chunk_idx.is_none());
+    let order = match (shuffled, password) {
+        _ if keyed => None,
+        (false, None) => None,
+        (false, Some(_)) if key.is_some() => None,
+        (true, Some(password)) => {
+            // The order depends on the chunk count, so every record is needed
+            if header.chunk_count != records.len() {
+                return Err(DendecError::ReferInvalidBed(format!(
+                    "header lists {} chunks but {} were read — a shuffled file cannot be \
+                     put back in order with records missing",
+                    header.chunk_count,
+                    records.len()
+                )));
+            }
+            Some(record_order(&derive(password)?, records.len()))
+        }
+        (true, None) => {
+            return Err(DendecError::ReferInvalidBed(
+                "records are unnamed, so the file was written with --shuffle — \
+                 unrefer it with --shuffle and the same password"
+                    .to_string(),
+            ));
+        }
+        (false, Some(_)) => {
+            return Err(DendecError::ReferInvalidBed(
+                "records are numbered, so the file was not shuffled — unrefer it without --shuffle"
+                    .to_string(),
+            ));
+        }
+    };
+
+    // ── Load table ────────────────────────────────────────────────────
+    if log {
+        info!("Loading {} reference table...", assembly);
+    }
+    let table = ReferTable::load(assembly)?;
+    check_table(&header, &mut records, &table, assembly)?;
 
     // ── Reverse lookup each coordinate ────────────────────────────────
-    let mut kmers: Vec<[u8; 8]> = Vec::with_capacity(records.len());
+    let mut kmers: Vec<Kmer> = vec![Kmer::default(); records.len()];
+
+    for (line, record) in records.iter().enumerate() {
+        // Position in the reassembled DNA, and the chunk to report
+        let (pos, chunk) = match &order {
+            Some(order) => (order[line], order[line]),
+            None => (line, record.chunk_idx.unwrap_or(line)),
+        };
 
-    for record in &records {
         // Resolve accession string → chrom_idx
         let chrom_idx = table
             .chrom_idx_for(&record.accession)
             .ok_or_else(|| DendecError::ReferAssemblyMismatch {
-                expected: "known hg38 accession".to_string(),
+                expected: format!("known {} accession", assembly),
                 got: record.accession.clone(),
             })?;
 
@@ -112,15 +459,14 @@ pub fn refer_decode(from: PathBuf, to: PathBuf) -> Result<()> {
             strand: record.strand,
         };
 
-        // O(1) reverse lookup → original 8-mer
-        let kmer = table
-            .reverse_lookup(&key)
-            .ok_or(DendecError::ReferChunkNotFound { chunk: record.chunk_idx })?;
-
-        kmers.push(kmer);
+        // O(log n) reverse lookup → original k-mer, complemented from
+        // the other strand if the table lists only that one there
+        kmers[pos] = table
+            .resolve(&key)
+            .ok_or(DendecError::ReferChunkNotFound { chunk })?;
     }
 
-    // ── Reassemble and write ──────────────────────────────────────────
+    // ── Reassemble and check ──────────────────────────────────────────
     let mut dna = reassemble(&kmers);
 
     // Defensive trim: if the original DNA length was recorded in the header,
@@ -129,14 +475,457 @@ pub fn refer_decode(from: PathBuf, to: PathBuf) -> Result<()> {
         dna.truncate(header.dna_length);
     }
 
-    std::fs::write(&to, dna.as_bytes()).map_err(DendecError::Io)?;
+    // Files from before the checksum was recorded skip this
+    if let Some(expected) = &header.payload_sha256 {
+        if !hex_digest(Sha256::new_with_prefix(&dna)).eq_ignore_ascii_case(expected) {
+            return Err(DendecError::ReferChecksumMismatch);
+        }
+        if log {
+            info!("  Payload checksum verified");
+        }
+    }
 
-    eprintln!(
-        "  Recovered {} bases → {}",
-        dna.len(),
-        to.display()
-    );
+    Ok(dna)
+}
+
+/// Set every record's chunk index from its `--name-style` name, then put
+/// the records in chunk order. An index out of range or given twice
+/// means the names were made under another password.
+fn decode_names(records: &mut [BedRecord], cipher: &NameCipher, chunk_count: usize) -> Result<()> {
+    let mut seen = vec![false; chunk_count.max(records.len())];
+    for record in records.iter_mut() {
+        let name = record.keyed_name.as_deref().ok_or_else(|| {
+            DendecError::ReferInvalidBed(
+                "some records have --name-style names and some do not — records from two files mixed?"
+                    .to_string(),
+            )
+        })?;
+        let idx = cipher.index(name).filter(|&i| i < seen.len() && !seen[i]).ok_or(DendecError::WrongPassword)?;
+        seen[idx] = true;
+        record.chunk_idx = Some(idx);
+    }
+    order_records(records)
+}
+
+/// Whether unreferring `path` needs a password: a bedGraph always does,
+/// other formats when their records were shuffled or carry
+/// `--name-style` names.
+pub fn needs_password(path: &Path) -> Result<bool> {
+    Ok(match format::detect(path)? {
+        Format::BedGraph => true,
+        _ => read_records(path, None)?.1.first().is_some_and(|r| r.chunk_idx.is_none()),
+    })
+}
+
+/// Whether the records of `path` carry `--name-style` names, which need
+/// the password to be put in order.
+pub fn has_keyed_names(path: &Path) -> Result<bool> {
+    Ok(match format::detect(path)? {
+        Format::BedGraph => false,
+        _ => read_records(path, None)?.1.first().is_some_and(|r| r.keyed_name.is_some()),
+    })
+}
 
+/// The password-derived record order for `count` chunks: line i of a
+/// shuffled BED holds chunk `order[i]`. A Fisher-Yates shuffle driven by
+/// the refer key's order CSPRNG.
+fn record_order(key: &ReferKey, count: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..count).collect();
+    order.shuffle(&mut key.order_rng());
+    order
+}
+
+/// Check a refer file against the table picked to unrefer it, before
+/// any lookups: a file made with another table would otherwise fail on
+/// its first chunk with no hint why. Compares the chunk size and table
+/// checksum the header records, then the accessions the records name,
+/// renaming chr1 to NC_000001.11 (or back) where the table lists the
+/// other style.
+fn check_table(
+    header: &BedHeader,
+    records: &mut [BedRecord],
+    table: &ReferTable,
+    assembly: Assembly,
+) -> Result<()> {
+    if header.chunk_size() != table.chunk_size() {
+        return Err(DendecError::ReferChunkSizeMismatch {
+            assembly,
+            file: header.chunk_size(),
+            table: table.chunk_size(),
+        });
+    }
+
+    // Files from before the checksum was recorded skip this
+    let short = |sha256: &str| sha256.get(..16).unwrap_or(sha256).to_string();
+    if let Some(recorded) = header.table_sha256.as_deref().filter(|s| !s.is_empty()) {
+        let sha256 = table.sha256();
+        if !recorded.eq_ignore_ascii_case(&sha256) {
+            return Err(DendecError::ReferAssemblyMismatch {
+                expected: format!("{} table {}…", assembly, short(&sha256)),
+                got: format!("table {}…", short(recorded)),
+            });
+        }
+    }
+
+    for record in records.iter_mut() {
+        if table.chrom_idx_for(&record.accession).is_none() {
+            if let Some(name) = chrom::counterpart(assembly, &record.accession) {
+                record.accession = name.to_string();
+            }
+        }
+    }
+    if let Some(record) = records.iter().find(|r| table.chrom_idx_for(&r.accession).is_none()) {
+        return Err(DendecError::ReferAssemblyMismatch {
+            expected: format!("known {} accession", assembly),
+            got: record.accession.clone(),
+        });
+    }
     Ok(())
 }
 
+/// Picks each chunk's coordinate for one file, preferring places the
+/// file has not used yet: a repeated chunk then repeats a line only once
+/// every option of its k-mer is taken. Each place is remembered, so the
+/// set grows to at most the table's coordinate count.
+struct Picker<'t, 'a> {
+    table: &'t ReferTable<'a>,
+    both_strands: bool,
+    used: HashSet<CoordKey>,
+}
+
+impl<'t, 'a> Picker<'t, 'a> {
+    fn new(table: &'t ReferTable<'a>, both_strands: bool) -> Self {
+        Picker { table, both_strands, used: HashSet::new() }
+    }
+
+    /// A coordinate for `kmer`, drawn by `rng` from the options not yet
+    /// used, or from all of them once none is left.
+    fn pick(&mut self, kmer: &[u8], rng: &mut impl Rng) -> Option<Coord> {
+        let coord = match self.both_strands {
+            true => self.table.lookup_either_strand(kmer, rng),
+            false => self.table.lookup(kmer, rng),
+        }?;
+        if self.used.insert(CoordKey::from(&coord)) {
+            return Some(coord);
+        }
+        // Drawn before: draw again among the rest, which keeps the pick
+        // uniform over the unused options
+        let mut fresh = self.table.options(kmer, self.both_strands);
+        fresh.retain(|c| !self.used.contains(&CoordKey::from(c)));
+        if fresh.is_empty() {
+            return Some(coord);
+        }
+        let pick = rng.gen_range(0..fresh.len());
+        let coord = fresh.swap_remove(pick);
+        self.used.insert(CoordKey::from(&coord));
+        Some(coord)
+    }
+}
+
+/// Finish `hasher` as lowercase hex, the form refer headers record.
+fn hex_digest(hasher: Sha256) -> String {
+    hasher.finalize().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn report_backup(backup: Option<PathBuf>) {
+    if let Some(backup) = backup {
+        info!("  Existing output moved to {}", backup.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn keyed(password: &str, select: bool, shuffle: bool) -> Option<Keying<'_>> {
+        Some(Keying { password, select, shuffle })
+    }
+
+    #[test]
+    fn test_keyed_refer_is_reproducible() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        // One 8-mer repeated, so every line is a fresh pick among its options
+        std::fs::write(&dna, "ATGCGATC".repeat(32)).unwrap();
+
+        let refer = |name: &str, password: &str| {
+            let bed = dir.path().join(name);
+            let keying = keyed(password, true, false);
+            refer_encode(dna.clone(), bed.clone(), keying, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+            std::fs::read_to_string(bed).unwrap()
+        };
+        let first = refer("a.bed", "hunter2");
+        assert_eq!(first, refer("b.bed", "hunter2"));
+        assert_ne!(first, refer("c.bed", "hunter3"));
+
+        // Unrefer needs no password
+        let back = dir.path().join("back.dna");
+        refer_decode(dir.path().join("a.bed"), back.clone(), None, None, OverwritePolicy::Refuse).unwrap();
+        assert_eq!(std::fs::read(back).unwrap(), std::fs::read(&dna).unwrap());
+    }
+
+    #[test]
+    fn test_shuffled_refer_needs_password() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        let bases: String = (0..64).map(|i| String::from_utf8(ReferTable::index_to_kmer(i * 1021, 8).to_vec()).unwrap()).collect();
+        std::fs::write(&dna, &bases).unwrap();
+
+        let bed = dir.path().join("out.bed");
+        let keying = keyed("hunter2", false, true);
+        refer_encode(dna.clone(), bed.clone(), keying, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+        let text = std::fs::read_to_string(&bed).unwrap();
+        assert!(text.lines().filter(|l| !l.starts_with('#')).all(|l| l.split('\t').nth(3) == Some(".")));
+
+        let unrefer = |password| {
+            let back = dir.path().join("back.dna");
+            refer_decode(bed.clone(), back.clone(), None, password, OverwritePolicy::Force)
+                .map(|_| std::fs::read_to_string(back).unwrap())
+        };
+        assert_eq!(unrefer(Some("hunter2")).unwrap(), bases);
+        assert!(matches!(unrefer(Some("hunter3")), Err(DendecError::ReferChecksumMismatch)));
+        assert!(unrefer(None).unwrap_err().to_string().contains("--shuffle"));
+    }
+
+    #[test]
+    fn test_name_style_roundtrip() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        let bases: String = (0..64).map(|i| String::from_utf8(ReferTable::index_to_kmer(i * 1021, 8).to_vec()).unwrap()).collect();
+        std::fs::write(&dna, &bases).unwrap();
+
+        let bed = dir.path().join("out.bed");
+        let refseq = ReferOptions { name_style: NameStyle::Refseq, ..Default::default() };
+        let err = refer_encode(dna.clone(), bed.clone(), None, &refseq, OverwritePolicy::Refuse);
+        assert!(matches!(err, Err(DendecError::ReferPasswordRequired(_))));
+        refer_encode(dna.clone(), bed.clone(), keyed("hunter2", false, true), &refseq, OverwritePolicy::Refuse).unwrap();
+        let text = std::fs::read_to_string(&bed).unwrap();
+        let names: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).filter_map(|l| l.split('\t').nth(3)).collect();
+        assert_eq!(names.len(), 64);
+        assert!(names.iter().all(|n| n.starts_with(['N', 'X']) && !n.contains("chunk")), "{:?}", names);
+
+        assert!(needs_password(&bed).unwrap() && has_keyed_names(&bed).unwrap());
+        assert_eq!(unrefer(&bed, None, Some("hunter2")).unwrap(), bases);
+        assert!(matches!(unrefer(&bed, None, Some("hunter3")), Err(DendecError::WrongPassword)));
+        assert!(matches!(unrefer(&bed, None, None), Err(DendecError::ReferPasswordRequired(_))));
+
+        let sam = dir.path().join("out.sam");
+        let probe = ReferOptions { format: Format::Sam, name_style: NameStyle::Probe, ..Default::default() };
+        refer_encode(dna.clone(), sam.clone(), keyed("hunter2", false, false), &probe, OverwritePolicy::Refuse).unwrap();
+        assert!(std::fs::read_to_string(&sam).unwrap().lines().filter(|l| !l.starts_with('@')).all(|l| l.starts_with("cg")));
+        assert_eq!(unrefer(&sam, None, Some("hunter2")).unwrap(), bases);
+    }
+
+    #[test]
+    fn test_edited_file_fails_checksum() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        std::fs::write(&dna, "ATGCGATCGGCTAGCAATGCGATC").unwrap();
+        let bed = dir.path().join("out.bed");
+        refer_encode(dna.clone(), bed.clone(), None, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+        let text = std::fs::read_to_string(&bed).unwrap();
+        let sha = hex_digest(Sha256::new_with_prefix("ATGCGATCGGCTAGCAATGCGATC"));
+        assert!(text.contains(&format!("##payload_sha256 {sha}\n")));
+
+        let unrefer = |text: &str| {
+            std::fs::write(&bed, text).unwrap();
+            refer_decode(bed.clone(), dir.path().join("back.dna"), None, None, OverwritePolicy::Force)
+        };
+        // The last record dropped, or the first two swapped
+        let lines: Vec<&str> = text.lines().collect();
+        let truncated = lines[..lines.len() - 1].join("\n");
+        assert!(matches!(unrefer(&truncated), Err(DendecError::ReferChecksumMismatch)));
+        let mut swapped = lines.clone();
+        let first = swapped.iter().position(|l| !l.starts_with('#')).unwrap();
+        let (a, b) = (swapped[first].replace("chunk_00000000", "chunk_00000001"), swapped[first + 1].replace("chunk_00000001", "chunk_00000000"));
+        swapped[first] = &a;
+        swapped[first + 1] = &b;
+        assert!(matches!(unrefer(&swapped.join("\n")), Err(DendecError::ReferChecksumMismatch)));
+
+        // Files without the line still unrefer
+        let old: Vec<&str> = lines.into_iter().filter(|l| !l.starts_with("##payload_sha256")).collect();
+        unrefer(&old.join("\n")).unwrap();
+    }
+
+    #[test]
+    fn test_short_last_chunk_and_chunk_size() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        // Half a chunk at the end, completed with filler and cut off again
+        std::fs::write(&dna, "ATGCGATCGGCT").unwrap();
+        let bed = dir.path().join("out.bed");
+        refer_encode(dna.clone(), bed.clone(), None, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+        let text = std::fs::read_to_string(&bed).unwrap();
+        assert!(text.contains("##chunk_size 8\n##dna_length 12\n##chunk_count 2\n"));
+        assert_eq!(unrefer(&bed, None, None).unwrap(), "ATGCGATCGGCT");
+
+        // The hg38 table has 8-base chunks; a file cut into 10s is refused
+        std::fs::write(&bed, text.replace("##chunk_size 8", "##chunk_size 10")).unwrap();
+        assert!(matches!(
+            unrefer(&bed, None, None),
+            Err(DendecError::ReferChunkSizeMismatch { file: 10, table: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn test_table_checked_before_lookups() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        std::fs::write(&dna, "ATGCGATCGGCTAATC").unwrap();
+        let bed = dir.path().join("out.bed");
+        refer_encode(dna.clone(), bed.clone(), None, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+        let text = std::fs::read_to_string(&bed).unwrap();
+        let line = format!("##table_sha256 {}\n", ReferTable::load(Assembly::Hg38).unwrap().sha256());
+        assert!(text.contains(&line));
+
+        // Made with another hg38 table
+        std::fs::write(&bed, text.replace(&line, &format!("##table_sha256 {}\n", "0".repeat(64)))).unwrap();
+        match unrefer(&bed, None, None) {
+            Err(DendecError::ReferAssemblyMismatch { got, .. }) => assert_eq!(got, "table 0000000000000000…"),
+            other => panic!("expected ReferAssemblyMismatch, got {:?}", other.map(|_| ())),
+        }
+
+        // Older files without the line are still checked by accession
+        let old = text.replace(&line, "");
+        std::fs::write(&bed, &old).unwrap();
+        assert_eq!(unrefer(&bed, None, None).unwrap(), "ATGCGATCGGCTAATC");
+        let last = old.lines().last().unwrap();
+        let moved = last.replacen(last.split('\t').next().unwrap(), "NC_000067.7", 1);
+        std::fs::write(&bed, old.replace(last, &moved)).unwrap();
+        match unrefer(&bed, None, None) {
+            Err(DendecError::ReferAssemblyMismatch { got, .. }) => assert_eq!(got, "NC_000067.7"),
+            other => panic!("expected ReferAssemblyMismatch, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_ucsc_chrom_style_roundtrip() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        std::fs::write(&dna, "ATGCGATCGGCTAATC").unwrap();
+
+        let bed = dir.path().join("out.bed");
+        let opts = ReferOptions { chrom_style: ChromStyle::Ucsc, ..Default::default() };
+        refer_encode(dna.clone(), bed.clone(), None, &opts, OverwritePolicy::Refuse).unwrap();
+        let text = std::fs::read_to_string(&bed).unwrap();
+        assert!(text.lines().filter(|l| !l.starts_with('#')).all(|l| l.starts_with("chr1\t")));
+        assert_eq!(unrefer(&bed, None, None).unwrap(), "ATGCGATCGGCTAATC");
+
+        let vcf = dir.path().join("out.vcf");
+        let opts = ReferOptions { format: Format::Vcf, chrom_style: ChromStyle::Ucsc, ..Default::default() };
+        refer_encode(dna.clone(), vcf.clone(), None, &opts, OverwritePolicy::Refuse).unwrap();
+        assert!(std::fs::read_to_string(&vcf).unwrap().contains("##contig=<ID=chr1>"));
+        assert_eq!(unrefer(&vcf, None, None).unwrap(), "ATGCGATCGGCTAATC");
+    }
+
+    #[test]
+    fn test_fasta_from_another_tool_roundtrip() {
+        // 21 bases, soft-masked in part: not a multiple of 4 or of 8
+        let dir = tempdir().unwrap();
+        let fasta = dir.path().join("in.fa");
+        std::fs::write(&fasta, ">amplicon_3 primer-trimmed\nATGCGATCGG\nctaatcGGATT\n").unwrap();
+
+        let bed = dir.path().join("out.bed");
+        refer_encode(fasta, bed.clone(), None, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+        assert!(std::fs::read_to_string(&bed).unwrap().contains("##dna_length 21\n"));
+        assert_eq!(unrefer(&bed, None, None).unwrap(), "ATGCGATCGGCTAATCGGATT");
+    }
+
+    #[test]
+    fn test_repeated_chunks_use_fresh_coordinates() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        // hg38 lists 8 places for every 8-mer: 8 repeats take each once,
+        // and a 9th has to reuse one
+        std::fs::write(&dna, "ATGCGATC".repeat(9)).unwrap();
+        let bed = dir.path().join("out.bed");
+        refer_encode(dna.clone(), bed.clone(), None, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+
+        let text = std::fs::read_to_string(&bed).unwrap();
+        let places: Vec<(&str, &str, &str)> = text
+            .lines()
+            .filter(|l| !l.starts_with('#'))
+            .map(|l| {
+                let cols: Vec<&str> = l.split('\t').collect();
+                (cols[0], cols[1], cols[5])
+            })
+            .collect();
+        let distinct: HashSet<_> = places[..8].iter().collect();
+        assert_eq!(distinct.len(), 8);
+        assert!(distinct.contains(&places[8]));
+        assert_eq!(unrefer(&bed, None, None).unwrap(), "ATGCGATC".repeat(9));
+    }
+
+    #[test]
+    fn test_both_strands_roundtrip() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        let bases: String = (0..64).map(|i| String::from_utf8(ReferTable::index_to_kmer(i * 1021, 8).to_vec()).unwrap()).collect();
+        std::fs::write(&dna, &bases).unwrap();
+
+        let bed = dir.path().join("out.bed");
+        let opts = ReferOptions { both_strands: true, ..Default::default() };
+        refer_encode(dna.clone(), bed.clone(), None, &opts, OverwritePolicy::Refuse).unwrap();
+        assert_eq!(unrefer(&bed, None, None).unwrap(), bases);
+    }
+
+    #[test]
+    fn test_bedgraph_refer_roundtrip() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        let bases: String = (0..64).map(|i| String::from_utf8(ReferTable::index_to_kmer(i * 1021, 8).to_vec()).unwrap()).collect();
+        std::fs::write(&dna, &bases).unwrap();
+
+        let graph = dir.path().join("out.bedgraph");
+        let bedgraph = ReferOptions { format: Format::BedGraph, ..Default::default() };
+        let err = refer_encode(dna.clone(), graph.clone(), None, &bedgraph, OverwritePolicy::Refuse);
+        assert!(matches!(err, Err(DendecError::ReferPasswordRequired(_))));
+        let keying = keyed("hunter2", false, false);
+        refer_encode(dna.clone(), graph.clone(), keying, &bedgraph, OverwritePolicy::Refuse).unwrap();
+
+        let unrefer = |password| {
+            let back = dir.path().join("back.dna");
+            refer_decode(graph.clone(), back.clone(), None, password, OverwritePolicy::Force)
+                .map(|_| std::fs::read_to_string(back).unwrap())
+        };
+        assert_eq!(unrefer(Some("hunter2")).unwrap(), bases);
+        assert!(matches!(unrefer(Some("hunter3")), Err(DendecError::WrongPassword)));
+        assert!(matches!(unrefer(None), Err(DendecError::ReferPasswordRequired(_))));
+    }
+
+    #[test]
+    fn test_fasta_refer_roundtrip() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        let bases: String = (0..64).map(|i| String::from_utf8(ReferTable::index_to_kmer(i * 1021, 8).to_vec()).unwrap()).collect();
+        std::fs::write(&dna, &bases).unwrap();
+
+        // A stand-in genome: every table coordinate's bases, N elsewhere
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
+        let mut genome: Vec<Vec<u8>> = table.chrom_extents().iter().map(|&len| vec![b'N'; len as usize]).collect();
+        for (idx, entry) in table.entries().enumerate() {
+            let kmer = ReferTable::index_to_kmer(idx, 8);
+            for coord in entry.iter() {
+                let bases = if coord.strand == 0 { kmer } else { reverse::reverse_complement(&kmer) };
+                let start = coord.start as usize;
+                genome[coord.chrom_idx as usize][start..start + 8].copy_from_slice(&bases);
+            }
+        }
+        let reference = dir.path().join("genome.fa");
+        let mut fa = Vec::new();
+        for (accession, seq) in table.accessions.iter().zip(&genome) {
+            fa.extend(format!(">{accession}\n").bytes());
+            fa.extend(seq);
+            fa.push(b'\n');
+        }
+        std::fs::write(&reference, fa).unwrap();
+
+        let out = dir.path().join("out.fa");
+        let opts = ReferOptions { format: Format::Fasta, reference: Some(reference), ..Default::default() };
+        refer_encode(dna.clone(), out.clone(), None, &opts, OverwritePolicy::Refuse).unwrap();
+        let back = dir.path().join("back.dna");
+        refer_decode(out, back.clone(), None, None, OverwritePolicy::Refuse).unwrap();
+        assert_eq!(std::fs::read_to_string(back).unwrap(), bases);
+    }
+}
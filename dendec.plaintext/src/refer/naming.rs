@@ -0,0 +1,272 @@
+/// refer/naming.rs — Record names for refer output
+///
+/// Records are named chunk_NNNNNNNN by default, which gives away both
+/// the tool and the chunk order. `--name-style` swaps in identifiers of
+/// a kind real annotation files carry:
+///
+///   chunk   chunk_00000042 (default)
+///   refseq  RefSeq transcript accessions: NM_001234567.2
+///   probe   Illumina methylation array probe IDs: cg04718469
+///
+/// A refseq or probe name is the chunk index under a keyed permutation
+/// of the style's number space, so every chunk gets a distinct name and
+/// the names look random. The permutation is a four-round Feistel
+/// network over 28 bits, HMAC-SHA256 as its round function, keyed from
+/// the refer password; values past the end of the number space are
+/// cycle-walked back into it. Unrefer inverts it with the same password,
+/// so the index never has to be read from the visible name.
+use std::fmt;
+use std::str::FromStr;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::crypto::ReferKey;
+use crate::error::{DendecError, Result};
+
+/// Bits the Feistel network permutes; both number spaces fit.
+const BITS: u32 = 28;
+const HALF: u32 = BITS / 2;
+const ROUNDS: u8 = 4;
+
+/// RefSeq accession prefixes: mRNA and non-coding RNA, curated and
+/// predicted.
+const REFSEQ_PREFIXES: [&str; 4] = ["NM", "NR", "XM", "XR"];
+
+/// How refer names its records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameStyle {
+    /// chunk_NNNNNNNN, the chunk index in plain sight
+    #[default]
+    Chunk,
+    /// RefSeq transcript accessions under the refer key
+    Refseq,
+    /// Methylation probe IDs under the refer key
+    Probe,
+}
+
+impl NameStyle {
+    pub const ALL: [NameStyle; 3] = [NameStyle::Chunk, NameStyle::Refseq, NameStyle::Probe];
+
+    /// Name as given to `--name-style`.
+    pub fn name(self) -> &'static str {
+        match self {
+            NameStyle::Chunk => "chunk",
+            NameStyle::Refseq => "refseq",
+            NameStyle::Probe => "probe",
+        }
+    }
+
+    /// Whether names in this style need the refer key.
+    pub fn is_keyed(self) -> bool {
+        self != NameStyle::Chunk
+    }
+
+    /// Count of distinct names, and so of chunks, this style can give.
+    pub fn capacity(self) -> u64 {
+        match self {
+            NameStyle::Chunk => u64::MAX,
+            // Prefix, version 1–4 and seven digits after a leading 00
+            NameStyle::Refseq => 4 * 4 * 10_000_000,
+            NameStyle::Probe => 100_000_000,
+        }
+    }
+
+    /// The name of keyed value `value`, below capacity().
+    fn format(self, value: u64) -> String {
+        match self {
+            NameStyle::Refseq => format!(
+                "{}_00{:07}.{}",
+                REFSEQ_PREFIXES[(value % 4) as usize],
+                value / 16,
+                value / 4 % 4 + 1
+            ),
+            _ => format!("cg{:08}", value),
+        }
+    }
+
+    /// The style and keyed value of a name format() wrote, if it is one.
+    fn parse(name: &str) -> Option<(NameStyle, u64)> {
+        let digits = |s: &str, n: usize| -> Option<u64> {
+            (s.len() == n && s.bytes().all(|b| b.is_ascii_digit())).then(|| s.parse().ok()).flatten()
+        };
+        if let Some(number) = name.strip_prefix("cg") {
+            return Some((NameStyle::Probe, digits(number, 8)?));
+        }
+        let (prefix, rest) = name.split_once('_')?;
+        let prefix = REFSEQ_PREFIXES.iter().position(|&p| p == prefix)? as u64;
+        let (number, version) = rest.strip_prefix("00")?.split_once('.')?;
+        let version = digits(version, 1).filter(|v| (1..=4).contains(v))?;
+        Some((NameStyle::Refseq, digits(number, 7)? * 16 + (version - 1) * 4 + prefix))
+    }
+}
+
+impl fmt::Display for NameStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for NameStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        NameStyle::ALL
+            .into_iter()
+            .find(|n| n.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = NameStyle::ALL.iter().map(|n| n.name()).collect();
+                format!("unknown name style '{s}' (expected one of: {})", names.join(", "))
+            })
+    }
+}
+
+/// How the records of one file are named.
+#[derive(Clone)]
+pub enum Naming {
+    /// chunk_NNNNNNNN
+    Chunk,
+    /// No name: shuffled records, put back in order by the password
+    Hidden,
+    /// A keyed style's names
+    Keyed(NameStyle, NameCipher),
+}
+
+impl Naming {
+    /// Naming in `style` for `chunks` records, keyed by `key`. Fails if
+    /// the style runs out of names or needs a key that is not there.
+    pub fn new(style: NameStyle, key: Option<&ReferKey>, chunks: usize) -> Result<Self> {
+        if !style.is_keyed() {
+            return Ok(Naming::Chunk);
+        }
+        let key = key.ok_or(DendecError::ReferPasswordRequired("--name-style"))?;
+        if chunks as u64 > style.capacity() {
+            return Err(DendecError::ReferTooManyChunks { style, limit: style.capacity() });
+        }
+        Ok(Naming::Keyed(style, NameCipher::new(key)))
+    }
+
+    /// The name of chunk `idx`, or None when records go unnamed.
+    pub fn name(&self, idx: usize) -> Option<String> {
+        match self {
+            Naming::Chunk => Some(format!("chunk_{:08}", idx)),
+            Naming::Hidden => None,
+            Naming::Keyed(style, cipher) => Some(style.format(cipher.encrypt(idx as u64, style.capacity()))),
+        }
+    }
+}
+
+/// `name` if it is a keyed style's name, kept for unrefer to decode
+/// with the key.
+pub fn keyed_name(name: &str) -> Option<String> {
+    NameStyle::parse(name).map(|_| name.to_string())
+}
+
+/// The keyed permutation behind refseq and probe names.
+#[derive(Clone)]
+pub struct NameCipher {
+    mac: Hmac<Sha256>,
+}
+
+impl NameCipher {
+    pub fn new(key: &ReferKey) -> Self {
+        let mac = <Hmac<Sha256> as Mac>::new_from_slice(&key.name_key())
+            .expect("HMAC-SHA256 accepts any key length");
+        NameCipher { mac }
+    }
+
+    /// The chunk index a keyed name stands for. None if `name` is not
+    /// one.
+    pub fn index(&self, name: &str) -> Option<usize> {
+        let (style, value) = NameStyle::parse(name)?;
+        Some(self.decrypt(value, style.capacity()) as usize)
+    }
+
+    /// Permute `x`, below `domain`, to another value below `domain`.
+    fn encrypt(&self, x: u64, domain: u64) -> u64 {
+        let mut y = self.feistel(x, false);
+        while y >= domain {
+            y = self.feistel(y, false);
+        }
+        y
+    }
+
+    /// Inverse of encrypt.
+    fn decrypt(&self, y: u64, domain: u64) -> u64 {
+        let mut x = self.feistel(y, true);
+        while x >= domain {
+            x = self.feistel(x, true);
+        }
+        x
+    }
+
+    /// One pass of the network over BITS bits, forwards or backwards.
+    fn feistel(&self, x: u64, inverse: bool) -> u64 {
+        let mask = (1u64 << HALF) - 1;
+        let (mut left, mut right) = (x >> HALF & mask, x & mask);
+        for i in 0..ROUNDS {
+            match inverse {
+                false => (left, right) = (right, left ^ self.round(i, right)),
+                true => (left, right) = (right ^ self.round(ROUNDS - 1 - i, left), left),
+            }
+        }
+        left << HALF | right
+    }
+
+    fn round(&self, round: u8, half: u64) -> u64 {
+        let mut mac = self.mac.clone();
+        mac.update(&[round]);
+        mac.update(&half.to_le_bytes());
+        let out = mac.finalize().into_bytes();
+        u64::from(u32::from_le_bytes([out[0], out[1], out[2], out[3]])) & ((1 << HALF) - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::derive_refer_key;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_keyed_names_roundtrip() {
+        let key = derive_refer_key("names").unwrap();
+        for style in [NameStyle::Refseq, NameStyle::Probe] {
+            let naming = Naming::new(style, Some(&key), 5000).unwrap();
+            let Naming::Keyed(_, cipher) = &naming else { panic!("not keyed") };
+            let names: Vec<String> = (0..5000).map(|i| naming.name(i).unwrap()).collect();
+            assert_eq!(names.iter().collect::<HashSet<_>>().len(), 5000);
+            for (i, name) in names.iter().enumerate() {
+                assert_eq!(keyed_name(name).as_deref(), Some(name.as_str()));
+                assert_eq!(cipher.index(name), Some(i));
+            }
+        }
+
+        let naming = Naming::new(NameStyle::Refseq, Some(&key), 1).unwrap();
+        let name = naming.name(0).unwrap();
+        assert!(name.len() == 14 && name[2..5] == *"_00" && name.as_bytes()[12] == b'.', "{name}");
+        assert!(Naming::new(NameStyle::Probe, Some(&key), 1).unwrap().name(0).unwrap().starts_with("cg"));
+    }
+
+    #[test]
+    fn test_names_need_key_and_room() {
+        assert!(matches!(Naming::new(NameStyle::Chunk, None, 3), Ok(Naming::Chunk)));
+        assert!(matches!(
+            Naming::new(NameStyle::Probe, None, 3),
+            Err(DendecError::ReferPasswordRequired(_))
+        ));
+        let key = derive_refer_key("names").unwrap();
+        assert!(matches!(
+            Naming::new(NameStyle::Probe, Some(&key), 100_000_001),
+            Err(DendecError::ReferTooManyChunks { limit: 100_000_000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_rejects_other_names() {
+        for name in ["chunk_00000001", ".", "NM_1234567.1", "NM_001234567.5", "YM_001234567.1", "cg1234567", "cgabcdefgh"] {
+            assert_eq!(keyed_name(name), None, "{name}");
+        }
+        assert!(keyed_name("XR_009999999.4").is_some());
+    }
+}
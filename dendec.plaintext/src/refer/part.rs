@@ -0,0 +1,363 @@
+/// refer/part.rs — Table build parts and dendec table merge
+///
+/// `table build --parts DIR` writes what it samples from each chromosome
+/// to DIR/<accession>.part as soon as that chromosome is read. A build
+/// that is stopped and run again finds the parts already written and
+/// skips their chromosomes, so a crash costs one chromosome, not the
+/// genome. Chromosomes can also be built on different machines, one
+/// `table build --parts` each, and the parts combined with `table merge`.
+///
+/// A part keeps, besides each k-mer's sampled coordinates, how many times
+/// the k-mer occurred. Merging two parts then keeps a uniform sample of
+/// every occurrence in both (see merge_samples), as one build over all
+/// their chromosomes would. The sample drawn is not the same one, so a
+/// merged table is not byte-identical to a single build; merging the same
+/// parts in the same order always gives the same table.
+///
+/// PART FORMAT (*.part), version 1
+/// ─────────────────────────────────────────────────────────────────────
+///  Offset  Len   Field
+///  0       4     Magic: 0x44 0x52 0x50 0x54  ("DRPT")
+///  4       1     Version: 0x01
+///  5       1     Chunk size k
+///  6       1     Max coords
+///  7       1     Flags: bit 0 set if built --forward-only
+///  8       var   Accession: [len: u8][utf8 bytes]
+///  ?       4     Entry count E (u32 LE)
+///  ?       var   E × [k-mer index: u32 LE][occurrences: u32 LE]
+///                    [count: u8][count × [start: u64 LE][strand: u8]]
+///                k-mers that never occurred are left out
+///  end-32  32    SHA-256 of every byte before it
+/// ─────────────────────────────────────────────────────────────────────
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+
+use crate::error::{DendecError, Result};
+use crate::output::{check_output, prepare_output, write_atomic_with, OverwritePolicy};
+use super::build::report_coverage;
+use super::coverage::write_coverage_report;
+use super::table::{write_table, Coord, CHECKSUM_LEN};
+
+pub const PART_MAGIC: [u8; 4] = [0x44, 0x52, 0x50, 0x54]; // "DRPT"
+pub const PART_VERSION: u8 = 0x01;
+
+/// Seed of the RNG that merges samples. Fixed, so merges are
+/// reproducible.
+const MERGE_SEED: u64 = 0x6465_6e64_6563_0002;
+
+/// What one chromosome contributed to a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub accession: String,
+    pub chunk_size: usize,
+    pub max_coords: usize,
+    pub forward_only: bool,
+    /// K-mers that occurred: index, occurrences, sampled coordinates.
+    /// Coordinates carry chrom_idx 0; merge gives them the part's.
+    pub entries: Vec<(u32, u32, Vec<Coord>)>,
+}
+
+impl Part {
+    /// Serialise in the part format, checksum trailer included.
+    pub fn write(&self, w: &mut impl Write) -> Result<()> {
+        let mut hasher = Sha256::new();
+        let mut put = |bytes: &[u8]| -> Result<()> {
+            hasher.update(bytes);
+            w.write_all(bytes).map_err(DendecError::Io)
+        };
+
+        put(&PART_MAGIC)?;
+        put(&[PART_VERSION, self.chunk_size as u8, self.max_coords as u8, self.forward_only as u8])?;
+        put(&[self.accession.len() as u8])?;
+        put(self.accession.as_bytes())?;
+        put(&(self.entries.len() as u32).to_le_bytes())?;
+        for (idx, seen, coords) in &self.entries {
+            put(&idx.to_le_bytes())?;
+            put(&seen.to_le_bytes())?;
+            put(&[coords.len() as u8])?;
+            for coord in coords {
+                put(&coord.start.to_le_bytes())?;
+                put(&[coord.strand])?;
+            }
+        }
+
+        w.write_all(&hasher.finalize()).map_err(DendecError::Io)
+    }
+
+    /// Read and check the part file at `path`.
+    pub fn read(path: &Path) -> Result<Part> {
+        let bytes = std::fs::read(path)?;
+        let invalid = |reason: &str| DendecError::TableBuild(format!("{}: {}", path.display(), reason));
+        if !bytes.starts_with(&PART_MAGIC) {
+            return Err(invalid("not a table build part (bad magic bytes)"));
+        }
+        if bytes.len() < 8 + CHECKSUM_LEN {
+            return Err(invalid("truncated"));
+        }
+        let (body, stored) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if Sha256::digest(body).as_slice() != stored {
+            return Err(invalid("checksum mismatch — the part is damaged; build it again"));
+        }
+        if body[4] != PART_VERSION {
+            return Err(invalid(&format!("unsupported part version {}", body[4])));
+        }
+
+        let mut pos = 8;
+        let mut take = |n: usize| -> Result<&[u8]> {
+            let out = body.get(pos..pos + n).ok_or_else(|| invalid("truncated"))?;
+            pos += n;
+            Ok(out)
+        };
+        let (chunk_size, max_coords, forward_only) = (body[5] as usize, body[6] as usize, body[7] & 1 == 1);
+        let len = take(1)?[0] as usize;
+        let accession = std::str::from_utf8(take(len)?)
+            .map_err(|_| invalid("accession is not valid UTF-8"))?
+            .to_string();
+        let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let idx = u32::from_le_bytes(take(4)?.try_into().unwrap());
+            let seen = u32::from_le_bytes(take(4)?.try_into().unwrap());
+            let kept = take(1)?[0] as usize;
+            if (idx as u64) >> (2 * chunk_size) != 0 || kept != max_coords.min(seen as usize) {
+                return Err(invalid(&format!("bad entry for k-mer {}", idx)));
+            }
+            let mut coords = Vec::with_capacity(kept);
+            for _ in 0..kept {
+                let start = u64::from_le_bytes(take(8)?.try_into().unwrap());
+                let strand = take(1)?[0];
+                coords.push(Coord { chrom_idx: 0, start, strand });
+            }
+            entries.push((idx, seen, coords));
+        }
+        if pos != body.len() {
+            return Err(invalid("unexpected bytes after the last entry"));
+        }
+        Ok(Part { accession, chunk_size, max_coords, forward_only, entries })
+    }
+
+    /// Whether `other` was built with the same chunk size, max coords and
+    /// strands, so the two can be merged.
+    pub fn same_settings(&self, other: &Part) -> bool {
+        (self.chunk_size, self.max_coords, self.forward_only)
+            == (other.chunk_size, other.max_coords, other.forward_only)
+    }
+
+    /// The settings, as the table build flags that set them.
+    pub fn settings(&self) -> String {
+        format!(
+            "--chunk-size {} --max-coords {}{}",
+            self.chunk_size,
+            self.max_coords,
+            if self.forward_only { " --forward-only" } else { "" }
+        )
+    }
+}
+
+/// Where `table build --parts` keeps the part for `accession` in `dir`.
+/// Bytes other than letters, digits, '.', '-' and '_' become '_'.
+pub fn part_path(dir: &Path, accession: &str) -> PathBuf {
+    let name: String = accession
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || ".-_".contains(c) { c } else { '_' })
+        .collect();
+    dir.join(format!("{name}.part"))
+}
+
+/// Merge `parts` into one table written atomically to `out`, and its
+/// coverage report to `report` if given. The parts' chromosomes are
+/// indexed in the order given.
+pub fn merge_parts(parts: &[PathBuf], out: &Path, report: Option<&Path>, overwrite: OverwritePolicy) -> Result<()> {
+    check_output(out, overwrite)?;
+    if parts.len() > u16::MAX as usize {
+        return Err(DendecError::TableBuild(format!(
+            "{} parts — a table holds at most {} chromosomes",
+            parts.len(),
+            u16::MAX
+        )));
+    }
+
+    let mut accessions: Vec<String> = Vec::new();
+    let mut first: Option<Part> = None;
+    let mut entries: Vec<Vec<Coord>> = Vec::new();
+    let mut seen: Vec<u64> = Vec::new();
+    let mut rng = StdRng::seed_from_u64(MERGE_SEED);
+    for (chrom_idx, path) in parts.iter().enumerate() {
+        let part = Part::read(path)?;
+        if accessions.contains(&part.accession) {
+            return Err(DendecError::TableBuild(format!(
+                "chromosome '{}' is in more than one part",
+                part.accession
+            )));
+        }
+        let settings = first.get_or_insert_with(|| Part { entries: Vec::new(), ..part.clone() });
+        if !part.same_settings(settings) {
+            return Err(DendecError::TableBuild(format!(
+                "{} was built with {}, the parts before it with {}",
+                path.display(),
+                part.settings(),
+                settings.settings()
+            )));
+        }
+        let table_size = 1usize << (2 * part.chunk_size);
+        entries.resize(table_size, Vec::new());
+        seen.resize(table_size, 0);
+
+        for (idx, part_seen, mut coords) in part.entries {
+            coords.iter_mut().for_each(|c| c.chrom_idx = chrom_idx as u16);
+            let i = idx as usize;
+            let kept = std::mem::take(&mut entries[i]);
+            entries[i] = merge_samples(kept, seen[i], coords, part_seen as u64, part.max_coords, &mut rng);
+            seen[i] += part_seen as u64;
+        }
+        accessions.push(part.accession);
+    }
+    let Some(settings) = first else {
+        return Err(DendecError::TableBuild("no parts to merge".to_string()));
+    };
+
+    let filled = entries.iter().filter(|e| !e.is_empty()).count();
+    let saturated = entries.iter().filter(|e| e.len() == settings.max_coords).count();
+    report_coverage(accessions.len(), filled, saturated, entries.len(), settings.chunk_size);
+    if let Some(backup) = prepare_output(out, overwrite)? {
+        info!("  Existing output moved to {}", backup.display());
+    }
+    let size = write_atomic_with(out, None, None, |w| write_table(w, settings.chunk_size, &accessions, &entries))?;
+    info!("Written {}  ({:.1} KB)", out.display(), size as f64 / 1024.0);
+    if let Some(report) = report {
+        write_coverage_report(report, out, settings.max_coords, settings.forward_only, &seen)?;
+    }
+    Ok(())
+}
+
+/// Merge `a`, a uniform sample of `seen_a` occurrences, and `b`, one of
+/// `seen_b`, into a uniform sample of up to `max` of all of them. Each
+/// slot goes to a side with probability its share of the occurrences not
+/// yet drawn, and takes a random coordinate of that side's sample.
+fn merge_samples(mut a: Vec<Coord>, seen_a: u64, mut b: Vec<Coord>, seen_b: u64, max: usize, rng: &mut impl Rng) -> Vec<Coord> {
+    if seen_a + seen_b <= max as u64 {
+        a.append(&mut b);
+        return a;
+    }
+    let (mut left_a, mut left_b) = (seen_a, seen_b);
+    let mut merged = Vec::with_capacity(max);
+    while merged.len() < max {
+        let side = match rng.gen_range(0..left_a + left_b) < left_a {
+            true => {
+                left_a -= 1;
+                &mut a
+            }
+            false => {
+                left_b -= 1;
+                &mut b
+            }
+        };
+        // Never empty: a side gives at most min(max, seen) coordinates,
+        // which is what its sample holds
+        let pick = rng.gen_range(0..side.len());
+        merged.push(side.swap_remove(pick));
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::build::{build_table, BuildOptions};
+    use crate::refer::table::{CoordKey, ReferTable};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_part_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = part_path(dir.path(), "chrUn|GL000220.1");
+        assert_eq!(path.file_name().unwrap(), "chrUn_GL000220.1.part");
+        let coord = |start, strand| Coord { chrom_idx: 0, start, strand };
+        let part = Part {
+            accession: "chrUn|GL000220.1".to_string(),
+            chunk_size: 8,
+            max_coords: 2,
+            forward_only: true,
+            entries: vec![(3, 1, vec![coord(5_000_000_000, 0)]), (65_535, 9, vec![coord(7, 0), coord(1, 0)])],
+        };
+        let mut bytes = Vec::new();
+        part.write(&mut bytes).unwrap();
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(Part::read(&path).unwrap(), part);
+
+        bytes[12] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(Part::read(&path).unwrap_err().to_string().contains("damaged"));
+    }
+
+    #[test]
+    fn test_merge_samples_uniform() {
+        // 8 of 12 occurrences, 4 in a and 8 in b: a's share should be 1/3
+        let coord = |start| Coord { chrom_idx: 0, start, strand: 0 };
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut from_a = 0;
+        for _ in 0..2_000 {
+            let a = (0..4).map(coord).collect();
+            let b = (100..106).map(coord).collect();
+            let merged = merge_samples(a, 4, b, 8, 6, &mut rng);
+            assert_eq!(merged.len(), 6);
+            from_a += merged.iter().filter(|c| c.start < 100).count();
+        }
+        let share = from_a as f64 / 12_000.0;
+        assert!((share - 1.0 / 3.0).abs() < 0.02, "{share}");
+
+        // Below max, everything is kept in order
+        let merged = merge_samples(vec![coord(1)], 1, vec![coord(2)], 1, 8, &mut rng);
+        assert_eq!(merged, [coord(1), coord(2)]);
+    }
+
+    #[test]
+    fn test_parts_build_resume_and_merge() {
+        let dir = tempdir().unwrap();
+        let seq = |n: usize| b"ACGTTGCAGGATCCTA".repeat(n);
+        let fasta = dir.path().join("genome.fa");
+        let text = [b">chr1\n".as_slice(), &seq(40), b"\n>chr2\n", &seq(30), b"\n>chrM\n", &seq(2), b"\n"].concat();
+        std::fs::write(&fasta, text).unwrap();
+        let parts = dir.path().join("parts");
+        let opts = BuildOptions { max_coords: 4, parts: Some(parts.clone()), ..Default::default() };
+
+        let out = dir.path().join("table.bin");
+        build_table(std::slice::from_ref(&fasta), &out, &opts, OverwritePolicy::Refuse).unwrap();
+        let bytes = std::fs::read(&out).unwrap();
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+        assert_eq!(table.accessions, ["chr1", "chr2", "chrM"]);
+        let options = table.options(b"ACGTTGCA", false);
+        assert_eq!(options.len(), 4);
+        for coord in options {
+            assert_eq!(&table.reverse_lookup(&CoordKey::from(&coord)).unwrap()[..], b"ACGTTGCA");
+        }
+
+        // A rerun samples no chromosome again: all N now, they still give
+        // the table of the parts
+        let names: Vec<PathBuf> = ["chr1", "chr2", "chrM"].iter().map(|a| part_path(&parts, a)).collect();
+        std::fs::write(&fasta, ">chr1\nNNNN\n>chr2\nNNNN\n>chrM\nNNNN\n").unwrap();
+        build_table(&[fasta], &out, &opts, OverwritePolicy::Force).unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), bytes);
+
+        // Merging the same parts by hand gives the same table
+        let merged = dir.path().join("merged.bin");
+        merge_parts(&names, &merged, None, OverwritePolicy::Refuse).unwrap();
+        assert_eq!(std::fs::read(&merged).unwrap(), bytes);
+
+        // Parts of one chromosome twice, or of other settings, are refused
+        let twice = [names[0].clone(), names[0].clone()];
+        assert!(merge_parts(&twice, &dir.path().join("x.bin"), None, OverwritePolicy::Refuse).is_err());
+        let mut other = Part::read(&names[2]).unwrap();
+        other.accession = "chrY".to_string();
+        other.max_coords = 8;
+        write_atomic_with(&part_path(&parts, "chrY"), None, None, |w| other.write(w)).unwrap();
+        let mixed = [names[0].clone(), part_path(&parts, "chrY")];
+        let err = merge_parts(&mixed, &dir.path().join("x.bin"), None, OverwritePolicy::Refuse).unwrap_err();
+        assert!(err.to_string().contains("--max-coords 8"), "{err}");
+    }
+}
@@ -1,20 +1,20 @@
 /// refer/reverse.rs — Reverse complement utility
 ///
-/// A pure function with no dependencies. Used during encode (strand selection)
-/// and decode (recovering the original 8-mer when the BED strand is -).
+/// Pure functions, depending only on chunk.rs for Kmer. A − strand k-mer
+/// lies on the reference as its reverse complement, so formats that show
+/// the reference bases under a coordinate (vcf.rs, sam.rs, fasta.rs) use
+/// it, and so do `--both-strands` lookups and unrefer (table.rs, mod.rs).
 ///
 /// The fixed mapping is biological convention:
 ///   A ↔ T   (adenine pairs with thymine)
 ///   G ↔ C   (guanine pairs with cytosine)
 /// The sequence is then reversed to give the 5'→3' complement strand.
+use super::chunk::Kmer;
 
-/// Compute the reverse complement of an 8-mer byte slice.
+/// Compute the reverse complement of a k-mer byte slice.
 /// Operates on uppercase A/T/G/C bytes only.
-/// This is again only for future purposes. Used for checking through the complement base sequence
-/// also, dead code 
-#[allow(dead_code)]
-pub fn reverse_complement(kmer: &[u8]) -> [u8; 8] {
-    let mut rc = [0u8; 8];
+pub fn reverse_complement(kmer: &[u8]) -> Kmer {
+    let mut rc = Kmer::new(kmer);
     for (i, &b) in kmer.iter().rev().enumerate() {
         rc[i] = match b {
             b'A' => b'T',
@@ -27,27 +27,50 @@ pub fn reverse_complement(kmer: &[u8]) -> [u8; 8] {
     rc
 }
 
+/// Reverse complement of any stretch of genome sequence. Keeps the case
+/// of soft-masked bases; anything other than A/T/G/C becomes N.
+pub fn reverse_complement_seq(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|&b| {
+            let c = match b.to_ascii_uppercase() {
+                b'A' => b'T',
+                b'T' => b'A',
+                b'G' => b'C',
+                b'C' => b'G',
+                _ => b'N',
+            };
+            if b.is_ascii_lowercase() { c.to_ascii_lowercase() } else { c }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_basic() {
-        assert_eq!(reverse_complement(b"ATGCGATC"), *b"GATCGCAT");
+        assert_eq!(&reverse_complement(b"ATGCGATC")[..], b"GATCGCAT");
+    }
+
+    #[test]
+    fn test_sequence_keeps_case() {
+        assert_eq!(reverse_complement_seq(b"ATGcgNTC"), b"GANcgCAT");
     }
 
     #[test]
     fn test_palindrome() {
         // A palindromic 8-mer is its own reverse complement
-        assert_eq!(reverse_complement(b"AATTAATT"), *b"AATTAATT");
+        assert_eq!(&reverse_complement(b"AATTAATT")[..], b"AATTAATT");
     }
 
     #[test]
     fn test_all_same_base() {
-        assert_eq!(reverse_complement(b"AAAAAAAA"), *b"TTTTTTTT");
-        assert_eq!(reverse_complement(b"CCCCCCCC"), *b"GGGGGGGG");
-        assert_eq!(reverse_complement(b"TTTTTTTT"), *b"AAAAAAAA");
-        assert_eq!(reverse_complement(b"GGGGGGGG"), *b"CCCCCCCC");
+        assert_eq!(&reverse_complement(b"AAAAAAAA")[..], b"TTTTTTTT");
+        assert_eq!(&reverse_complement(b"CCCCCCCC")[..], b"GGGGGGGG");
+        assert_eq!(&reverse_complement(b"TTTTTTTT")[..], b"AAAAAAAA");
+        assert_eq!(&reverse_complement(b"GGGGGGGG")[..], b"CCCCCCCC");
     }
 
     #[test]
@@ -55,7 +78,7 @@ mod tests {
         let original = b"ATGCGATC";
         let rc = reverse_complement(original);
         let rc_rc = reverse_complement(&rc);
-        assert_eq!(&rc_rc, original);
+        assert_eq!(&rc_rc[..], original);
     }
 }
 
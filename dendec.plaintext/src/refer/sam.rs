@@ -0,0 +1,260 @@
+/// refer/sam.rs — SAM read and write
+///
+/// `refer --format sam` writes each chunk as a short read aligned to
+/// its coordinate, so the payload passes for alignment output:
+///
+///  @HD  VN:1.6  SO:unsorted
+///  @SQ  SN:NC_000001.11  LN:248946058  AS:hg38
+///  @SQ  SN:NC_000007.14  LN:159345973  AS:hg38
+///  @PG  ID:dendec-refer  PN:dendec-refer  VN:0.1.0
+///  @CO  chunk_size:8
+///  @CO  dna_length:168432
+///  @CO  chunk_count:21054
+///  @CO  payload_sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///  @CO  table_sha256:a415178627e2c5200c58944182e5d2feecc55543bfc7f0cbd994385be46efc71
+///  chunk_00000000  0   NC_000001.11  883402  60  8M  *  0  0  ATGCGATC  ?GIB?DH@
+///  chunk_00000001  16  NC_000007.14  553085  60  8M  *  0  0  GATCGCAT  I@?FEC?H
+///
+/// There is one @SQ line per accession the table holds coordinates on.
+/// Tables record no chromosome lengths, so LN is the end of the furthest
+/// of them; SAM forbids an LN of 0, so accessions without any are left
+/// out.
+///
+/// FLAG 16 marks a − strand read; as SAM requires, its SEQ is then the
+/// reverse complement of the chunk, which is what the reference holds
+/// there. POS is 1-based, so it is the table's start + 1. QNAME is the
+/// chunk name, "*" when shuffled. QUAL is derived from the coordinate,
+/// so it varies like real reads yet a keyed refer stays reproducible.
+/// Unrefer reads QNAME, FLAG, RNAME and POS.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::error::{DendecError, Result};
+use super::assembly::Assembly;
+use super::coordinate::{
+    order_records, parse_chunk_name, BedHeader, BedRecord, REFER_VERSION,
+};
+use super::chunk::MAX_CHUNK_SIZE;
+use super::format::{Chunk, Meta, RecordWriter};
+use super::naming::{keyed_name, Naming};
+use super::reverse::reverse_complement;
+
+/// Start of the first line of every refer SAM; unrefer recognises the
+/// format by it.
+pub const SAM_SIGNATURE: &str = "@HD\t";
+
+/// FLAG bit for a read on the reverse strand.
+const FLAG_REVERSE: u16 = 0x10;
+
+/// Streams a dendec-refer SAM file, one read at a time.
+pub struct SamWriter<W: Write> {
+    w: W,
+    chunk_size: usize,
+    /// Put the chunk name in QNAME; "*" when records are shuffled.
+    naming: Naming,
+}
+
+impl<W: Write> SamWriter<W> {
+    /// Write the @HD, @SQ, @PG and @CO header lines.
+    pub fn new(mut w: W, meta: &Meta) -> Result<Self> {
+        writeln!(w, "{}VN:1.6\tSO:unsorted", SAM_SIGNATURE).map_err(DendecError::Io)?;
+        let contigs = meta.accessions.iter().zip(meta.contig_lengths).filter(|(_, &length)| length > 0);
+        for (accession, length) in contigs {
+            writeln!(w, "@SQ\tSN:{}\tLN:{}\tAS:{}", accession, length, meta.assembly.name())
+                .map_err(DendecError::Io)?;
+        }
+        writeln!(w, "@PG\tID:dendec-refer\tPN:dendec-refer\tVN:{}", REFER_VERSION).map_err(DendecError::Io)?;
+        writeln!(w, "@CO\tchunk_size:{}", meta.chunk_size).map_err(DendecError::Io)?;
+        writeln!(w, "@CO\tdna_length:{}", meta.dna_length).map_err(DendecError::Io)?;
+        writeln!(w, "@CO\tchunk_count:{}", meta.chunk_count).map_err(DendecError::Io)?;
+        writeln!(w, "@CO\tpayload_sha256:{}", meta.payload_sha256).map_err(DendecError::Io)?;
+        writeln!(w, "@CO\ttable_sha256:{}", meta.table_sha256).map_err(DendecError::Io)?;
+        Ok(SamWriter { w, chunk_size: meta.chunk_size, naming: meta.naming.clone() })
+    }
+}
+
+impl<W: Write> RecordWriter for SamWriter<W> {
+    fn push(&mut self, chunk: &Chunk) -> Result<()> {
+        let (flag, seq) = match chunk.strand {
+            0 => (0, chunk.kmer),
+            _ => (FLAG_REVERSE, reverse_complement(&chunk.kmer)),
+        };
+
+        // Phred 30–40 per base, from any fixed mix of the coordinate
+        let mut h = chunk.start.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let mut qual = [0u8; MAX_CHUNK_SIZE];
+        let qual = &mut qual[..self.chunk_size];
+        for q in qual.iter_mut() {
+            *q = b'?' + (h % 11) as u8;
+            h = h.rotate_right(7);
+        }
+
+        let qname = self.naming.name(chunk.idx).unwrap_or_else(|| "*".to_string());
+        writeln!(
+            self.w,
+            "{}\t{}\t{}\t{}\t60\t{}M\t*\t0\t0\t{}\t{}",
+            qname,
+            flag,
+            chunk.accession,
+            chunk.start + 1,
+            self.chunk_size,
+            // Safe: bases are A/T/G/C and qualities printable ASCII
+            std::str::from_utf8(&seq).unwrap_or_default(),
+            std::str::from_utf8(qual).unwrap_or_default()
+        )
+        .map_err(DendecError::Io)?;
+        Ok(())
+    }
+}
+
+/// Parse a dendec-refer SAM file from `path`.
+///
+/// Returns the header metadata and the records, sorted by chunk index
+/// unless they are shuffled.
+pub fn read_sam(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
+    let reader = BufReader::new(File::open(path).map_err(DendecError::Io)?);
+    let invalid = |reason: String| DendecError::ReferInvalidBed(format!("SAM: {}", reason));
+
+    let mut header = BedHeader::default();
+    let mut records = Vec::new();
+
+    for raw in reader.lines() {
+        let line = raw.map_err(DendecError::Io)?;
+        let line = line.trim_end();
+
+        // ── Header lines ──────────────────────────────────────────────
+        if let Some(fields) = line.strip_prefix('@') {
+            let mut fields = fields.split('\t');
+            match fields.next() {
+                Some("SQ") => {
+                    if let Some(value) = fields.find_map(|f| f.strip_prefix("AS:")) {
+                        header.assembly = Some(Assembly::from_header_value(value)?);
+                    }
+                }
+                Some("CO") => match fields.next().and_then(|f| f.split_once(':')) {
+                    Some(("chunk_size", value)) => header.chunk_size = value.parse().unwrap_or(0),
+                    Some(("dna_length", value)) => header.dna_length = value.parse().unwrap_or(0),
+                    Some(("chunk_count", value)) => header.chunk_count = value.parse().unwrap_or(0),
+                    Some(("payload_sha256", value)) => header.payload_sha256 = Some(value.to_string()),
+                    Some(("table_sha256", value)) => header.table_sha256 = Some(value.to_string()),
+                    _ => {}
+                },
+                _ => {}
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        // ── Alignment lines ───────────────────────────────────────────
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 11 {
+            return Err(invalid(format!("expected 11 tab-separated columns, got {}: {:?}", cols.len(), line)));
+        }
+
+        let flag: u16 = cols[1]
+            .parse()
+            .map_err(|_| invalid(format!("invalid FLAG '{}' in line: {}", cols[1], line)))?;
+        let pos: u64 = cols[3]
+            .parse()
+            .ok()
+            .filter(|&p| p > 0)
+            .ok_or_else(|| invalid(format!("invalid POS '{}' in line: {}", cols[3], line)))?;
+        let seq = cols[9];
+        if seq.len() != header.chunk_size() || !seq.bytes().all(|b| matches!(b, b'A' | b'T' | b'G' | b'C')) {
+            return Err(invalid(format!(
+                "SEQ '{}' is not a {}-mer in line: {}",
+                seq,
+                header.chunk_size(),
+                line
+            )));
+        }
+
+        let chunk_idx = match cols[0] {
+            "*" => None,
+            name => parse_chunk_name(name)?,
+        };
+
+        records.push(BedRecord {
+            accession: cols[2].to_string(),
+            start: pos - 1,
+            strand: u8::from(flag & FLAG_REVERSE != 0),
+            chunk_idx,
+            keyed_name: keyed_name(cols[0]),
+        });
+    }
+
+    order_records(&mut records)?;
+    Ok((header, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::chunk::Kmer;
+    use tempfile::tempdir;
+
+    fn write(chunk_names: bool) -> String {
+        let accessions = ["NC_000001.11".to_string(), "NC_000007.14".to_string(), "NC_000008.11".to_string()];
+        let meta = Meta {
+            assembly: Assembly::Hg38,
+            chunk_size: 8,
+            dna_length: 16,
+            payload_sha256: "",
+            table_sha256: "",
+            chunk_count: 2,
+            naming: match chunk_names {
+                true => Naming::Chunk,
+                false => Naming::Hidden,
+            },
+            accessions: &accessions,
+            contig_lengths: &[883409, 553092, 0],
+            key: None,
+            reference: None,
+        };
+        let mut sam = SamWriter::new(Vec::new(), &meta).unwrap();
+        sam.push(&Chunk { idx: 0, accession: "NC_000001.11", start: 883401, strand: 0, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        sam.push(&Chunk { idx: 1, accession: "NC_000007.14", start: 553084, strand: 1, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        String::from_utf8(sam.w).unwrap()
+    }
+
+    #[test]
+    fn test_write_and_read_roundtrip() {
+        let text = write(true);
+        assert!(text.starts_with("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:NC_000001.11\tLN:883409\tAS:hg38\n"));
+        assert!(!text.contains("NC_000008.11"), "@SQ with LN:0");
+        assert!(text.contains("chunk_00000000\t0\tNC_000001.11\t883402\t60\t8M\t*\t0\t0\tATGCGATC\t"));
+        // SEQ of a reverse read is the reverse complement
+        assert!(text.contains("chunk_00000001\t16\tNC_000007.14\t553085\t60\t8M\t*\t0\t0\tGATCGCAT\t"));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.sam");
+        std::fs::write(&path, text).unwrap();
+        let (header, records) = read_sam(&path).unwrap();
+        assert_eq!((header.assembly, header.dna_length, header.chunk_count), (Some(Assembly::Hg38), 16, 2));
+        assert_eq!((records[0].start, records[0].strand, records[0].chunk_idx), (883401, 0, Some(0)));
+        assert_eq!((records[1].start, records[1].strand, records[1].chunk_idx), (553084, 1, Some(1)));
+
+        std::fs::write(&path, write(false)).unwrap();
+        let (_, records) = read_sam(&path).unwrap();
+        assert!(records.iter().all(|r| r.chunk_idx.is_none()));
+    }
+
+    #[test]
+    fn test_bad_records_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bad.sam");
+        for line in [
+            "chunk_00000000\t0\tNC_000001.11\t0\t60\t8M\t*\t0\t0\tATGCGATC\t*",
+            "chunk_00000000\tx\tNC_000001.11\t10\t60\t8M\t*\t0\t0\tATGCGATC\t*",
+            "chunk_00000000\t0\tNC_000001.11\t10\t60\t4M\t*\t0\t0\tATGC\t*",
+            "read1\t0\tNC_000001.11\t10\t60\t8M\t*\t0\t0\tATGCGATC\t*",
+            "chunk_00000000\t0\tNC_000001.11\t10\t60\t8M",
+        ] {
+            std::fs::write(&path, format!("@HD\tVN:1.6\n{line}\n")).unwrap();
+            assert!(read_sam(&path).is_err(), "accepted {line:?}");
+        }
+    }
+}
@@ -0,0 +1,210 @@
+/// refer/stats.rs — dendec table stats
+///
+/// Summarises a lookup table so a custom build can be judged before refer
+/// relies on it:
+///
+///   size         bytes once decompressed, chromosomes, coordinates,
+///                chunk size k
+///   coverage     k-mers with at least one coordinate; refer fails on
+///                any .dna containing one that has none
+///   options      histogram of coordinate options per k-mer — more
+///                options mean more varied BED output for repeated k-mers
+///   chromosomes  coordinates and covered span per accession
+///
+/// The table is parsed exactly as refer parses it, so a table that loads
+/// here loads there.
+use std::path::Path;
+
+use crate::error::Result;
+use super::assembly::Assembly;
+use super::table::{read_table_file, ReferTable};
+
+/// Figures reported by `dendec table stats`.
+pub struct TableStats {
+    /// Size of the serialised table in bytes.
+    pub size: usize,
+    /// Bases per k-mer.
+    pub chunk_size: usize,
+    /// Possible k-mers, 4^k.
+    pub entries: usize,
+    /// Total coordinates across all k-mers.
+    pub coords: usize,
+    /// k-mers with at least one coordinate.
+    pub covered: usize,
+    /// `histogram[n]` is the number of k-mers with exactly n options.
+    pub histogram: Vec<usize>,
+    pub chromosomes: Vec<ChromStats>,
+}
+
+/// Per-accession share of a table.
+pub struct ChromStats {
+    pub accession: String,
+    pub coords: usize,
+    /// Forward-strand (+) coordinates among `coords`.
+    pub forward: usize,
+    /// Lowest start and highest end of any coordinate, if it has any.
+    pub span: Option<(u64, u64)>,
+}
+
+impl TableStats {
+    /// Gather statistics from a loaded table of `size` serialised bytes.
+    pub fn collect(table: &ReferTable, size: usize) -> Self {
+        let mut chromosomes: Vec<ChromStats> = table
+            .accessions
+            .iter()
+            .map(|a| ChromStats { accession: a.clone(), coords: 0, forward: 0, span: None })
+            .collect();
+        let mut histogram = Vec::new();
+        let mut coords = 0;
+
+        for entry in table.entries() {
+            if histogram.len() <= entry.len() {
+                histogram.resize(entry.len() + 1, 0);
+            }
+            histogram[entry.len()] += 1;
+            coords += entry.len();
+
+            for coord in entry.iter() {
+                // Indices past the accession table fail refer, not stats
+                let Some(chrom) = chromosomes.get_mut(coord.chrom_idx as usize) else {
+                    continue;
+                };
+                chrom.coords += 1;
+                if coord.strand == 0 {
+                    chrom.forward += 1;
+                }
+                let end = coord.start.saturating_add(table.chunk_size() as u64);
+                chrom.span = Some(match chrom.span {
+                    Some((lo, hi)) => (lo.min(coord.start), hi.max(end)),
+                    None => (coord.start, end),
+                });
+            }
+        }
+
+        let entries = table.entry_count();
+        let covered = entries - histogram.first().copied().unwrap_or(0);
+        TableStats { size, chunk_size: table.chunk_size(), entries, coords, covered, histogram, chromosomes }
+    }
+}
+
+/// Load the table at `file`, or the embedded one for `assembly`, and
+/// print its statistics to stdout.
+pub fn run_stats(file: Option<&Path>, assembly: Assembly) -> Result<()> {
+    let (label, stats) = match file {
+        Some(path) => {
+            let bytes = read_table_file(path)?;
+            let table = ReferTable::from_bytes(&bytes)?;
+            (path.display().to_string(), TableStats::collect(&table, bytes.len()))
+        }
+        None => {
+            let bytes = assembly.table_bytes()?;
+            let table = ReferTable::from_bytes(bytes)?;
+            let packed = assembly.compressed_table().map_or(0, |b| b.len());
+            (
+                format!("embedded {assembly} table ({:.1} KB compressed)", packed as f64 / 1024.0),
+                TableStats::collect(&table, bytes.len()),
+            )
+        }
+    };
+    print_stats(&label, &stats);
+    Ok(())
+}
+
+fn print_stats(label: &str, stats: &TableStats) {
+    println!("{label}");
+    println!();
+    println!(
+        "  size          {:.1} KB  |  {} chromosome(s)  |  {} coordinates",
+        stats.size as f64 / 1024.0,
+        stats.chromosomes.len(),
+        stats.coords
+    );
+    println!("  chunk size    {} bases", stats.chunk_size);
+    println!(
+        "  coverage      {}/{} {}-mers  ({:.2}%)",
+        stats.covered,
+        stats.entries,
+        stats.chunk_size,
+        stats.covered as f64 * 100.0 / stats.entries as f64
+    );
+    if stats.covered > 0 {
+        println!(
+            "  mean options  {:.2} per covered {}-mer",
+            stats.coords as f64 / stats.covered as f64,
+            stats.chunk_size
+        );
+    }
+
+    println!();
+    println!("  options per {}-mer", stats.chunk_size);
+    for (n, &count) in stats.histogram.iter().enumerate().filter(|(_, &c)| c > 0) {
+        println!("    {:>3}  {:>6}  ({:.2}%)", n, count, count as f64 * 100.0 / stats.entries as f64);
+    }
+
+    println!();
+    println!("  chromosomes");
+    for chrom in &stats.chromosomes {
+        let share = if stats.coords == 0 { 0.0 } else { chrom.coords as f64 * 100.0 / stats.coords as f64 };
+        let span = match chrom.span {
+            Some((lo, hi)) => format!("{lo}–{hi}"),
+            None => "unused".to_string(),
+        };
+        println!(
+            "    {:<16} {:>8}  ({:>5.1}%)  +{} / -{}  span {}",
+            chrom.accession,
+            chrom.coords,
+            share,
+            chrom.forward,
+            chrom.coords - chrom.forward,
+            span
+        );
+    }
+
+    if stats.covered < stats.entries {
+        println!();
+        println!(
+            "  Warning: {} {}-mers have no coverage — refer fails on any .dna containing them",
+            stats.entries - stats.covered,
+            stats.chunk_size
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::build::TableBuilder;
+
+    #[test]
+    fn test_stats_of_built_table() {
+        // AAAAAAAA at 0 and 1, AAAAAAAC at 2, plus the reverse
+        // complements TTTTTTTT (twice) and GTTTTTTT
+        let mut builder = TableBuilder::new(4, 8, false, false);
+        builder.read_fasta(&b">chr1\nAAAAAAAAAC\n>chr2\nNNNN\n"[..]).unwrap();
+        let mut bytes = Vec::new();
+        builder.write(&mut bytes).unwrap();
+
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+        let stats = TableStats::collect(&table, bytes.len());
+
+        assert_eq!(stats.size, bytes.len());
+        assert_eq!(stats.coords, 6);
+        assert_eq!(stats.covered, 4);
+        assert_eq!((stats.chunk_size, stats.entries), (8, 65_536));
+        assert_eq!(stats.histogram, [65_536 - 4, 2, 2]);
+
+        let chr1 = &stats.chromosomes[0];
+        assert_eq!((chr1.coords, chr1.forward, chr1.span), (6, 3, Some((0, 10))));
+        let chr2 = &stats.chromosomes[1];
+        assert_eq!((chr2.accession.as_str(), chr2.coords, chr2.span), ("chr2", 0, None));
+    }
+
+    #[test]
+    #[ignore = "data/table.bin is still the chr1-prefix sample; rebuild it from GRCh38"]
+    fn test_embedded_hg38_spans_chromosomes() {
+        let bytes = Assembly::Hg38.table_bytes().unwrap();
+        let stats = TableStats::collect(&ReferTable::from_bytes(bytes).unwrap(), bytes.len());
+        let used = stats.chromosomes.iter().filter(|c| c.coords > 0).count();
+        assert!(used > 1, "embedded hg38 table holds coordinates on {used} chromosome(s)");
+    }
+}
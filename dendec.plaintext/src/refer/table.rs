@@ -1,58 +1,118 @@
 /// refer/table.rs — Embedded lookup table with forward and reverse indices
 ///
-/// The pre-built table (data/table.bin) is embedded at compile time via
-/// include_bytes!. At runtime, load() parses the binary once and builds
-/// two indices in memory:
+/// Pre-built tables (data/table.bin for hg38, data/<assembly>.bin for the
+/// others) are embedded zstd-compressed at compile time via include_bytes!
+/// — see build.rs and assembly.rs. load() decompresses the chosen one once;
+/// after that nothing is parsed up front:
 ///
-///   forward:  8-mer base-4 index → Vec<Coord>   (encode path, O(1))
-///   reverse:  CoordKey → u16 8-mer index        (decode path, O(1))
+///   forward:  k-mer index → offsets → 6-byte records   (encode path, O(1))
+///   reverse:  sorted packed u128 records              (decode path, O(log n))
+///
+/// k is the table's chunk size, 8 unless it was built with
+/// `table build --chunk-size`. The forward index is a fixed-stride section
+/// of the table, read in place. The reverse index packs each coordinate
+/// and its k-mer index into one u128 (see CoordKey::packed): 9 MB for a
+/// full hg38 table, sorted once from the forward records on the first
+/// reverse lookup. It is not stored — sorted keys compress poorly, and
+/// shipping it would add megabytes to the binary to save a sort of a few
+/// milliseconds on decode.
 ///
 /// The fixed base-4 mapping used here (A=0, T=1, G=2, C=3) is completely
 /// independent of the key-derived permuted mapping in dendec core. Refer
 /// treats the ATGC string as opaque characters — it never interprets the
 /// cryptographic meaning of the bases.
 ///
-/// BINARY FORMAT (data/table.bin)
+/// BINARY FORMAT (data/*.bin), version 5
 /// ─────────────────────────────────────────────────────────────────────
-///  Offset  Len   Field
-///  0       4     Magic: 0x44 0x52 0x46 0x54  ("DRFT")
-///  4       1     Version: 0x01
-///  5       2     Chromosome count (u16 LE)
-///  7       var   Accession strings: [len: u8][utf8 bytes] × count
-///  ?       var   65,536 8-mer entries:
-///                  [count: u8]
-///                  [chrom_idx: u8][start: u32 LE][strand: u8] × count
+///  Offset  Len       Field
+///  0       4         Magic: 0x44 0x52 0x46 0x54  ("DRFT")
+///  4       1         Version: 0x05
+///  5       1         Chunk size k (8 to 12)
+///  6       2         Chromosome count (u16 LE)
+///  8       var       Accession strings: [len: u8][utf8 bytes] × count
+///  ?       4         Coordinate count N (u32 LE)
+///  ?       4×(4^k+1) Offsets (u32 LE): k-mer i owns forward records
+///                    offsets[i]..offsets[i+1]
+///  ?       11×N      Forward records, grouped by k-mer:
+///                    [chrom_idx: u16 LE][start: u64 LE][strand: u8]
+///  end-32  32        SHA-256 of every byte before it
 /// ─────────────────────────────────────────────────────────────────────
-
-use std::collections::HashMap;
+///
+/// Version 5 widened the records for large genomes: plant chromosomes
+/// past 4 Gb, and assemblies of thousands of scaffolds. Version 4 is the
+/// same with 6-byte records, [chrom_idx: u8][start: u32 LE][strand: u8],
+/// so at most 256 chromosomes of up to 4 Gb; version 3 is version 4
+/// without the chunk size byte, and always k = 8. Both are read in place.
+/// Versions 1 and 2 stored each 8-mer as [count: u8] followed by 6-byte
+/// records, with no index (and, in version 1, no checksum). They still
+/// load: from_bytes converts them to version 5 in memory.
+///
+/// A table may also be a zstd frame wrapping the above; unpack() tells the
+/// two apart by their first four bytes, so plain tables still load.
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
 use rand::Rng;
+use sha2::{Digest, Sha256};
 use crate::error::{DendecError, Result};
-
-const MAGIC: [u8; 4] = [0x44, 0x52, 0x46, 0x54]; // "DRFT"
-const VERSION: u8 = 0x01;
-pub const TABLE_SIZE: usize = 65_536; // 4^8
-pub const KMER_LEN: usize = 8;
-
-/// The pre-built lookup table embedded at compile time.
-/// Compilation fails if data/table.bin does not exist — intentional.
-static TABLE_BYTES: &[u8] = include_bytes!("../../data/table.bin");
+use super::assembly::Assembly;
+use super::chunk::{Kmer, DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE};
+use super::reverse::reverse_complement;
+
+pub const MAGIC: [u8; 4] = [0x44, 0x52, 0x46, 0x54]; // "DRFT"
+pub const VERSION: u8 = 0x05;
+pub const CHECKSUM_LEN: usize = 32;
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// Forward record length from version 5 on, and before it.
+const RECORD_LEN: usize = 11;
+const NARROW_RECORD_LEN: usize = 6;
 
 /// A single genome coordinate from the lookup table.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Coord {
-    pub chrom_idx: u8, // index into the accession string table
-    pub start: u32,    // 0-based start position (BED convention)
-    pub strand: u8,    // 0 = forward (+), 1 = reverse (-)
+    pub chrom_idx: u16, // index into the accession string table
+    pub start: u64,     // 0-based start position (BED convention)
+    pub strand: u8,     // 0 = forward (+), 1 = reverse (-)
+}
+
+impl Coord {
+    /// Read a forward record, RECORD_LEN or NARROW_RECORD_LEN bytes long.
+    fn from_record(rec: &[u8]) -> Self {
+        match rec.len() {
+            NARROW_RECORD_LEN => Coord {
+                chrom_idx: rec[0] as u16,
+                start: u32::from_le_bytes([rec[1], rec[2], rec[3], rec[4]]) as u64,
+                strand: rec[5],
+            },
+            _ => Coord {
+                chrom_idx: u16::from_le_bytes([rec[0], rec[1]]),
+                start: u64::from_le_bytes(rec[2..10].try_into().unwrap()),
+                strand: rec[10],
+            },
+        }
+    }
 }
 
 /// Key used for the reverse index — uniquely identifies a coordinate.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CoordKey {
-    pub chrom_idx: u8,
-    pub start: u32,
+    pub chrom_idx: u16,
+    pub start: u64,
     pub strand: u8,
 }
 
+impl CoordKey {
+    /// Pack into the top bits of a reverse record:
+    ///
+    ///   [chrom_idx: 16][start: 64][strand: 1][k-mer index: 32]
+    ///
+    /// Records sort by coordinate, and the low 32 bits carry the k-mer.
+    fn packed(&self) -> u128 {
+        ((self.chrom_idx as u128) << 97) | ((self.start as u128) << 33) | (((self.strand & 1) as u128) << 32)
+    }
+}
+
 impl From<&Coord> for CoordKey {
     fn from(c: &Coord) -> Self {
         CoordKey {
@@ -63,119 +123,215 @@ impl From<&Coord> for CoordKey {
     }
 }
 
-/// The loaded, in-memory refer table with both indices ready to use.
-pub struct ReferTable {
+/// The coordinate options of one k-mer, read in place from the table.
+#[derive(Clone, Copy)]
+pub struct Entry<'t> {
+    records: &'t [u8],
+    record_len: usize,
+}
+
+impl<'t> Entry<'t> {
+    pub fn len(&self) -> usize {
+        self.records.len() / self.record_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Coord {
+        Coord::from_record(&self.records[i * self.record_len..(i + 1) * self.record_len])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Coord> + 't {
+        self.records.chunks_exact(self.record_len).map(Coord::from_record)
+    }
+}
+
+/// A refer table over its serialised bytes, forward index read in place.
+///
+/// Borrows the bytes (the embedded tables live for the whole run), or
+/// owns them when an older format had to be converted.
+pub struct ReferTable<'a> {
     /// RefSeq accession strings in chrom_idx order.
     /// e.g. accessions[0] = "NC_000001.11"
     pub accessions: Vec<String>,
 
-    /// Forward index: base-4 8-mer index → available genome coordinates.
-    /// Used on the encode path.
-    forward: Vec<Vec<Coord>>,
+    data: Cow<'a, [u8]>,
+    /// Bases per k-mer.
+    chunk_size: usize,
+    /// Bytes per forward record: RECORD_LEN, or NARROW_RECORD_LEN
+    /// before version 5.
+    record_len: usize,
+    /// Number of coordinates, N.
+    count: usize,
+    /// Byte offsets of the offsets and forward sections.
+    offsets_at: usize,
+    forward_at: usize,
+    /// Packed (coordinate, k-mer index) records, sorted; built on first use.
+    reverse: OnceLock<Vec<u128>>,
+}
 
-    /// Reverse index: coordinate key → base-4 8-mer index.
-    /// Used on the decode path.
-    reverse: HashMap<CoordKey, u16>,
+impl ReferTable<'static> {
+    /// Open the embedded table for `assembly`.
+    ///
+    /// Called once at the start of refer_encode or refer_decode. The table
+    /// is decompressed on first use; opening it only checks its header,
+    /// checksum and offsets. Fails with ReferAssemblyUnavailable if that
+    /// table was not compiled in.
+    pub fn load(assembly: Assembly) -> Result<Self> {
+        Self::from_bytes(assembly.table_bytes()?)
+    }
 }
 
-impl ReferTable {
-    /// Parse the embedded table.bin and build both indices.
+impl<'a> ReferTable<'a> {
+    /// Open a table in the binary format above, e.g. one written by
+    /// `dendec table build`.
     ///
-    /// Called once at the start of refer_encode or refer_decode.
-    /// Parsing is fast — a linear scan of ~3 MB of binary data.
-    pub fn load() -> Result<Self> {
-        let bytes = TABLE_BYTES;
-        let mut cur = 0usize;
-
-        // ── Magic ─────────────────────────────────────────────────────
-        if bytes.len() < 7 || &bytes[0..4] != MAGIC {
-            return Err(DendecError::ReferTableCorrupt);
+    /// Every structural problem is reported as ReferTableInvalid naming
+    /// what is wrong and where, so a bad custom table can be fixed. The
+    /// records themselves are checked by validate().
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        let (version, body) = check_header(bytes)?;
+        if version < 3 {
+            let mut r = ByteReader { bytes: body, pos: 5 };
+            let accessions = read_accessions(&mut r, NARROW_RECORD_LEN)?;
+            let entries = read_legacy_entries(&mut r, accessions.len())?;
+            let mut upgraded = Vec::new();
+            write_table(&mut upgraded, DEFAULT_CHUNK_SIZE, &accessions, &entries)?;
+            return Self::index(VERSION, Cow::Owned(upgraded));
         }
-        cur += 4;
+        Self::index(version, Cow::Borrowed(bytes))
+    }
 
-        // ── Version ───────────────────────────────────────────────────
-        if bytes[cur] != VERSION {
-            return Err(DendecError::ReferTableCorrupt);
+    /// Locate the sections of a version 3 to 5 table and check its
+    /// offsets.
+    fn index(version: u8, data: Cow<'a, [u8]>) -> Result<Self> {
+        let body = &data[..data.len() - CHECKSUM_LEN];
+        let mut r = ByteReader { bytes: body, pos: 5 };
+        let chunk_size = match version {
+            3 => DEFAULT_CHUNK_SIZE,
+            _ => r.u8("chunk size")? as usize,
+        };
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+            return Err(invalid(format!(
+                "chunk size {} is not supported (expected {} to {})",
+                chunk_size, MIN_CHUNK_SIZE, MAX_CHUNK_SIZE
+            )));
+        }
+        let record_len = match version {
+            3 | 4 => NARROW_RECORD_LEN,
+            _ => RECORD_LEN,
+        };
+        let accessions = read_accessions(&mut r, record_len)?;
+
+        let count = u32::from_le_bytes(r.take(4, "coordinate count")?.try_into().unwrap()) as usize;
+        let entry_count = 1usize << (2 * chunk_size);
+        let offsets_at = r.pos;
+        let forward_at = offsets_at + 4 * (entry_count + 1);
+        let expected = forward_at + record_len * count;
+        if body.len() != expected {
+            return Err(invalid(format!(
+                "{} coordinates need {} bytes before the checksum, found {}",
+                count,
+                expected,
+                body.len()
+            )));
         }
-        cur += 1;
 
-        // ── Chromosome count ──────────────────────────────────────────
-        if cur + 2 > bytes.len() {
-            return Err(DendecError::ReferTableCorrupt);
+        let table = ReferTable {
+            accessions,
+            data,
+            chunk_size,
+            record_len,
+            count,
+            offsets_at,
+            forward_at,
+            reverse: OnceLock::new(),
+        };
+        if table.offset(0) != 0 {
+            return Err(invalid(format!("offset of {}-mer entry 0 is not 0", chunk_size)));
         }
-        let chrom_count = u16::from_le_bytes(
-            bytes[cur..cur + 2]
-                .try_into()
-                .map_err(|_| DendecError::ReferTableCorrupt)?,
-        ) as usize;
-        cur += 2;
-
-        // ── Accession strings ─────────────────────────────────────────
-        let mut accessions = Vec::with_capacity(chrom_count);
-        for _ in 0..chrom_count {
-            if cur >= bytes.len() {
-                return Err(DendecError::ReferTableCorrupt);
-            }
-            let len = bytes[cur] as usize;
-            cur += 1;
-            if cur + len > bytes.len() {
-                return Err(DendecError::ReferTableCorrupt);
+        for idx in 0..entry_count {
+            if table.offset(idx + 1) < table.offset(idx) {
+                return Err(invalid(format!(
+                    "offsets go backwards at {}-mer entry {} ({})",
+                    chunk_size,
+                    idx,
+                    kmer_name(idx, chunk_size)
+                )));
             }
-            let s = std::str::from_utf8(&bytes[cur..cur + len])
-                .map_err(|_| DendecError::ReferTableCorrupt)?;
-            accessions.push(s.to_string());
-            cur += len;
         }
+        if table.offset(entry_count) != count {
+            return Err(invalid(format!(
+                "offsets end at {}, but the table has {} coordinates",
+                table.offset(entry_count),
+                count
+            )));
+        }
+        Ok(table)
+    }
 
-        // ── 65,536 8-mer entries ──────────────────────────────────────
-        //
-        // CRITICAL: the loop index must be usize, not u16.
-        // TABLE_SIZE is 65,536 — exactly u16::MAX + 1. Casting it to u16
-        // silently overflows to 0, producing an empty 0..0 range. The loop
-        // would never execute and `forward` would remain empty, causing every
-        // subsequent index access to panic. The cast to u16 is applied only
-        // when inserting into `reverse`, where idx ≤ 65,535 and is safe.
-        let mut forward: Vec<Vec<Coord>> = Vec::with_capacity(TABLE_SIZE);
-        let mut reverse: HashMap<CoordKey, u16> =
-            HashMap::with_capacity(TABLE_SIZE * 8);
-
-        for idx in 0..TABLE_SIZE {
-            if cur >= bytes.len() {
-                return Err(DendecError::ReferTableCorrupt);
-            }
-            let count = bytes[cur] as usize;
-            cur += 1;
+    /// The table's SHA-256 checksum, lowercase hex — its identity.
+    ///
+    /// Refer files record it so unrefer can tell a file made with another
+    /// table from a damaged one.
+    pub fn sha256(&self) -> String {
+        self.data[self.data.len() - CHECKSUM_LEN..].iter().map(|b| format!("{b:02x}")).collect()
+    }
 
-            let mut coords = Vec::with_capacity(count);
-            for _ in 0..count {
-                if cur + 6 > bytes.len() {
-                    return Err(DendecError::ReferTableCorrupt);
+    /// Check every record's chromosome index and strand.
+    ///
+    /// Opening a table does not read the records, so `table verify` calls
+    /// this to catch damage a checksum cannot (a table built wrong).
+    pub fn validate(&self) -> Result<()> {
+        for (idx, entry) in self.entries().enumerate() {
+            for coord in entry.iter() {
+                if coord.chrom_idx as usize >= self.accessions.len() {
+                    return Err(invalid(format!(
+                        "{}-mer entry {} ({}) refers to chromosome {}, but only {} are listed",
+                        self.chunk_size,
+                        idx,
+                        kmer_name(idx, self.chunk_size),
+                        coord.chrom_idx,
+                        self.accessions.len()
+                    )));
+                }
+                if coord.strand > 1 {
+                    return Err(invalid(format!(
+                        "{}-mer entry {} ({}) has strand byte {} (expected 0 or 1)",
+                        self.chunk_size,
+                        idx,
+                        kmer_name(idx, self.chunk_size),
+                        coord.strand
+                    )));
                 }
-                let chrom_idx = bytes[cur];
-                let start = u32::from_le_bytes(
-                    bytes[cur + 1..cur + 5]
-                        .try_into()
-                        .map_err(|_| DendecError::ReferTableCorrupt)?,
-                );
-                let strand = bytes[cur + 5];
-                cur += 6;
-
-                // Safe: idx is in 0..=65_535, which fits exactly in u16
-                reverse.insert(
-                    CoordKey { chrom_idx, start, strand },
-                    idx as u16,
-                );
-                coords.push(Coord { chrom_idx, start, strand });
             }
-            forward.push(coords);
         }
+        Ok(())
+    }
 
-        Ok(ReferTable { accessions, forward, reverse })
+    fn offset(&self, idx: usize) -> usize {
+        let at = self.offsets_at + 4 * idx;
+        u32::from_le_bytes(self.data[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    /// The reverse index, sorted from the forward records on first call.
+    fn reverse(&self) -> &[u128] {
+        self.reverse.get_or_init(|| {
+            let mut reverse = Vec::with_capacity(self.count);
+            for (idx, entry) in self.entries().enumerate() {
+                // Safe: idx is below 4^MAX_CHUNK_SIZE = 2^24, well within 32 bits
+                reverse.extend(entry.iter().map(|c| CoordKey::from(&c).packed() | idx as u128));
+            }
+            reverse.sort_unstable();
+            reverse
+        })
     }
 
     // ── Index conversion ──────────────────────────────────────────────
 
-    /// Convert an 8-mer byte slice to its base-4 index.
+    /// Convert a k-mer byte slice to its base-4 index.
     ///
     /// Fixed mapping: A=0, T=1, G=2, C=3.
     /// Returns None for any non-ATGC byte (should not occur after
@@ -195,12 +351,12 @@ impl ReferTable {
         Some(idx)
     }
 
-    /// Convert a base-4 index back to an 8-mer byte array.
+    /// Convert a base-4 index back to a k-mer of `k` bases.
     ///
     /// Fixed mapping: 0=A, 1=T, 2=G, 3=C. Inverse of kmer_to_index.
-    pub fn index_to_kmer(mut idx: u16) -> [u8; KMER_LEN] {
-        let mut kmer = [b'A'; KMER_LEN];
-        for i in (0..KMER_LEN).rev() {
+    pub fn index_to_kmer(mut idx: usize, k: usize) -> Kmer {
+        let mut kmer = [b'A'; MAX_CHUNK_SIZE];
+        for i in (0..k).rev() {
             kmer[i] = match idx & 0b11 {
                 0 => b'A',
                 1 => b'T',
@@ -210,53 +366,358 @@ impl ReferTable {
             };
             idx >>= 2;
         }
-        kmer
+        Kmer::new(&kmer[..k])
     }
 
     // ── Lookup ────────────────────────────────────────────────────────
 
-    /// Forward lookup: 8-mer → a randomly selected genome coordinate.
+    /// Forward lookup: k-mer → a genome coordinate picked by `rng`.
     ///
-    /// Random selection among the available coordinate options ensures that
-    /// repeated 8-mers in the DNA produce varied coordinates in the BED
-    /// output rather than mechanical repetition.
+    /// Picking among the available coordinate options ensures that
+    /// repeated k-mers in the DNA produce varied coordinates in the BED
+    /// output rather than mechanical repetition. A seeded `rng` makes the
+    /// picks reproducible.
     ///
-    /// Returns None only if the 8-mer has no coverage — should not occur
-    /// with a complete table but handled defensively.
-    pub fn lookup(&self, kmer: &[u8]) -> Option<Coord> {
-        let idx = Self::kmer_to_index(kmer)?;
-        let options = &self.forward[idx];
+    /// Returns None if the k-mer is not the table's chunk size or has no
+    /// coverage — should not occur with a complete table but handled
+    /// defensively.
+    pub fn lookup(&self, kmer: &[u8], rng: &mut impl Rng) -> Option<Coord> {
+        if kmer.len() != self.chunk_size {
+            return None;
+        }
+        let options = self.entry(Self::kmer_to_index(kmer)?);
         if options.is_empty() {
             return None;
         }
-        let pick = rand::thread_rng().gen_range(0..options.len());
-        Some(options[pick].clone())
+        let pick = rng.gen_range(0..options.len());
+        Some(options.get(pick))
+    }
+
+    /// Forward lookup over both strands: like lookup, but the options also
+    /// include the places the table lists for the k-mer's reverse
+    /// complement, on the other strand — the k-mer is what that strand
+    /// reads there. Tables from `table build` list both strands alike, so
+    /// this adds options only to a `--forward-only` table (or one from
+    /// elsewhere).
+    pub fn lookup_either_strand(&self, kmer: &[u8], rng: &mut impl Rng) -> Option<Coord> {
+        let options = self.options(kmer, true);
+        if options.is_empty() {
+            return None;
+        }
+        let pick = rng.gen_range(0..options.len());
+        options.into_iter().nth(pick)
+    }
+
+    /// Every coordinate lookup picks from, or lookup_either_strand with
+    /// `both_strands`. Places already among the k-mer's own are not
+    /// listed twice. Empty if the k-mer is not the table's chunk size.
+    pub fn options(&self, kmer: &[u8], both_strands: bool) -> Vec<Coord> {
+        let index = |kmer: &[u8]| Self::kmer_to_index(kmer).filter(|_| kmer.len() == self.chunk_size);
+        let Some(idx) = index(kmer) else {
+            return Vec::new();
+        };
+        let mut options: Vec<Coord> = self.entry(idx).iter().collect();
+        if let Some(rc) = index(&reverse_complement(kmer)).filter(|_| both_strands) {
+            let own = options.len();
+            for coord in self.entry(rc).iter() {
+                let flipped = Coord { strand: coord.strand ^ 1, ..coord };
+                let key = CoordKey::from(&flipped);
+                if !options[..own].iter().any(|c| CoordKey::from(c) == key) {
+                    options.push(flipped);
+                }
+            }
+        }
+        options
+    }
+
+    /// Resolve a record's coordinate to the k-mer it stands for.
+    ///
+    /// Tries the coordinate as given, then the other strand at the same
+    /// place, whose k-mer is the reverse complement of the one wanted —
+    /// how a `--both-strands` record into a one-strand table reads back.
+    pub fn resolve(&self, key: &CoordKey) -> Option<Kmer> {
+        self.reverse_lookup(key).or_else(|| {
+            let other = CoordKey { strand: key.strand ^ 1, ..key.clone() };
+            self.reverse_lookup(&other).map(|kmer| reverse_complement(&kmer))
+        })
     }
 
-    /// Reverse lookup: coordinate key → 8-mer byte array.
+    /// Reverse lookup: coordinate key → k-mer.
     ///
     /// Returns None if the coordinate is not in the index, which indicates
     /// a tampered or incompatible BED file.
-    pub fn reverse_lookup(&self, key: &CoordKey) -> Option<[u8; KMER_LEN]> {
-        let &idx = self.reverse.get(key)?;
-        Some(Self::index_to_kmer(idx))
+    pub fn reverse_lookup(&self, key: &CoordKey) -> Option<Kmer> {
+        let packed = key.packed();
+        let reverse = self.reverse();
+        let at = reverse.partition_point(|&r| r < packed);
+        let &record = reverse.get(at)?;
+        if record & !0xffff_ffff != packed {
+            return None;
+        }
+        Some(Self::index_to_kmer(record as u32 as usize, self.chunk_size))
+    }
+
+    /// Coordinate options for the k-mer with base-4 index `idx`.
+    pub fn entry(&self, idx: usize) -> Entry<'_> {
+        let (from, to) = (self.offset(idx), self.offset(idx + 1));
+        let (from, to) = (self.forward_at + self.record_len * from, self.forward_at + self.record_len * to);
+        Entry { records: &self.data[from..to], record_len: self.record_len }
+    }
+
+    /// Coordinate options for every k-mer, in base-4 index order.
+    pub fn entries(&self) -> impl Iterator<Item = Entry<'_>> + '_ {
+        (0..self.entry_count()).map(|idx| self.entry(idx))
+    }
+
+    /// Bases per k-mer: the chunk size refer cuts DNA into for this table.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Number of possible k-mers, 4^k.
+    pub fn entry_count(&self) -> usize {
+        1 << (2 * self.chunk_size)
+    }
+
+    /// Total coordinates across all k-mers.
+    pub fn coord_count(&self) -> usize {
+        self.count
     }
 
     // ── Accession resolution ─────────────────────────────────────────
 
     /// Resolve a chromosome accession string to its chrom_idx.
-    /// Linear search over at most 25 entries — effectively O(1).
-    pub fn chrom_idx_for(&self, accession: &str) -> Option<u8> {
+    /// Linear search, over at most 25 entries for the embedded tables.
+    pub fn chrom_idx_for(&self, accession: &str) -> Option<u16> {
         self.accessions
             .iter()
             .position(|a| a == accession)
-            .map(|i| i as u8)
+            .map(|i| i as u16)
     }
 
     /// Get the RefSeq accession string for a chrom_idx.
-    pub fn accession_for(&self, chrom_idx: u8) -> Option<&str> {
+    pub fn accession_for(&self, chrom_idx: u16) -> Option<&str> {
         self.accessions.get(chrom_idx as usize).map(|s| s.as_str())
     }
+
+    /// End of the furthest k-mer on each chromosome, in chrom_idx order.
+    /// Tables record no chromosome lengths; this is the nearest they know.
+    pub fn chrom_extents(&self) -> Vec<u64> {
+        let mut extents = vec![0u64; self.accessions.len()];
+        for coord in self.entries().flat_map(|e| e.iter()) {
+            if let Some(end) = extents.get_mut(coord.chrom_idx as usize) {
+                *end = (*end).max(coord.start.saturating_add(self.chunk_size as u64));
+            }
+        }
+        extents
+    }
+}
+
+/// Decompress a zstd-compressed table; plain tables pass through.
+///
+/// The first four bytes decide: zstd's frame magic, or anything else
+/// (normally "DRFT"), which from_bytes then checks.
+pub fn unpack(bytes: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if !bytes.starts_with(&ZSTD_MAGIC) {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    zstd::stream::decode_all(bytes)
+        .map(Cow::Owned)
+        .map_err(|e| invalid(format!("cannot decompress zstd table: {}", e)))
+}
+
+/// Read a table file, decompressing it if it is zstd-compressed.
+pub fn read_table_file(path: &Path) -> Result<Vec<u8>> {
+    let bytes = std::fs::read(path).map_err(DendecError::Io)?;
+    Ok(match unpack(&bytes)? {
+        Cow::Borrowed(_) => bytes,
+        Cow::Owned(plain) => plain,
+    })
+}
+
+/// Check magic, version and — from version 2 — the SHA-256 trailer.
+///
+/// Returns the version and the bytes the trailer covers (everything for
+/// version 1, which has none).
+pub fn check_header(bytes: &[u8]) -> Result<(u8, &[u8])> {
+    match table_version(bytes)? {
+        1 => Ok((1, bytes)),
+        v => Ok((v, verify_checksum(bytes)?)),
+    }
+}
+
+/// Check the magic bytes and return the format version.
+pub fn table_version(bytes: &[u8]) -> Result<u8> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not a dendec refer table (bad magic bytes)".to_string()));
+    }
+    match bytes.get(4) {
+        None => Err(invalid("truncated before the version byte".to_string())),
+        Some(&v) if (1..=VERSION).contains(&v) => Ok(v),
+        Some(v) => Err(invalid(format!(
+            "unsupported table version {} — this dendec reads versions 1 to {}",
+            v, VERSION
+        ))),
+    }
+}
+
+/// Check a version 2+ table's SHA-256 trailer and return the bytes it covers.
+pub fn verify_checksum(bytes: &[u8]) -> Result<&[u8]> {
+    if bytes.len() < 5 + CHECKSUM_LEN {
+        return Err(invalid("truncated before the checksum".to_string()));
+    }
+    let (body, stored) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if Sha256::digest(body).as_slice() != stored {
+        return Err(invalid(
+            "checksum mismatch — the table is damaged; rebuild or re-download it".to_string(),
+        ));
+    }
+    Ok(body)
+}
+
+/// Serialise accessions and per-k-mer coordinates as a version 5 table,
+/// checksum included. `entries` holds one list per k-mer, 4^chunk_size
+/// in all. Returns the byte count.
+pub fn write_table(
+    w: &mut impl Write,
+    chunk_size: usize,
+    accessions: &[String],
+    entries: &[impl AsRef<[Coord]>],
+) -> Result<u64> {
+    let mut size = 0u64;
+    let mut hasher = Sha256::new();
+    let mut put = |bytes: &[u8]| -> Result<()> {
+        size += bytes.len() as u64;
+        hasher.update(bytes);
+        w.write_all(bytes).map_err(DendecError::Io)
+    };
+
+    put(&MAGIC)?;
+    put(&[VERSION])?;
+    put(&[chunk_size as u8])?;
+    put(&(accessions.len() as u16).to_le_bytes())?;
+    for accession in accessions {
+        put(&[accession.len() as u8])?;
+        put(accession.as_bytes())?;
+    }
+
+    let count: usize = entries.iter().map(|e| e.as_ref().len()).sum();
+    put(&(count as u32).to_le_bytes())?;
+    let mut offset = 0u32;
+    put(&offset.to_le_bytes())?;
+    for entry in entries {
+        offset += entry.as_ref().len() as u32;
+        put(&offset.to_le_bytes())?;
+    }
+
+    for entry in entries {
+        for coord in entry.as_ref() {
+            put(&coord.chrom_idx.to_le_bytes())?;
+            put(&coord.start.to_le_bytes())?;
+            put(&[coord.strand])?;
+        }
+    }
+
+    w.write_all(&hasher.finalize()).map_err(DendecError::Io)?;
+    Ok(size + CHECKSUM_LEN as u64)
+}
+
+/// Read the chromosome count and accessions. Records of
+/// `record_len` NARROW_RECORD_LEN index chromosomes with one byte.
+fn read_accessions(r: &mut ByteReader, record_len: usize) -> Result<Vec<String>> {
+    let chrom_count = u16::from_le_bytes([r.u8("chromosome count")?, r.u8("chromosome count")?]) as usize;
+    if record_len == NARROW_RECORD_LEN && chrom_count > u8::MAX as usize + 1 {
+        return Err(invalid(format!(
+            "{} chromosomes declared, but chrom_idx is one byte (at most 256)",
+            chrom_count
+        )));
+    }
+
+    let mut accessions = Vec::with_capacity(chrom_count);
+    for i in 0..chrom_count {
+        let what = || format!("accession {}", i);
+        let len = r.u8(&what())? as usize;
+        if len == 0 {
+            return Err(invalid(format!("accession {} is empty", i)));
+        }
+        let s = std::str::from_utf8(r.take(len, &what())?)
+            .map_err(|_| invalid(format!("accession {} is not valid UTF-8", i)))?;
+        accessions.push(s.to_string());
+    }
+    Ok(accessions)
+}
+
+/// Read the 65,536 count-prefixed entries of a version 1 or 2 table.
+fn read_legacy_entries(r: &mut ByteReader, chrom_count: usize) -> Result<Vec<Vec<Coord>>> {
+    // CRITICAL: the loop index must be usize, not u16.
+    // 4^8 is 65,536 — exactly u16::MAX + 1. Casting it to u16 silently
+    // overflows to 0, producing an empty 0..0 range and no entries at all.
+    let table_size = 1usize << (2 * DEFAULT_CHUNK_SIZE);
+    let mut entries: Vec<Vec<Coord>> = Vec::with_capacity(table_size);
+    for idx in 0..table_size {
+        let what = || format!("8-mer entry {} ({})", idx, kmer_name(idx, DEFAULT_CHUNK_SIZE));
+        let count = r.u8(&what())? as usize;
+
+        let mut coords = Vec::with_capacity(count);
+        for _ in 0..count {
+            let coord = Coord::from_record(r.take(NARROW_RECORD_LEN, &what())?);
+            if coord.chrom_idx as usize >= chrom_count {
+                return Err(invalid(format!(
+                    "{} refers to chromosome {}, but only {} are listed",
+                    what(),
+                    coord.chrom_idx,
+                    chrom_count
+                )));
+            }
+            if coord.strand > 1 {
+                return Err(invalid(format!(
+                    "{} has strand byte {} (expected 0 or 1)",
+                    what(),
+                    coord.strand
+                )));
+            }
+            coords.push(coord);
+        }
+        entries.push(coords);
+    }
+
+    if r.pos != r.bytes.len() {
+        return Err(invalid(format!(
+            "{} unexpected bytes after the last 8-mer entry",
+            r.bytes.len() - r.pos
+        )));
+    }
+    Ok(entries)
+}
+
+fn invalid(reason: String) -> DendecError {
+    DendecError::ReferTableInvalid(reason)
+}
+
+/// Name a k-mer index for error messages: `12345`, 8 → `ATGCATGC`.
+pub fn kmer_name(idx: usize, k: usize) -> String {
+    String::from_utf8_lossy(&ReferTable::index_to_kmer(idx, k)).into_owned()
+}
+
+/// Bounds-checked cursor over table bytes.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize, what: &str) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            return Err(invalid(format!("truncated in {} at byte {}", what, self.pos)));
+        }
+        let out = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn u8(&mut self, what: &str) -> Result<u8> {
+        Ok(self.take(1, what)?[0])
+    }
 }
 
 #[cfg(test)]
@@ -276,57 +737,192 @@ mod tests {
     #[test]
     fn test_kmer_to_index_atgcatgc() {
         let idx = ReferTable::kmer_to_index(b"ATGCATGC").unwrap();
-        assert!(idx < TABLE_SIZE);
+        assert!(idx < 65_536);
     }
 
     #[test]
     fn test_index_to_kmer_roundtrip() {
-        for idx in [0u16, 1, 100, 255, 1000, 32768, 65535] {
-            let kmer = ReferTable::index_to_kmer(idx);
+        for idx in [0, 1, 100, 255, 1000, 32768, 65535] {
+            let kmer = ReferTable::index_to_kmer(idx, 8);
             let back = ReferTable::kmer_to_index(&kmer).unwrap();
-            assert_eq!(back, idx as usize, "roundtrip failed for idx {}", idx);
+            assert_eq!(back, idx, "roundtrip failed for idx {}", idx);
         }
+        let kmer = ReferTable::index_to_kmer(1 << 23, 12);
+        assert_eq!(&kmer[..], b"GAAAAAAAAAAA");
+        assert_eq!(ReferTable::kmer_to_index(&kmer), Some(1 << 23));
     }
 
     #[test]
     fn test_table_loads_without_panic() {
-        let table = ReferTable::load().expect("table.bin failed to load");
+        let table = ReferTable::load(Assembly::Hg38).expect("table.bin failed to load");
         assert!(!table.accessions.is_empty());
-        assert_eq!(table.forward.len(), TABLE_SIZE);
+        assert_eq!(table.chunk_size(), DEFAULT_CHUNK_SIZE);
+        assert_eq!(table.entries().count(), 65_536);
+    }
+
+    #[test]
+    fn test_missing_assembly_reported() {
+        for assembly in Assembly::ALL {
+            if assembly.compressed_table().is_none() {
+                assert!(matches!(
+                    ReferTable::load(assembly),
+                    Err(DendecError::ReferAssemblyUnavailable(_))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_compressed_and_plain_tables_load() {
+        let plain = Assembly::Hg38.table_bytes().unwrap();
+        assert!(plain.starts_with(&MAGIC));
+        assert!(matches!(unpack(plain).unwrap(), Cow::Borrowed(_)));
+
+        let packed = zstd::bulk::compress(plain, 3).unwrap();
+        assert_eq!(&*unpack(&packed).unwrap(), plain);
+        assert!(unpack(&packed[..packed.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_version_1_table_upgrades() {
+        // chr1 with TTTTTTTT at 7 (+) and 3 (-); every other 8-mer empty
+        let mut v1 = MAGIC.to_vec();
+        v1.extend_from_slice(&[1, 1, 0, 4]);
+        v1.extend_from_slice(b"chr1");
+        v1.extend_from_slice(&[0, 2, 0, 7, 0, 0, 0, 0, 0, 3, 0, 0, 0, 1]);
+        v1.resize(v1.len() + 65_536 - 2, 0);
+
+        let table = ReferTable::from_bytes(&v1).unwrap();
+        table.validate().unwrap();
+        assert_eq!(table.coord_count(), 2);
+        assert!(matches!(table.data, Cow::Owned(_)));
+        let entry = table.entry(1);
+        assert_eq!((entry.get(0).start, entry.get(1).strand), (7, 1));
+        let key = CoordKey { chrom_idx: 0, start: 3, strand: 1 };
+        assert_eq!(&table.reverse_lookup(&key).unwrap()[..], b"AAAAAAAT");
+
+        v1.pop();
+        let err = ReferTable::from_bytes(&v1).err().unwrap().to_string();
+        assert!(err.contains("truncated in 8-mer entry 65535 (CCCCCCCC)"), "{err}");
+    }
+
+    #[test]
+    fn test_wide_records_roundtrip() {
+        // Past the reach of version 4: chromosome 300 and a start past 4 Gb
+        let accessions: Vec<String> = (0..301).map(|i| format!("scaffold_{i}")).collect();
+        let mut entries = vec![Vec::new(); 65_536];
+        let start = 5_000_000_000;
+        entries[7].push(Coord { chrom_idx: 300, start, strand: 1 });
+        let mut bytes = Vec::new();
+        write_table(&mut bytes, 8, &accessions, &entries).unwrap();
+        assert_eq!(table_version(&bytes).unwrap(), VERSION);
+
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+        table.validate().unwrap();
+        let coord = table.entry(7).get(0);
+        assert_eq!((coord.chrom_idx, coord.start, coord.strand), (300, start, 1));
+        assert_eq!(table.accession_for(300), Some("scaffold_300"));
+        assert_eq!(table.chrom_extents()[300], start + 8);
+        let key = CoordKey { chrom_idx: 300, start, strand: 1 };
+        assert_eq!(table.reverse_lookup(&key), Some(ReferTable::index_to_kmer(7, 8)));
+
+        // The embedded version 3 table is still read in place
+        let embedded = Assembly::Hg38.table_bytes().unwrap();
+        assert_eq!(table_version(embedded).unwrap(), 3);
+        let table = ReferTable::from_bytes(embedded).unwrap();
+        assert!(matches!(table.data, Cow::Borrowed(_)));
+        assert_eq!(table.record_len, NARROW_RECORD_LEN);
     }
 
     #[test]
     fn test_full_coverage() {
-        let table = ReferTable::load().unwrap();
-        let missing = table.forward.iter().filter(|e| e.is_empty()).count();
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
+        let missing = table.entries().filter(|e| e.is_empty()).count();
         assert_eq!(missing, 0, "{} 8-mers have no coverage", missing);
     }
 
     #[test]
     fn test_lookup_returns_coord() {
-        let table = ReferTable::load().unwrap();
-        let coord = table.lookup(b"ATGCGATC");
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
+        let coord = table.lookup(b"ATGCGATC", &mut rand::thread_rng());
         assert!(coord.is_some(), "lookup returned None for valid 8-mer");
     }
 
+    #[test]
+    fn test_seeded_lookup_is_reproducible() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
+        let picks = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..64)
+                .map(|_| table.lookup(b"ATGCGATC", &mut rng).unwrap().start)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(picks(1), picks(1));
+        assert_ne!(picks(1), picks(2));
+    }
+
     #[test]
     fn test_forward_reverse_roundtrip() {
-        let table = ReferTable::load().unwrap();
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
         let kmer = b"ATGCGATC";
-        let coord = table.lookup(kmer).expect("lookup failed");
+        let coord = table.lookup(kmer, &mut rand::thread_rng()).expect("lookup failed");
         let key = CoordKey::from(&coord);
         let recovered = table.reverse_lookup(&key).expect("reverse lookup failed");
-        assert_eq!(&recovered, kmer, "roundtrip did not recover original 8-mer");
+        assert_eq!(&recovered[..], kmer, "roundtrip did not recover original 8-mer");
+    }
+
+    #[test]
+    fn test_either_strand_lookup() {
+        use rand::{rngs::StdRng, SeedableRng};
+        // hg38 lists both strands alike, so there is nothing to add
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
+        let own: Vec<CoordKey> = table.entry(ReferTable::kmer_to_index(b"ATGCGATC").unwrap()).iter().map(|c| CoordKey::from(&c)).collect();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..64 {
+            let coord = table.lookup_either_strand(b"ATGCGATC", &mut rng).unwrap();
+            assert!(own.contains(&CoordKey::from(&coord)));
+        }
+        assert!(table.lookup_either_strand(b"ATGCGATCGG", &mut rng).is_none());
+
+        // A one-strand table: TTTTTTTA at 0 (+) and its complement TAAAAAAA at 5 (+)
+        let mut entries = vec![Vec::new(); 65_536];
+        let ttttttta = ReferTable::kmer_to_index(b"TTTTTTTA").unwrap();
+        let taaaaaaa = ReferTable::kmer_to_index(b"TAAAAAAA").unwrap();
+        entries[ttttttta].push(Coord { chrom_idx: 0, start: 0, strand: 0 });
+        entries[taaaaaaa].push(Coord { chrom_idx: 0, start: 5, strand: 0 });
+        let mut bytes = Vec::new();
+        write_table(&mut bytes, 8, &["chr1".to_string()], &entries).unwrap();
+        let table = ReferTable::from_bytes(&bytes).unwrap();
+
+        let mut starts = [false; 2];
+        for _ in 0..64 {
+            let coord = table.lookup_either_strand(b"TTTTTTTA", &mut rng).unwrap();
+            assert_eq!(coord.strand, u8::from(coord.start == 5));
+            starts[usize::from(coord.start == 5)] = true;
+            assert_eq!(&table.resolve(&CoordKey::from(&coord)).unwrap()[..], b"TTTTTTTA");
+        }
+        assert_eq!(starts, [true, true]);
+        assert_eq!(table.resolve(&CoordKey { chrom_idx: 0, start: 1, strand: 1 }), None);
+    }
+
+    #[test]
+    fn test_reverse_lookup_misses() {
+        // hg38 coordinates begin after chr1's leading run of N
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
+        assert_eq!(table.reverse_lookup(&CoordKey { chrom_idx: 0, start: 0, strand: 0 }), None);
+        assert_eq!(table.reverse_lookup(&CoordKey { chrom_idx: 255, start: 0, strand: 0 }), None);
+        assert_eq!(table.reverse_lookup(&CoordKey { chrom_idx: 0, start: u64::MAX, strand: 1 }), None);
     }
 
     #[test]
     fn test_all_kmers_roundtrip() {
-        let table = ReferTable::load().unwrap();
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
         // Spot-check 256 evenly spaced indices across the full range
-        for i in (0u16..=65535).step_by(256) {
-            let kmer = ReferTable::index_to_kmer(i);
+        for i in (0..65_536).step_by(256) {
+            let kmer = ReferTable::index_to_kmer(i, 8);
             let coord = table
-                .lookup(&kmer)
+                .lookup(&kmer, &mut rand::thread_rng())
                 .unwrap_or_else(|| panic!("no coord for idx {}", i));
             let key = CoordKey::from(&coord);
             let recovered = table
@@ -0,0 +1,277 @@
+/// refer/tells.rs — dendec refer --audit
+///
+/// Reads back the file refer just wrote and looks for the statistics that
+/// set it apart from real annotation, one line per tell:
+///
+///   lengths      every record spans the same chunk size
+///   duplicates   the same coordinate used more than once
+///   names        chunk_NNNNNNNN names that count the chunks off in order
+///   order        records not sorted by position, as most tools write them
+///   chromosomes  records spread unlike the chromosome lengths
+///
+/// Each tell is rated low, medium or high, with the refer option that
+/// lowers it where there is one. The report is advice: it never fails
+/// the command.
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use crate::crypto::derive_refer_key;
+use crate::error::Result;
+use super::assembly::Assembly;
+use super::chrom;
+use super::coordinate::BedRecord;
+use super::format::{self, read_records, Format};
+use super::table::ReferTable;
+
+/// Fewest records the chromosome spread is judged on; fewer scatter too
+/// much to mean anything.
+const SPREAD_MIN_RECORDS: usize = 100;
+
+/// How much one tell gives the file away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Risk {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // pad, not write_str, so the report's column widths apply
+        f.pad(match self {
+            Risk::Low => "low",
+            Risk::Medium => "medium",
+            Risk::High => "HIGH",
+        })
+    }
+}
+
+/// One line of the risk report.
+pub struct Tell {
+    pub risk: Risk,
+    pub name: &'static str,
+    pub detail: String,
+    /// Refer option that lowers the risk, if any does.
+    pub suggestion: Option<&'static str>,
+}
+
+/// Analyse the refer file at `path` and print the risk report to
+/// stdout. `password` unlocks a bedGraph's records.
+pub fn run_tells(path: &Path, password: Option<&str>) -> Result<()> {
+    let format = format::detect(path)?;
+    let key = match (format, password) {
+        (Format::BedGraph, Some(password)) => Some(derive_refer_key(password)?),
+        _ => None,
+    };
+    let (header, records) = read_records(path, key.as_ref())?;
+    let assembly = header.assembly.unwrap_or_default();
+    let table = ReferTable::load(assembly)?;
+
+    println!();
+    println!("Auditing {} for statistical tells", path.display());
+    println!();
+    let tells = tells(format, &records, &table, assembly);
+    for tell in &tells {
+        println!("  {:<6} {:<12} {}", tell.risk, tell.name, tell.detail);
+        if let Some(suggestion) = tell.suggestion {
+            println!("  {:<6} {:<12} → {}", "", "", suggestion);
+        }
+    }
+
+    let count = |risk| tells.iter().filter(|t| t.risk == risk).count();
+    println!();
+    println!("  {} high and {} medium risk tell(s).", count(Risk::High), count(Risk::Medium));
+    Ok(())
+}
+
+/// Rate every tell in `records`, as read back from a `format` file made
+/// with `table`, the one for `assembly`.
+///
+/// Readers return named records in chunk order, which is the order refer
+/// writes them in, and unnamed records in file order; so `records` are in
+/// file order except in a bedGraph, which is written sorted.
+pub fn tells(format: Format, records: &[BedRecord], table: &ReferTable, assembly: Assembly) -> Vec<Tell> {
+    let mut tells = Vec::new();
+    let mut push = |risk, name, detail: String, suggestion| tells.push(Tell { risk, name, detail, suggestion });
+    let k = table.chunk_size();
+
+    // ── Lengths ───────────────────────────────────────────────────────
+    match format {
+        Format::Sam => push(
+            Risk::Low,
+            "lengths",
+            format!("every read is {k} bases, as in any single-run SAM"),
+            None,
+        ),
+        Format::Fasta => push(
+            Risk::Medium,
+            "lengths",
+            format!("regions share one length, a {k}-base core and equal flanks"),
+            Some("--format sam, where reads of one length are the norm"),
+        ),
+        _ => push(
+            Risk::High,
+            "lengths",
+            format!("every record spans exactly {k} bases; real features vary in length"),
+            Some("--format sam, where reads of one length are the norm"),
+        ),
+    }
+
+    // ── Duplicates ────────────────────────────────────────────────────
+    let mut seen = HashSet::new();
+    let repeats = records.iter().filter(|r| !seen.insert((&r.accession, r.start, r.strand))).count();
+    let share = repeats as f64 / records.len().max(1) as f64;
+    push(
+        match repeats {
+            0 => Risk::Low,
+            _ if share <= 0.01 => Risk::Medium,
+            _ => Risk::High,
+        },
+        "duplicates",
+        match repeats {
+            0 => "no coordinate is used twice".to_string(),
+            n => format!("{} record(s) repeat an earlier coordinate ({:.1}%)", n, 100.0 * share),
+        },
+        (repeats > 0).then_some("--both-strands, or a table built with a higher --max-coords"),
+    );
+
+    // ── Names ─────────────────────────────────────────────────────────
+    let named = records.first().is_some_and(|r| r.chunk_idx.is_some());
+    let keyed = records.first().is_some_and(|r| r.keyed_name.is_some());
+    match (format, named, keyed) {
+        (Format::BedGraph, _, _) => push(Risk::Low, "names", "bedGraph records carry no name".to_string(), None),
+        (_, _, true) => push(Risk::Low, "names", "--name-style names, which look like real identifiers".to_string(), None),
+        (_, true, _) => push(
+            Risk::High,
+            "names",
+            "chunk_NNNNNNNN names count the chunks off in order".to_string(),
+            Some("--name-style refseq or probe, or --shuffle"),
+        ),
+        _ => push(Risk::Low, "names", "records are unnamed".to_string(), None),
+    }
+
+    // ── Order ─────────────────────────────────────────────────────────
+    let sorted = format == Format::BedGraph || is_sorted(records);
+    push(
+        if sorted { Risk::Low } else { Risk::High },
+        "order",
+        match sorted {
+            true => "records are sorted by chromosome and position".to_string(),
+            false => "records are not sorted by position, as most tools write them".to_string(),
+        },
+        (!sorted).then_some("--format bedgraph, which is written in position order"),
+    );
+
+    // ── Chromosome spread ─────────────────────────────────────────────
+    let distance = spread_distance(records, table, assembly);
+    push(
+        match distance {
+            None => Risk::Low,
+            Some(d) if d <= 0.1 => Risk::Low,
+            Some(d) if d <= 0.25 => Risk::Medium,
+            Some(_) => Risk::High,
+        },
+        "chromosomes",
+        match distance {
+            None => format!("too few records to judge (fewer than {SPREAD_MIN_RECORDS})"),
+            Some(d) => format!("{:.0}% of records lie off a spread by chromosome length", 100.0 * d),
+        },
+        None,
+    );
+
+    tells
+}
+
+/// Whether each chromosome's records form one run, in ascending
+/// position.
+fn is_sorted(records: &[BedRecord]) -> bool {
+    let mut done = HashSet::new();
+    records.windows(2).all(|pair| match pair[0].accession == pair[1].accession {
+        true => pair[0].start <= pair[1].start,
+        false => done.insert(&pair[0].accession) && !done.contains(&pair[1].accession),
+    })
+}
+
+/// Total variation distance between the records' share of each
+/// chromosome and that chromosome's share of the genome: the fraction of
+/// records that would have to move for the two to match. None below
+/// SPREAD_MIN_RECORDS.
+fn spread_distance(records: &[BedRecord], table: &ReferTable, assembly: Assembly) -> Option<f64> {
+    if records.len() < SPREAD_MIN_RECORDS {
+        return None;
+    }
+    let lengths = table.chrom_extents();
+    let genome: u64 = lengths.iter().sum();
+    let mut counts = vec![0usize; lengths.len()];
+    for record in records {
+        // --chrom-style ucsc names map back to the table's
+        let idx = table
+            .chrom_idx_for(&record.accession)
+            .or_else(|| table.chrom_idx_for(chrom::counterpart(assembly, &record.accession)?))?;
+        counts[idx as usize] += 1;
+    }
+    let distance = counts
+        .iter()
+        .zip(&lengths)
+        .map(|(&n, &len)| (n as f64 / records.len() as f64 - len as f64 / genome.max(1) as f64).abs())
+        .sum::<f64>();
+    Some(distance / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OverwritePolicy;
+    use crate::refer::{refer_encode, ReferOptions};
+    use tempfile::tempdir;
+
+    fn risks(tells: &[Tell]) -> Vec<(&'static str, Risk)> {
+        tells.iter().map(|t| (t.name, t.risk)).collect()
+    }
+
+    #[test]
+    fn test_default_bed_tells() {
+        let dir = tempdir().unwrap();
+        let dna = dir.path().join("in.dna");
+        let bases: String = (0..1200).map(|i| ["ATGC", "GGAT", "CTTA"][i % 3]).collect();
+        std::fs::write(&dna, &bases).unwrap();
+        let bed = dir.path().join("out.bed");
+        refer_encode(dna, bed.clone(), None, &ReferOptions::default(), OverwritePolicy::Refuse).unwrap();
+
+        let (_, mut records) = read_records(&bed, None).unwrap();
+        let table = ReferTable::load(Assembly::Hg38).unwrap();
+        let tells = tells(Format::Bed, &records, &table, Assembly::Hg38);
+        let found = risks(&tells);
+        for name in ["lengths", "names", "order"] {
+            assert!(found.contains(&(name, Risk::High)), "{name}: {found:?}");
+        }
+        assert!(tells.iter().filter(|t| t.risk == Risk::High).all(|t| t.suggestion.is_some()));
+        // 600 chunks from three 8-mers of 8 places each must repeat some
+        assert!(found.contains(&("duplicates", Risk::High)));
+        assert!(tells.iter().any(|t| t.name == "chromosomes" && t.detail.contains('%')));
+
+        // Sorted, unnamed SAM reads lose those three tells
+        let table_idx = |r: &BedRecord| table.chrom_idx_for(&r.accession);
+        records.sort_by_key(|r| (table_idx(r), r.start));
+        records.iter_mut().for_each(|r| r.chunk_idx = None);
+        let found = risks(&super::tells(Format::Sam, &records, &table, Assembly::Hg38));
+        for name in ["lengths", "names", "order"] {
+            assert!(found.contains(&(name, Risk::Low)), "{name}: {found:?}");
+        }
+    }
+
+    #[test]
+    fn test_is_sorted() {
+        let record = |accession: &str, start| BedRecord {
+            accession: accession.to_string(),
+            start,
+            strand: 0,
+            chunk_idx: None,
+            keyed_name: None,
+        };
+        assert!(is_sorted(&[record("chr2", 5), record("chr2", 9), record("chr1", 1)]));
+        assert!(!is_sorted(&[record("chr2", 9), record("chr2", 5)]));
+        assert!(!is_sorted(&[record("chr1", 1), record("chr2", 5), record("chr1", 7)]));
+    }
+}
@@ -0,0 +1,294 @@
+/// refer/twobit.rs — UCSC .2bit genome input
+///
+/// `table build` and `refer --reference` read genomes as FASTA text.
+/// TwoBitFasta presents a .2bit file as that text, one record after
+/// another, so either takes a .2bit without a conversion step. Records
+/// are read in index order and a line at a time, never whole.
+///
+/// LAYOUT (integers are u32 in the byte order the signature is written
+/// in; version 1 widens record offsets to u64)
+///
+///   header  signature 0x1A412743, version, sequence count, reserved
+///   index   [name length: u8][name][record offset] × sequence count
+///   record  base count, N block count, N block starts, N block sizes,
+///           mask block count, mask block starts, mask block sizes,
+///           reserved, then the bases four to a byte, first base in the
+///           high bits: T=0, C=1, A=2, G=3
+///
+/// Bases inside an N block come out as N, and bases inside a mask block
+/// in lowercase, as in the soft-masked FASTA the file was made from.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// First four bytes of a .2bit file written little-endian.
+pub const TWOBIT_SIGNATURE: [u8; 4] = [0x43, 0x27, 0x41, 0x1a];
+/// The same written big-endian.
+const TWOBIT_SIGNATURE_BE: [u8; 4] = [0x1a, 0x41, 0x27, 0x43];
+
+/// Bases per FASTA line; a multiple of 4, so lines start on a byte.
+const LINE_WIDTH: usize = 60;
+
+/// Base of each 2-bit code.
+const BASES: [u8; 4] = [b'T', b'C', b'A', b'G'];
+
+/// Whether `sample`, the start of a file, is a .2bit signature.
+pub fn is_twobit(sample: &[u8]) -> bool {
+    sample.starts_with(&TWOBIT_SIGNATURE) || sample.starts_with(&TWOBIT_SIGNATURE_BE)
+}
+
+/// A .2bit file read as FASTA text.
+pub struct TwoBitFasta {
+    file: BufReader<File>,
+    big_endian: bool,
+    /// Name and offset of every record, in index order.
+    index: Vec<(String, u64)>,
+    /// Next record of `index` to open.
+    next: usize,
+    record: Option<RecordState>,
+    /// Text produced but not yet read, from `out_pos` on.
+    out: Vec<u8>,
+    out_pos: usize,
+}
+
+/// The record being written out.
+struct RecordState {
+    size: u64,
+    /// Bases written so far.
+    pos: u64,
+    n_blocks: Vec<(u64, u64)>,
+    mask_blocks: Vec<(u64, u64)>,
+}
+
+impl TwoBitFasta {
+    /// Open `path` and read its header and index.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut signature = [0u8; 4];
+        file.read_exact(&mut signature)?;
+        let big_endian = match signature {
+            TWOBIT_SIGNATURE => false,
+            TWOBIT_SIGNATURE_BE => true,
+            _ => return Err(invalid("not a .2bit file".to_string())),
+        };
+        let mut reader = TwoBitFasta {
+            file,
+            big_endian,
+            index: Vec::new(),
+            next: 0,
+            record: None,
+            out: Vec::new(),
+            out_pos: 0,
+        };
+
+        let version = reader.u32()?;
+        if version > 1 {
+            return Err(invalid(format!("unsupported .2bit version {version}")));
+        }
+        let count = reader.u32()?;
+        reader.u32()?;
+        for _ in 0..count {
+            let mut len = [0u8; 1];
+            reader.file.read_exact(&mut len)?;
+            let mut name = vec![0u8; len[0] as usize];
+            reader.file.read_exact(&mut name)?;
+            let offset = match version {
+                0 => reader.u32()? as u64,
+                _ => reader.u32()? as u64 | (reader.u32()? as u64) << 32,
+            };
+            reader.index.push((String::from_utf8_lossy(&name).into_owned(), offset));
+        }
+        Ok(reader)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.file.read_exact(&mut bytes)?;
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// `count` blocks as starts then sizes, as (start, end).
+    fn blocks(&mut self) -> io::Result<Vec<(u64, u64)>> {
+        let count = self.u32()? as usize;
+        let starts = (0..count).map(|_| self.u32()).collect::<io::Result<Vec<_>>>()?;
+        let sizes = (0..count).map(|_| self.u32()).collect::<io::Result<Vec<_>>>()?;
+        let mut blocks: Vec<(u64, u64)> =
+            starts.iter().zip(&sizes).map(|(&s, &n)| (s as u64, s as u64 + n as u64)).collect();
+        blocks.sort_unstable();
+        Ok(blocks)
+    }
+
+    /// Produce the next piece of text: a header line, or a sequence line.
+    /// False once every record is written.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.out.clear();
+        self.out_pos = 0;
+
+        let Some(record) = self.record.as_mut() else {
+            let Some((name, offset)) = self.index.get(self.next).cloned() else {
+                return Ok(false);
+            };
+            self.next += 1;
+            self.file.seek(SeekFrom::Start(offset))?;
+            let size = self.u32()? as u64;
+            let n_blocks = self.blocks()?;
+            let mask_blocks = self.blocks()?;
+            self.u32()?;
+            self.out.extend_from_slice(format!(">{name}\n").as_bytes());
+            self.record = Some(RecordState { size, pos: 0, n_blocks, mask_blocks });
+            return Ok(true);
+        };
+
+        if record.pos >= record.size {
+            self.record = None;
+            return self.fill();
+        }
+        let len = (record.size - record.pos).min(LINE_WIDTH as u64) as usize;
+        let mut packed = [0u8; LINE_WIDTH / 4];
+        self.file.read_exact(&mut packed[..len.div_ceil(4)]).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => invalid("record truncated".to_string()),
+            _ => e,
+        })?;
+        for i in 0..len {
+            let pos = record.pos + i as u64;
+            let code = packed[i / 4] >> (6 - 2 * (i % 4)) & 3;
+            let base = match (within(&record.n_blocks, pos), within(&record.mask_blocks, pos)) {
+                (true, masked) => if masked { b'n' } else { b'N' },
+                (false, true) => BASES[code as usize].to_ascii_lowercase(),
+                (false, false) => BASES[code as usize],
+            };
+            self.out.push(base);
+        }
+        self.out.push(b'\n');
+        record.pos += len as u64;
+        Ok(true)
+    }
+}
+
+impl Read for TwoBitFasta {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for TwoBitFasta {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.out_pos >= self.out.len() && !self.fill()? {
+            return Ok(&[]);
+        }
+        Ok(&self.out[self.out_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.out_pos += amt;
+    }
+}
+
+/// Whether `pos` lies in one of `blocks`, sorted by start.
+fn within(blocks: &[(u64, u64)], pos: u64) -> bool {
+    let i = blocks.partition_point(|&(start, _)| start <= pos);
+    i > 0 && pos < blocks[i - 1].1
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!(".2bit: {reason}"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// A version 0 .2bit of `records`: name, bases (any case, N allowed).
+    pub(crate) fn twobit(records: &[(&str, &[u8])], big_endian: bool) -> Vec<u8> {
+        let u32 = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let blocks = |seq: &[u8], hit: &dyn Fn(u8) -> bool| {
+            let mut blocks: Vec<(u32, u32)> = Vec::new();
+            for (i, &b) in seq.iter().enumerate() {
+                match blocks.last_mut() {
+                    Some((start, size)) if hit(b) && (*start + *size) as usize == i => *size += 1,
+                    _ if hit(b) => blocks.push((i as u32, 1)),
+                    _ => {}
+                }
+            }
+            let mut out = u32(blocks.len() as u32).to_vec();
+            blocks.iter().for_each(|b| out.extend(u32(b.0)));
+            blocks.iter().for_each(|b| out.extend(u32(b.1)));
+            out
+        };
+
+        let mut bodies = Vec::new();
+        for (_, seq) in records {
+            let mut body = u32(seq.len() as u32).to_vec();
+            body.extend(blocks(seq, &|b| b.eq_ignore_ascii_case(&b'N')));
+            body.extend(blocks(seq, &|b| b.is_ascii_lowercase()));
+            body.extend(u32(0));
+            for four in seq.chunks(4) {
+                let mut byte = 0u8;
+                for (i, b) in four.iter().enumerate() {
+                    let code = BASES.iter().position(|x| x.eq_ignore_ascii_case(b)).unwrap_or(0) as u8;
+                    byte |= code << (6 - 2 * i);
+                }
+                body.push(byte);
+            }
+            bodies.push(body);
+        }
+
+        let mut out = if big_endian { TWOBIT_SIGNATURE_BE } else { TWOBIT_SIGNATURE }.to_vec();
+        out.extend(u32(0));
+        out.extend(u32(records.len() as u32));
+        out.extend(u32(0));
+        let index_len: usize = records.iter().map(|(name, _)| 1 + name.len() + 4).sum();
+        let mut offset = out.len() + index_len;
+        for ((name, _), body) in records.iter().zip(&bodies) {
+            out.push(name.len() as u8);
+            out.extend_from_slice(name.as_bytes());
+            out.extend(u32(offset as u32));
+            offset += body.len();
+        }
+        bodies.iter().for_each(|b| out.extend(b));
+        out
+    }
+
+    #[test]
+    fn test_twobit_reads_as_fasta() {
+        let chr1: Vec<u8> = b"ACGTacgtNNNNGGCCTTAA".repeat(4).into_iter().chain(*b"GAT").collect();
+        let records: [(&str, &[u8]); 2] = [("chr1", &chr1), ("chrM", b"TTAGC")];
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("genome.2bit");
+        for big_endian in [false, true] {
+            std::fs::write(&path, twobit(&records, big_endian)).unwrap();
+            let mut text = String::new();
+            TwoBitFasta::open(&path).unwrap().read_to_string(&mut text).unwrap();
+            let (head, tail) = chr1.split_at(LINE_WIDTH);
+            let expected = format!(
+                ">chr1\n{}\n{}\n>chrM\nTTAGC\n",
+                String::from_utf8_lossy(head),
+                String::from_utf8_lossy(tail)
+            );
+            assert_eq!(text, expected);
+        }
+    }
+
+    #[test]
+    fn test_bad_twobit_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("genome.2bit");
+        let mut bytes = twobit(&[("chr1", b"ACGTACGTACGT")], false);
+        bytes.truncate(bytes.len() - 1);
+        std::fs::write(&path, &bytes).unwrap();
+        let err = TwoBitFasta::open(&path).unwrap().read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{err}");
+
+        bytes[4] = 7;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(TwoBitFasta::open(&path).is_err());
+    }
+}
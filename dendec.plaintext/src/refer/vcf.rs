@@ -0,0 +1,249 @@
+/// refer/vcf.rs — VCF read and write
+///
+/// `refer --format vcf` writes each chunk as a variant call, for labs
+/// where VCF is the everyday artifact rather than BED:
+///
+///  ##fileformat=VCFv4.2
+///  ##source=dendec-refer v0.1.0
+///  ##reference=GCF_000001405.40 hg38
+///  ##chunk_size=8
+///  ##dna_length=168432
+///  ##chunk_count=21054
+///  ##payload_sha256=9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///  ##table_sha256=a415178627e2c5200c58944182e5d2feecc55543bfc7f0cbd994385be46efc71
+///  ##contig=<ID=NC_000001.11>
+///  ##INFO=<ID=DP,Number=1,Type=Integer,Description="Total read depth">
+///  #CHROM        POS     ID              REF       ALT  QUAL  FILTER  INFO
+///  NC_000001.11  883402  chunk_00000000  ATGCGATC  A    57    PASS    DP=31
+///  NC_000007.14  553085  chunk_00000001  G         A    43    PASS    DP=18
+///
+/// Each record sits on the reference bases of its coordinate: the chunk
+/// itself on the + strand, its reverse complement on the − strand. The
+/// variant type carries the strand:
+///
+///   forward (+)  a deletion — REF is the whole chunk, ALT its first base
+///   reverse (−)  a transition SNV on the first base — REF is that base
+///
+/// POS is 1-based, so it is the table's start + 1. ID holds the chunk
+/// name, "." when shuffled. QUAL and DP are derived from the coordinate,
+/// so they vary like real calls yet a keyed refer stays reproducible.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::error::{DendecError, Result};
+use super::assembly::Assembly;
+use super::coordinate::{
+    chunk_name, order_records, parse_chunk_name, BedHeader, BedRecord, REFER_VERSION,
+};
+use super::format::{Chunk, Meta, RecordWriter};
+use super::naming::{keyed_name, Naming};
+use super::reverse::reverse_complement;
+
+/// First line of every VCF; unrefer recognises the format by it.
+pub const VCF_SIGNATURE: &str = "##fileformat=VCF";
+
+/// Streams a dendec-refer VCF file, one record at a time.
+pub struct VcfWriter<W: Write> {
+    w: W,
+    /// Put the chunk name in ID; "." when records are shuffled.
+    naming: Naming,
+}
+
+impl<W: Write> VcfWriter<W> {
+    /// Write the meta lines and the #CHROM column header.
+    pub fn new(mut w: W, meta: &Meta) -> Result<Self> {
+        writeln!(w, "{}v4.2", VCF_SIGNATURE).map_err(DendecError::Io)?;
+        writeln!(w, "##source=dendec-refer v{}", REFER_VERSION).map_err(DendecError::Io)?;
+        writeln!(w, "##reference={}", meta.assembly.header_value()).map_err(DendecError::Io)?;
+        writeln!(w, "##chunk_size={}", meta.chunk_size).map_err(DendecError::Io)?;
+        writeln!(w, "##dna_length={}", meta.dna_length).map_err(DendecError::Io)?;
+        writeln!(w, "##chunk_count={}", meta.chunk_count).map_err(DendecError::Io)?;
+        writeln!(w, "##payload_sha256={}", meta.payload_sha256).map_err(DendecError::Io)?;
+        writeln!(w, "##table_sha256={}", meta.table_sha256).map_err(DendecError::Io)?;
+        for accession in meta.accessions {
+            writeln!(w, "##contig=<ID={}>", accession).map_err(DendecError::Io)?;
+        }
+        writeln!(w, "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total read depth\">")
+            .map_err(DendecError::Io)?;
+        writeln!(w, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO").map_err(DendecError::Io)?;
+        Ok(VcfWriter { w, naming: meta.naming.clone() })
+    }
+}
+
+impl<W: Write> RecordWriter for VcfWriter<W> {
+    fn push(&mut self, chunk: &Chunk) -> Result<()> {
+        let bases = match chunk.strand {
+            0 => chunk.kmer,
+            _ => reverse_complement(&chunk.kmer),
+        };
+        let (reference, alt) = match chunk.strand {
+            0 => (&bases[..], bases[0]),
+            _ => (&bases[..1], transition(bases[0])),
+        };
+
+        // Any fixed mix of the coordinate will do; it only has to look varied
+        let h = chunk.start.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let qual = 20 + (h >> 40) % 80;
+        let depth = 8 + (h >> 52) % 60;
+
+        writeln!(
+            self.w,
+            "{}\t{}\t{}\t{}\t{}\t{}\tPASS\tDP={}",
+            chunk.accession,
+            chunk.start + 1,
+            chunk_name(&self.naming, chunk.idx),
+            // Safe: both are slices of A/T/G/C bases
+            std::str::from_utf8(reference).unwrap_or_default(),
+            alt as char,
+            qual,
+            depth
+        )
+        .map_err(DendecError::Io)?;
+        Ok(())
+    }
+}
+
+/// The purine↔purine or pyrimidine↔pyrimidine partner of a base — the
+/// most common kind of point mutation.
+fn transition(base: u8) -> u8 {
+    match base {
+        b'A' => b'G',
+        b'G' => b'A',
+        b'C' => b'T',
+        _ => b'C',
+    }
+}
+
+/// Parse a dendec-refer VCF file from `path`.
+///
+/// Returns the header metadata and the records, sorted by chunk index
+/// unless they are shuffled.
+pub fn read_vcf(path: &Path) -> Result<(BedHeader, Vec<BedRecord>)> {
+    let reader = BufReader::new(File::open(path).map_err(DendecError::Io)?);
+    let invalid = |reason: String| DendecError::ReferInvalidBed(format!("VCF: {}", reason));
+
+    let mut header = BedHeader::default();
+    let mut records = Vec::new();
+
+    for raw in reader.lines() {
+        let line = raw.map_err(DendecError::Io)?;
+        let line = line.trim();
+
+        // ── Meta lines ────────────────────────────────────────────────
+        if let Some(meta) = line.strip_prefix("##") {
+            let (key, value) = meta.split_once('=').unwrap_or((meta, ""));
+            match key {
+                "reference" => header.assembly = Some(Assembly::from_header_value(value)?),
+                "chunk_size" => header.chunk_size = value.parse().unwrap_or(0),
+                "dna_length" => header.dna_length = value.parse().unwrap_or(0),
+                "chunk_count" => header.chunk_count = value.parse().unwrap_or(0),
+                "payload_sha256" => header.payload_sha256 = Some(value.to_string()),
+                "table_sha256" => header.table_sha256 = Some(value.to_string()),
+                _ => {}
+            }
+            continue;
+        }
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        // ── Data lines ────────────────────────────────────────────────
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 8 {
+            return Err(invalid(format!("expected 8 tab-separated columns, got {}: {:?}", cols.len(), line)));
+        }
+
+        let pos: u64 = cols[1]
+            .parse()
+            .ok()
+            .filter(|&p| p > 0)
+            .ok_or_else(|| invalid(format!("invalid POS '{}' in line: {}", cols[1], line)))?;
+
+        let reference = cols[3];
+        if !reference.bytes().all(|b| matches!(b, b'A' | b'T' | b'G' | b'C')) || cols[4].is_empty() {
+            return Err(invalid(format!("invalid REF/ALT '{}'/'{}' in line: {}", reference, cols[4], line)));
+        }
+        let strand = match reference.len() {
+            n if n == header.chunk_size() => 0,
+            1 => 1,
+            n => {
+                return Err(invalid(format!(
+                    "REF of {} bases — expected {} (deletion, + strand) or 1 (SNV, - strand)",
+                    n,
+                    header.chunk_size()
+                )))
+            }
+        };
+
+        records.push(BedRecord {
+            accession: cols[0].to_string(),
+            start: pos - 1,
+            strand,
+            chunk_idx: parse_chunk_name(cols[2])?,
+            keyed_name: keyed_name(cols[2]),
+        });
+    }
+
+    order_records(&mut records)?;
+    Ok((header, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::chunk::Kmer;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_and_read_roundtrip() {
+        let accessions = ["NC_000001.11".to_string(), "NC_000007.14".to_string()];
+        let meta = Meta {
+            assembly: Assembly::Hg38,
+            chunk_size: 8,
+            dna_length: 16,
+            payload_sha256: "",
+            table_sha256: "",
+            chunk_count: 2,
+            naming: Naming::Chunk,
+            accessions: &accessions,
+            contig_lengths: &[],
+            key: None,
+            reference: None,
+        };
+        let mut vcf = VcfWriter::new(Vec::new(), &meta).unwrap();
+        vcf.push(&Chunk { idx: 0, accession: "NC_000001.11", start: 883401, strand: 0, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+        vcf.push(&Chunk { idx: 1, accession: "NC_000007.14", start: 553084, strand: 1, kmer: Kmer::new(b"ATGCGATC") }).unwrap();
+
+        let text = String::from_utf8(vcf.w).unwrap();
+        assert!(text.starts_with("##fileformat=VCFv4.2\n"));
+        assert!(text.contains("##contig=<ID=NC_000001.11>\n##contig=<ID=NC_000007.14>\n"));
+        assert!(text.contains("\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n"));
+        assert!(text.contains("NC_000001.11\t883402\tchunk_00000000\tATGCGATC\tA\t"));
+        // Reverse complement GATCGCAT; the SNV sits on its first base
+        assert!(text.contains("NC_000007.14\t553085\tchunk_00000001\tG\tA\t"));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.vcf");
+        std::fs::write(&path, text).unwrap();
+        let (header, records) = read_vcf(&path).unwrap();
+        assert_eq!((header.assembly, header.dna_length, header.chunk_count), (Some(Assembly::Hg38), 16, 2));
+        assert_eq!((records[0].start, records[0].strand, records[0].chunk_idx), (883401, 0, Some(0)));
+        assert_eq!((records[1].start, records[1].strand, records[1].chunk_idx), (553084, 1, Some(1)));
+    }
+
+    #[test]
+    fn test_bad_records_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bad.vcf");
+        for line in [
+            "NC_000001.11\t0\t.\tATGCGATC\tA\t50\tPASS\tDP=9",
+            "NC_000001.11\t10\t.\tATGC\tA\t50\tPASS\tDP=9",
+            "NC_000001.11\t10\t.\tATGCGATN\tA\t50\tPASS\tDP=9",
+            "NC_000001.11\t10\t.\tATGCGATC\tA",
+        ] {
+            std::fs::write(&path, format!("##fileformat=VCFv4.2\n{line}\n")).unwrap();
+            assert!(read_vcf(&path).is_err(), "accepted {line:?}");
+        }
+    }
+}
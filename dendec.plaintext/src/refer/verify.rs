@@ -0,0 +1,259 @@
+/// refer/verify.rs — dendec table verify
+///
+/// Validates a lookup table end to end and reports each check on its own
+/// line, so a broken custom table says exactly what to fix:
+///
+///   header       magic bytes and a known format version
+///   checksum     stored SHA-256 matches the contents (version 2)
+///   parse        accession strings, offsets and every record in bounds
+///   accessions   no chromosome listed twice
+///   coverage     every k-mer has at least one coordinate
+///   reverse      no coordinate listed under two different k-mers
+///
+/// Header and parse failures stop the run, since later checks need a
+/// loaded table. A version 1 table has no checksum; that is a warning.
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::error::{DendecError, Result};
+use super::assembly::Assembly;
+use super::table::{
+    kmer_name, read_table_file, table_version, verify_checksum, CoordKey, ReferTable, CHECKSUM_LEN,
+};
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // pad, not write_str, so the report's column widths apply
+        f.pad(match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        })
+    }
+}
+
+/// One line of the verify report.
+pub struct Check {
+    pub status: Status,
+    pub name: &'static str,
+    pub detail: String,
+}
+
+/// Verify the table at `file`, or the embedded one for `assembly`, and
+/// print the report to stdout. Fails with TableVerifyFailed if any check
+/// failed.
+pub fn run_verify(file: Option<&Path>, assembly: Assembly) -> Result<()> {
+    let owned;
+    let (label, bytes) = match file {
+        Some(path) => {
+            owned = read_table_file(path)?;
+            (path.display().to_string(), &owned[..])
+        }
+        None => (format!("embedded {assembly} table"), assembly.table_bytes()?),
+    };
+
+    println!("Verifying {label}");
+    println!();
+    let checks = verify_table(bytes);
+    for check in &checks {
+        println!("  {:<5} {:<11} {}", check.status, check.name, check.detail);
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failed > 0 {
+        return Err(DendecError::TableVerifyFailed(failed));
+    }
+    println!();
+    println!("  Table is usable for refer.");
+    Ok(())
+}
+
+/// Run every check on a serialised table.
+pub fn verify_table(bytes: &[u8]) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut push = |status, name, detail: String| checks.push(Check { status, name, detail });
+
+    // ── Header and checksum ───────────────────────────────────────────
+    let version = match table_version(bytes) {
+        Ok(version) => version,
+        Err(e) => {
+            push(Status::Fail, "header", reason(e));
+            return checks;
+        }
+    };
+    push(Status::Ok, "header", format!("dendec refer table, version {version}"));
+    if version == 1 {
+        push(
+            Status::Warn,
+            "checksum",
+            "none stored (version 1) — rebuild with `dendec table build` to add one".to_string(),
+        );
+    } else if let Err(e) = verify_checksum(bytes) {
+        push(Status::Fail, "checksum", reason(e));
+        return checks;
+    } else {
+        let stored = &bytes[bytes.len() - CHECKSUM_LEN..];
+        let hex: String = stored[..8].iter().map(|b| format!("{b:02x}")).collect();
+        push(Status::Ok, "checksum", format!("sha256 {hex}… matches"));
+    }
+
+    // ── Full parse ────────────────────────────────────────────────────
+    let table = match ReferTable::from_bytes(bytes).and_then(|t| t.validate().map(|_| t)) {
+        Ok(t) => t,
+        Err(e) => {
+            push(Status::Fail, "parse", reason(e));
+            return checks;
+        }
+    };
+    let coords = table.coord_count();
+    let (k, entries) = (table.chunk_size(), table.entry_count());
+    push(
+        Status::Ok,
+        "parse",
+        format!(
+            "{} chromosome(s), {} {}-mer entries, {} coordinates",
+            table.accessions.len(),
+            entries,
+            k,
+            coords
+        ),
+    );
+
+    // ── Accessions ────────────────────────────────────────────────────
+    let duplicate = table
+        .accessions
+        .iter()
+        .enumerate()
+        .find(|(i, a)| table.accessions[..*i].contains(a));
+    match duplicate {
+        Some((_, a)) => push(
+            Status::Fail,
+            "accessions",
+            format!("'{a}' is listed twice — unrefer would resolve every use to the first"),
+        ),
+        None => push(Status::Ok, "accessions", format!("{} unique", table.accessions.len())),
+    }
+
+    // ── Coverage ──────────────────────────────────────────────────────
+    let mut missing = table.entries().enumerate().filter(|(_, e)| e.is_empty());
+    match missing.next() {
+        Some((first, _)) => push(
+            Status::Fail,
+            "coverage",
+            format!(
+                "{} {}-mers have no coordinates (first: {}) — refer fails on any .dna containing them; \
+                 build from more sequence",
+                missing.count() + 1,
+                k,
+                kmer_name(first, k)
+            ),
+        ),
+        None => push(Status::Ok, "coverage", format!("all {entries} {k}-mers covered")),
+    }
+
+    // ── Reverse index ─────────────────────────────────────────────────
+    let mut seen: HashMap<CoordKey, usize> = HashMap::with_capacity(coords);
+    let mut clashes = 0usize;
+    let mut first_clash = None;
+    for (idx, entry) in table.entries().enumerate() {
+        for coord in entry.iter() {
+            let key = CoordKey::from(&coord);
+            match seen.get(&key) {
+                Some(&other) if other != idx => {
+                    clashes += 1;
+                    first_clash.get_or_insert(key);
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(key, idx);
+                }
+            }
+        }
+    }
+    match first_clash {
+        Some(key) => push(
+            Status::Fail,
+            "reverse",
+            format!(
+                "{} coordinate(s) listed under two {}-mers (first: {}:{} {}) — unrefer cannot tell them apart",
+                clashes,
+                k,
+                table.accession_for(key.chrom_idx).unwrap_or("?"),
+                key.start,
+                if key.strand == 0 { '+' } else { '-' }
+            ),
+        ),
+        None => push(Status::Ok, "reverse", format!("every coordinate maps to one {k}-mer")),
+    }
+
+    checks
+}
+
+/// The reason inside a table error, without the error's own prefix.
+fn reason(e: DendecError) -> String {
+    match e {
+        DendecError::ReferTableInvalid(reason) => reason,
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refer::build::TableBuilder;
+    use sha2::Digest;
+
+    fn statuses(bytes: &[u8]) -> Vec<(&'static str, Status)> {
+        verify_table(bytes).into_iter().map(|c| (c.name, c.status)).collect()
+    }
+
+    #[test]
+    fn test_embedded_table_verifies() {
+        let bytes = Assembly::Hg38.table_bytes().unwrap();
+        assert!(verify_table(bytes).iter().all(|c| c.status == Status::Ok));
+    }
+
+    #[test]
+    fn test_damage_is_pinpointed() {
+        let mut builder = TableBuilder::new(2, 8, false, false);
+        builder.read_fasta(&b">chr1\nAAAAAAAAAC\n"[..]).unwrap();
+        let mut bytes = Vec::new();
+        builder.write(&mut bytes).unwrap();
+
+        // Sparse but intact: only coverage fails
+        let checks = statuses(&bytes);
+        assert!(checks.contains(&("coverage", Status::Fail)));
+        assert!(checks.contains(&("parse", Status::Ok)));
+
+        // One flipped byte breaks the checksum and stops the run
+        let mut damaged = bytes.clone();
+        damaged[20] ^= 0xff;
+        let checks = verify_table(&damaged);
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[1].status, Status::Fail);
+        assert!(checks[1].detail.contains("checksum mismatch"), "{}", checks[1].detail);
+
+        // A bad record under a valid checksum is caught by the parse,
+        // which names the 8-mer it belongs to. AAAAAAAA's first record
+        // opens the forward section: magic, version, chunk size,
+        // chromosome count, "chr1", coordinate count, then 65,537 offsets.
+        let mut corrupt = bytes[..bytes.len() - CHECKSUM_LEN].to_vec();
+        let strand = 4 + 1 + 1 + 2 + 5 + 4 + 4 * (65_536 + 1) + 10;
+        corrupt[strand] = 2;
+        let digest = sha2::Sha256::digest(&corrupt);
+        corrupt.extend_from_slice(&digest);
+        let checks = verify_table(&corrupt);
+        assert_eq!(checks[1].status, Status::Ok);
+        assert_eq!(checks[2].status, Status::Fail);
+        assert!(checks[2].detail.contains("8-mer entry 0 (AAAAAAAA) has strand byte 2"), "{}", checks[2].detail);
+    }
+}
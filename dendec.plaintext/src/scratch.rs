@@ -0,0 +1,231 @@
+/// scratch.rs — Temporary files that never outlive dendec
+///
+/// Some steps need scratch space: the refer readers work on files, so a
+/// refer file that arrives in memory (serve, libdendec) is written out
+/// first. Scratch files go through here rather than ad hoc temp files,
+/// so the same rules hold everywhere:
+///
+///   anonymous()    no name at all — O_TMPFILE on Linux, or a file
+///                  unlinked the moment it is created elsewhere. Nothing
+///                  is left behind however the process ends.
+///
+///   ScratchFile    a named file, for code that has to open a path.
+///                  Created owner-only (0600) in the system temp
+///                  directory, removed when dropped, including while a
+///                  panic unwinds, and by the handler `cleanup_on_signal`
+///                  installs if Ctrl-C or SIGTERM arrives first.
+///
+/// Files that replace an output are a different job; output.rs creates
+/// those next to their target so the final rename is atomic.
+///
+/// The signal handler is opt-in. The binary installs it at startup; a
+/// program linking libdendec keeps its own handlers and still gets the
+/// drop-time cleanup. Only SIGKILL (or a power cut) can leave a named
+/// scratch file behind, in the temp directory, never next to user data.
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+use tempfile::NamedTempFile;
+
+use crate::error::{DendecError, Result};
+
+/// Name prefix of named scratch files.
+const SCRATCH_PREFIX: &str = ".dendec-scratch-";
+
+/// An unnamed read-write scratch file, gone as soon as it is closed.
+pub fn anonymous() -> Result<File> {
+    tempfile::tempfile().map_err(DendecError::Io)
+}
+
+/// A named scratch file, removed when dropped.
+pub struct ScratchFile {
+    file: NamedTempFile,
+    #[cfg(unix)]
+    slot: Option<usize>,
+}
+
+impl ScratchFile {
+    /// An empty scratch file.
+    pub fn new() -> Result<Self> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(SCRATCH_PREFIX);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(std::fs::Permissions::from_mode(0o600));
+        }
+        let file = builder.tempfile().map_err(DendecError::Io)?;
+        #[cfg(unix)]
+        let slot = live::register(file.path());
+        Ok(Self {
+            file,
+            #[cfg(unix)]
+            slot,
+        })
+    }
+
+    /// A scratch file holding `bytes`, rewound to the start.
+    pub fn with_contents(bytes: &[u8]) -> Result<Self> {
+        let mut scratch = Self::new()?;
+        let file = scratch.file.as_file_mut();
+        file.write_all(bytes).map_err(DendecError::Io)?;
+        file.flush().map_err(DendecError::Io)?;
+        file.seek(SeekFrom::Start(0)).map_err(DendecError::Io)?;
+        Ok(scratch)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    pub fn as_file(&self) -> &File {
+        self.file.as_file()
+    }
+
+    pub fn as_file_mut(&mut self) -> &mut File {
+        self.file.as_file_mut()
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        // The file itself goes when `self.file` drops, right after
+        #[cfg(unix)]
+        if let Some(slot) = self.slot {
+            live::release(slot);
+        }
+    }
+}
+
+/// Remove named scratch files when SIGINT, SIGTERM or SIGHUP arrives,
+/// then let the signal end the process as it would have. Call once,
+/// early; later calls do nothing. A no-op off Unix.
+pub fn cleanup_on_signal() {
+    #[cfg(unix)]
+    live::install_handler();
+}
+
+/// The paths of live scratch files, kept where a signal handler can
+/// reach them: a fixed table of C strings swapped in and out
+/// atomically, since a handler may not lock or allocate.
+#[cfg(unix)]
+mod live {
+    use std::ffi::{c_char, c_int, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+    use std::sync::Once;
+
+    /// Scratch files the handler can see at once. A file past this many
+    /// is still removed on drop, just not on a signal.
+    const SLOTS: usize = 64;
+
+    pub(super) static LIVE: [AtomicPtr<c_char>; SLOTS] = [const { AtomicPtr::new(ptr::null_mut()) }; SLOTS];
+
+    static INSTALL: Once = Once::new();
+
+    /// Record `path`; the slot it took, if one was free.
+    pub fn register(path: &Path) -> Option<usize> {
+        let raw = CString::new(path.as_os_str().as_bytes()).ok()?.into_raw();
+        let slot = LIVE.iter().position(|s| {
+            s.compare_exchange(ptr::null_mut(), raw, Ordering::AcqRel, Ordering::Acquire).is_ok()
+        });
+        if slot.is_none() {
+            // SAFETY: `raw` came from into_raw above and was not stored
+            drop(unsafe { CString::from_raw(raw) });
+        }
+        slot
+    }
+
+    /// Forget the path in `slot`, unless a signal already took it.
+    pub fn release(slot: usize) {
+        let raw = LIVE[slot].swap(ptr::null_mut(), Ordering::AcqRel);
+        if !raw.is_null() {
+            // SAFETY: only `register` stores pointers, from into_raw
+            drop(unsafe { CString::from_raw(raw) });
+        }
+    }
+
+    /// Unlink every registered file. Async-signal-safe: atomics and
+    /// unlink only. The strings are leaked; the process is ending.
+    fn unlink_all() {
+        LIVE.iter().for_each(unlink);
+    }
+
+    pub(super) fn unlink(slot: &AtomicPtr<c_char>) {
+        let raw = slot.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !raw.is_null() {
+            // SAFETY: a NUL-terminated string from `register`
+            unsafe { libc::unlink(raw) };
+        }
+    }
+
+    extern "C" fn on_signal(sig: c_int) {
+        unlink_all();
+        // SAFETY: signal and raise are async-signal-safe; restoring the
+        // default action makes the re-raised signal end the process
+        unsafe {
+            libc::signal(sig, libc::SIG_DFL);
+            libc::raise(sig);
+        }
+    }
+
+    pub fn install_handler() {
+        INSTALL.call_once(|| {
+            for sig in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+                // SAFETY: `on_signal` only does async-signal-safe work
+                unsafe { libc::signal(sig, on_signal as extern "C" fn(c_int) as libc::sighandler_t) };
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_scratch_files_are_private_and_cleaned_up() {
+        let mut anon = anonymous().unwrap();
+        anon.write_all(b"no name").unwrap();
+        anon.seek(SeekFrom::Start(0)).unwrap();
+        let mut back = String::new();
+        anon.read_to_string(&mut back).unwrap();
+        assert_eq!(back, "no name");
+
+        let scratch = ScratchFile::with_contents(b"refer text").unwrap();
+        let path = scratch.path().to_path_buf();
+        assert_eq!(std::fs::read(&path).unwrap(), b"refer text");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        drop(scratch);
+        assert!(!path.exists());
+
+        // A panic unwinding past a scratch file still removes it
+        let path = std::panic::catch_unwind(|| {
+            let scratch = ScratchFile::new().unwrap();
+            let path = scratch.path().to_path_buf();
+            std::panic::panic_any(path);
+        })
+        .unwrap_err()
+        .downcast::<std::path::PathBuf>()
+        .unwrap();
+        assert!(!path.exists());
+
+        // What the signal handler does to each file, without the signal
+        #[cfg(unix)]
+        {
+            let scratch = ScratchFile::new().unwrap();
+            let path = scratch.path().to_path_buf();
+            live::unlink(&live::LIVE[scratch.slot.unwrap()]);
+            assert!(!path.exists());
+            drop(scratch);
+        }
+    }
+}
@@ -0,0 +1,669 @@
+/// serve.rs — dendec serve: encode, decode and refer over HTTP
+///
+/// One long-running dendec for a team, so the CLI need not be installed
+/// everywhere. Plain HTTP/1.1 on std::net, one request per connection,
+/// no dependencies:
+///
+///   POST /encode[?group=N]     plaintext          → DNA (text/plain)
+///   POST /decode               DNA or refer file  → plaintext (octet-stream)
+///   POST /refer[?assembly=A]   DNA                → BED (text/plain)
+///   GET  /health               → "dendec 0.1.0"
+///
+/// The input is the raw request body, or the one non-option part of a
+/// multipart/form-data upload (a file field or a textarea, any name).
+/// The password comes per request, in the X-Dendec-Password header or a
+/// `password` form field; a shuffled or keyed refer file also needs
+/// X-Dendec-Refer-Password or `refer_password`. Never in the query
+/// string, which proxies log.
+///
+/// HARDENING
+///
+///   - Binds 127.0.0.1 unless told otherwise; put TLS in front of it.
+///   - --token-file: every request but /health needs
+///     `Authorization: Bearer <token>`, compared in constant time.
+///   - Bodies over --max-body are refused unread (413), chunked bodies
+///     are refused (411), and slow clients time out.
+///   - At most --jobs requests run at once, each holding an Argon2id
+///     derivation's memory; the rest get 503 at once rather than queue.
+///   - --hardened: every failed decode, refer uploads included, is the
+///     same 422 after the same Argon2id run, so responses do not say
+///     where a forgery failed.
+///   - Passwords and payloads are never logged: one line per request,
+///     with the peer, route, status and sizes.
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use sha2::Sha256;
+
+use crate::crypto::MasterKeyCache;
+use crate::encoding::{decode_raw_cached, encode_raw, harden};
+use crate::error::{DendecError, Result};
+use crate::refer::assembly::Assembly;
+use crate::refer::format::is_refer_bed;
+use crate::refer::{refer_bytes, unrefer};
+use crate::scratch::ScratchFile;
+
+/// Longest request line plus headers accepted.
+const MAX_HEAD: usize = 16 * 1024;
+
+/// How long a client may stall reading or writing.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a refused client may stall reading its 503.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Key for comparing bearer tokens through their HMACs.
+const TOKEN_KEY: &[u8] = b"dendec-serve-token";
+
+/// Form fields that are options rather than the input.
+const OPTION_FIELDS: [&str; 4] = ["password", "refer_password", "group", "assembly"];
+
+/// Settings for dendec serve.
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    /// Address to listen on, e.g. 127.0.0.1:8480.
+    pub listen: String,
+    /// Largest request body accepted, in bytes.
+    pub max_body: u64,
+    /// Requests handled at once.
+    pub jobs: usize,
+    /// File holding the bearer token every request must carry.
+    pub token_file: Option<PathBuf>,
+    /// Answer every failed decode alike; see `encoding::harden`.
+    pub hardened: bool,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        Self { listen: "127.0.0.1:8480".to_string(), max_body: 64 << 20, jobs: 2, token_file: None, hardened: false }
+    }
+}
+
+/// What the handlers share.
+struct Server {
+    max_body: u64,
+    jobs: usize,
+    /// HMAC of the bearer token, if one is required.
+    token: Option<Vec<u8>>,
+    hardened: bool,
+    active: AtomicUsize,
+}
+
+/// Run the server on `opts.listen` until the process is stopped.
+pub fn serve(opts: &ServeOptions) -> Result<()> {
+    let listener = TcpListener::bind(&opts.listen).map_err(DendecError::Io)?;
+    info!("dendec serve listening on http://{}", listener.local_addr().map_err(DendecError::Io)?);
+    if opts.token_file.is_none() {
+        warn!("no --token-file; anyone who can reach this address can use it.");
+    }
+    serve_on(listener, opts)
+}
+
+/// Run the server on an already bound `listener`.
+pub fn serve_on(listener: TcpListener, opts: &ServeOptions) -> Result<()> {
+    let token = match &opts.token_file {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(DendecError::Io)?;
+            let token = text.trim();
+            if token.is_empty() {
+                return Err(DendecError::Serve(format!("token file {} is empty", path.display())));
+            }
+            Some(token_mac(token.as_bytes()).finalize().into_bytes().to_vec())
+        }
+        None => None,
+    };
+    let server = Arc::new(Server {
+        max_body: opts.max_body,
+        jobs: opts.jobs.max(1),
+        token,
+        hardened: opts.hardened,
+        active: AtomicUsize::new(0),
+    });
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let server = Arc::clone(&server);
+        if server.active.fetch_add(1, Ordering::SeqCst) >= server.jobs {
+            server.active.fetch_sub(1, Ordering::SeqCst);
+            // Off the accepting thread, so a client slow to read its 503
+            // does not hold up everyone else's accept
+            std::thread::spawn(move || {
+                let _ = stream.set_write_timeout(Some(BUSY_TIMEOUT));
+                let _ = respond(&stream, &Response::error(503, "Busy — try again shortly"));
+            });
+            continue;
+        }
+        std::thread::spawn(move || {
+            handle(&server, stream);
+            server.active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+// ── Requests and responses ────────────────────────────────────────────
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    /// Names lowercased.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn query(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+        Self { status: 200, content_type, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8", body: format!("{message}\n").into_bytes() }
+    }
+}
+
+/// A failed request: the status to answer with and why.
+struct HttpError(u16, String);
+
+impl From<DendecError> for HttpError {
+    fn from(e: DendecError) -> Self {
+        let status = match e {
+            DendecError::DecryptionFailed
+            | DendecError::WrongPassword
+            | DendecError::DecodeFailed
+            | DendecError::ReferChecksumMismatch => 422,
+            DendecError::Io(_) | DendecError::ReferTableCorrupt => 500,
+            _ => 400,
+        };
+        HttpError(status, e.to_string())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        422 => "Unprocessable Content",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn respond(mut stream: &TcpStream, response: &Response) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\
+         Cache-Control: no-store\r\nX-Content-Type-Options: nosniff\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    if response.status == 401 {
+        head.push_str("WWW-Authenticate: Bearer\r\n");
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+/// Serve one connection: read its request, answer it, log it.
+fn handle(server: &Server, stream: TcpStream) {
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_else(|_| "?".to_string());
+
+    let (route, response) = match read_request(&stream, server.max_body) {
+        Ok(request) => {
+            let route = format!("{} {}", request.method, request.path);
+            let response = route_request(server, &request)
+                .unwrap_or_else(|HttpError(status, message)| Response::error(status, &message));
+            (route, response)
+        }
+        Err(HttpError(status, message)) => ("-".to_string(), Response::error(status, &message)),
+    };
+    let _ = respond(&stream, &response);
+    linger(&stream);
+    info!("{peer} {route} {} {} bytes", response.status, response.body.len());
+}
+
+/// Close the sending side and discard what the client is still sending,
+/// briefly, so a refused upload is not cut off by a reset before the
+/// client reads the answer.
+fn linger(stream: &TcpStream) {
+    let _ = stream.shutdown(Shutdown::Write);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+    let _ = std::io::copy(&mut Read::take(stream, 1 << 20), &mut std::io::sink());
+}
+
+/// Read the request line, headers and body from `stream`.
+fn read_request(stream: &TcpStream, max_body: u64) -> std::result::Result<Request, HttpError> {
+    let mut reader = BufReader::new(stream);
+    let mut head_len = 0;
+    let mut line = || -> std::result::Result<String, HttpError> {
+        let mut buf = Vec::new();
+        let n = (&mut reader)
+            .take((MAX_HEAD - head_len) as u64 + 1)
+            .read_until(b'\n', &mut buf)
+            .map_err(|_| HttpError(408, "Timed out reading the request".to_string()))?;
+        head_len += n;
+        if head_len > MAX_HEAD {
+            return Err(HttpError(413, "Request headers too large".to_string()));
+        }
+        if n == 0 {
+            return Err(HttpError(400, "Incomplete request".to_string()));
+        }
+        let text = String::from_utf8(buf).map_err(|_| HttpError(400, "Request headers are not UTF-8".to_string()))?;
+        Ok(text.trim_end_matches(['\r', '\n']).to_string())
+    };
+
+    let request_line = line()?;
+    let mut words = request_line.split(' ');
+    let (Some(method), Some(target), Some(_version), None) = (words.next(), words.next(), words.next(), words.next())
+    else {
+        return Err(HttpError(400, "Malformed request line".to_string()));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| {
+            let (k, v) = kv.split_once('=').unwrap_or((kv, ""));
+            match (percent_decode(k), percent_decode(v)) {
+                (Some(k), Some(v)) => Ok((k, v)),
+                _ => Err(HttpError(400, format!("Malformed query parameter: {kv}"))),
+            }
+        })
+        .collect::<std::result::Result<_, _>>()?;
+
+    let mut headers = Vec::new();
+    loop {
+        let header = line()?;
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| HttpError(400, format!("Malformed header: {header}")))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    let mut request = Request { method: method.to_string(), path: path.to_string(), query, headers, body: Vec::new() };
+    if request.header("transfer-encoding").is_some() {
+        return Err(HttpError(411, "Chunked bodies are not accepted; send Content-Length".to_string()));
+    }
+    let length = match request.header("content-length") {
+        Some(n) => n.parse::<u64>().map_err(|_| HttpError(400, "Invalid Content-Length".to_string()))?,
+        None => 0,
+    };
+    if length > max_body {
+        return Err(HttpError(413, format!("Body exceeds the {max_body}-byte limit")));
+    }
+    if request.header("expect").is_some_and(|e| e.eq_ignore_ascii_case("100-continue")) {
+        let _ = (&mut &*stream).write_all(b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+    request.body = vec![0; length as usize];
+    reader
+        .read_exact(&mut request.body)
+        .map_err(|_| HttpError(400, "Body shorter than Content-Length".to_string()))?;
+    Ok(request)
+}
+
+// ── Routes ────────────────────────────────────────────────────────────
+
+fn route_request(server: &Server, request: &Request) -> std::result::Result<Response, HttpError> {
+    let method = request.method.as_str();
+    if request.path == "/health" {
+        return match method {
+            "GET" => Ok(Response::ok("text/plain; charset=utf-8", format!("dendec {}\n", env!("CARGO_PKG_VERSION")).into_bytes())),
+            _ => Err(HttpError(405, "Use GET".to_string())),
+        };
+    }
+    if !matches!(request.path.as_str(), "/encode" | "/decode" | "/refer") {
+        return Err(HttpError(404, format!("No route {}; use /encode, /decode or /refer", request.path)));
+    }
+    if method != "POST" {
+        return Err(HttpError(405, "Use POST".to_string()));
+    }
+    authorize(server, request)?;
+
+    let form = Form::parse(request)?;
+    match request.path.as_str() {
+        "/encode" => {
+            let group = match form.option(request, "group") {
+                Some(n) => Some(n.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    HttpError(400, format!("Invalid group '{n}': expected a positive number"))
+                })?),
+                None => None,
+            };
+            let password = form.password(request, "password", "X-Dendec-Password")?;
+            if password.is_empty() {
+                return Err(HttpError(400, "An empty password provides no security".to_string()));
+            }
+            let mut dna = encode_raw(form.input, &password, group)?;
+            dna.push('\n');
+            Ok(Response::ok("text/plain; charset=utf-8", dna.into_bytes()))
+        }
+        "/decode" => {
+            let password = form.password(request, "password", "X-Dendec-Password")?;
+            let refer_password = match is_refer_bed(form.input) {
+                true => form.optional(request, "refer_password", "X-Dendec-Refer-Password")?,
+                false => None,
+            };
+            let keys = MasterKeyCache::new(&password);
+            let decode = |keys: &MasterKeyCache| decode_input(form.input, refer_password.as_deref(), keys);
+            // Unrefer runs under harden too, so a bad refer file fails as a bad password does
+            let plaintext = match server.hardened {
+                true => harden(&keys, decode)?,
+                false => decode(&keys)?,
+            };
+            Ok(Response::ok("application/octet-stream", plaintext))
+        }
+        _ => {
+            let assembly = match form.option(request, "assembly") {
+                Some(name) => name.parse::<Assembly>().map_err(|e| HttpError(400, e))?,
+                None => Assembly::default(),
+            };
+            let bases: Vec<u8> = text(form.input)?.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+            let mut bed = Vec::new();
+            refer_bytes(&bases, assembly, &mut bed)?;
+            Ok(Response::ok("text/plain; charset=utf-8", bed))
+        }
+    }
+}
+
+/// Check the bearer token, if the server requires one.
+fn authorize(server: &Server, request: &Request) -> std::result::Result<(), HttpError> {
+    let Some(expected) = &server.token else { return Ok(()) };
+    let given = request
+        .header("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    token_mac(given.trim().as_bytes())
+        .verify_slice(expected).map_err(|_| HttpError(401, "Missing or wrong bearer token".to_string()))
+}
+
+fn token_mac(token: &[u8]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(TOKEN_KEY).expect("HMAC accepts keys of any length");
+    mac.update(token);
+    mac
+}
+
+fn text(input: &[u8]) -> std::result::Result<&str, HttpError> {
+    std::str::from_utf8(input).map_err(|_| HttpError(400, "Input is not UTF-8 text".to_string()))
+}
+
+/// Decode an uploaded `.dna`, or refer file by way of its DNA.
+fn decode_input(input: &[u8], refer_password: Option<&str>, keys: &MasterKeyCache) -> Result<Vec<u8>> {
+    let dna = match is_refer_bed(input) {
+        true => unrefer_text(input, refer_password)?,
+        false => String::from_utf8(input.to_vec())?,
+    };
+    decode_raw_cached(&dna, keys)
+}
+
+/// Unrefer a refer file held in memory. The readers work on files,
+/// telling formats apart by content.
+fn unrefer_text(input: &[u8], password: Option<&str>) -> Result<String> {
+    let file = ScratchFile::with_contents(input)?;
+    unrefer(file.path(), None, password)
+}
+
+// ── Forms ─────────────────────────────────────────────────────────────
+
+/// A request's input and form fields: a multipart/form-data body split
+/// into its parts, or any other body taken whole as the input.
+struct Form<'a> {
+    input: &'a [u8],
+    fields: Vec<(String, &'a [u8])>,
+}
+
+impl<'a> Form<'a> {
+    fn parse(request: &'a Request) -> std::result::Result<Self, HttpError> {
+        let content_type = request.header("content-type").unwrap_or_default();
+        let Some(boundary) = multipart_boundary(content_type) else {
+            return Ok(Form { input: &request.body, fields: Vec::new() });
+        };
+        let parts = multipart(&request.body, &boundary)?;
+        let (inputs, fields): (Vec<_>, Vec<_>) =
+            parts.into_iter().partition(|(name, _)| !OPTION_FIELDS.contains(&name.as_str()));
+        match inputs.as_slice() {
+            [(_, input)] => Ok(Form { input, fields }),
+            [] => Err(HttpError(400, "Form has no input part".to_string())),
+            _ => Err(HttpError(400, "Form has more than one input part".to_string())),
+        }
+    }
+
+    fn field(&self, name: &str) -> std::result::Result<Option<&'a str>, HttpError> {
+        match self.fields.iter().find(|(n, _)| n == name) {
+            Some((_, value)) => text(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// An option from the query string or, failing that, the form.
+    fn option<'r>(&self, request: &'r Request, name: &str) -> Option<&'r str>
+    where
+        'a: 'r,
+    {
+        request.query(name).or_else(|| self.field(name).ok().flatten())
+    }
+
+    /// A password from its header or form field, if given.
+    fn optional(&self, request: &Request, field: &str, header: &str) -> std::result::Result<Option<String>, HttpError> {
+        match request.header(&header.to_ascii_lowercase()) {
+            Some(p) => Ok(Some(p.to_string())),
+            None => Ok(self.field(field)?.map(str::to_string)),
+        }
+    }
+
+    /// A password that must be given.
+    fn password(&self, request: &Request, field: &str, header: &str) -> std::result::Result<String, HttpError> {
+        self.optional(request, field, header)?
+            .ok_or_else(|| HttpError(400, format!("No password: send the {header} header or a '{field}' form field")))
+    }
+}
+
+/// The boundary of a multipart/form-data Content-Type.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, v)| v.trim().trim_matches('"').to_string())
+        .filter(|b| !b.is_empty())
+}
+
+/// Split a multipart body into (field name, contents) pairs.
+fn multipart<'a>(body: &'a [u8], boundary: &str) -> std::result::Result<Vec<(String, &'a [u8])>, HttpError> {
+    let malformed = || HttpError(400, "Malformed multipart body".to_string());
+    let delimiter = format!("--{boundary}");
+    let next_delimiter = format!("\r\n--{boundary}");
+
+    let mut rest = &body[find(body, delimiter.as_bytes()).ok_or_else(malformed)? + delimiter.len()..];
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = rest.strip_prefix(b"\r\n").ok_or_else(malformed)?;
+        let head_end = find(rest, b"\r\n\r\n").ok_or_else(malformed)?;
+        let head = std::str::from_utf8(&rest[..head_end]).map_err(|_| malformed())?;
+        rest = &rest[head_end + 4..];
+        let end = find(rest, next_delimiter.as_bytes()).ok_or_else(malformed)?;
+        let name = head
+            .lines()
+            .filter_map(|l| l.split_once(':'))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("content-disposition"))
+            .and_then(|(_, v)| v.split(';').map(str::trim).find_map(|p| p.strip_prefix("name=")))
+            .map(|n| n.trim_matches('"').to_string())
+            .ok_or_else(malformed)?;
+        parts.push((name, &rest[..end]));
+        rest = &rest[end + next_delimiter.len()..];
+    }
+}
+
+/// Undo a query string's encoding: `+` is a space, `%XX` a byte. None
+/// for a bad escape or a result that is not UTF-8.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = rest.get(..2).filter(|h| h.iter().all(u8::is_ascii_hexdigit))?;
+                bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+                rest = &rest[2..];
+            }
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Start a server on a free port; returns its address.
+    fn start(opts: ServeOptions) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || serve_on(listener, &opts));
+        addr
+    }
+
+    /// Send a raw request; returns the status and body.
+    fn send(addr: &str, head: &str, body: &[u8]) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = format!("{head}\r\nContent-Length: {}\r\n\r\n", body.len());
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = find(&response, b"\r\n\r\n").unwrap();
+        let status = std::str::from_utf8(&response[9..12]).unwrap().parse().unwrap();
+        (status, response[split + 4..].to_vec())
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("hg38").as_deref(), Some("hg38"));
+        assert_eq!(percent_decode("hg38%20").as_deref(), Some("hg38 "));
+        assert_eq!(percent_decode("a+b%2Bc%e2%86%92").as_deref(), Some("a b+c→"));
+        assert_eq!(percent_decode("%4"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%+1"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn test_encode_refer_decode_over_http() {
+        let addr = start(ServeOptions::default());
+        let plaintext = b"sample sheet \x00\xff v2";
+
+        let (status, dna) = send(&addr, "POST /encode HTTP/1.1\r\nX-Dendec-Password: pw", plaintext);
+        assert_eq!(status, 200, "{}", String::from_utf8_lossy(&dna));
+
+        let (status, bed) = send(&addr, "POST /refer?assembly=hg%33%38 HTTP/1.1", &dna);
+        assert_eq!(status, 200, "{}", String::from_utf8_lossy(&bed));
+        assert!(bed.starts_with(b"##dendec-refer"));
+
+        // The BED as a form upload, password as a form field
+        let mut form = Vec::new();
+        form.extend_from_slice(b"--XyZ\r\nContent-Disposition: form-data; name=\"password\"\r\n\r\npw\r\n");
+        form.extend_from_slice(b"--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.bed\"\r\n");
+        form.extend_from_slice(b"Content-Type: text/plain\r\n\r\n");
+        form.extend_from_slice(&bed);
+        form.extend_from_slice(b"\r\n--XyZ--\r\n");
+        let head = "POST /decode HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XyZ";
+        let (status, back) = send(&addr, head, &form);
+        assert_eq!(status, 200, "{}", String::from_utf8_lossy(&back));
+        assert_eq!(back, plaintext);
+
+        let (status, body) = send(&addr, "POST /decode HTTP/1.1\r\nX-Dendec-Password: wrong", &dna);
+        assert_eq!(status, 422);
+        assert!(String::from_utf8_lossy(&body).contains("password"));
+    }
+
+    #[test]
+    fn test_hardened_refer_failures_look_alike() {
+        let addr = start(ServeOptions { hardened: true, ..Default::default() });
+        let (_, dna) = send(&addr, "POST /encode HTTP/1.1\r\nX-Dendec-Password: pw", b"hardened");
+        let (_, bed) = send(&addr, "POST /refer HTTP/1.1", &dna);
+        let bed = String::from_utf8(bed).unwrap();
+
+        // A refer file its table rejects, before any key is derived
+        let corrupt = bed.replace("##chunk_size 8", "##chunk_size 10");
+        let bad_refer = send(&addr, "POST /decode HTTP/1.1\r\nX-Dendec-Password: pw", corrupt.as_bytes());
+        let bad_password = send(&addr, "POST /decode HTTP/1.1\r\nX-Dendec-Password: wrong", bed.as_bytes());
+        assert_eq!(bad_refer.0, 422);
+        assert_eq!(bad_refer, bad_password);
+        assert_eq!(send(&addr, "POST /decode HTTP/1.1\r\nX-Dendec-Password: pw", bed.as_bytes()).1, b"hardened");
+    }
+
+    #[test]
+    fn test_rejected_requests() {
+        let dir = tempdir().unwrap();
+        let token_file = dir.path().join("token");
+        std::fs::write(&token_file, "s3cret\n").unwrap();
+        let addr = start(ServeOptions { max_body: 64, token_file: Some(token_file), ..Default::default() });
+
+        let (status, body) = send(&addr, "GET /health HTTP/1.1", b"");
+        assert_eq!((status, body), (200, format!("dendec {}\n", env!("CARGO_PKG_VERSION")).into_bytes()));
+        assert_eq!(send(&addr, "POST /encode HTTP/1.1", b"x").0, 401);
+        assert_eq!(send(&addr, "POST /encode HTTP/1.1\r\nAuthorization: Bearer nope", b"x").0, 401);
+
+        let auth = "Authorization: Bearer s3cret";
+        assert_eq!(send(&addr, &format!("POST /encode HTTP/1.1\r\n{auth}"), &[b'x'; 65]).0, 413);
+        assert_eq!(send(&addr, &format!("GET /encode HTTP/1.1\r\n{auth}"), b"").0, 405);
+        assert_eq!(send(&addr, &format!("POST /nope HTTP/1.1\r\n{auth}"), b"").0, 404);
+        let (status, body) = send(&addr, &format!("POST /encode HTTP/1.1\r\n{auth}"), b"x");
+        assert_eq!(status, 400);
+        assert!(String::from_utf8_lossy(&body).contains("X-Dendec-Password"));
+        let head = format!("POST /refer?assembly=panTro6 HTTP/1.1\r\n{auth}");
+        assert_eq!(send(&addr, &head, b"ACGT").0, 400);
+        let head = format!("POST /refer?assembly=hg38%2 HTTP/1.1\r\n{auth}");
+        assert_eq!(send(&addr, &head, b"ACGT").0, 400);
+        let head = format!("POST /encode HTTP/1.1\r\n{auth}\r\nContent-Type: multipart/form-data; boundary=b");
+        assert_eq!(send(&addr, &head, b"--b\r\nno end").0, 400);
+    }
+}
@@ -0,0 +1,182 @@
+/// stats.rs — Where one encode or decode spent its time, for --stats
+///
+/// `--stats` ends an encode or decode with a summary on stderr:
+///
+///   plaintext and DNA sizes, and how many times larger the DNA is
+///   KDF         Argon2id time and runs
+///   cipher      ChaCha20-Poly1305 time and throughput
+///   bases       bytes ↔ bases conversion time and throughput
+///   total       everything after the password prompt
+///
+/// The stages time themselves: `crypto` and `dna` hold a `timer` while
+/// they work, which adds to process-wide counters once `enable` has been
+/// called and costs one relaxed load otherwise. Encode seals chunks on
+/// all cores, so cipher time is summed over threads — CPU time — and its
+/// throughput is per core. Base time includes handing bases to the
+/// output or pulling them from the input, which it cannot separate.
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::bench::{fmt_duration, fmt_rate};
+use crate::wrap::transform::human_size;
+
+/// A stage of the pipeline that `--stats` times.
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Kdf,
+    Cipher,
+    Bases,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NANOS: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+static CALLS: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+
+/// Start counting. Nothing is timed before this is called.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Count the time until the returned guard is dropped towards `stage`,
+/// if counting is enabled.
+pub fn timer(stage: Stage) -> Timer {
+    Timer { stage, start: ENABLED.load(Ordering::Relaxed).then(Instant::now) }
+}
+
+pub struct Timer {
+    stage: Stage,
+    start: Option<Instant>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            NANOS[self.stage as usize].fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            CALLS[self.stage as usize].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Time counted so far.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    pub kdf: Duration,
+    pub kdf_runs: u64,
+    pub cipher: Duration,
+    pub bases: Duration,
+}
+
+pub fn timings() -> Timings {
+    let nanos = |stage: Stage| Duration::from_nanos(NANOS[stage as usize].load(Ordering::Relaxed));
+    Timings {
+        kdf: nanos(Stage::Kdf),
+        kdf_runs: CALLS[Stage::Kdf as usize].load(Ordering::Relaxed),
+        cipher: nanos(Stage::Cipher),
+        bases: nanos(Stage::Bases),
+    }
+}
+
+/// A writer that counts the bytes passing through it.
+pub struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, count: 0 }
+    }
+
+    /// The inner writer and the number of bytes written to it.
+    pub fn finish(self) -> (W, u64) {
+        (self.inner, self.count)
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The `--stats` summary of one encode or decode.
+#[derive(Debug, Clone, Copy)]
+pub struct Report {
+    /// Plaintext bytes read (encode) or written (decode)
+    pub plaintext: u64,
+    /// DNA bytes written (encode) or read (decode), spaces included
+    pub dna: u64,
+    pub elapsed: Duration,
+    pub timings: Timings,
+}
+
+impl Report {
+    pub fn lines(&self) -> Vec<String> {
+        let t = &self.timings;
+        let expansion = match self.plaintext {
+            0 => "—".to_string(),
+            n => format!("{:.2}×", self.dna as f64 / n as f64),
+        };
+        let plaintext = self.plaintext as usize;
+        vec![
+            "Stats:".to_string(),
+            format!("  plaintext  {}", human_size(self.plaintext)),
+            format!("  DNA        {}  ({expansion} the plaintext)", human_size(self.dna)),
+            format!(
+                "  KDF        {}  ({} Argon2id run{})",
+                fmt_duration(t.kdf),
+                t.kdf_runs,
+                if t.kdf_runs == 1 { "" } else { "s" }
+            ),
+            format!("  cipher     {}  ({} per core)", fmt_duration(t.cipher), fmt_rate(plaintext, t.cipher)),
+            format!("  bases      {}  ({})", fmt_duration(t.bases), fmt_rate(plaintext, t.bases)),
+            format!("  total      {}", fmt_duration(self.elapsed)),
+        ]
+    }
+
+    /// Print the summary to stderr.
+    pub fn print(&self) {
+        for line in self.lines() {
+            info!("{line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_lines() {
+        let report = Report {
+            plaintext: 2 * 1024 * 1024,
+            dna: 8 * 1024 * 1024 + 1024,
+            elapsed: Duration::from_millis(1500),
+            timings: Timings {
+                kdf: Duration::from_millis(600),
+                kdf_runs: 1,
+                cipher: Duration::from_millis(500),
+                bases: Duration::from_secs(1),
+            },
+        };
+        let lines = report.lines();
+        assert_eq!(lines[1], "  plaintext  2.0 MB");
+        assert_eq!(lines[2], "  DNA        8.0 MB  (4.00× the plaintext)");
+        assert_eq!(lines[3], "  KDF        600 ms  (1 Argon2id run)");
+        assert_eq!(lines[4], "  cipher     500 ms  (4.0 MiB/s per core)");
+        assert_eq!(lines[5], "  bases      1.00 s  (2.0 MiB/s)");
+        assert_eq!(lines[6], "  total      1.50 s");
+
+        let empty = Report { plaintext: 0, dna: 300, ..report };
+        assert!(empty.lines()[2].contains("(— the plaintext)"));
+    }
+}
@@ -0,0 +1,243 @@
+/// view.rs — DNA laid out for reading on a terminal
+///
+/// A continuous sequence of thousands of bases is unreadable on a
+/// screen and easy to lose one's place in when copying by hand. When
+/// encode prints to a terminal, the sequence goes through `DnaView`
+/// instead, laid out the way sequence viewers and GenBank's ORIGIN
+/// block do it:
+///
+///          1 ATGCATGCAT GCATGCATGC ATGCATGCAT ...
+///         61 GCATGCATGC ATGCATGCAT GCATGCATGC ...
+///
+/// Each line starts with the 1-based position of its first base, bases
+/// come in blocks of ten (or `--group N`), and a line holds as many
+/// blocks as fit the terminal. Each base has its own color: A green,
+/// C blue, G yellow, T red.
+///
+/// The view is for people. Pipes and `--as` files get the plain
+/// sequence, which is what decode reads; `--color never` prints the
+/// plain sequence on a terminal too, and `--color always` forces the
+/// view into a pipe, e.g. for `less -R`.
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Bases per block when `--group` is not given.
+pub const DEFAULT_BLOCK: usize = 10;
+
+/// Width assumed when the terminal does not say.
+const FALLBACK_WIDTH: usize = 80;
+
+/// Columns for the position ruler, enough for a 4 GB payload.
+const RULER_WIDTH: usize = 10;
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+
+/// When to lay DNA out with colors and rulers (`--color`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Only on a terminal, and not under NO_COLOR or TERM=dumb
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 3] = [ColorMode::Auto, ColorMode::Always, ColorMode::Never];
+
+    /// Name as given to `--color`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMode::Auto => "auto",
+            ColorMode::Always => "always",
+            ColorMode::Never => "never",
+        }
+    }
+
+    /// Whether output to a stream that `is_terminal` or not gets the view.
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                is_terminal
+                    && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::env::var_os("TERM").is_none_or(|t| t != "dumb")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        ColorMode::ALL
+            .into_iter()
+            .find(|c| c.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown color mode '{s}' (expected auto, always or never)"))
+    }
+}
+
+/// Columns of the terminal on stdout: its window size, else $COLUMNS,
+/// else 80.
+pub fn terminal_width() -> usize {
+    #[cfg(unix)]
+    {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: TIOCGWINSZ fills a winsize and touches nothing else
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+            return size.ws_col as usize;
+        }
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(FALLBACK_WIDTH)
+}
+
+/// Lays out the DNA written to it for a terminal; see the module doc.
+///
+/// Whitespace in the input is dropped, so grouped or continuous encode
+/// output both work, except that a newline ends the current line: the
+/// newline encode prints last finishes the view too.
+pub struct DnaView<W: Write> {
+    inner: W,
+    block: usize,
+    per_line: usize,
+    color: bool,
+    /// Bases written so far
+    position: u64,
+    /// Whether the current line has its ruler yet
+    line_open: bool,
+    /// The base whose color was last set on this line
+    current: Option<u8>,
+}
+
+impl<W: Write> DnaView<W> {
+    /// A view `width` columns wide with blocks of `block` bases (0 means
+    /// the default), colored if `color` is set.
+    pub fn new(inner: W, block: usize, width: usize, color: bool) -> Self {
+        let block = if block == 0 { DEFAULT_BLOCK } else { block };
+        // Blocks are spaced apart, with no space after the last
+        let blocks = (width + 1).saturating_sub(RULER_WIDTH) / (block + 1);
+        Self { inner, block, per_line: blocks.max(1) * block, color, position: 0, line_open: false, current: None }
+    }
+
+    /// End the last line and return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.close_line()?;
+        Ok(self.inner)
+    }
+
+    fn column(&self) -> usize {
+        (self.position % self.per_line as u64) as usize
+    }
+
+    /// Finish the current line, if one is open.
+    fn close_line(&mut self) -> io::Result<()> {
+        if !self.line_open {
+            return Ok(());
+        }
+        if self.current.take().is_some() {
+            self.inner.write_all(RESET.as_bytes())?;
+        }
+        self.line_open = false;
+        self.inner.write_all(b"\n")
+    }
+
+    /// A newline in the input: the next base starts a new sequence,
+    /// numbered from 1 again.
+    fn end_line(&mut self) -> io::Result<()> {
+        self.close_line()?;
+        self.position = 0;
+        Ok(())
+    }
+
+    fn base(&mut self, base: u8) -> io::Result<()> {
+        if !self.line_open {
+            let ruler = format!("{:>width$} ", self.position + 1, width = RULER_WIDTH - 1);
+            match self.color {
+                true => write!(self.inner, "{DIM}{ruler}{RESET}")?,
+                false => self.inner.write_all(ruler.as_bytes())?,
+            }
+            self.line_open = true;
+        } else if self.column().is_multiple_of(self.block) {
+            self.inner.write_all(b" ")?;
+        }
+        if self.color && self.current != Some(base) {
+            self.inner.write_all(color_of(base).as_bytes())?;
+            self.current = Some(base);
+        }
+        self.inner.write_all(&[base])?;
+        self.position += 1;
+        if self.column() == 0 {
+            self.close_line()?;
+        }
+        Ok(())
+    }
+}
+
+/// ANSI color for one base; anything else in the default color.
+fn color_of(base: u8) -> &'static str {
+    match base.to_ascii_uppercase() {
+        b'A' => "\x1b[32m",
+        b'C' => "\x1b[34m",
+        b'G' => "\x1b[33m",
+        b'T' => "\x1b[31m",
+        _ => RESET,
+    }
+}
+
+impl<W: Write> Write for DnaView<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            match b {
+                b'\n' => self.end_line()?,
+                b if b.is_ascii_whitespace() => {}
+                b => self.base(b)?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_wraps_with_rulers_and_colors() {
+        // 10 ruler columns and two blocks of 4 with a space between fit in 19
+        let mut view = DnaView::new(Vec::new(), 4, 19, false);
+        view.write_all(b"ATGC ATGCAT\nGCA").unwrap();
+        let plain = String::from_utf8(view.finish().unwrap()).unwrap();
+        assert_eq!(
+            plain,
+            "        1 ATGC ATGC\n        9 AT\n        1 GCA\n"
+        );
+
+        let mut view = DnaView::new(Vec::new(), 4, 80, true);
+        view.write_all(b"AAC\n").unwrap();
+        let colored = String::from_utf8(view.finish().unwrap()).unwrap();
+        assert_eq!(colored, format!("{DIM}        1 {RESET}\x1b[32mAA\x1b[34mC{RESET}\n"));
+
+        assert!(!ColorMode::Never.enabled(true));
+        assert!(ColorMode::Always.enabled(false));
+        assert!(!ColorMode::Auto.enabled(false));
+        assert_eq!("ALWAYS".parse::<ColorMode>(), Ok(ColorMode::Always));
+    }
+}
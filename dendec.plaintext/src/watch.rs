@@ -0,0 +1,141 @@
+/// watch.rs — Keep one file's .dna in step with it
+///
+/// `dendec encode --watch notes.md --as notes.md.dna` encodes notes.md
+/// at once, then again every time it changes, until interrupted. Point
+/// `--as` into a cloud-synced folder and the folder only ever holds the
+/// encrypted copy of a file you keep editing locally.
+///
+/// The password is stretched once for the session; each encode has its
+/// own file salt and nonces, as any encode does. Saves are debounced
+/// the way `wrap --watch` debounces (the same settle time), so an
+/// editor's burst of writes is one encode. The target is replaced
+/// atomically, so a sync client never uploads a half-written file, and
+/// only when the plaintext's SHA-256 differs from the last one encoded:
+/// a save that changes nothing does not touch the target.
+///
+/// The parent directory is watched rather than the file itself, because
+/// many editors save by writing a new file and renaming it over the old
+/// one. If the file is deleted, the watch waits for it to come back.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Instant;
+
+use log::{info, warn};
+use notify::{RecursiveMode, Watcher};
+
+use crate::crypto::MasterKey;
+use crate::encoding::encode_stream_with_master;
+use crate::error::{DendecError, Result};
+use crate::output::write_atomic_with;
+use crate::wrap::manifest::sha256;
+use crate::wrap::watch::{is_content_event, POLL, SETTLE};
+
+/// One plaintext file and the .dna kept for it.
+pub struct FileSync<'a> {
+    source: PathBuf,
+    target: PathBuf,
+    master: &'a MasterKey,
+    group: Option<usize>,
+    /// SHA-256 of the plaintext last encoded
+    last: Option<[u8; 32]>,
+}
+
+impl<'a> FileSync<'a> {
+    pub fn new(source: &Path, target: &Path, master: &'a MasterKey, group: Option<usize>) -> Self {
+        Self { source: source.to_path_buf(), target: target.to_path_buf(), master, group, last: None }
+    }
+
+    /// Encode the source if it changed since the last encode. Returns
+    /// whether the target was written.
+    pub fn sync(&mut self) -> Result<bool> {
+        // Read whole: an editor may truncate the file under a mapping
+        let plaintext = fs::read(&self.source).map_err(DendecError::Io)?;
+        let digest = sha256(&plaintext);
+        if self.last == Some(digest) {
+            return Ok(false);
+        }
+        write_atomic_with(&self.target, None, None, |file| {
+            encode_stream_with_master(plaintext.as_slice(), file, self.master, self.group)?;
+            Ok(())
+        })?;
+        self.last = Some(digest);
+        Ok(true)
+    }
+}
+
+/// Encode `source` to `target` now and after every change, until
+/// interrupted.
+pub fn watch_encode(source: &Path, target: &Path, master: &MasterKey, group: Option<usize>) -> Result<()> {
+    let watch_err = |e: notify::Error| DendecError::WrapWatch(e.to_string());
+    let source = source.canonicalize().map_err(DendecError::Io)?;
+    let dir = source.parent().unwrap_or(Path::new("/")).to_path_buf();
+
+    let mut sync = FileSync::new(&source, target, master, group);
+    sync.sync()?;
+    info!("Written to {}", target.display());
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(watch_err)?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive).map_err(watch_err)?;
+    info!("Watching {} — each change is encoded to {}. Press Ctrl-C to stop.", source.display(), target.display());
+
+    let mut last_event: Option<Instant> = None;
+    loop {
+        match rx.recv_timeout(POLL) {
+            Ok(Ok(event)) if is_content_event(&event.kind) && event.paths.contains(&source) => {
+                last_event = Some(Instant::now());
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => warn!("watch error: {e}"),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if last_event.is_none_or(|at| at.elapsed() < SETTLE) {
+            continue;
+        }
+        last_event = None;
+        if !source.is_file() {
+            warn!("{} is gone; waiting for it to come back", source.display());
+            continue;
+        }
+        match sync.sync() {
+            Ok(true) => info!("  Encoded {} → {}", source.display(), target.display()),
+            Ok(false) => {}
+            Err(e) => warn!("  could not encode {}: {e}", source.display()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::derive_master_key;
+    use crate::encoding::decode_raw;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sync_encodes_only_changes() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("notes.md");
+        let target = dir.path().join("synced").join("notes.md.dna");
+        fs::create_dir(target.parent().unwrap()).unwrap();
+        fs::write(&source, b"first draft\n").unwrap();
+
+        let master = derive_master_key("watch").unwrap();
+        let mut sync = FileSync::new(&source, &target, &master, None);
+        assert!(sync.sync().unwrap());
+        let first = fs::read_to_string(&target).unwrap();
+        assert_eq!(decode_raw(&first, "watch").unwrap(), b"first draft\n");
+
+        // A save with the same content leaves the target alone
+        fs::write(&source, b"first draft\n").unwrap();
+        assert!(!sync.sync().unwrap());
+        assert_eq!(fs::read_to_string(&target).unwrap(), first);
+
+        fs::write(&source, b"second draft\n").unwrap();
+        assert!(sync.sync().unwrap());
+        assert_eq!(decode_raw(&fs::read_to_string(&target).unwrap(), "watch").unwrap(), b"second draft\n");
+    }
+}
@@ -0,0 +1,204 @@
+/// wrap/archive.rs — One encrypted container instead of a .dna per file
+///
+/// `wrap -e --archive out.dna <command|dir>` packs every file the wrap
+/// would have encoded into a tar, encodes that as a single payload, and
+/// writes it to `out.dna`. What an observer sees is one file of one size;
+/// the number of files, their names, and the tree they formed are all
+/// inside the ciphertext. One artifact is also simpler to move around.
+///
+/// Selection is that of a per-file encode: ignore rules, `.dendec.toml`,
+/// and the skip rules in classify.rs all apply. Once the archive is in
+/// place, the originals are removed, along with any directories that
+/// leaves empty, unless `--keep`. `--verify` decodes the written archive
+/// back before anything is removed.
+///
+/// `wrap -d --archive out.dna [DIR]` unpacks it into DIR (default: the
+/// current directory) under the usual overwrite rules, checking every
+/// path before writing any. The payload is a plain tar, so
+/// `dendec decode -f out.dna --as - | tar x` works as well.
+///
+/// No manifest is written: the archive is its own record, and
+/// extracting it is the undo.
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use log::{info, warn};
+
+use crate::crypto::derive_master_key;
+use crate::encoding::{decode_raw, encode_raw_with_master};
+use crate::error::{DendecError, Result};
+use crate::output::{check_output, prepare_output, write_atomic};
+use crate::wrap::classify::{classify_for_encode, FileClass};
+use crate::wrap::manifest::sha256;
+use crate::wrap::transform::{human_size, verify_output};
+use crate::wrap::WrapOptions;
+
+/// Pack the encodable files among `files` (all under `root`) into one
+/// encrypted archive at `out`. Paths inside are relative to `root`.
+pub fn archive_files(
+    root: &Path,
+    files: &[PathBuf],
+    out: &Path,
+    password: &str,
+    opts: &WrapOptions,
+) -> Result<()> {
+    check_output(out, opts.overwrite)?;
+
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.follow_symlinks(false);
+    let mut archived = Vec::new();
+    let mut skipped = 0;
+    for path in files {
+        match classify_for_encode(path, opts) {
+            FileClass::Encode => {
+                let name = path.strip_prefix(root).unwrap_or(path);
+                builder
+                    .append_path_with_name(path, name)
+                    .map_err(|e| DendecError::WrapFileFailed {
+                        path: path.clone(),
+                        reason: e.to_string(),
+                    })?;
+                archived.push(path.clone());
+            }
+            FileClass::Skip(reason) => {
+                info!("  Skipping {}  ({})", path.display(), reason.label());
+                skipped += 1;
+            }
+            FileClass::Decode => skipped += 1,
+        }
+    }
+    if archived.is_empty() {
+        return Err(DendecError::WrapNoFilesFound);
+    }
+    let tar = builder.into_inner().map_err(DendecError::Io)?;
+
+    info!("Archiving {} file(s) into {}...", archived.len(), out.display());
+    let master = derive_master_key(password)?;
+    let dna = encode_raw_with_master(&tar, &master, None)?;
+    prepare_output(out, opts.overwrite)?;
+    write_atomic(out, dna.as_bytes(), None)?;
+
+    if opts.verify {
+        if let Err(reason) = verify_output(out, &master, &sha256(&tar)) {
+            let _ = fs::remove_file(out);
+            return Err(DendecError::WrapVerifyFailed { path: out.to_path_buf(), reason });
+        }
+    }
+
+    info!("");
+    info!(
+        "  {} files archived ({} → {}{})  |  {} skipped",
+        archived.len(),
+        human_size(tar.len() as u64),
+        human_size(dna.len() as u64),
+        if opts.verify { ", verified" } else { "" },
+        skipped
+    );
+    if !opts.keep {
+        remove_archived(root, &archived);
+    }
+    Ok(())
+}
+
+/// Unpack the archive at `archive` into `dest`, then remove the archive
+/// unless `opts.keep`.
+pub fn extract_archive(archive: &Path, dest: &Path, password: &str, opts: &WrapOptions) -> Result<()> {
+    let dna = fs::read_to_string(archive).map_err(DendecError::Io)?;
+    info!("Extracting {} into {}...", archive.display(), dest.display());
+    let tar = decode_raw(&dna, password)?;
+    let not_archive = |e: std::io::Error| {
+        DendecError::WrapArchive(format!("{} does not hold a wrap archive ({e})", archive.display()))
+    };
+
+    // Check every path before writing anything
+    let mut targets = Vec::new();
+    for entry in tar::Archive::new(&tar[..]).entries().map_err(not_archive)? {
+        let entry = entry.map_err(not_archive)?;
+        let name = entry.path().map_err(not_archive)?.into_owned();
+        if name.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(DendecError::WrapArchive(format!(
+                "{} would be written outside {}",
+                name.display(),
+                dest.display()
+            )));
+        }
+        let target = dest.join(&name);
+        if entry.header().entry_type().is_file() {
+            check_output(&target, opts.overwrite)?;
+        }
+        targets.push(target);
+    }
+
+    fs::create_dir_all(dest).map_err(DendecError::Io)?;
+    let mut entries = tar::Archive::new(&tar[..]);
+    for (entry, target) in entries.entries().map_err(DendecError::Io)?.zip(&targets) {
+        let mut entry = entry.map_err(DendecError::Io)?;
+        prepare_output(target, opts.overwrite)?;
+        entry.unpack_in(dest).map_err(DendecError::Io)?;
+        info!("  Extracted {}", target.display());
+    }
+
+    info!("");
+    info!(
+        "  {} files extracted  ({} → {})",
+        targets.len(),
+        human_size(dna.len() as u64),
+        human_size(tar.len() as u64)
+    );
+    if !opts.keep {
+        if let Err(e) = fs::remove_file(archive) {
+            warn!("  could not remove archive {}: {e}", archive.display());
+        }
+    }
+    Ok(())
+}
+
+/// Remove archived originals, then each directory under `root` that
+/// held one and is now empty.
+fn remove_archived(root: &Path, files: &[PathBuf]) {
+    for path in files {
+        if let Err(e) = fs::remove_file(path) {
+            warn!("  could not remove original {}: {e}", path.display());
+        }
+    }
+    for path in files {
+        let parents = path.ancestors().skip(1).take_while(|d| *d != root && d.starts_with(root));
+        for dir in parents {
+            // Fails, and stops the climb, at the first non-empty one
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(root.join("src/deep")).unwrap();
+        fs::write(root.join("README.md"), b"# project\n").unwrap();
+        fs::write(root.join("src/deep/lib.rs"), b"pub fn f() {}\n").unwrap();
+        fs::write(root.join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0, 0, 0]).unwrap();
+        let files = vec![root.join("README.md"), root.join("logo.png"), root.join("src/deep/lib.rs")];
+
+        let out = dir.path().join("project.dna");
+        archive_files(&root, &files, &out, "archive", &WrapOptions::default()).unwrap();
+        // Binary skipped and kept; the rest, and the emptied dirs, gone
+        assert!(root.join("logo.png").exists());
+        assert!(!root.join("README.md").exists());
+        assert!(!root.join("src").exists());
+
+        let dest = dir.path().join("restored");
+        extract_archive(&out, &dest, "archive", &WrapOptions::default()).unwrap();
+        assert_eq!(fs::read(dest.join("README.md")).unwrap(), b"# project\n");
+        assert_eq!(fs::read(dest.join("src/deep/lib.rs")).unwrap(), b"pub fn f() {}\n");
+        assert!(!dest.join("logo.png").exists());
+        assert!(!out.exists());
+    }
+}
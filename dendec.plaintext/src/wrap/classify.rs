@@ -1,17 +1,48 @@
 /// wrap/classify.rs — File classification for wrap
 ///
 /// Determines whether a file should be encoded, decoded, or skipped.
-/// Binary detection samples the first 512 bytes and checks the ratio
-/// of non-UTF-8-safe bytes. This mirrors the approach used by git
-/// and most editors to detect binary files.
+/// Binary detection samples the first 512 bytes. The sample's magic
+/// number is checked first (the `infer` crate knows ~200 formats), so a
+/// renamed image or archive is still caught. Samples without a known
+/// signature fall back to text checks: valid UTF-8 or a Unicode BOM is
+/// text, and otherwise the ratio of control bytes decides, mirroring
+/// the approach used by git and most editors.
+///
+/// Known binary extensions are a fast path only: they skip the read for
+/// obvious binaries. A file with an unfamiliar extension is judged by
+/// its content alone.
+///
+/// What counts as a dendec payload is decided by content too: the sample
+/// must open with a dendec header (see `encoding::is_dendec_payload`).
+/// Encode skips payloads whatever their name, so nothing is encoded
+/// twice, and encodes a `.dna` file that is not one (e.g. a genomics
+/// file). Decode skips `.dna` files that are not payloads instead of
+/// failing on them. A refer BED (`wrap --refer`) counts as a payload on
+/// both sides; any other `.bed` is left to encode like ordinary text.
+/// The `.dna` extension is only trusted when the file cannot be read.
+/// With `--sniff`, decode also probes files with other names and
+/// decodes any payload it finds, in place.
+///
+/// The built-in directory and extension lists below can be extended per
+/// project (`.dendec.toml`) or per run (`--exclude-dir`, `--binary-ext`);
+/// the extra entries arrive through `WrapOptions`.
+///
+/// Encoding is byte-safe, so skipping binaries is a default, not a
+/// limitation: `--include-binary` encodes them too, optionally only up
+/// to `--binary-max-size`. Independently, `--max-size` skips any file
+/// above a size, text or not, in both modes.
 use std::fs;
 use std::path::Path;
 
+use crate::encoding::is_dendec_payload;
+use crate::refer::format::is_refer_bed;
+use crate::wrap::WrapOptions;
+
 /// Known binary extensions — fast path to skip obvious binaries
 /// without reading file contents.
 const BINARY_EXTENSIONS: &[&str] = &[
     // images
-    "png", "jpg", "jpeg", "gif", "bmp", "ico", "webp", "tiff", "svg",
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "webp", "tiff",
     // archives
     "zip", "tar", "gz", "bz2", "xz", "zst", "7z", "rar",
     // compiled
@@ -24,9 +55,15 @@ const BINARY_EXTENSIONS: &[&str] = &[
     "db", "sqlite", "pyc", "class",
 ];
 
+/// Directories never descended into — VCS metadata and build output.
+const EXCLUDED_DIRS: &[&str] = &[".git", "target", "node_modules", ".svn", ".hg"];
+
 /// A .dna file produced by dendec
 const DNA_EXTENSION: &str = "dna";
 
+/// A refer BED written by `wrap --refer`
+const REFER_EXTENSION: &str = "bed";
+
 /// Classification decision for a file.
 #[derive(Debug, PartialEq)]
 pub enum FileClass {
@@ -43,51 +80,142 @@ pub enum SkipReason {
     Binary,
     AlreadyDna,
     NotDna,
+    /// Named .dna, but the content is not a dendec payload
+    NotPayload,
     ExcludedDir,
+    /// Larger than `--max-size`
+    TooLarge,
     #[allow(dead_code)]
     // This is preserved for future rollouts
     ReadError,
 }
 
+impl SkipReason {
+    /// Short label shown in progress output and the JSON report.
+    pub fn label(&self) -> &'static str {
+        match self {
+            SkipReason::Binary => "binary",
+            SkipReason::AlreadyDna => "already encoded",
+            SkipReason::NotDna => "not .dna",
+            SkipReason::NotPayload => "not a dendec payload",
+            SkipReason::ExcludedDir => "excluded dir",
+            SkipReason::TooLarge => "over --max-size",
+            SkipReason::ReadError => "read error",
+        }
+    }
+}
+
 /// Classify a file for encode mode.
-pub fn classify_for_encode(path: &Path) -> FileClass {
-    if is_excluded_dir(path) {
+pub fn classify_for_encode(path: &Path, opts: &WrapOptions) -> FileClass {
+    if is_excluded_dir(path, opts) {
         return FileClass::Skip(SkipReason::ExcludedDir);
     }
-    // Skip files that are already .dna
-    if has_extension(path, DNA_EXTENSION) {
-        return FileClass::Skip(SkipReason::AlreadyDna);
+    if exceeds_max_size(path, opts) {
+        return FileClass::Skip(SkipReason::TooLarge);
     }
     // Fast path: known binary extension
-    if has_known_binary_extension(path) {
+    if has_known_binary_extension(path, opts) && !binary_allowed(path, opts) {
         return FileClass::Skip(SkipReason::Binary);
     }
-    // Content inspection: sample first 512 bytes
-    if is_binary_content(path) {
+    // Content inspection of the first 512 bytes
+    let Ok(sample) = read_sample(path, 512) else {
+        // Can't look inside: go by the name, and let the encode itself
+        // report the read error
+        if has_extension(path, DNA_EXTENSION) {
+            return FileClass::Skip(SkipReason::AlreadyDna);
+        }
+        return FileClass::Encode;
+    };
+    // Already a payload, whatever it is called — never encode twice
+    if is_dendec_payload(&sample) || is_refer_bed(&sample) {
+        return FileClass::Skip(SkipReason::AlreadyDna);
+    }
+    if is_binary_sample(&sample) && !binary_allowed(path, opts) {
         return FileClass::Skip(SkipReason::Binary);
     }
     FileClass::Encode
 }
 
+/// Whether `--include-binary` (and its size cap, if any) admits `path`.
+fn binary_allowed(path: &Path, opts: &WrapOptions) -> bool {
+    if !opts.include_binary {
+        return false;
+    }
+    match opts.binary_max_size {
+        None => true,
+        Some(max) => fs::metadata(path).map(|m| m.len() <= max).unwrap_or(false),
+    }
+}
+
 /// Classify a file for decode mode.
-pub fn classify_for_decode(path: &Path) -> FileClass {
-    if is_excluded_dir(path) {
+pub fn classify_for_decode(path: &Path, opts: &WrapOptions) -> FileClass {
+    if is_excluded_dir(path, opts) {
         return FileClass::Skip(SkipReason::ExcludedDir);
     }
-    // Only decode .dna files
-    if has_extension(path, DNA_EXTENSION) {
-        return FileClass::Decode;
+    // Only .dna files and refer BEDs, unless --sniff asks to look inside the rest
+    let named = has_extension(path, DNA_EXTENSION);
+    let bed = has_extension(path, REFER_EXTENSION);
+    if !named && !bed && !opts.sniff {
+        return FileClass::Skip(SkipReason::NotDna);
+    }
+    if exceeds_max_size(path, opts) {
+        return FileClass::Skip(SkipReason::TooLarge);
+    }
+    match read_sample(path, 512) {
+        Ok(sample) if is_dendec_payload(&sample) => FileClass::Decode,
+        // Any other .bed is a genomics file like any other
+        Ok(sample) if bed && is_refer_bed(&sample) => FileClass::Decode,
+        Ok(_) if named => FileClass::Skip(SkipReason::NotPayload),
+        Ok(_) => FileClass::Skip(SkipReason::NotDna),
+        // An unreadable .dna is still handed to decode, which reports why
+        Err(_) if named => FileClass::Decode,
+        Err(_) => FileClass::Skip(SkipReason::NotDna),
+    }
+}
+
+/// True if `path` is named like a dendec payload (`*.dna`).
+pub fn has_dna_extension(path: &Path) -> bool {
+    has_extension(path, DNA_EXTENSION)
+}
+
+/// True if `path` is named like a `wrap --refer` output (`*.bed`).
+pub fn has_refer_extension(path: &Path) -> bool {
+    has_extension(path, REFER_EXTENSION)
+}
+
+/// True if the file at `path` is a refer BED, judged by its first bytes.
+pub fn is_refer_file(path: &Path) -> bool {
+    read_sample(path, 512)
+        .map(|sample| is_refer_bed(&sample))
+        .unwrap_or(false)
+}
+
+/// True if the file at `path` starts with a dendec header. Decode uses
+/// this to spot a double-encoded file whose first layer it just removed.
+pub fn is_payload_file(path: &Path) -> bool {
+    read_sample(path, 512)
+        .map(|sample| is_dendec_payload(&sample))
+        .unwrap_or(false)
+}
+
+/// Whether `path` is larger than `--max-size`. A file whose size can't
+/// be read is left for the transform to report.
+fn exceeds_max_size(path: &Path, opts: &WrapOptions) -> bool {
+    match opts.max_size {
+        None => false,
+        Some(max) => fs::metadata(path).map(|m| m.len() > max).unwrap_or(false),
     }
-    FileClass::Skip(SkipReason::NotDna)
 }
 
-/// Check if path is inside an excluded directory (.git, target, node_modules).
-fn is_excluded_dir(path: &Path) -> bool {
-    path.components().any(|c| {
-        matches!(
-            c.as_os_str().to_str().unwrap_or(""),
-            ".git" | "target" | "node_modules" | ".svn" | ".hg"
-        )
+/// Check if path is inside an excluded directory (.git, target,
+/// node_modules, or one added through `opts`).
+fn is_excluded_dir(path: &Path, opts: &WrapOptions) -> bool {
+    let Some(dir) = path.parent() else {
+        return false;
+    };
+    dir.components().any(|c| {
+        let name = c.as_os_str().to_str().unwrap_or("");
+        EXCLUDED_DIRS.contains(&name) || opts.exclude_dirs.iter().any(|d| d == name)
     })
 }
 
@@ -98,36 +226,54 @@ fn has_extension(path: &Path, ext: &str) -> bool {
         .unwrap_or(false)
 }
 
-fn has_known_binary_extension(path: &Path) -> bool {
+fn has_known_binary_extension(path: &Path, opts: &WrapOptions) -> bool {
     path.extension()
         .and_then(|e| e.to_str())
         .map(|e| {
             BINARY_EXTENSIONS
                 .iter()
                 .any(|&b| e.eq_ignore_ascii_case(b))
+                || opts
+                    .binary_extensions
+                    .iter()
+                    .any(|b| e.eq_ignore_ascii_case(b.trim_start_matches('.')))
         })
         .unwrap_or(false)
 }
 
-/// Sample up to 512 bytes of the file.
-/// If more than 10% of bytes are non-printable non-whitespace, treat as binary.
+/// Decide from a leading sample whether content is binary.
 ///
-/// This mirrors git's binary detection heuristic.
-fn is_binary_content(path: &Path) -> bool {
-    let sample = match read_sample(path, 512) {
-        Ok(b) => b,
-        Err(_) => return false, // if we can't read it, try to encode anyway
-    };
-
+/// In order: a recognised non-text magic number → binary; a Unicode BOM
+/// → text (UTF-16 is full of null bytes); a null byte → binary; valid
+/// UTF-8 → text; otherwise more than 10% non-printable non-whitespace
+/// bytes → binary. The last two mirror git's heuristic.
+fn is_binary_sample(sample: &[u8]) -> bool {
     if sample.is_empty() {
         return false;
     }
 
+    if let Some(kind) = infer::get(sample) {
+        if kind.matcher_type() != infer::MatcherType::Text {
+            return true;
+        }
+    }
+
+    if has_unicode_bom(sample) {
+        return false;
+    }
+
     // Null byte is a definitive binary indicator
     if sample.contains(&0u8) {
         return true;
     }
 
+    // The sample may end mid-character; only an error before that counts
+    match std::str::from_utf8(sample) {
+        Ok(_) => return false,
+        Err(e) if e.error_len().is_none() => return false,
+        Err(_) => {}
+    }
+
     let non_text = sample
         .iter()
         .filter(|&&b| b < 0x08 || (b > 0x0D && b < 0x20 && b != 0x1B))
@@ -137,7 +283,13 @@ fn is_binary_content(path: &Path) -> bool {
     non_text * 10 > sample.len()
 }
 
-fn read_sample(path: &Path, max_bytes: usize) -> std::io::Result<Vec<u8>> {
+fn has_unicode_bom(sample: &[u8]) -> bool {
+    sample.starts_with(&[0xEF, 0xBB, 0xBF])
+        || sample.starts_with(&[0xFF, 0xFE])
+        || sample.starts_with(&[0xFE, 0xFF])
+}
+
+pub(crate) fn read_sample(path: &Path, max_bytes: usize) -> std::io::Result<Vec<u8>> {
     use std::io::Read;
     let mut f = fs::File::open(path)?;
     let mut buf = vec![0u8; max_bytes];
@@ -155,31 +307,50 @@ mod tests {
     #[test]
     fn test_dna_file_skipped_in_encode() {
         let p = PathBuf::from("file.rs.dna");
-        assert_eq!(classify_for_encode(&p), FileClass::Skip(SkipReason::AlreadyDna));
+        assert_eq!(classify_for_encode(&p, &WrapOptions::default()), FileClass::Skip(SkipReason::AlreadyDna));
     }
 
     #[test]
     fn test_non_dna_skipped_in_decode() {
         let p = PathBuf::from("file.rs");
-        assert_eq!(classify_for_decode(&p), FileClass::Skip(SkipReason::NotDna));
+        assert_eq!(classify_for_decode(&p, &WrapOptions::default()), FileClass::Skip(SkipReason::NotDna));
     }
 
     #[test]
     fn test_dna_file_decoded_in_decode() {
         let p = PathBuf::from("file.rs.dna");
-        assert_eq!(classify_for_decode(&p), FileClass::Decode);
+        assert_eq!(classify_for_decode(&p, &WrapOptions::default()), FileClass::Decode);
     }
 
     #[test]
     fn test_git_dir_excluded() {
         let p = PathBuf::from(".git/config");
-        assert_eq!(classify_for_encode(&p), FileClass::Skip(SkipReason::ExcludedDir));
+        assert_eq!(classify_for_encode(&p, &WrapOptions::default()), FileClass::Skip(SkipReason::ExcludedDir));
+    }
+
+    #[test]
+    fn test_configured_skip_lists() {
+        let opts = WrapOptions {
+            exclude_dirs: vec!["vendor".into()],
+            binary_extensions: vec![".psd".into()],
+            ..Default::default()
+        };
+        let vendored = PathBuf::from("vendor/lib/util.rs");
+        assert_eq!(classify_for_encode(&vendored, &opts), FileClass::Skip(SkipReason::ExcludedDir));
+        assert_eq!(classify_for_decode(&vendored.with_extension("rs.dna"), &opts), FileClass::Skip(SkipReason::ExcludedDir));
+        assert_eq!(classify_for_encode(&PathBuf::from("art/cover.PSD"), &opts), FileClass::Skip(SkipReason::Binary));
+
+        // A file merely named like an excluded directory is not excluded
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("vendor");
+        std::fs::write(&file, b"plain text").unwrap();
+        assert_eq!(classify_for_encode(&file, &opts), FileClass::Encode);
     }
 
     #[test]
     fn test_binary_extension_skipped() {
         let p = PathBuf::from("image.png");
-        assert_eq!(classify_for_encode(&p), FileClass::Skip(SkipReason::Binary));
+        assert_eq!(classify_for_encode(&p, &WrapOptions::default()), FileClass::Skip(SkipReason::Binary));
     }
 
     #[test]
@@ -187,7 +358,126 @@ mod tests {
         let dir = tempdir().unwrap();
         let path = dir.path().join("hello.rs");
         std::fs::write(&path, b"fn main() {}").unwrap();
-        assert_eq!(classify_for_encode(&path), FileClass::Encode);
+        assert_eq!(classify_for_encode(&path, &WrapOptions::default()), FileClass::Encode);
+    }
+
+    #[test]
+    fn test_renamed_binary_detected_by_magic() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        // A gzip stream with no null bytes in its first 512 bytes
+        let mut gz = vec![0x1f, 0x8b, 0x08, 0x08];
+        gz.extend([b'a'; 100]);
+        std::fs::write(&path, gz).unwrap();
+        assert_eq!(classify_for_encode(&path, &WrapOptions::default()), FileClass::Skip(SkipReason::Binary));
+    }
+
+    #[test]
+    fn test_text_with_odd_extension_encoded() {
+        let dir = tempdir().unwrap();
+        let svg = dir.path().join("icon.svg");
+        std::fs::write(&svg, b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>").unwrap();
+        assert_eq!(classify_for_encode(&svg, &WrapOptions::default()), FileClass::Encode);
+
+        // UTF-16 with a BOM is text despite its null bytes
+        let utf16 = dir.path().join("strings.res");
+        let mut data = vec![0xFF, 0xFE];
+        data.extend("hello".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        std::fs::write(&utf16, data).unwrap();
+        assert_eq!(classify_for_encode(&utf16, &WrapOptions::default()), FileClass::Encode);
+
+        // Accented UTF-8 and ANSI colour codes are not "non-printable"
+        let log = dir.path().join("build.out");
+        std::fs::write(&log, "\x1b[1;32mréussi\x1b[0m ✓\n".repeat(20)).unwrap();
+        assert_eq!(classify_for_encode(&log, &WrapOptions::default()), FileClass::Encode);
+    }
+
+    #[test]
+    fn test_max_size_skips_large_files() {
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("small.log");
+        let large = dir.path().join("large.log");
+        std::fs::write(&small, b"ok\n").unwrap();
+        std::fs::write(&large, "line\n".repeat(100)).unwrap();
+
+        let opts = WrapOptions { max_size: Some(100), ..Default::default() };
+        assert_eq!(classify_for_encode(&small, &opts), FileClass::Encode);
+        assert_eq!(classify_for_encode(&large, &opts), FileClass::Skip(SkipReason::TooLarge));
+
+        let dna = dir.path().join("large.log.dna");
+        std::fs::write(&dna, "ATGC".repeat(100)).unwrap();
+        assert_eq!(classify_for_decode(&dna, &opts), FileClass::Skip(SkipReason::TooLarge));
+    }
+
+    #[test]
+    fn test_include_binary_with_size_cap() {
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("logo.png");
+        let large = dir.path().join("video.mp4");
+        std::fs::write(&small, [0u8; 16]).unwrap();
+        std::fs::write(&large, [0u8; 64]).unwrap();
+
+        let all = WrapOptions { include_binary: true, ..Default::default() };
+        assert_eq!(classify_for_encode(&small, &all), FileClass::Encode);
+        assert_eq!(classify_for_encode(&large, &all), FileClass::Encode);
+
+        let capped = WrapOptions { binary_max_size: Some(32), ..all };
+        assert_eq!(classify_for_encode(&small, &capped), FileClass::Encode);
+        assert_eq!(classify_for_encode(&large, &capped), FileClass::Skip(SkipReason::Binary));
+    }
+
+    #[test]
+    fn test_payloads_detected_by_content() {
+        let dir = tempdir().unwrap();
+        let payload = crate::encoding::encode_raw(b"secret", "classify", Some(10)).unwrap();
+        let opts = WrapOptions::default();
+
+        // A renamed payload is not encoded again
+        let renamed = dir.path().join("notes.txt");
+        std::fs::write(&renamed, &payload).unwrap();
+        assert_eq!(classify_for_encode(&renamed, &opts), FileClass::Skip(SkipReason::AlreadyDna));
+
+        // A double-encoded file decodes once, and its output is flagged
+        let nested = dir.path().join("x.dna.dna");
+        std::fs::write(&nested, &payload).unwrap();
+        assert_eq!(classify_for_decode(&nested, &opts), FileClass::Decode);
+        assert!(is_payload_file(&nested));
+
+        // A genomics file that merely ends in .dna is data, not a payload
+        let genome = dir.path().join("chr21.dna");
+        std::fs::write(&genome, "GATTACA\n".repeat(20)).unwrap();
+        assert_eq!(classify_for_decode(&genome, &opts), FileClass::Skip(SkipReason::NotPayload));
+        assert_eq!(classify_for_encode(&genome, &opts), FileClass::Encode);
+    }
+
+    #[test]
+    fn test_refer_beds_are_payloads() {
+        let dir = tempdir().unwrap();
+        let opts = WrapOptions::default();
+        let referred = dir.path().join("main.rs.bed");
+        let peaks = dir.path().join("peaks.bed");
+        std::fs::write(&referred, "##dendec-refer v0.1.0\n##assembly=GCF_000001405.40 hg38\n").unwrap();
+        std::fs::write(&peaks, "chr1\t100\t200\tpeak1\t0\t+\n").unwrap();
+
+        assert_eq!(classify_for_decode(&referred, &opts), FileClass::Decode);
+        assert_eq!(classify_for_encode(&referred, &opts), FileClass::Skip(SkipReason::AlreadyDna));
+        // Someone else's BED stays data
+        assert_eq!(classify_for_decode(&peaks, &opts), FileClass::Skip(SkipReason::NotDna));
+        assert_eq!(classify_for_encode(&peaks, &opts), FileClass::Encode);
+    }
+
+    #[test]
+    fn test_sniff_decodes_renamed_payloads() {
+        let dir = tempdir().unwrap();
+        let smuggled = dir.path().join("notes.txt");
+        let plain = dir.path().join("readme.txt");
+        std::fs::write(&smuggled, crate::encoding::encode_raw(b"x", "sniff", None).unwrap()).unwrap();
+        std::fs::write(&plain, b"just text").unwrap();
+
+        assert_eq!(classify_for_decode(&smuggled, &WrapOptions::default()), FileClass::Skip(SkipReason::NotDna));
+        let sniff = WrapOptions { sniff: true, ..Default::default() };
+        assert_eq!(classify_for_decode(&smuggled, &sniff), FileClass::Decode);
+        assert_eq!(classify_for_decode(&plain, &sniff), FileClass::Skip(SkipReason::NotDna));
     }
 
     #[test]
@@ -195,6 +485,6 @@ mod tests {
         let dir = tempdir().unwrap();
         let path = dir.path().join("bin.dat");
         std::fs::write(&path, b"hello\x00world").unwrap();
-        assert_eq!(classify_for_encode(&path), FileClass::Skip(SkipReason::Binary));
+        assert_eq!(classify_for_encode(&path, &WrapOptions::default()), FileClass::Skip(SkipReason::Binary));
     }
 }
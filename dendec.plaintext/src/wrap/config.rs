@@ -0,0 +1,101 @@
+/// wrap/config.rs — Project configuration for wrap classification
+///
+/// The built-in skip lists (excluded directory names and known binary
+/// extensions, see classify.rs) suit most projects, but monorepos keep
+/// generated trees under names like `vendor/` or `out/`, and some teams
+/// have their own binary formats. Rather than patching the source, list
+/// them in `.dendec.toml`:
+///
+///   [wrap]
+///   exclude_dirs = ["vendor", "out"]
+///   binary_extensions = ["psd", "blend"]
+///
+/// The file is looked up from the wrap root upward, and the nearest one
+/// wins, so a config at the top of a monorepo also covers a wrap of one
+/// of its packages. Entries extend the built-in lists; the `--exclude-dir`
+/// and `--binary-ext` flags extend them further. The config file is never
+/// transformed itself.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::{DendecError, Result};
+
+/// File name of the project config.
+pub const CONFIG_NAME: &str = ".dendec.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    wrap: WrapConfig,
+}
+
+/// The `[wrap]` section of `.dendec.toml`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WrapConfig {
+    /// Directory names skipped wherever they appear, like `.git`
+    #[serde(default)]
+    pub exclude_dirs: Vec<String>,
+    /// Extensions treated as binary without reading the file
+    #[serde(default)]
+    pub binary_extensions: Vec<String>,
+}
+
+/// Find the nearest `.dendec.toml` at or above `root`.
+pub fn find(root: &Path) -> Option<PathBuf> {
+    root.ancestors()
+        .map(|dir| dir.join(CONFIG_NAME))
+        .find(|p| p.is_file())
+}
+
+/// Load the config that applies to `root`, with the path it came from.
+/// `Ok(None)` if there is none.
+pub fn load(root: &Path) -> Result<Option<(PathBuf, WrapConfig)>> {
+    let Some(path) = find(root) else {
+        return Ok(None);
+    };
+    let text = fs::read_to_string(&path).map_err(DendecError::Io)?;
+    let file: ConfigFile = toml::from_str(&text).map_err(|e| DendecError::WrapConfig {
+        path: path.clone(),
+        reason: e.message().to_string(),
+    })?;
+    Ok(Some((path, file.wrap)))
+}
+
+/// True if `path` is a project config — never itself transformed.
+pub fn is_config(path: &Path) -> bool {
+    path.file_name().map(|n| n == CONFIG_NAME).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_config_found_in_parent() {
+        let dir = tempdir().unwrap();
+        let pkg = dir.path().join("packages/app");
+        fs::create_dir_all(&pkg).unwrap();
+        fs::write(
+            dir.path().join(CONFIG_NAME),
+            "[wrap]\nexclude_dirs = [\"vendor\", \"out\"]\nbinary_extensions = [\"psd\"]\n",
+        )
+        .unwrap();
+
+        let (path, cfg) = load(&pkg).unwrap().unwrap();
+        assert_eq!(path, dir.path().join(CONFIG_NAME));
+        assert_eq!(cfg.exclude_dirs, vec!["vendor", "out"]);
+        assert_eq!(cfg.binary_extensions, vec!["psd"]);
+    }
+
+    #[test]
+    fn test_unknown_key_rejected() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(CONFIG_NAME), "[wrap]\nexclude_dir = [\"vendor\"]\n").unwrap();
+        assert!(matches!(load(dir.path()), Err(DendecError::WrapConfig { .. })));
+    }
+}
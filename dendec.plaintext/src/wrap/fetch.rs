@@ -1,20 +1,51 @@
 /// wrap/fetch.rs — Command execution and output detection
 ///
-/// Runs the user-supplied command as a subprocess, waits for it to finish,
-/// and returns the working directory so the snapshot diff can find what
-/// was produced. Also handles stdout-capturing for commands like curl that
-/// write to stdout rather than disk.
-use std::path::PathBuf;
+/// Runs the user-supplied command as a subprocess and waits for it to
+/// finish. Also handles stdout-capturing for commands like curl that
+/// write to stdout rather than disk, and infers where known commands put
+/// their output so the snapshot only has to walk that part of the tree.
+///
+/// The child inherits dendec's environment plus any `--env KEY=VAL`
+/// pairs. `--clean-env` starts it from an allowlist instead (see
+/// `KEPT_ENV`), so CI secrets in the parent do not leak into a fetch.
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
+use log::info;
+
 use crate::error::{DendecError, Result};
+use crate::wrap::WrapOptions;
+
+/// rsync options that take a separate value (`-e ssh`, `--exclude PAT`)
+const RSYNC_SHORT_VALUES: &str = "efTBM";
+const RSYNC_LONG_VALUES: &[&str] = &[
+    "rsh", "rsync-path", "filter", "exclude", "include", "exclude-from", "include-from",
+    "files-from", "log-file", "log-file-format", "password-file", "port", "timeout",
+    "contimeout", "partial-dir", "temp-dir", "compare-dest", "copy-dest", "link-dest",
+    "backup-dir", "suffix", "chmod", "chown", "bwlimit", "max-size", "min-size",
+    "block-size", "remote-option", "out-format", "info", "debug", "iconv", "usermap",
+    "groupmap", "skip-compress", "modify-window", "max-delete", "address",
+    "checksum-choice", "compress-choice", "compress-level", "sockopts",
+];
+
+/// scp and sftp options that take a value (`-P 2222`, `-i key`)
+const SCP_SHORT_VALUES: &str = "cDFiJloPSX";
+const SFTP_SHORT_VALUES: &str = "BbcDFiJloPRSX";
+
+/// docker options before the subcommand that take a value (`-H host`)
+const DOCKER_SHORT_VALUES: &str = "cHl";
+const DOCKER_LONG_VALUES: &[&str] = &["config", "context", "host", "log-level", "tlscacert", "tlscert", "tlskey"];
+
+/// Variables a `--clean-env` child still gets: enough to find programs,
+/// resolve `~`, and print in the user's locale.
+const KEPT_ENV: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "TERM", "LANG", "LC_ALL", "TMPDIR", "TZ",
+    // Windows cannot start most programs without these
+    "SYSTEMROOT", "WINDIR", "COMSPEC", "PATHEXT", "TEMP", "TMP", "USERPROFILE",
+];
 
 /// Result of running a wrapped command.
 pub struct FetchResult {
-    /// Directory to scan for produced files.
-    /// This is a future rollout feature, now a dead code 
-    #[allow(dead_code)]
-    pub scan_root: PathBuf,
     /// Raw stdout bytes if the command wrote to stdout (curl without -o, etc.)
     pub stdout_bytes: Option<Vec<u8>>,
 }
@@ -25,22 +56,25 @@ pub struct FetchResult {
 /// will appear in the snapshot diff. If the command writes to stdout
 /// (bare curl, cat, etc.) the bytes are captured and returned separately
 /// so the caller can decode them directly.
-pub fn run_command(args: &[String], capture_stdout: bool) -> Result<FetchResult> {
+///
+/// With `opts.shell`, the arguments are joined into one string and run by
+/// the platform shell, so pipelines and redirections work. `opts.env` and
+/// `opts.clean_env` shape the child's environment. With `opts.pty`, the
+/// command runs under a pseudo-terminal so it can prompt (see pty.rs).
+pub fn run_command(args: &[String], capture_stdout: bool, opts: &WrapOptions) -> Result<FetchResult> {
     if args.is_empty() {
         return Err(DendecError::WrapNoFilesFound);
     }
 
-    let program = &args[0];
-    let rest = &args[1..];
+    info!("  Running: {}", args.join(" "));
 
-    let scan_root = std::env::current_dir().map_err(DendecError::Io)?;
-
-    eprintln!("  Running: {}", args.join(" "));
+    if opts.pty {
+        return run_with_pty(args, capture_stdout, opts);
+    }
 
     if capture_stdout {
         // Capture stdout — used when command is expected to write to stdout
-        let output = Command::new(program)
-            .args(rest)
+        let output = build_command(args, opts)
             .stdout(Stdio::piped())
             .stderr(Stdio::inherit()) // let stderr through so user sees progress
             .output()
@@ -49,13 +83,11 @@ pub fn run_command(args: &[String], capture_stdout: bool) -> Result<FetchResult>
         check_exit(&output.status, args)?;
 
         Ok(FetchResult {
-            scan_root,
             stdout_bytes: Some(output.stdout),
         })
     } else {
         // Inherit stdout — command writes to disk (git clone, wget, etc.)
-        let status = Command::new(program)
-            .args(rest)
+        let status = build_command(args, opts)
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .status()
@@ -63,13 +95,58 @@ pub fn run_command(args: &[String], capture_stdout: bool) -> Result<FetchResult>
 
         check_exit(&status, args)?;
 
-        Ok(FetchResult {
-            scan_root,
-            stdout_bytes: None,
-        })
+        Ok(FetchResult { stdout_bytes: None })
     }
 }
 
+#[cfg(unix)]
+fn run_with_pty(args: &[String], capture_stdout: bool, opts: &WrapOptions) -> Result<FetchResult> {
+    let (status, stdout_bytes) =
+        super::pty::run_in_pty(build_command(args, opts), capture_stdout).map_err(DendecError::Io)?;
+    check_exit(&status, args)?;
+    Ok(FetchResult { stdout_bytes })
+}
+
+#[cfg(not(unix))]
+fn run_with_pty(_args: &[String], _capture_stdout: bool, _opts: &WrapOptions) -> Result<FetchResult> {
+    Err(DendecError::WrapPtyUnsupported)
+}
+
+/// Build the process for `args`: exec'd directly, or via `sh -c`
+/// (`cmd /C` on Windows) when `opts.shell` is set, with its environment.
+fn build_command(args: &[String], opts: &WrapOptions) -> Command {
+    let mut cmd = if opts.shell {
+        let line = args.join(" ");
+        let mut cmd = if cfg!(windows) {
+            let mut c = Command::new("cmd");
+            c.arg("/C");
+            c
+        } else {
+            let mut c = Command::new("sh");
+            c.arg("-c");
+            c
+        };
+        cmd.arg(line);
+        cmd
+    } else {
+        let mut cmd = Command::new(&args[0]);
+        cmd.args(&args[1..]);
+        cmd
+    };
+
+    if opts.clean_env {
+        cmd.env_clear();
+        for (key, value) in std::env::vars_os() {
+            let upper = key.to_string_lossy().to_ascii_uppercase();
+            if KEPT_ENV.contains(&upper.as_str()) {
+                cmd.env(key, value);
+            }
+        }
+    }
+    cmd.envs(opts.env.iter().map(|(k, v)| (k, v)));
+    cmd
+}
+
 fn check_exit(status: &std::process::ExitStatus, args: &[String]) -> Result<()> {
     if !status.success() {
         return Err(DendecError::WrapCommandFailed {
@@ -96,11 +173,270 @@ pub fn writes_to_disk(args: &[String]) -> bool {
         "curl" => args
             .iter()
             .any(|a| a == "-o" || a == "--output" || a == "-O"),
+        // archive tools extract to disk unless asked to print members
+        "tar" => !(tar_has_flag(args, 'O') || args.iter().any(|a| a == "--to-stdout")),
+        "unzip" => !args.iter().skip(1).any(|a| a == "-p" || a == "-c"),
+        // rsync with only a source lists it on stdout
+        "rsync" => {
+            !args.iter().any(|a| a == "--list-only")
+                && operands(args, RSYNC_SHORT_VALUES, RSYNC_LONG_VALUES).len() >= 2
+        }
+        // remote copies land in a local file or directory
+        "scp" | "sftp" => true,
+        // docker cp to `-`, and save or export without -o, write a tar to stdout
+        "docker" => match docker_subcommand(args) {
+            Some(("cp", rest)) => operands(rest, "", &[]).get(1) != Some(&"-"),
+            Some(("save" | "export", rest)) => flag_value(rest, "-o", "--output").is_some_and(|v| v != "-"),
+            _ => true,
+        },
         // conservative default: assume disk
         _ => true,
     }
 }
 
+/// Infer where a command will write, relative to the working directory.
+///
+/// Walking only this path keeps the before/after snapshots cheap and
+/// stops unrelated changes elsewhere in the tree from being picked up.
+/// The result may be a file (curl -o) — a snapshot of a file path holds
+/// just that file. `None` means the command is unknown and the whole
+/// working directory must be scanned.
+///
+///   git clone <url> [dir]         → the clone directory
+///   curl -o FILE / --output FILE  → FILE
+///   curl --output-dir DIR         → DIR
+///   wget -O FILE / --output-document=FILE → FILE
+///   wget -P DIR / --directory-prefix=DIR  → DIR
+///   tar -x … -C DIR, unzip … -d DIR, 7z x … -oDIR → DIR (see archive_target)
+///   rsync/scp … SRC DEST, sftp SRC DEST → DEST (see remote_copy_target)
+///   docker cp CTR:SRC DEST, docker save/export -o FILE → DEST or FILE
+///                                 (see docker_target)
+pub fn scan_root_for(args: &[String]) -> Option<PathBuf> {
+    let program = args.first().map(|s| s.as_str()).unwrap_or("");
+    match program {
+        "git" if args.get(1).map(|s| s == "clone").unwrap_or(false) => git_clone_target(args),
+        "tar" | "unzip" | "7z" | "7za" | "7zz" => archive_target(args),
+        "rsync" | "scp" | "sftp" => remote_copy_target(args),
+        "docker" => docker_target(args),
+        "curl" => flag_value(args, "-o", "--output")
+            .or_else(|| flag_value(args, "", "--output-dir"))
+            .map(PathBuf::from),
+        "wget" => flag_value(args, "-O", "--output-document")
+            .or_else(|| flag_value(args, "-P", "--directory-prefix"))
+            .filter(|v| *v != "-")
+            .map(PathBuf::from),
+        _ => None,
+    }
+}
+
+/// Value of a flag given as `-s VALUE`, `--long VALUE`, or `--long=VALUE`.
+fn flag_value<'a>(args: &'a [String], short: &str, long: &str) -> Option<&'a str> {
+    args.iter().enumerate().find_map(|(i, a)| {
+        if (!short.is_empty() && a == short) || (!long.is_empty() && a == long) {
+            args.get(i + 1).map(|s| s.as_str())
+        } else if !long.is_empty() {
+            a.strip_prefix(long).and_then(|rest| rest.strip_prefix('='))
+        } else {
+            None
+        }
+    })
+}
+
+/// Directory that owns a scan root: the root itself, or the parent of a
+/// file root. The manifest and ignore rules are anchored there.
+pub fn scan_root_dir(scan_root: &Path) -> PathBuf {
+    if scan_root.is_dir() {
+        return scan_root.to_path_buf();
+    }
+    match scan_root.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Extract the target directory of an archive extraction.
+///
+/// `tar -xzf a.tar.gz -C out`  → `out`   (also `--directory[=]out`)
+/// `unzip a.zip -d out`        → `out`
+/// `7z x a.7z -oout`           → `out`
+///
+/// Returns `None` when the command is not an extraction or extracts into
+/// the working directory, which then has to be scanned as a whole.
+pub fn archive_target(args: &[String]) -> Option<PathBuf> {
+    let program = args.first().map(|s| s.as_str()).unwrap_or("");
+    let dir = match program {
+        "tar" => {
+            let extracts = tar_has_flag(args, 'x')
+                || args.iter().any(|a| a == "--extract" || a == "--get");
+            if !extracts {
+                return None;
+            }
+            flag_value(args, "-C", "--directory")
+        }
+        "unzip" => flag_value(args, "-d", ""),
+        "7z" | "7za" | "7zz" => {
+            if !matches!(args.get(1).map(|s| s.as_str()), Some("x") | Some("e")) {
+                return None;
+            }
+            args.iter()
+                .skip(2)
+                .find_map(|a| a.strip_prefix("-o"))
+                .filter(|d| !d.is_empty())
+        }
+        _ => None,
+    }?;
+    Some(PathBuf::from(dir))
+}
+
+/// Extract the local destination of a remote copy.
+///
+/// `rsync -av -e ssh host:src/ mirror`   → `mirror`
+/// `scp -P 2222 host:a.txt host:b.txt out` → `out`
+/// `sftp host:backups/db.sql restore`    → `restore`
+///
+/// Returns `None` for uploads (the destination is remote), for rsync
+/// listings, and for an sftp fetch into the working directory.
+pub fn remote_copy_target(args: &[String]) -> Option<PathBuf> {
+    let program = args.first().map(|s| s.as_str()).unwrap_or("");
+    let operands = match program {
+        "rsync" => operands(args, RSYNC_SHORT_VALUES, RSYNC_LONG_VALUES),
+        "scp" => operands(args, SCP_SHORT_VALUES, &[]),
+        "sftp" => operands(args, SFTP_SHORT_VALUES, &[]),
+        _ => return None,
+    };
+    let dest = match program {
+        // sftp host:path [local-dir]
+        "sftp" => operands.get(1)?,
+        // rsync/scp SRC... DEST
+        _ if operands.len() >= 2 => operands.last()?,
+        _ => return None,
+    };
+    if is_remote(dest) || matches!(*dest, "." | "./") {
+        return None;
+    }
+    Some(PathBuf::from(dest))
+}
+
+/// Extract where a docker command puts a container artifact on the host.
+///
+/// `docker cp web:/var/log/app logs`       → `logs`
+/// `docker cp web:/etc/nginx .`            → `nginx`
+/// `docker save -o images/app.tar app:1.2` → `images/app.tar`
+/// `docker container export --output=fs.tar web` → `fs.tar`
+///
+/// Returns `None` for copies into a container or to stdout, for a copy
+/// of a directory's contents (`SRC/.`) into `.`, for save and export
+/// without `-o` (their tar is captured from stdout), and for every other
+/// subcommand.
+pub fn docker_target(args: &[String]) -> Option<PathBuf> {
+    let (verb, rest) = docker_subcommand(args)?;
+    match verb {
+        "cp" => {
+            let operands = operands(rest, "", &[]);
+            let [src, dest] = operands[..] else { return None };
+            if dest == "-" || is_remote(dest) {
+                return None;
+            }
+            if !matches!(dest, "." | "./") {
+                return Some(PathBuf::from(dest));
+            }
+            // Into `.`, the copy keeps the source's own name
+            let (_, path) = src.split_once(':')?;
+            if path.trim_end_matches('/').ends_with("/.") {
+                return None;
+            }
+            Path::new(path).file_name().map(PathBuf::from)
+        }
+        "save" | "export" => flag_value(rest, "-o", "--output").filter(|v| *v != "-").map(PathBuf::from),
+        _ => None,
+    }
+}
+
+/// The docker subcommand and its arguments, the subcommand first, past
+/// any global options. `container cp` and `image save` become `cp` and
+/// `save`, as the management commands are aliases.
+fn docker_subcommand(args: &[String]) -> Option<(&str, &[String])> {
+    let mut i = 1;
+    while let Some(a) = args.get(i) {
+        if let Some(long) = a.strip_prefix("--") {
+            if DOCKER_LONG_VALUES.contains(&long) {
+                i += 1;
+            }
+        } else if let Some(short) = a.strip_prefix('-') {
+            if short.len() == 1 && DOCKER_SHORT_VALUES.contains(short) {
+                i += 1;
+            }
+        } else {
+            break;
+        }
+        i += 1;
+    }
+    if matches!(args.get(i).map(|s| s.as_str()), Some("container" | "image")) {
+        i += 1;
+    }
+    Some((args.get(i)?.as_str(), &args[i..]))
+}
+
+/// Operands of a getopt-style command line: every argument that is not
+/// an option or an option's value. `short_values` lists the one-letter
+/// options that take a value, `long_values` the long ones (which may also
+/// be written `--opt=value`). `--` ends the options.
+fn operands<'a>(args: &'a [String], short_values: &str, long_values: &[&str]) -> Vec<&'a str> {
+    let mut out = Vec::new();
+    let mut rest = args.iter().skip(1);
+    while let Some(a) = rest.next() {
+        if a == "--" {
+            out.extend(rest.map(|s| s.as_str()));
+            break;
+        }
+        if let Some(long) = a.strip_prefix("--") {
+            if long_values.contains(&long) {
+                rest.next();
+            }
+        } else if let Some(bundle) = a.strip_prefix('-').filter(|b| !b.is_empty()) {
+            // `-rP 2222` takes the next argument; `-P2222` carries its own
+            if let Some(i) = bundle.find(|c| short_values.contains(c)) {
+                if i == bundle.len() - 1 {
+                    rest.next();
+                }
+            }
+        } else {
+            out.push(a.as_str());
+        }
+    }
+    out
+}
+
+/// `host:path`, `user@host:path`, `host::module`, or a URL — a path the
+/// copy reaches over the network. A colon after a slash, or after a
+/// Windows drive letter, belongs to a local path.
+fn is_remote(arg: &str) -> bool {
+    if arg.contains("://") {
+        return true;
+    }
+    match arg.find(':') {
+        Some(1) if arg.as_bytes()[0].is_ascii_alphabetic() => false,
+        Some(i) => !arg[..i].contains('/'),
+        None => false,
+    }
+}
+
+/// Does a tar invocation set the one-letter option `flag`?
+///
+/// tar bundles short options: `-xzf`, or old-style `xzf` as the first
+/// argument with no dash. Only bundles are inspected, never file names.
+fn tar_has_flag(args: &[String], flag: char) -> bool {
+    args.iter().skip(1).enumerate().any(|(i, a)| {
+        let bundle = match a.strip_prefix('-') {
+            Some(rest) if !rest.starts_with('-') => rest,
+            Some(_) => return false,
+            None if i == 0 => a.as_str(),
+            None => return false,
+        };
+        bundle.chars().all(|c| c.is_ascii_alphabetic()) && bundle.contains(flag)
+    })
+}
+
 /// Extract the target directory name for git clone.
 ///
 /// `git clone https://github.com/user/repo` → `repo`
@@ -132,3 +468,157 @@ pub fn git_clone_target(args: &[String]) -> Option<PathBuf> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_scan_root_for_known_commands() {
+        assert_eq!(
+            scan_root_for(&args("git clone https://github.com/user/repo.git")),
+            Some(PathBuf::from("repo"))
+        );
+        assert_eq!(
+            scan_root_for(&args("curl -L -o out/config.toml https://x/config.toml")),
+            Some(PathBuf::from("out/config.toml"))
+        );
+        assert_eq!(
+            scan_root_for(&args("wget --directory-prefix=dl https://x/a.txt")),
+            Some(PathBuf::from("dl"))
+        );
+        assert_eq!(scan_root_for(&args("wget -O - https://x/a.txt")), None);
+        assert_eq!(scan_root_for(&args("make all")), None);
+    }
+
+    #[test]
+    fn test_archive_targets() {
+        assert_eq!(
+            archive_target(&args("tar -xzf src.tar.gz -C vendor")),
+            Some(PathBuf::from("vendor"))
+        );
+        assert_eq!(
+            archive_target(&args("tar xf src.tar --directory=vendor")),
+            Some(PathBuf::from("vendor"))
+        );
+        assert_eq!(
+            archive_target(&args("unzip -q assets.zip -d assets")),
+            Some(PathBuf::from("assets"))
+        );
+        assert_eq!(archive_target(&args("7z x data.7z -oout")), Some(PathBuf::from("out")));
+
+        // Creating an archive, or extracting into cwd, has no narrower target
+        assert_eq!(archive_target(&args("tar -czf out.tar.gz -C vendor .")), None);
+        assert_eq!(archive_target(&args("tar -xf archive-x.tar")), None);
+        assert_eq!(archive_target(&args("7z a data.7z -oout")), None);
+    }
+
+    #[test]
+    fn test_remote_copy_targets() {
+        assert_eq!(
+            scan_root_for(&args("rsync -av -e ssh --exclude .cache host:src/ mirror")),
+            Some(PathBuf::from("mirror"))
+        );
+        assert_eq!(
+            scan_root_for(&args("scp -rP 2222 -i id_ed25519 user@host:a.txt host:b.txt out")),
+            Some(PathBuf::from("out"))
+        );
+        assert_eq!(
+            scan_root_for(&args("sftp -b batch host:backups/db.sql restore")),
+            Some(PathBuf::from("restore"))
+        );
+        assert_eq!(
+            scan_root_for(&args("rsync -a ./vendor/lib:v2 backup")),
+            Some(PathBuf::from("backup"))
+        );
+
+        // Uploads, fetches into cwd, and listings have no narrower target
+        assert_eq!(scan_root_for(&args("scp notes.txt host:notes.txt")), None);
+        assert_eq!(scan_root_for(&args("rsync -a src/ rsync://host/module/")), None);
+        assert_eq!(scan_root_for(&args("scp host:a.txt .")), None);
+        assert_eq!(scan_root_for(&args("sftp host:backups/db.sql")), None);
+        assert!(!writes_to_disk(&args("rsync host:src/")));
+        assert!(!writes_to_disk(&args("rsync --list-only host:src/ x")));
+        assert!(writes_to_disk(&args("rsync -a host:src/ x")));
+    }
+
+    #[test]
+    fn test_docker_targets() {
+        assert_eq!(
+            scan_root_for(&args("docker cp -a web:/var/log/app logs")),
+            Some(PathBuf::from("logs"))
+        );
+        assert_eq!(
+            scan_root_for(&args("docker --context prod container cp web:/etc/nginx/ .")),
+            Some(PathBuf::from("nginx"))
+        );
+        assert_eq!(
+            scan_root_for(&args("docker save -o images/app.tar app:1.2 app:1.3")),
+            Some(PathBuf::from("images/app.tar"))
+        );
+        assert_eq!(
+            scan_root_for(&args("docker -H ssh://build container export --output=fs.tar web")),
+            Some(PathBuf::from("fs.tar"))
+        );
+
+        // Copies in, contents into cwd, and stdout have no narrower target
+        assert_eq!(scan_root_for(&args("docker cp config.yml web:/etc/app/")), None);
+        assert_eq!(scan_root_for(&args("docker cp web:/srv/data/. .")), None);
+        assert_eq!(scan_root_for(&args("docker cp web:/srv/data -")), None);
+        assert_eq!(scan_root_for(&args("docker image save app:1.2")), None);
+        assert!(!writes_to_disk(&args("docker cp web:/srv/data -")));
+        assert!(!writes_to_disk(&args("docker save app:1.2")));
+        assert!(!writes_to_disk(&args("docker export -o - web")));
+        assert!(writes_to_disk(&args("docker image save --output app.tar app:1.2")));
+        assert!(writes_to_disk(&args("docker cp web:/srv/data out")));
+    }
+
+    #[test]
+    fn test_archive_to_stdout_is_not_disk() {
+        assert!(writes_to_disk(&args("tar -xf a.tar")));
+        assert!(!writes_to_disk(&args("tar -xOf a.tar notes.txt")));
+        assert!(!writes_to_disk(&args("unzip -p a.zip notes.txt")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shell_runs_pipeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("piped.txt");
+        let line = format!("printf 'a\\nb\\n' | wc -l > '{}'", out.display());
+        let shell = WrapOptions { shell: true, ..Default::default() };
+        run_command(&[line], false, &shell).unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap().trim(), "2");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_env_and_clean_env() {
+        // cargo sets CARGO_MANIFEST_DIR for the test process
+        let check = |script: &str, opts: WrapOptions| {
+            let opts = WrapOptions { shell: true, ..opts };
+            run_command(&[script.to_string()], true, &opts).is_ok()
+        };
+        let token = vec![("DENDEC_TEST_TOKEN".to_string(), "s3cret".to_string())];
+
+        assert!(check(r#"[ -n "$CARGO_MANIFEST_DIR" ]"#, WrapOptions::default()));
+        assert!(check(
+            r#"[ "$DENDEC_TEST_TOKEN" = s3cret ] && [ -n "$CARGO_MANIFEST_DIR" ]"#,
+            WrapOptions { env: token.clone(), ..Default::default() }
+        ));
+        assert!(check(
+            r#"[ "$DENDEC_TEST_TOKEN" = s3cret ] && [ -z "$CARGO_MANIFEST_DIR" ] && [ -n "$PATH" ]"#,
+            WrapOptions { env: token, clean_env: true, ..Default::default() }
+        ));
+    }
+
+    #[test]
+    fn test_scan_root_dir_of_file() {
+        assert_eq!(scan_root_dir(Path::new("out/config.toml")), PathBuf::from("out"));
+        assert_eq!(scan_root_dir(Path::new("config.toml")), PathBuf::from("."));
+    }
+}
@@ -0,0 +1,98 @@
+/// wrap/hooks.rs — Per-file commands around each transform
+///
+/// `--pre-hook CMD` runs before a file is encoded or decoded, on the file
+/// about to be read; `--post-hook CMD` runs after, on the file just
+/// written. Typical uses are normalising a source before it is encrypted
+/// (`sed -i 's/[ \t]*$//'`) and fixing up results (`chmod 600`).
+///
+/// CMD goes through the shell like `wrap -c` (`sh -c`, or `cmd /C` on
+/// Windows). The file it is for is passed as `$1` (Unix) and, on every
+/// platform, in the environment:
+///
+///   DENDEC_FILE   the file the hook is for
+///   DENDEC_HOOK   pre | post
+///   DENDEC_MODE   encode | decode
+///
+/// A pre-hook that exits non-zero fails the file: it is not transformed
+/// and its source stays. A post-hook runs once the output is in place, so
+/// its failure only warns. Hooks may change their file; what is recorded
+/// in the manifest is the content after them. Their output is captured,
+/// and the last line of stderr is shown if they fail.
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use log::debug;
+
+use crate::error::{DendecError, Result};
+
+/// When a hook runs relative to the transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    Pre,
+    Post,
+}
+
+impl HookStage {
+    fn name(self) -> &'static str {
+        match self {
+            HookStage::Pre => "pre",
+            HookStage::Post => "post",
+        }
+    }
+}
+
+/// Run the hook `cmd` for `path` and wait for it.
+pub fn run_hook(cmd: &str, stage: HookStage, path: &Path, encode_mode: bool) -> Result<()> {
+    let mut command = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg(cmd);
+        c
+    } else {
+        // $0 is "sh", as for any `sh -c` script; $1 is the file
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(cmd).arg("sh").arg(path);
+        c
+    };
+    command
+        .env("DENDEC_FILE", path)
+        .env("DENDEC_HOOK", stage.name())
+        .env("DENDEC_MODE", if encode_mode { "encode" } else { "decode" })
+        .stdin(Stdio::null());
+
+    debug!("  {} hook on {}: {cmd}", stage.name(), path.display());
+    let failed = |reason: String| DendecError::WrapHookFailed { hook: stage.name(), reason };
+    let output = command.output().map_err(|e| failed(format!("cannot run '{cmd}': {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let code = output
+            .status
+            .code()
+            .map_or("killed by a signal".to_string(), |c| format!("exit code {c}"));
+        return Err(failed(match stderr.lines().rev().find(|l| !l.trim().is_empty()) {
+            Some(last) => format!("{code}: {}", last.trim()),
+            None => code,
+        }));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_hook_gets_path_and_env() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a b.txt");
+        fs::write(&file, b"x").unwrap();
+
+        let cmd = r#"[ "$1" = "$DENDEC_FILE" ] && echo "$DENDEC_HOOK $DENDEC_MODE" > "$1""#;
+        run_hook(cmd, HookStage::Post, &file, false).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "post decode\n");
+
+        let err = run_hook("echo nope >&2; exit 3", HookStage::Pre, &file, true).unwrap_err();
+        assert_eq!(err.to_string(), "pre-hook failed: exit code 3: nope");
+    }
+}
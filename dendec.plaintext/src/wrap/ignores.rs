@@ -0,0 +1,111 @@
+/// wrap/ignores.rs — .gitignore and .dendecignore support for encode
+///
+/// Build artifacts and local junk listed in a project's ignore files
+/// should not be encrypted into .dna files. Before an encode, the wrap
+/// root is walked with gitignore semantics (the `ignore` crate, as used
+/// by ripgrep), and any candidate file the walk does not yield is left
+/// alone. Rules come from:
+///
+///   .gitignore        in the root, its parents, and every subdirectory
+///   .git/info/exclude of the enclosing repository
+///   .dendecignore     same syntax, for rules that only apply to dendec
+///
+/// A .gitignore is honoured even outside a git repository. The user's
+/// global git excludes file is not read, so results do not depend on
+/// who runs the wrap. Decode ignores all of this: every .dna is restored.
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+/// Project-level ignore file read only by dendec.
+pub const DENDECIGNORE: &str = ".dendecignore";
+
+/// Split `files` into those to encode and the number excluded by ignore
+/// rules under `root`. `.dendecignore` files themselves are configuration
+/// and are always excluded.
+pub fn filter_ignored(root: &Path, files: Vec<PathBuf>) -> (Vec<PathBuf>, usize) {
+    let allowed = unignored_files(root);
+    let before = files.len();
+    let kept: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|p| allowed.contains(p) && !is_dendecignore(p))
+        .collect();
+    let excluded = before - kept.len();
+    (kept, excluded)
+}
+
+/// Every file under `root` that no ignore rule excludes.
+fn unignored_files(root: &Path) -> HashSet<PathBuf> {
+    WalkBuilder::new(root)
+        .hidden(false)
+        .parents(true)
+        .git_ignore(true)
+        .git_exclude(true)
+        .git_global(false)
+        .require_git(false)
+        .add_custom_ignore_filename(DENDECIGNORE)
+        .follow_links(false)
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|e| e.into_path())
+        .collect()
+}
+
+fn is_dendecignore(path: &Path) -> bool {
+    path.file_name().map(|n| n == DENDECIGNORE).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_gitignore_and_dendecignore_respected() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join(".gitignore"), "build/\n*.log\n").unwrap();
+        fs::write(root.join(DENDECIGNORE), "secrets.local\n").unwrap();
+
+        let files: Vec<PathBuf> = [
+            ".gitignore",
+            DENDECIGNORE,
+            "build/out.txt",
+            "debug.log",
+            "secrets.local",
+            "src/main.rs",
+        ]
+        .iter()
+        .map(|f| {
+            let p = root.join(f);
+            if !p.exists() {
+                fs::write(&p, b"x").unwrap();
+            }
+            p
+        })
+        .collect();
+
+        let (kept, excluded) = filter_ignored(root, files);
+        assert_eq!(kept, vec![root.join(".gitignore"), root.join("src/main.rs")]);
+        assert_eq!(excluded, 4);
+    }
+
+    #[test]
+    fn test_nested_gitignore() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::write(root.join("pkg/.gitignore"), "generated.rs\n").unwrap();
+        fs::write(root.join("pkg/generated.rs"), b"x").unwrap();
+        fs::write(root.join("generated.rs"), b"x").unwrap();
+
+        let files = vec![root.join("pkg/generated.rs"), root.join("generated.rs")];
+        let (kept, _) = filter_ignored(root, files);
+        assert_eq!(kept, vec![root.join("generated.rs")]);
+    }
+}
@@ -0,0 +1,224 @@
+/// wrap/journal.rs — Progress journal for resuming interrupted wraps
+///
+/// The manifest is written only once a batch finishes, so while a batch
+/// runs each transformed file is also appended to `.dendec-wrap.partial`
+/// and synced to disk before its source is removed. A wrap cut short
+/// (Ctrl-C, a crash, a full disk) thus leaves a record of the files it
+/// had already transformed:
+///
+///   ##dendec-wrap v1
+///   ##mode encode
+///   ##key <KDF salt hex> <master key fingerprint hex>
+///   src/main.rs<TAB>src/main.rs.dna<TAB><sha256 source><TAB><sha256 output>
+///
+/// Entry lines are the manifest's (see manifest.rs). `##key` names the
+/// master key the run used by its Argon2 salt and `MasterKey::fingerprint`,
+/// which identifies the password without revealing it. It is written with
+/// the first entry that has one; a decode of v1 payloads only has none.
+///
+/// When the batch finishes, the manifest is written and the journal
+/// removed. While a journal remains, wrap refuses to start over in that
+/// directory. `--resume` continues instead: the password is checked
+/// against `##key`, every journaled file whose output is still as written
+/// is skipped (and its source removed, if the interruption came just
+/// before that), and the final manifest covers both runs.
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::crypto::{MasterKey, FINGERPRINT_LEN, SALT_LEN};
+use crate::error::{DendecError, Result};
+use crate::wrap::manifest::{from_hex, header, sha256_file, to_hex, Manifest, ManifestEntry};
+
+/// File name of the journal inside the wrapped directory.
+pub const JOURNAL_NAME: &str = ".dendec-wrap.partial";
+
+/// The master key an interrupted run used, as recorded in `##key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedKey {
+    pub salt: [u8; SALT_LEN],
+    pub fingerprint: [u8; FINGERPRINT_LEN],
+}
+
+impl RecordedKey {
+    /// Check that `master` (derived from the new password and `self.salt`)
+    /// is the key the interrupted run used.
+    pub fn check(&self, master: &MasterKey) -> Result<()> {
+        if master.fingerprint() != self.fingerprint {
+            return Err(DendecError::WrapResume(
+                "the password differs from the one the interrupted wrap used".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What an interrupted run left behind.
+#[derive(Debug)]
+pub struct Interrupted {
+    /// The files it finished, in manifest form
+    pub manifest: Manifest,
+    pub key: Option<RecordedKey>,
+    /// Length of the journal up to its last complete line
+    len: u64,
+}
+
+impl Interrupted {
+    /// Entries whose output is still exactly as the run wrote it. Where
+    /// the source is still there too, it is removed now unless `keep`.
+    pub fn completed(&self, keep: bool) -> Vec<ManifestEntry> {
+        let mut done = Vec::new();
+        for e in &self.manifest.entries {
+            if !unchanged(&e.output, &e.output_sha256) {
+                continue;
+            }
+            if !keep && e.source != e.output && unchanged(&e.source, &e.source_sha256) {
+                if let Err(err) = fs::remove_file(&e.source) {
+                    warn!("  could not remove {}: {err}", e.source.display());
+                }
+            }
+            done.push(e.clone());
+        }
+        done
+    }
+}
+
+/// Append-only record of the batch in progress.
+pub struct Journal {
+    file: File,
+    root: PathBuf,
+    keyed: bool,
+}
+
+impl Journal {
+    /// Path of the journal for a wrap rooted at `root`.
+    pub fn path_in(root: &Path) -> PathBuf {
+        root.join(JOURNAL_NAME)
+    }
+
+    /// Start the journal for a new batch.
+    pub fn start(root: &Path, encode_mode: bool) -> Result<Self> {
+        let file = File::create(Self::path_in(root)).map_err(DendecError::Io)?;
+        let mut journal = Self { file, root: root.to_path_buf(), keyed: false };
+        journal.append(&header(encode_mode))?;
+        Ok(journal)
+    }
+
+    /// Reopen an interrupted run's journal to carry on appending to it,
+    /// dropping any line the interruption cut short.
+    pub fn reopen(root: &Path, interrupted: &Interrupted) -> Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(Self::path_in(root))
+            .map_err(DendecError::Io)?;
+        file.set_len(interrupted.len).map_err(DendecError::Io)?;
+        Ok(Self { file, root: root.to_path_buf(), keyed: interrupted.key.is_some() })
+    }
+
+    /// Record one transformed file, and `key` too if none is recorded yet.
+    pub fn record(&mut self, entry: &ManifestEntry, key: Option<&MasterKey>) -> Result<()> {
+        let mut text = String::new();
+        if let (false, Some(master)) = (self.keyed, key) {
+            text.push_str(&format!(
+                "##key {} {}\n",
+                to_hex(&master.salt),
+                to_hex(&master.fingerprint())
+            ));
+        }
+        text.push_str(&entry.line(&self.root));
+        self.append(&text)?;
+        self.keyed |= key.is_some();
+        Ok(())
+    }
+
+    /// The batch finished: the manifest now holds everything, so the
+    /// journal goes.
+    pub fn finish(self) -> Result<()> {
+        let path = Self::path_in(&self.root);
+        drop(self.file);
+        fs::remove_file(path).map_err(DendecError::Io)
+    }
+
+    fn append(&mut self, text: &str) -> Result<()> {
+        self.file.write_all(text.as_bytes()).map_err(DendecError::Io)?;
+        self.file.sync_data().map_err(DendecError::Io)
+    }
+}
+
+/// Read the journal an interrupted wrap left in `root`, if any.
+pub fn load(root: &Path) -> Result<Option<Interrupted>> {
+    let mut text = match fs::read_to_string(Journal::path_in(root)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(DendecError::Io(e)),
+    };
+    // A line cut off by the interruption was never synced as complete
+    if !text.ends_with('\n') {
+        text.truncate(text.rfind('\n').map_or(0, |i| i + 1));
+    }
+
+    let manifest = Manifest::parse(&text, root)?;
+    let key = text.lines().find_map(|line| {
+        let mut fields = line.strip_prefix("##key ")?.split(' ');
+        Some(RecordedKey {
+            salt: from_hex(fields.next()?)?,
+            fingerprint: from_hex(fields.next()?)?,
+        })
+    });
+    Ok(Some(Interrupted { manifest, key, len: text.len() as u64 }))
+}
+
+/// True if `path` exists with the SHA-256 `expected`.
+fn unchanged(path: &Path, expected: &[u8; 32]) -> bool {
+    sha256_file(path).map(|hash| hash == *expected).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{derive_master_key, derive_master_key_with_salt};
+    use crate::wrap::transform::encode_files;
+    use crate::wrap::WrapOptions;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_interrupted_encode_resumes() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let a = root.join("a.txt");
+        fs::write(&a, b"first\n").unwrap();
+
+        let master = derive_master_key("resume").unwrap();
+        let mut journal = Journal::start(root, true).unwrap();
+        let summary = encode_files(std::slice::from_ref(&a), &master, &WrapOptions::default(), Some(&mut journal));
+        assert_eq!(summary.transformed, 1);
+        // Interrupted: no finish, and half of the next line on disk
+        drop(journal);
+        let mut file = OpenOptions::new().append(true).open(Journal::path_in(root)).unwrap();
+        file.write_all(b"b.txt\tb.tx").unwrap();
+
+        let run = load(root).unwrap().unwrap();
+        assert!(run.manifest.encode_mode);
+        assert_eq!(run.manifest.entries, summary.entries);
+        let key = run.key.unwrap();
+        assert_eq!(key.salt, master.salt);
+        key.check(&derive_master_key_with_salt("resume", &key.salt).unwrap()).unwrap();
+        assert!(matches!(
+            key.check(&derive_master_key_with_salt("other", &key.salt).unwrap()),
+            Err(DendecError::WrapResume(_))
+        ));
+        assert_eq!(run.completed(false), summary.entries);
+
+        // The torn line goes before anything is appended
+        let mut journal = Journal::reopen(root, &run).unwrap();
+        journal.record(&summary.entries[0], Some(&master)).unwrap();
+        let run = load(root).unwrap().unwrap();
+        assert_eq!(run.manifest.entries.len(), 2);
+        assert_eq!(run.key, Some(key));
+
+        journal.finish().unwrap();
+        assert!(load(root).unwrap().is_none());
+    }
+}
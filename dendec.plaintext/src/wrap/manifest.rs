@@ -0,0 +1,375 @@
+/// wrap/manifest.rs — Record of the last wrap in a directory
+///
+/// After transforming files, wrap writes `.dendec-wrap` into the
+/// directory it worked in. `dendec unwrap --undo` reads it back to
+/// reverse that wrap. Only the most recent wrap is kept.
+///
+/// FORMAT (UTF-8 text, same `##` header style as refer's BED files)
+///
+///   ##dendec-wrap v1
+///   ##mode encode
+///   src/main.rs<TAB>src/main.rs.dna<TAB><sha256 source><TAB><sha256 output><TAB><mtime>
+///
+/// One line per transformed file: the path wrap read, the path it
+/// wrote, the SHA-256 of each as hex, and the modification time the
+/// source had, as seconds and nanoseconds since the Unix epoch
+/// (`1700000000.250000000`). Paths are relative to the manifest's
+/// directory when they lie inside it. Tabs, newlines, and backslashes in
+/// paths are written as `\t`, `\n`, and `\\`. Manifests from before the
+/// mtime column have four columns and still load.
+///
+/// Decode restores each file's mtime from the last encode's manifest
+/// (see RecordedMtimes), so it survives anything that moves the `.dna`
+/// files with the manifest but resets their own times, such as a git
+/// checkout. Without a manifest entry, the `.dna`'s own mtime is used.
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{DendecError, Result};
+use crate::output::write_atomic;
+use crate::wrap::journal::JOURNAL_NAME;
+
+/// File name of the manifest inside the wrapped directory.
+pub const MANIFEST_NAME: &str = ".dendec-wrap";
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// One transformed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The file wrap read (the original, or the .dna when decoding)
+    pub source: PathBuf,
+    /// The file wrap wrote
+    pub output: PathBuf,
+    pub source_sha256: [u8; 32],
+    pub output_sha256: [u8; 32],
+    /// The source's modification time when wrap read it, if known
+    pub mtime: Option<SystemTime>,
+}
+
+impl ManifestEntry {
+    /// This entry as one manifest line, paths relative to `root`.
+    pub fn line(&self, root: &Path) -> String {
+        let mut line = format!(
+            "{}\t{}\t{}\t{}",
+            escape(&relative_to(&self.source, root)),
+            escape(&relative_to(&self.output, root)),
+            to_hex(&self.source_sha256),
+            to_hex(&self.output_sha256)
+        );
+        if let Some(mtime) = self.mtime.and_then(format_mtime) {
+            line.push('\t');
+            line.push_str(&mtime);
+        }
+        line.push('\n');
+        line
+    }
+}
+
+/// Everything one wrap did to a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub encode_mode: bool,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Path of the manifest for a wrap rooted at `root`.
+    pub fn path_in(root: &Path) -> PathBuf {
+        root.join(MANIFEST_NAME)
+    }
+
+    /// Write the manifest into `root`, replacing any previous one.
+    pub fn write(&self, root: &Path) -> Result<PathBuf> {
+        let mut text = header(self.encode_mode);
+        for e in &self.entries {
+            text.push_str(&e.line(root));
+        }
+
+        let path = Self::path_in(root);
+        write_atomic(&path, text.as_bytes(), None)?;
+        Ok(path)
+    }
+
+    /// Read the manifest from `root`. Entry paths come back joined onto
+    /// `root`, ready to use.
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = Self::path_in(root);
+        let text = fs::read_to_string(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DendecError::WrapManifestMissing(path.clone()),
+            _ => DendecError::Io(e),
+        })?;
+        Self::parse(&text, root)
+    }
+
+    /// Parse manifest text whose relative paths are under `root`. Other
+    /// `##` lines after the header are skipped (see journal.rs).
+    pub fn parse(text: &str, root: &Path) -> Result<Self> {
+        let corrupt = |line: usize, reason: &str| DendecError::WrapManifestCorrupt {
+            line,
+            reason: reason.to_string(),
+        };
+
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l));
+        match lines.next() {
+            Some((_, l)) if l == format!("##dendec-wrap v{MANIFEST_VERSION}") => {}
+            _ => return Err(corrupt(1, "missing ##dendec-wrap header")),
+        }
+        let encode_mode = match lines.next() {
+            Some((_, "##mode encode")) => true,
+            Some((_, "##mode decode")) => false,
+            _ => return Err(corrupt(2, "missing ##mode encode|decode")),
+        };
+
+        let mut entries = Vec::new();
+        for (n, line) in lines {
+            if line.is_empty() || line.starts_with("##") {
+                continue;
+            }
+            let cols: Vec<&str> = line.split('\t').collect();
+            if !(4..=5).contains(&cols.len()) {
+                return Err(corrupt(n, "expected 4 or 5 tab-separated columns"));
+            }
+            entries.push(ManifestEntry {
+                source: root.join(unescape(cols[0]).ok_or_else(|| corrupt(n, "bad escape"))?),
+                output: root.join(unescape(cols[1]).ok_or_else(|| corrupt(n, "bad escape"))?),
+                source_sha256: from_hex(cols[2]).ok_or_else(|| corrupt(n, "bad SHA-256"))?,
+                output_sha256: from_hex(cols[3]).ok_or_else(|| corrupt(n, "bad SHA-256"))?,
+                mtime: match cols.get(4) {
+                    Some(col) => Some(parse_mtime(col).ok_or_else(|| corrupt(n, "bad mtime"))?),
+                    None => None,
+                },
+            });
+        }
+
+        Ok(Self { encode_mode, entries })
+    }
+
+    /// Delete the manifest from `root` once its wrap has been undone.
+    pub fn remove(root: &Path) -> Result<()> {
+        fs::remove_file(Self::path_in(root)).map_err(DendecError::Io)
+    }
+}
+
+/// Original mtimes from the last encode's manifest, for decode to
+/// restore.
+#[derive(Debug, Clone, Default)]
+pub struct RecordedMtimes(HashMap<PathBuf, ([u8; 32], SystemTime)>);
+
+impl RecordedMtimes {
+    /// The mtimes recorded in `root`'s manifest, if it is an encode's.
+    /// A missing or unreadable manifest gives none.
+    pub fn load(root: &Path) -> Self {
+        let entries = match Manifest::load(root) {
+            Ok(manifest) if manifest.encode_mode => manifest.entries,
+            _ => Vec::new(),
+        };
+        Self(
+            entries
+                .into_iter()
+                .filter_map(|e| Some((e.output, (e.output_sha256, e.mtime?))))
+                .collect(),
+        )
+    }
+
+    /// The mtime recorded for the source of `output`, provided `output`
+    /// still hashes to `sha256` as it did when written.
+    pub fn get(&self, output: &Path, sha256: &[u8; 32]) -> Option<SystemTime> {
+        self.0.get(output).filter(|(recorded, _)| recorded == sha256).map(|&(_, mtime)| mtime)
+    }
+}
+
+/// `mtime` as the manifest writes it; None before the epoch.
+fn format_mtime(mtime: SystemTime) -> Option<String> {
+    let since = mtime.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("{}.{:09}", since.as_secs(), since.subsec_nanos()))
+}
+
+fn parse_mtime(s: &str) -> Option<SystemTime> {
+    let (secs, nanos) = s.split_once('.')?;
+    if nanos.len() != 9 {
+        return None;
+    }
+    let since = Duration::new(secs.parse().ok()?, nanos.parse().ok().filter(|&n| n < 1_000_000_000)?);
+    UNIX_EPOCH.checked_add(since)
+}
+
+/// The `##dendec-wrap` and `##mode` lines every manifest starts with.
+pub fn header(encode_mode: bool) -> String {
+    format!(
+        "##dendec-wrap v{}\n##mode {}\n",
+        MANIFEST_VERSION,
+        if encode_mode { "encode" } else { "decode" }
+    )
+}
+
+/// True if `path` is a wrap manifest or progress journal — never itself
+/// transformed.
+pub fn is_manifest(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n == MANIFEST_NAME || n == JOURNAL_NAME)
+        .unwrap_or(false)
+}
+
+/// SHA-256 of a byte slice.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// SHA-256 of the file at `path`, read in pieces rather than whole.
+pub fn sha256_file(path: &Path) -> std::io::Result<[u8; 32]> {
+    let mut hasher = HashingWriter::new(std::io::sink());
+    std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finish().1)
+}
+
+/// A writer that hashes everything passed through it to `inner`, so an
+/// output's SHA-256 comes without reading it back.
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, hasher: Sha256::new() }
+    }
+
+    /// The inner writer and the SHA-256 of all that was written.
+    pub fn finish(self) -> (W, [u8; 32]) {
+        (self.inner, self.hasher.finalize().into())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn relative_to(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => out.push('\\'),
+            't' => out.push('\t'),
+            'n' => out.push('\n'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn from_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    if s.len() != N * 2 || !s.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let manifest = Manifest {
+            encode_mode: true,
+            entries: vec![ManifestEntry {
+                source: root.join("src/odd\tname.rs"),
+                output: root.join("src/odd\tname.rs.dna"),
+                source_sha256: sha256(b"source"),
+                output_sha256: sha256(b"output"),
+                mtime: Some(UNIX_EPOCH + Duration::new(1_700_000_000, 250_000_000)),
+            }],
+        };
+
+        let path = manifest.write(root).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("src/odd\\tname.rs\t"));
+        assert!(text.ends_with("\t1700000000.250000000\n"));
+        assert_eq!(Manifest::load(root).unwrap(), manifest);
+
+        // Recorded for the .dna as written, not for one changed since
+        let output = &manifest.entries[0].output;
+        let mtimes = RecordedMtimes::load(root);
+        assert_eq!(mtimes.get(output, &sha256(b"output")), manifest.entries[0].mtime);
+        assert_eq!(mtimes.get(output, &sha256(b"other")), None);
+
+        // A manifest from before the mtime column still loads
+        let old = text.replace("\t1700000000.250000000", "");
+        fs::write(&path, old).unwrap();
+        assert_eq!(Manifest::load(root).unwrap().entries[0].mtime, None);
+    }
+
+    #[test]
+    fn test_missing_manifest() {
+        let dir = tempdir().unwrap();
+        assert!(matches!(
+            Manifest::load(dir.path()),
+            Err(DendecError::WrapManifestMissing(_))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_manifest_line_reported() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            Manifest::path_in(dir.path()),
+            "##dendec-wrap v1\n##mode decode\na\tb\tnot-hex\tnot-hex\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            Manifest::load(dir.path()),
+            Err(DendecError::WrapManifestCorrupt { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_sha256_known_vector() {
+        assert_eq!(
+            to_hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
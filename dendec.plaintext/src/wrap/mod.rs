@@ -12,23 +12,183 @@
 ///   decode (local dir):
 ///     walk directory → decode .dna files → report
 ///
+///   --refer: each encoded file is referred on to a BED (`x.bed` rather
+///   than `x.dna`), and decode unrefers any refer BED it finds first
+///
 ///   decode (command):
 ///     snapshot → run command → diff → decode .dna files → report
+///
+///   --archive: the files go into one encrypted tar instead, and
+///   decode unpacks one (see archive.rs)
+///
+/// Every run that transforms at least one file ends by writing a
+/// manifest (see manifest.rs) into the directory it worked in, which
+/// `dendec unwrap --undo` uses to reverse it. Until then, progress is
+/// kept in a journal so an interrupted run can be resumed (journal.rs).
+pub mod archive;
 pub mod classify;
+pub mod config;
 pub mod fetch;
+pub mod hooks;
+pub mod ignores;
+pub mod journal;
+pub mod manifest;
+pub mod progress;
+#[cfg(unix)]
+pub mod pty;
+pub mod report;
 pub mod snapshot;
 pub mod transform;
+pub mod undo;
+pub mod watch;
 
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use log::info;
 use walkdir::WalkDir;
 
+use crate::crypto::{derive_master_key, derive_master_key_with_salt, MasterKeyCache};
 use crate::error::{DendecError, Result};
-use fetch::{git_clone_target, run_command, writes_to_disk};
+use crate::output::OverwritePolicy;
+use crate::refer::assembly::Assembly;
+use config::{is_config, WrapConfig};
+pub use watch::run_watch;
+use fetch::{run_command, scan_root_dir, scan_root_for, writes_to_disk};
+use ignores::filter_ignored;
+use journal::Journal;
+use manifest::{is_manifest, Manifest, RecordedMtimes};
+use report::write_report;
 use snapshot::Snapshot;
-use transform::{decode_files, encode_files, print_summary};
+use transform::{decode_files, encode_files, print_summary, remove_sources, TransformSummary};
+
+/// Options that shape how wrap transforms files, set from CLI flags.
+#[derive(Debug, Clone, Default)]
+pub struct WrapOptions {
+    /// What to do when an output file already exists
+    pub overwrite: OverwritePolicy,
+    /// What a failed file means for the rest of the batch
+    pub on_error: FailurePolicy,
+    /// Leave source files in place instead of deleting them (`--keep`)
+    pub keep: bool,
+    /// Decode each new .dna and compare before deleting its original (`--verify`)
+    pub verify: bool,
+    /// Continue an interrupted wrap from its journal (`--resume`)
+    pub resume: bool,
+    /// Encode files even if .gitignore / .dendecignore exclude them (`--no-ignore`)
+    pub no_ignore: bool,
+    /// Snapshot only this path instead of an inferred one (`--scan-root`)
+    pub scan_root: Option<PathBuf>,
+    /// Run the command through the shell, e.g. for pipelines (`-c`)
+    pub shell: bool,
+    /// Extra environment variables for the command (`--env KEY=VAL`)
+    pub env: Vec<(String, String)>,
+    /// Start the command from a minimal environment (`--clean-env`)
+    pub clean_env: bool,
+    /// Run the command under a pseudo-terminal so it can prompt (`--pty`)
+    pub pty: bool,
+    /// Encode binary files too instead of skipping them (`--include-binary`)
+    pub include_binary: bool,
+    /// Largest binary file `include_binary` admits, in bytes (`--binary-max-size`)
+    pub binary_max_size: Option<u64>,
+    /// Decode payloads found by content under any name, in place (`--sniff`)
+    pub sniff: bool,
+    /// Skip any file larger than this, in bytes (`--max-size`)
+    pub max_size: Option<u64>,
+    /// Extra directory names to skip (`--exclude-dir`, `.dendec.toml`)
+    pub exclude_dirs: Vec<String>,
+    /// Extra extensions to treat as binary (`--binary-ext`, `.dendec.toml`)
+    pub binary_extensions: Vec<String>,
+    /// Pack into, or with decode unpack, this one archive (`--archive`)
+    pub archive: Option<PathBuf>,
+    /// Refer each encoded file on to a BED under this assembly (`--refer`)
+    pub refer: Option<Assembly>,
+    /// Run before each file is transformed, on that file (`--pre-hook`)
+    pub pre_hook: Option<String>,
+    /// Run after each file is transformed, on its output (`--post-hook`)
+    pub post_hook: Option<String>,
+    /// Transform this many files at once, on as many threads (`--jobs`)
+    pub jobs: Option<usize>,
+    /// Pause after each file, to spare the disk (`--throttle`)
+    pub throttle: Option<Duration>,
+    /// Write a JSON report of every file's outcome here (`--report`)
+    pub report: Option<PathBuf>,
+    /// Original mtimes for decode to restore, from the encode manifest
+    pub mtimes: RecordedMtimes,
+}
+
+/// What a file that fails to transform means for the rest of the batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// Transform everything else, then report the failures (default,
+    /// `--continue-on-error`).
+    #[default]
+    Continue,
+    /// Stop at the first failure and roll back what the batch did
+    /// (`--abort-on-error`). Sources are kept until every file succeeded.
+    Abort,
+}
+
+impl FailurePolicy {
+    /// Build a policy from the `--abort-on-error` flag;
+    /// `--continue-on-error` only spells out the default.
+    pub fn from_flags(abort_on_error: bool) -> Self {
+        if abort_on_error {
+            FailurePolicy::Abort
+        } else {
+            FailurePolicy::Continue
+        }
+    }
+}
+
+impl WrapOptions {
+    /// These options with a project config's skip lists added.
+    fn with_config(&self, config: WrapConfig) -> Self {
+        let mut opts = self.clone();
+        opts.exclude_dirs.extend(config.exclude_dirs);
+        opts.binary_extensions.extend(config.binary_extensions);
+        opts
+    }
+}
+
+/// `opts` merged with the `.dendec.toml` that applies to `root`, if any.
+fn apply_config(root: &Path, opts: &WrapOptions) -> Result<WrapOptions> {
+    Ok(match config::load(root)? {
+        Some((path, config)) => {
+            info!("  Using skip lists from {}", path.display());
+            opts.with_config(config)
+        }
+        None => opts.clone(),
+    })
+}
 
 /// Entry point for `dendec wrap -e <command>` and `dendec wrap -d <command>`.
-pub fn run_wrap(encode_mode: bool, command: &[String], password: &str) -> Result<()> {
+pub fn run_wrap(
+    encode_mode: bool,
+    command: &[String],
+    password: &str,
+    opts: &WrapOptions,
+) -> Result<()> {
+
+    // ── Archive extraction / missing target ──────────────────────
+    // -d --archive takes at most a destination, not a command
+    //   dendec wrap -d --archive myproject.dna ./restored
+    if let Some(archive) = &opts.archive {
+        if !encode_mode {
+            if command.len() > 1 {
+                return Err(DendecError::WrapArchive(
+                    "-d --archive takes at most one destination directory".into(),
+                ));
+            }
+            let dest = command.first().map_or(Path::new("."), Path::new);
+            return archive::extract_archive(archive, dest, password, opts);
+        }
+        if command.is_empty() {
+            return Err(DendecError::WrapArchive(
+                "nothing to archive: give a directory or a command".into(),
+            ));
+        }
+    }
 
     // ── Local directory shortcut ──────────────────────────────────
     // If the entire "command" is just a single path to an existing
@@ -36,25 +196,36 @@ pub fn run_wrap(encode_mode: bool, command: &[String], password: &str) -> Result
     // This handles:
     //   dendec wrap -e ./myproject
     //   dendec wrap -d ./myproject
-    if command.len() == 1 {
+    if command.len() == 1 && !opts.shell {
         let candidate = Path::new(&command[0]);
         if candidate.is_dir() {
-            return transform_directory(encode_mode, candidate, password);
+            return transform_directory(encode_mode, candidate, password, opts);
         }
     }
 
     // ── Determine command behaviour ───────────────────────────────
-    let to_disk = writes_to_disk(command);
+    // A shell line is opaque: assume it writes to disk and scan cwd
+    let to_disk = opts.shell || writes_to_disk(command);
     let cwd = std::env::current_dir().map_err(DendecError::Io)?;
 
-    let is_git_clone = command.first().map(|s| s == "git").unwrap_or(false)
-        && command.get(1).map(|s| s == "clone").unwrap_or(false);
+    // ── Bound the snapshot walk ──────────────────────────────────
+    // --scan-root wins; otherwise use where a known command writes
+    // (git clone's target, curl -o's file, ...); otherwise all of cwd.
+    // Walking less is faster and ignores unrelated changes elsewhere.
+    let inferred = if opts.shell { None } else { scan_root_for(command) };
+    let scan_root = match opts.scan_root.clone().or(inferred) {
+        Some(root) => cwd.join(root),
+        None => cwd.clone(),
+    };
+    if to_disk && scan_root != cwd {
+        info!("  Scanning {} for output", scan_root.display());
+    }
 
     // ── Snapshot before ──────────────────────────────────────────
-    let before = Snapshot::capture(&cwd);
+    let before = Snapshot::capture(&scan_root);
 
     // ── Run the command ──────────────────────────────────────────
-    let result = run_command(command, !to_disk)?;
+    let result = run_command(command, !to_disk, opts)?;
 
     // ── Handle stdout-output commands ────────────────────────────
     // If the command wrote to stdout (e.g. bare curl without -o),
@@ -64,33 +235,15 @@ pub fn run_wrap(encode_mode: bool, command: &[String], password: &str) -> Result
     }
 
     // ── Snapshot after ───────────────────────────────────────────
-    let after = Snapshot::capture(&cwd);
+    let after = Snapshot::capture(&scan_root);
     let changed: Vec<PathBuf> = before.diff(&after).into_iter().cloned().collect();
 
     if changed.is_empty() {
         return Err(DendecError::WrapNoFilesFound);
     }
 
-    // ── For git clone, narrow scan to the cloned directory ───────
-    // git clone creates a new subdirectory. We only want to process
-    // files inside that directory, not anything else that happened
-    // to change in cwd during the clone.
-    let files_to_process: Vec<PathBuf> = if is_git_clone {
-        if let Some(target) = git_clone_target(command) {
-            let target_abs = cwd.join(&target);
-            changed
-                .into_iter()
-                .filter(|p| p.starts_with(&target_abs))
-                .collect()
-        } else {
-            changed
-        }
-    } else {
-        changed
-    };
-
-    eprintln!();
-    run_transform(encode_mode, &files_to_process, password)
+    info!("");
+    run_transform(encode_mode, &scan_root_dir(&scan_root), &changed, password, opts)
 }
 
 /// Walk a local directory and transform all appropriate files.
@@ -103,8 +256,9 @@ fn transform_directory(
     encode_mode: bool,
     dir: &Path,
     password: &str,
+    opts: &WrapOptions,
 ) -> Result<()> {
-    eprintln!("  Scanning {}...", dir.display());
+    info!("  Scanning {}...", dir.display());
 
     let files: Vec<PathBuf> = WalkDir::new(dir)
         .follow_links(false)
@@ -118,41 +272,192 @@ fn transform_directory(
         return Err(DendecError::WrapNoFilesFound);
     }
 
-    eprintln!();
-    run_transform(encode_mode, &files, password)
+    info!("");
+    run_transform(encode_mode, dir, &files, password, opts)
 }
 
 /// Common transform dispatch used by both the command and directory paths.
 ///
 /// Encodes or decodes the given file list, prints progress per file,
 /// and prints a summary at the end. Returns an error if any files failed.
-fn run_transform(encode_mode: bool, files: &[PathBuf], password: &str) -> Result<()> {
-    if encode_mode {
-        eprintln!("Encoding {} file(s)...", files.len());
-        eprintln!();
-        let summary = encode_files(files, password);
-        print_summary(&summary, "encode");
+///
+/// Encode derives one master key for the whole batch; decode shares a
+/// master key cache, so a batch written by one wrap costs one Argon2id.
+/// The manifest for `root` is written even if some files failed, so the
+/// ones that succeeded can still be undone, and so is the `--report`
+/// (see report.rs). In encode mode, files that
+/// ignore rules under `root` exclude are dropped first (see ignores.rs).
+/// Skip lists from the `.dendec.toml` that applies to `root` are merged
+/// into `opts` (see config.rs). Each finished file is journaled; a
+/// journal left by an interrupted run is an error unless `opts.resume`,
+/// which skips the files it lists (see journal.rs). With `opts.archive`,
+/// the files go into one archive instead (see archive.rs).
+///
+/// Under `FailurePolicy::Abort` the batch stops at the first failure and
+/// everything the manifest would have recorded, resumed files included,
+/// is undone (see undo.rs); sources are removed only once all succeeded.
+fn run_transform(
+    encode_mode: bool,
+    root: &Path,
+    files: &[PathBuf],
+    password: &str,
+    opts: &WrapOptions,
+) -> Result<()> {
+    let mut opts = apply_config(root, opts)?;
+    if !encode_mode {
+        opts.mtimes = RecordedMtimes::load(root);
+    }
+    let opts = &opts;
 
-        if summary.failed > 0 {
-            return Err(DendecError::WrapFileFailed {
-                path: PathBuf::from("<multiple>"),
-                reason: format!("{} file(s) failed to encode", summary.failed),
-            });
+    let mut files: Vec<PathBuf> = files
+        .iter()
+        .filter(|p| !is_manifest(p) && !is_config(p))
+        .cloned()
+        .collect();
+    let mut excluded = 0;
+    if encode_mode && !opts.no_ignore {
+        let (kept, n) = filter_ignored(root, files);
+        if n > 0 {
+            info!("  {n} file(s) excluded by .gitignore / .dendecignore");
+        }
+        files = kept;
+        excluded = n;
+    }
+
+    if let Some(out) = &opts.archive {
+        return archive::archive_files(root, &files, out, password, opts);
+    }
+
+    // ── Resume an interrupted run, or start journaling this one ──
+    let mode = if encode_mode { "encode" } else { "decode" };
+    let interrupted = journal::load(root)?;
+    if interrupted.is_some() && !opts.resume {
+        return Err(DendecError::WrapInterrupted(Journal::path_in(root)));
+    }
+    let (mut journal, done) = match &interrupted {
+        Some(run) => {
+            if run.manifest.encode_mode != encode_mode {
+                return Err(DendecError::WrapResume(format!(
+                    "the interrupted wrap used {}",
+                    if run.manifest.encode_mode { "-e, not -d" } else { "-d, not -e" }
+                )));
+            }
+            let done = run.completed(opts.keep);
+            info!("  Resuming: {} file(s) already {mode}d", done.len());
+            let finished: HashSet<&PathBuf> =
+                done.iter().flat_map(|e| [&e.source, &e.output]).collect();
+            files.retain(|p| !finished.contains(p));
+            (Journal::reopen(root, run)?, done)
+        }
+        None => {
+            if opts.resume {
+                info!("  No interrupted wrap in {} — starting from scratch", root.display());
+            }
+            (Journal::start(root, encode_mode)?, Vec::new())
         }
+    };
+    let recorded_key = interrupted.and_then(|run| run.key);
+    let files = &files[..];
+
+    let started = Instant::now();
+    let summary = if encode_mode {
+        info!("Encoding {} file(s)...", files.len());
+        info!("");
+        // A resumed encode carries on under the interrupted run's key
+        let master = match recorded_key {
+            Some(key) => {
+                let master = derive_master_key_with_salt(password, &key.salt)?;
+                key.check(&master)?;
+                master
+            }
+            None => derive_master_key(password)?,
+        };
+        encode_files(files, &master, opts, Some(&mut journal))
     } else {
-        eprintln!("Decoding {} file(s)...", files.len());
-        eprintln!();
-        let summary = decode_files(files, password);
-        print_summary(&summary, "decode");
+        info!("Decoding {} file(s)...", files.len());
+        info!("");
+        let keys = MasterKeyCache::new(password);
+        if let Some(key) = recorded_key {
+            let master = keys.get(&key.salt)?;
+            key.check(&master)?;
+        }
+        decode_files(files, &keys, opts, Some(&mut journal))
+    };
+    print_summary(&summary, mode);
+    if let Some(path) = &opts.report {
+        write_report(path, mode, root, &summary, excluded, started.elapsed())?;
+        info!("  Report written to {}", path.display());
+    }
 
+    let mut entries = done;
+    entries.extend_from_slice(&summary.entries);
+    if opts.on_error == FailurePolicy::Abort {
         if summary.failed > 0 {
-            return Err(DendecError::WrapFileFailed {
-                path: PathBuf::from("<multiple>"),
-                reason: format!("{} file(s) failed to decode", summary.failed),
-            });
+            return roll_back(root, encode_mode, entries, journal, password, &summary);
         }
+        if !opts.keep {
+            remove_sources(&summary.entries);
+        }
+    }
+    write_manifest(root, encode_mode, &entries)?;
+    journal.finish()?;
+
+    if summary.failed > 0 {
+        return Err(DendecError::WrapFileFailed {
+            path: PathBuf::from("<multiple>"),
+            reason: format!("{} file(s) failed to {mode}", summary.failed),
+        });
+    }
+
+    Ok(())
+}
+
+/// Undo an aborted batch: record `entries` as the manifest, then reverse
+/// it as `dendec unwrap --undo` would. If that fails too, the manifest
+/// stays for a later undo.
+fn roll_back(
+    root: &Path,
+    encode_mode: bool,
+    entries: Vec<manifest::ManifestEntry>,
+    journal: Journal,
+    password: &str,
+    summary: &TransformSummary,
+) -> Result<()> {
+    let rolled_back = entries.len();
+    info!("");
+    info!("Aborting after {} failure(s)...", summary.failed);
+    if !entries.is_empty() {
+        info!("");
+        let manifest = Manifest { encode_mode, entries };
+        manifest.write(root)?;
+        journal.finish()?;
+        undo::run_undo(root, &manifest, password, false)?;
+    } else {
+        journal.finish()?;
     }
+    Err(DendecError::WrapAborted {
+        failed: summary.failed,
+        not_attempted: summary.not_attempted,
+        rolled_back,
+    })
+}
 
+/// Record a finished batch in `root`'s manifest, replacing the previous
+/// one. Nothing is written when no file was transformed.
+fn write_manifest(
+    root: &Path,
+    encode_mode: bool,
+    entries: &[manifest::ManifestEntry],
+) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let path = Manifest { encode_mode, entries: entries.to_vec() }.write(root)?;
+    info!(
+        "  Manifest written to {}  (undo with: dendec unwrap --undo {})",
+        path.display(),
+        root.display()
+    );
     Ok(())
 }
 
@@ -167,18 +472,47 @@ fn handle_stdout_output(encode_mode: bool, bytes: Vec<u8>, password: &str) -> Re
     use crate::encoding::{decode_raw, encode_raw};
 
     if encode_mode {
-        eprintln!("Encoding stdout output...");
+        info!("Encoding stdout output...");
         let dna = encode_raw(&bytes, password, None)?;
         println!("{dna}");
     } else {
-        eprintln!("Decoding stdout output...");
+        info!("Decoding stdout output...");
         let dna_string = String::from_utf8(bytes)
             .map_err(DendecError::Utf8)?;
         let plaintext = decode_raw(&dna_string, password)?;
-        let text = String::from_utf8(plaintext)
-            .map_err(DendecError::Utf8)?;
-        print!("{text}");
+        crate::output::write_plaintext_stdout(plaintext, false)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_abort_on_error_rolls_back() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"].iter().map(|n| root.join(n)).collect();
+        for f in &files {
+            fs::write(f, b"plain\n").unwrap();
+        }
+        // b.txt cannot be encoded: its output is in the way
+        fs::write(root.join("b.txt.dna"), b"not ours").unwrap();
+
+        let abort = WrapOptions { on_error: FailurePolicy::Abort, ..Default::default() };
+        let err = run_transform(true, root, &files, "abort", &abort).unwrap_err();
+        assert!(matches!(
+            err,
+            DendecError::WrapAborted { failed: 1, not_attempted: 1, rolled_back: 1 }
+        ));
+        // Everything as before the wrap
+        assert!(files.iter().all(|f| fs::read(f).unwrap() == b"plain\n"));
+        assert!(!root.join("a.txt.dna").exists() && !root.join("c.txt.dna").exists());
+        assert_eq!(fs::read(root.join("b.txt.dna")).unwrap(), b"not ours");
+        assert!(!Manifest::path_in(root).exists() && !Journal::path_in(root).exists());
+    }
+}
@@ -0,0 +1,226 @@
+/// wrap/progress.rs — Per-batch progress on stderr
+///
+/// On a terminal, a batch shows one line that is redrawn in place:
+///
+///   [█████████░░░░░░░░░░░░░░░] 152/400 files  18.2 MB / 47.9 MB  6.1 MB/s  ETA 0:05
+///
+/// Files, bytes (of all inputs, including skipped ones), throughput since
+/// the batch began, and the time the remaining bytes take at that rate.
+/// Failures and warnings are printed above the bar; the per-file "ok" and
+/// "Skipping" lines are not, since on a big tree they scroll everything
+/// else away. The summary and `--report` still account for every file.
+///
+/// When stderr is not a terminal (CI logs, `2> file`), or `TERM=dumb`,
+/// there is no bar and every file gets its line, as before.
+///
+/// A process driving wrap itself (dendec daemon) can also observe each
+/// file's outcome, see set_observer.
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use log::{info, log, Level};
+
+use crate::logging;
+use crate::wrap::transform::human_size;
+
+/// Width of the bar itself, in cells.
+const BAR_WIDTH: usize = 24;
+
+/// Minimum time between redraws, so tiny files do not flood the terminal.
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+
+/// One file dealt with, as an observer sees it.
+pub struct FileEvent<'a> {
+    pub path: &'a Path,
+    /// "ok", "failed" or "skipped"
+    pub outcome: &'static str,
+    /// Sizes when ok, the reason when failed, the label when skipped.
+    pub detail: &'a str,
+    /// Files dealt with so far, this one included, of `total`.
+    pub done: usize,
+    pub total: usize,
+}
+
+type Observer = Box<dyn Fn(&FileEvent) + Send + Sync>;
+
+static OBSERVER: RwLock<Option<Observer>> = RwLock::new(None);
+
+/// Call `observer` with every file's outcome in every batch from now
+/// on, besides drawing progress as usual; None stops it.
+pub fn set_observer(observer: Option<Observer>) {
+    *OBSERVER.write().unwrap_or_else(|e| e.into_inner()) = observer;
+}
+
+/// Progress of one batch over a known list of files.
+pub struct Progress {
+    /// Draw a bar instead of a line per file
+    bar: bool,
+    total_files: usize,
+    total_bytes: u64,
+    done_files: usize,
+    done_bytes: u64,
+    started: Instant,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    /// Progress over `paths`, drawn as a bar if stderr is a terminal.
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let bar = logging::interactive()
+            && io::stderr().is_terminal()
+            && std::env::var_os("TERM").is_none_or(|t| t != "dumb");
+        let total_bytes = paths
+            .iter()
+            .filter_map(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum();
+        Self {
+            bar,
+            total_files: paths.len(),
+            total_bytes,
+            done_files: 0,
+            done_bytes: 0,
+            started: Instant::now(),
+            last_draw: None,
+        }
+    }
+
+    /// A file was transformed; `verb` is "Encoding" or "Decoding", and
+    /// `detail` e.g. its sizes.
+    pub fn ok(&mut self, verb: &str, path: &Path, detail: &str) {
+        self.observe(path, "ok", detail);
+        if !self.bar {
+            info!("  {verb} {}... ok  ({detail})", path.display());
+        }
+    }
+
+    /// A file failed to transform.
+    pub fn failed(&mut self, verb: &str, path: &Path, reason: &str) {
+        self.observe(path, "failed", reason);
+        self.note(Level::Warn, &format!("  {verb} {} failed: {reason}", path.display()));
+    }
+
+    /// A file was not transformed, for `label` (see `SkipReason::label`).
+    pub fn skipped(&mut self, path: &Path, label: &str) {
+        self.observe(path, "skipped", label);
+        if !self.bar {
+            info!("  Skipping {}  ({})", path.display(), label);
+        }
+    }
+
+    /// Tell the observer, if any, about a file; `advance` follows.
+    fn observe(&self, path: &Path, outcome: &'static str, detail: &str) {
+        if let Some(observer) = OBSERVER.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            observer(&FileEvent { path, outcome, detail, done: self.done_files + 1, total: self.total_files });
+        }
+    }
+
+    /// Log a line that must not be lost under the bar.
+    pub fn note(&mut self, level: Level, line: &str) {
+        if self.bar {
+            eprint!("\r\x1b[2K");
+        }
+        log!(level, "{line}");
+        if self.bar {
+            self.draw(true);
+        }
+    }
+
+    /// A file of `bytes` is dealt with, whatever the outcome.
+    pub fn advance(&mut self, bytes: u64) {
+        self.done_files += 1;
+        self.done_bytes += bytes;
+        if self.bar {
+            self.draw(self.done_files == self.total_files);
+        }
+    }
+
+    /// Clear the bar before the summary is printed.
+    pub fn finish(&mut self) {
+        if self.bar && self.last_draw.is_some() {
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+
+    fn draw(&mut self, force: bool) {
+        let now = Instant::now();
+        if !force && self.last_draw.is_some_and(|t| now.duration_since(t) < REDRAW_EVERY) {
+            return;
+        }
+        self.last_draw = Some(now);
+        eprint!("\r\x1b[2K{}", self.render(now.duration_since(self.started)));
+        let _ = io::stderr().flush();
+    }
+
+    /// The bar line after `elapsed`.
+    fn render(&self, elapsed: Duration) -> String {
+        let fraction = if self.total_bytes > 0 {
+            self.done_bytes as f64 / self.total_bytes as f64
+        } else if self.total_files > 0 {
+            self.done_files as f64 / self.total_files as f64
+        } else {
+            1.0
+        };
+        let filled = ((fraction * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+
+        let secs = elapsed.as_secs_f64();
+        let rate = if secs > 0.0 { self.done_bytes as f64 / secs } else { 0.0 };
+        let eta = if rate > 0.0 {
+            let remaining = self.total_bytes.saturating_sub(self.done_bytes) as f64;
+            clock(Duration::from_secs_f64(remaining / rate))
+        } else {
+            "--:--".to_string()
+        };
+
+        format!(
+            "  [{}{}] {}/{} files  {} / {}  {}/s  ETA {}",
+            "█".repeat(filled),
+            "░".repeat(BAR_WIDTH - filled),
+            self.done_files,
+            self.total_files,
+            human_size(self.done_bytes),
+            human_size(self.total_bytes),
+            human_size(rate as u64),
+            eta
+        )
+    }
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour up.
+fn clock(d: Duration) -> String {
+    let s = d.as_secs();
+    if s >= 3600 {
+        format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+    } else {
+        format!("{}:{:02}", s / 60, s % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bar_render_and_eta() {
+        let mut progress = Progress {
+            bar: false,
+            total_files: 4,
+            total_bytes: 4 * 1024 * 1024,
+            done_files: 0,
+            done_bytes: 0,
+            started: Instant::now(),
+            last_draw: None,
+        };
+        assert!(progress.render(Duration::ZERO).ends_with("0/4 files  0 B / 4.0 MB  0 B/s  ETA --:--"));
+
+        progress.advance(1024 * 1024);
+        let line = progress.render(Duration::from_secs(2));
+        assert!(line.starts_with(&format!("  [{}{}]", "█".repeat(6), "░".repeat(18))));
+        // 1 MB in 2 s leaves 3 MB at 512 KB/s
+        assert!(line.ends_with("1/4 files  1.0 MB / 4.0 MB  512.0 KB/s  ETA 0:06"), "{line}");
+        assert_eq!(clock(Duration::from_secs(3725)), "1:02:05");
+    }
+}
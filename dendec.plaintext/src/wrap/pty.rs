@@ -0,0 +1,189 @@
+/// wrap/pty.rs — Run the wrapped command under a pseudo-terminal
+///
+/// Some fetches prompt: `gh repo clone` for a login, `git clone` over SSH
+/// for a passphrase or host key confirmation. Such prompts go to the
+/// controlling terminal, and when wrap captures stdout (or runs from a
+/// context with no terminal attached) they are lost or refused, and the
+/// command hangs or fails.
+///
+/// `--pty` opens a pseudo-terminal pair and starts the command in a new
+/// session with the pty as its controlling terminal, stdin, and stderr.
+/// Its stdout is the pty too, unless wrap captures stdout, in which case
+/// it stays a pipe so prompts never end up in the captured bytes. dendec
+/// then relays in both directions:
+///
+///   dendec stdin  ──▶ pty master ──▶ command (keystrokes, passwords)
+///   command       ──▶ pty master ──▶ dendec stdout, or stderr if capturing
+///
+/// While the command runs, dendec's own terminal (if stdin is one) is in
+/// raw mode so each keystroke is passed on as typed; the pty does the
+/// echoing, and hides it for password prompts. The mode is restored when
+/// the command exits, even on error.
+///
+/// Unix only: other platforms get `DendecError::WrapPtyUnsupported`.
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+/// Run `cmd` under a new pseudo-terminal and wait for it.
+///
+/// Returns the exit status, and the command's stdout if `capture_stdout`.
+pub fn run_in_pty(mut cmd: Command, capture_stdout: bool) -> io::Result<(ExitStatus, Option<Vec<u8>>)> {
+    let (master, slave) = open_pty()?;
+
+    cmd.stdin(Stdio::from(slave.try_clone()?));
+    cmd.stderr(Stdio::from(slave.try_clone()?));
+    if capture_stdout {
+        cmd.stdout(Stdio::piped());
+    } else {
+        cmd.stdout(Stdio::from(slave.try_clone()?));
+    }
+
+    // SAFETY: setsid and ioctl are async-signal-safe, as pre_exec requires
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            // Make the pty (now fd 0) the session's controlling terminal
+            if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = cmd.spawn()?;
+    // Only the child may hold the slave open, or the master never sees EOF
+    drop(cmd);
+    drop(slave);
+
+    let _raw = RawModeGuard::enable();
+
+    let stdout_reader = child.stdout.take().map(|mut out| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            out.read_to_end(&mut buf).map(|_| buf)
+        })
+    });
+
+    // Keystrokes to the command. Not joined: it stays blocked on stdin
+    // after the command exits, and its next write fails harmlessly.
+    let mut to_child = File::from(master.try_clone()?);
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buf = [0u8; 1024];
+        while let Ok(n) = stdin.read(&mut buf) {
+            if n == 0 || to_child.write_all(&buf[..n]).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Terminal output from the command, until the slave side closes
+    let mut from_child = File::from(master);
+    let mut buf = [0u8; 4096];
+    loop {
+        match from_child.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                let data = &buf[..n];
+                if capture_stdout {
+                    let mut err = io::stderr();
+                    err.write_all(data)?;
+                    err.flush()?;
+                } else {
+                    let mut out = io::stdout();
+                    out.write_all(data)?;
+                    out.flush()?;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            // Linux reports a closed slave as EIO rather than EOF
+            Err(_) => break,
+        }
+    }
+
+    let status = child.wait()?;
+    let stdout = match stdout_reader {
+        Some(handle) => Some(
+            handle
+                .join()
+                .map_err(|_| io::Error::other("stdout reader panicked"))??,
+        ),
+        None => None,
+    };
+    Ok((status, stdout))
+}
+
+/// Open a pty pair sized like dendec's own terminal, if it has one.
+fn open_pty() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let have_size = [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO]
+        .iter()
+        .any(|&fd| unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0);
+
+    let (mut master, mut slave) = (-1, -1);
+    let winp = if have_size { &mut size as *mut libc::winsize } else { std::ptr::null_mut() };
+    // SAFETY: both out-pointers are valid; name and termios may be null
+    let rc = unsafe {
+        libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null_mut(), winp)
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: openpty succeeded, so both descriptors are open and ours
+    Ok(unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) })
+}
+
+/// Puts dendec's terminal in raw mode and restores it on drop. Does
+/// nothing when stdin is not a terminal.
+struct RawModeGuard {
+    saved: Option<libc::termios>,
+}
+
+impl RawModeGuard {
+    fn enable() -> Self {
+        let fd = io::stdin().as_raw_fd();
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: fd is stdin and `saved` is a valid termios to fill
+        if unsafe { libc::isatty(fd) } != 1 || unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Self { saved: None };
+        }
+        let mut raw = saved;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Self { saved: None };
+            }
+        }
+        Self { saved: Some(saved) }
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            unsafe {
+                libc::tcsetattr(io::stdin().as_raw_fd(), libc::TCSANOW, saved);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_sees_a_terminal() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("[ -t 0 ] && [ -t 2 ] && [ ! -t 1 ] && echo on-a-tty");
+        let (status, stdout) = run_in_pty(cmd, true).unwrap();
+        assert!(status.success());
+        assert_eq!(stdout.unwrap(), b"on-a-tty\n");
+    }
+}
@@ -0,0 +1,214 @@
+/// wrap/report.rs — Machine-readable JSON report of a wrap run
+///
+/// `wrap --report PATH` writes what happened to every file the batch
+/// looked at, so a CI pipeline can audit exactly what was encrypted
+/// instead of scraping the progress output:
+///
+///   {
+///     "dendec_version": "0.1.0",
+///     "mode": "encode",
+///     "root": "/work/myproject",
+///     "summary": { "transformed": 15, "skipped": 1, "failed": 0,
+///                  "not_attempted": 0, "excluded_by_ignore": 3, "input_bytes": 48213,
+///                  "output_bytes": 771584, "duration_ms": 412.5 },
+///     "files": [
+///       { "path": "/work/myproject/src/main.rs",
+///         "output": "/work/myproject/src/main.rs.dna",
+///         "outcome": "transformed", "reason": null,
+///         "input_bytes": 1234, "output_bytes": 19968,
+///         "duration_ms": 3.1 },
+///       { "path": "/work/myproject/assets/logo.png", "output": null,
+///         "outcome": "skipped", "reason": "binary", ... }
+///     ]
+///   }
+///
+/// `reason` is the skip label or the error message. Files dropped by
+/// ignore rules never reach classification and are only counted. The
+/// report is written even when files failed — that is when it matters.
+/// Under `--abort-on-error`, files after the first failure are only
+/// counted as `not_attempted`, and the files reported as transformed
+/// were rolled back right after the report was written.
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::error::{DendecError, Result};
+use crate::output::write_atomic;
+use crate::wrap::transform::TransformSummary;
+
+/// What happened to one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Transformed,
+    Skipped,
+    Failed,
+}
+
+/// One file's line in the report.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileRecord {
+    #[serde(serialize_with = "lossy_path")]
+    pub path: PathBuf,
+    #[serde(serialize_with = "lossy_opt_path")]
+    pub output: Option<PathBuf>,
+    pub outcome: Outcome,
+    pub reason: Option<String>,
+    pub input_bytes: u64,
+    pub output_bytes: Option<u64>,
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    pub duration: Duration,
+}
+
+impl FileRecord {
+    pub fn transformed(path: &Path, output: &Path, input_bytes: u64, output_bytes: u64, duration: Duration) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            output: Some(output.to_path_buf()),
+            outcome: Outcome::Transformed,
+            reason: None,
+            input_bytes,
+            output_bytes: Some(output_bytes),
+            duration,
+        }
+    }
+
+    pub fn skipped(path: &Path, reason: &str, input_bytes: u64, duration: Duration) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            output: None,
+            outcome: Outcome::Skipped,
+            reason: Some(reason.to_string()),
+            input_bytes,
+            output_bytes: None,
+            duration,
+        }
+    }
+
+    pub fn failed(path: &Path, reason: String, input_bytes: u64, duration: Duration) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            output: None,
+            outcome: Outcome::Failed,
+            reason: Some(reason),
+            input_bytes,
+            output_bytes: None,
+            duration,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    dendec_version: &'static str,
+    mode: &'a str,
+    #[serde(serialize_with = "lossy_path")]
+    root: &'a Path,
+    summary: ReportSummary,
+    files: &'a [FileRecord],
+}
+
+#[derive(Serialize)]
+struct ReportSummary {
+    transformed: usize,
+    skipped: usize,
+    failed: usize,
+    not_attempted: usize,
+    excluded_by_ignore: usize,
+    input_bytes: u64,
+    output_bytes: u64,
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    duration: Duration,
+}
+
+/// Write the report for a finished batch to `path`.
+///
+/// `mode` is "encode" or "decode"; `excluded` is the number of files
+/// ignore rules dropped before classification; `elapsed` covers the
+/// whole batch, key derivation included.
+pub fn write_report(
+    path: &Path,
+    mode: &str,
+    root: &Path,
+    summary: &TransformSummary,
+    excluded: usize,
+    elapsed: Duration,
+) -> Result<()> {
+    let transformed = summary.records.iter().filter(|r| r.outcome == Outcome::Transformed);
+    let report = Report {
+        dendec_version: env!("CARGO_PKG_VERSION"),
+        mode,
+        root,
+        summary: ReportSummary {
+            transformed: summary.transformed,
+            skipped: summary.skipped,
+            failed: summary.failed,
+            not_attempted: summary.not_attempted,
+            excluded_by_ignore: excluded,
+            input_bytes: transformed.clone().map(|r| r.input_bytes).sum(),
+            output_bytes: transformed.filter_map(|r| r.output_bytes).sum(),
+            duration: elapsed,
+        },
+        files: &summary.records,
+    };
+
+    let mut json = serde_json::to_vec_pretty(&report).map_err(|e| DendecError::Io(e.into()))?;
+    json.push(b'\n');
+    write_atomic(path, &json, None)
+}
+
+fn lossy_path<S: Serializer, P: AsRef<Path>>(path: P, s: S) -> std::result::Result<S::Ok, S::Error> {
+    s.serialize_str(&path.as_ref().to_string_lossy())
+}
+
+fn lossy_opt_path<S: Serializer>(path: &Option<PathBuf>, s: S) -> std::result::Result<S::Ok, S::Error> {
+    match path {
+        Some(p) => lossy_path(p, s),
+        None => s.serialize_none(),
+    }
+}
+
+fn millis<S: Serializer>(d: &Duration, s: S) -> std::result::Result<S::Ok, S::Error> {
+    // Microsecond resolution is plenty and keeps the numbers short
+    s.serialize_f64(d.as_micros() as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_report_json_shape() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let summary = TransformSummary {
+            transformed: 1,
+            skipped: 1,
+            failed: 1,
+            records: vec![
+                FileRecord::transformed(&root.join("a.rs"), &root.join("a.rs.dna"), 10, 300, Duration::from_micros(1500)),
+                FileRecord::skipped(&root.join("b.png"), "binary", 99, Duration::ZERO),
+                FileRecord::failed(&root.join("c.rs"), "Permission denied".into(), 5, Duration::ZERO),
+            ],
+            ..Default::default()
+        };
+
+        let out = root.join("report.json");
+        write_report(&out, "encode", root, &summary, 2, Duration::from_millis(40)).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+
+        assert_eq!(json["mode"], "encode");
+        assert_eq!(json["summary"]["transformed"], 1);
+        assert_eq!(json["summary"]["excluded_by_ignore"], 2);
+        assert_eq!(json["summary"]["input_bytes"], 10);
+        assert_eq!(json["summary"]["output_bytes"], 300);
+        assert_eq!(json["summary"]["duration_ms"], 40.0);
+        assert_eq!(json["files"][0]["outcome"], "transformed");
+        assert_eq!(json["files"][0]["duration_ms"], 1.5);
+        assert_eq!(json["files"][1]["reason"], "binary");
+        assert!(json["files"][1]["output"].is_null());
+        assert_eq!(json["files"][2]["outcome"], "failed");
+    }
+}
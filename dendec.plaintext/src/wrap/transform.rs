@@ -1,193 +1,511 @@
 /// wrap/transform.rs — Batch file encode/decode with progress reporting
 ///
 /// Iterates a list of file paths, classifies each one, and applies
-/// encode_raw or decode_raw. Reports per-file progress to stderr.
-/// Original files are replaced by .dna files (encode) or vice versa (decode).
+/// encode_stream_with_master or decode_stream_cached. The password goes
+/// through Argon2id once per batch, not once per file. Files are mapped
+/// or streamed, never read whole, so memory stays bounded per file
+/// whatever its size. Reports progress to
+/// stderr: a bar on a terminal, a line per file otherwise (progress.rs).
+/// Original files are replaced by .dna files (encode) or vice versa (decode),
+/// unless `--keep` asks for both to stay. With `--refer` the encoded .dna
+/// is referred on to a .bed in memory instead (refer::refer_bytes), and
+/// decode unrefers any refer BED before decoding it.
+///
+/// Outputs are written with `output::write_atomic` (temp file, fsync,
+/// rename), and a source is removed only after its output is in place.
+/// A crash at any point leaves the source intact; at worst its output
+/// sits beside it too. With `--verify`, an encoded file is also read
+/// back and decoded before its original goes, and must hash the same.
+///
+/// `--jobs` spreads the files over worker threads, which also run any
+/// `--pre-hook` / `--post-hook` (hooks.rs); removal, journaling and
+/// reporting stay on the calling thread.
 use std::fs;
-use std::io::Write;
+use std::io::{self, Read, Seek};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::encoding::{decode_raw, encode_raw};
+use log::{debug, info, warn, Level};
+use rayon::ThreadPoolBuilder;
+
+use crate::crypto::{MasterKey, MasterKeyCache};
+use crate::encoding::{decode_stream_cached, decode_stream_with_master, encode_stream_with_master};
 use crate::error::{DendecError, Result};
-use crate::wrap::classify::{classify_for_decode, classify_for_encode, FileClass, SkipReason};
+use crate::mmap::map_file;
+use crate::output::{check_output, prepare_output, write_atomic_with};
+use crate::refer::{refer_bytes, unrefer_quiet};
+use crate::wrap::classify::{
+    classify_for_decode, classify_for_encode, is_payload_file, is_refer_file, FileClass,
+};
+use crate::wrap::hooks::{run_hook, HookStage};
+use crate::wrap::journal::Journal;
+use crate::wrap::manifest::{sha256, sha256_file, HashingWriter, ManifestEntry};
+use crate::wrap::progress::Progress;
+use crate::wrap::report::FileRecord;
+use crate::wrap::{FailurePolicy, WrapOptions};
 
 /// Summary of a batch transform operation.
+#[derive(Default)]
 pub struct TransformSummary {
     pub transformed: usize,
     pub skipped: usize,
     pub failed: usize,
+    /// Files left alone because `--abort-on-error` stopped the batch
+    pub not_attempted: usize,
     pub failures: Vec<(PathBuf, String)>,
+    /// One record per transformed file, for the wrap manifest
+    pub entries: Vec<ManifestEntry>,
+    /// One record per file looked at, for `--report`
+    pub records: Vec<FileRecord>,
 }
 
-/// Encode all appropriate files in `paths` using `password`.
-///
-/// Each source file is read, encoded, written to `<original>.dna`,
-/// and the original is deleted on success.
-pub fn encode_files(paths: &[PathBuf], password: &str) -> TransformSummary {
-    let mut summary = TransformSummary {
-        transformed: 0,
-        skipped: 0,
-        failed: 0,
-        failures: Vec::new(),
-    };
+/// What became of one file, as worked out by `encode_files` or
+/// `decode_files` (possibly on a worker thread).
+enum FileResult {
+    /// Transformed: its manifest record, the output's size, and why the
+    /// post-hook failed if it did
+    Done(ManifestEntry, u64, Option<String>),
+    Skipped(&'static str),
+    Failed(DendecError),
+    /// Classified for the other direction; counted as skipped silently
+    OtherMode,
+    /// Not looked at: an earlier file failed under `--abort-on-error`
+    NotAttempted,
+}
 
-    for path in paths {
-        match classify_for_encode(path) {
-            FileClass::Encode => {
-                eprint!("  Encoding {}... ", path.display());
-                match encode_file(path, password) {
-                    Ok(dna_path) => {
-                        let orig_size = fs::metadata(path)
-                            .map(|m| m.len())
-                            .unwrap_or(0);
-                        let dna_size = fs::metadata(&dna_path)
-                            .map(|m| m.len())
-                            .unwrap_or(0);
-                        eprintln!(
-                            "ok  ({} → {})",
-                            human_size(orig_size),
-                            human_size(dna_size)
-                        );
-                        // Remove original after successful encode
-                        if let Err(e) = fs::remove_file(path) {
-                            eprintln!("  Warning: could not remove original {}: {e}", path.display());
-                        }
-                        summary.transformed += 1;
-                    }
-                    Err(e) => {
-                        eprintln!("FAILED: {e}");
-                        summary.failed += 1;
-                        summary.failures.push((path.clone(), e.to_string()));
-                    }
-                }
+impl TransformSummary {
+    /// Account for one file's result, reporting skips and failures.
+    fn add(&mut self, verb: &str, path: &Path, in_size: u64, elapsed: Duration, result: FileResult, progress: &mut Progress) {
+        match result {
+            FileResult::Done(entry, out_size, _) => {
+                self.records.push(FileRecord::transformed(path, &entry.output, in_size, out_size, elapsed));
+                self.transformed += 1;
+                self.entries.push(entry);
             }
-            FileClass::Skip(reason) => {
-                let label = match reason {
-                    SkipReason::Binary => "binary",
-                    SkipReason::AlreadyDna => "already .dna",
-                    SkipReason::ExcludedDir => "excluded dir",
-                    SkipReason::NotDna => "not .dna",
-                    SkipReason::ReadError => "read error",
-                };
-                eprintln!("  Skipping {}  ({})", path.display(), label);
-                summary.skipped += 1;
+            FileResult::Skipped(label) => {
+                progress.skipped(path, label);
+                self.records.push(FileRecord::skipped(path, label, in_size, elapsed));
+                self.skipped += 1;
             }
-            FileClass::Decode => {
-                // Should not happen in encode mode but handle gracefully
-                summary.skipped += 1;
+            FileResult::Failed(e) => {
+                progress.failed(verb, path, &e.to_string());
+                self.records.push(FileRecord::failed(path, e.to_string(), in_size, elapsed));
+                self.failed += 1;
+                self.failures.push((path.to_path_buf(), e.to_string()));
             }
+            FileResult::OtherMode => {
+                debug!("  {} is for the other direction, skipped", path.display());
+                self.skipped += 1;
+            }
+            FileResult::NotAttempted => self.not_attempted += 1,
         }
     }
+}
+
+/// Encode all appropriate files in `paths` under one master key.
+///
+/// Each source file is read, encoded, written to `<original>.dna` with
+/// the original's mtime, and the original is deleted on success unless
+/// `opts.keep` is set.
+/// An existing `.dna` file is handled according to `opts.overwrite`.
+/// Each finished file is recorded in `journal`, if given, before its
+/// original is removed (see journal.rs).
+pub fn encode_files(
+    paths: &[PathBuf],
+    master: &MasterKey,
+    opts: &WrapOptions,
+    mut journal: Option<&mut Journal>,
+) -> TransformSummary {
+    let mut summary = TransformSummary::default();
+    let mut progress = Progress::new(paths);
+
+    let work = |path: &Path| match classify_for_encode(path, opts) {
+        FileClass::Encode => with_hooks(path, true, opts, || encode_file(path, master, opts)),
+        FileClass::Skip(reason) => FileResult::Skipped(reason.label()),
+        // Should not happen in encode mode but handle gracefully
+        FileClass::Decode => FileResult::OtherMode,
+    };
+
+    for_each_file(paths, opts, work, |path, in_size, elapsed, result| {
+        if let FileResult::Done(entry, dna_size, hook_failure) = &result {
+            progress.ok(
+                "Encoding",
+                path,
+                &format!(
+                    "{} → {}{}",
+                    human_size(in_size),
+                    human_size(*dna_size),
+                    if opts.verify { ", verified" } else { "" }
+                ),
+            );
+            warn_hook_failure(hook_failure, &entry.output, &mut progress);
+            record_in_journal(journal.as_deref_mut(), entry, Some(master), &mut progress);
+            // Remove original after successful encode
+            if opts.keep {
+                // Mirroring: the original stays next to its .dna
+            } else if opts.on_error == FailurePolicy::Abort {
+                // Removed by the caller once the whole batch succeeded
+            } else if let Err(e) = fs::remove_file(path) {
+                progress.note(Level::Warn, &format!("  could not remove original {}: {e}", path.display()));
+            }
+        }
+        summary.add("Encoding", path, in_size, elapsed, result, &mut progress);
+        progress.advance(in_size);
+    });
 
+    progress.finish();
     summary
 }
 
-/// Decode all `.dna` files in `paths` using `password`.
+/// Decode all `.dna` files in `paths`, sharing master keys via `keys`.
 ///
-/// Each `.dna` file is decoded, written to the original path (extension
-/// stripped), and the `.dna` file is deleted on success.
-pub fn decode_files(paths: &[PathBuf], password: &str) -> TransformSummary {
-    let mut summary = TransformSummary {
-        transformed: 0,
-        skipped: 0,
-        failed: 0,
-        failures: Vec::new(),
+/// Each `.dna` file (or refer `.bed`, see open_payload) is decoded,
+/// written to the original path (extension stripped) with the `.dna`'s
+/// mtime, and the `.dna` file is deleted on success unless `opts.keep`
+/// is set. An existing file at the original path is skipped as
+/// "unchanged" if it already holds the decoded content, and otherwise
+/// handled according to `opts.overwrite`. A payload without a `.dna`
+/// name (found by `opts.sniff`) is replaced by its plaintext in place.
+/// Finished files are recorded in `journal` as in `encode_files`.
+pub fn decode_files(
+    paths: &[PathBuf],
+    keys: &MasterKeyCache,
+    opts: &WrapOptions,
+    mut journal: Option<&mut Journal>,
+) -> TransformSummary {
+    let mut summary = TransformSummary::default();
+    let mut progress = Progress::new(paths);
+
+    let work = |path: &Path| match classify_for_decode(path, opts) {
+        FileClass::Decode => match already_decoded(path, keys) {
+            Ok(true) => FileResult::Skipped("unchanged"),
+            Ok(false) => with_hooks(path, false, opts, || decode_file(path, keys, opts)),
+            Err(e) => FileResult::Failed(e),
+        },
+        FileClass::Skip(reason) => FileResult::Skipped(reason.label()),
+        FileClass::Encode => FileResult::OtherMode,
     };
 
-    for path in paths {
-        match classify_for_decode(path) {
-            FileClass::Decode => {
-                eprint!("  Decoding {}... ", path.display());
-                match decode_file(path, password) {
-                    Ok(out_path) => {
-                        let dna_size = fs::metadata(path)
-                            .map(|m| m.len())
-                            .unwrap_or(0);
-                        let out_size = fs::metadata(&out_path)
-                            .map(|m| m.len())
-                            .unwrap_or(0);
-                        eprintln!(
-                            "ok  ({} → {})",
-                            human_size(dna_size),
-                            human_size(out_size)
-                        );
-                        // Remove .dna file after successful decode
-                        if let Err(e) = fs::remove_file(path) {
-                            eprintln!("  Warning: could not remove .dna file {}: {e}", path.display());
-                        }
-                        summary.transformed += 1;
-                    }
-                    Err(e) => {
-                        eprintln!("FAILED: {e}");
-                        summary.failed += 1;
-                        summary.failures.push((path.clone(), e.to_string()));
-                    }
-                }
-            }
-            FileClass::Skip(reason) => {
-                let label = match reason {
-                    SkipReason::NotDna => "not .dna",
-                    SkipReason::ExcludedDir => "excluded dir",
-                    SkipReason::Binary => "binary",
-                    SkipReason::AlreadyDna => "already .dna",
-                    SkipReason::ReadError => "read error",
-                };
-                eprintln!("  Skipping {}  ({})", path.display(), label);
-                summary.skipped += 1;
-            }
-            FileClass::Encode => {
-                summary.skipped += 1;
+    for_each_file(paths, opts, work, |path, in_size, elapsed, result| {
+        if let FileResult::Done(entry, out_size, hook_failure) = &result {
+            // x.dna.dna: one layer off, another to go
+            let nested = if is_payload_file(&entry.output) {
+                ", nested payload — decode again"
+            } else {
+                ""
+            };
+            progress.ok(
+                "Decoding",
+                path,
+                &format!("{} → {}{}", human_size(in_size), human_size(*out_size), nested),
+            );
+            warn_hook_failure(hook_failure, &entry.output, &mut progress);
+            record_in_journal(journal.as_deref_mut(), entry, keys.any().as_deref(), &mut progress);
+            // Remove .dna file after successful decode
+            if opts.keep || entry.source == entry.output {
+                // Mirroring: the .dna stays next to its plaintext
+            } else if opts.on_error == FailurePolicy::Abort {
+                // Removed by the caller once the whole batch succeeded
+            } else if let Err(e) = fs::remove_file(path) {
+                progress.note(Level::Warn, &format!("  could not remove .dna file {}: {e}", path.display()));
             }
         }
-    }
+        summary.add("Decoding", path, in_size, elapsed, result, &mut progress);
+        progress.advance(in_size);
+    });
 
+    progress.finish();
     summary
 }
 
-/// Encode a single file. Returns the path of the written .dna file.
-fn encode_file(path: &Path, password: &str) -> Result<PathBuf> {
-    let plaintext = fs::read(path).map_err(DendecError::Io)?;
-    let dna = encode_raw(&plaintext, password, None)?;
+/// Transform `path` with `transform`, running `opts.pre_hook` on it
+/// before and `opts.post_hook` on the output after.
+fn with_hooks(
+    path: &Path,
+    encode_mode: bool,
+    opts: &WrapOptions,
+    transform: impl FnOnce() -> Result<ManifestEntry>,
+) -> FileResult {
+    if let Some(cmd) = &opts.pre_hook {
+        if let Err(e) = run_hook(cmd, HookStage::Pre, path, encode_mode) {
+            return FileResult::Failed(e);
+        }
+    }
+    let mut entry = match transform() {
+        Ok(entry) => entry,
+        Err(e) => return FileResult::Failed(e),
+    };
+    let mut hook_failure = None;
+    if let Some(cmd) = &opts.post_hook {
+        match run_hook(cmd, HookStage::Post, &entry.output, encode_mode) {
+            // The manifest records the output as the hook left it
+            Ok(()) => match sha256_file(&entry.output) {
+                Ok(hash) => entry.output_sha256 = hash,
+                Err(e) => hook_failure = Some(format!("cannot read {} after it: {e}", entry.output.display())),
+            },
+            Err(e) => hook_failure = Some(e.to_string()),
+        }
+    }
+    let out_size = fs::metadata(&entry.output).map(|m| m.len()).unwrap_or(0);
+    FileResult::Done(entry, out_size, hook_failure)
+}
+
+/// Report a post-hook that failed on `output`; the transform stands.
+fn warn_hook_failure(failure: &Option<String>, output: &Path, progress: &mut Progress) {
+    if let Some(reason) = failure {
+        progress.note(Level::Warn, &format!("  {reason} on {}", output.display()));
+    }
+}
+
+/// Run `work` on every path and hand each result to `finish` on this
+/// thread, with the path's size and how long its work took.
+///
+/// Without `opts.jobs`, files are worked through in order right here.
+/// With it, the work runs on a pool of that many threads and results
+/// arrive as files finish. The pool also bounds the chunk parallelism
+/// inside each encode, so `--jobs 1` keeps a whole wrap on one core.
+/// `opts.throttle` is slept after each file, on the thread that did it.
+/// Under `FailurePolicy::Abort`, a failure stops every file not yet
+/// started; those come back as `NotAttempted`.
+fn for_each_file<W, F>(paths: &[PathBuf], opts: &WrapOptions, work: W, mut finish: F)
+where
+    W: Fn(&Path) -> FileResult + Sync,
+    F: FnMut(&Path, u64, Duration, FileResult),
+{
+    let stop = AtomicBool::new(false);
+    let run_one = |path: &Path| {
+        let started = Instant::now();
+        let in_size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        if stop.load(Ordering::Relaxed) {
+            return (in_size, Duration::ZERO, FileResult::NotAttempted);
+        }
+        let result = work(path);
+        if matches!(result, FileResult::Failed(_)) && opts.on_error == FailurePolicy::Abort {
+            stop.store(true, Ordering::Relaxed);
+        }
+        let elapsed = started.elapsed();
+        if let Some(pause) = opts.throttle {
+            thread::sleep(pause);
+        }
+        (in_size, elapsed, result)
+    };
+
+    let pool = opts.jobs.and_then(|n| match ThreadPoolBuilder::new().num_threads(n).build() {
+        Ok(pool) => Some(pool),
+        Err(e) => {
+            warn!("  cannot start {n} worker thread(s), running sequentially: {e}");
+            None
+        }
+    });
+    let Some(pool) = pool else {
+        for path in paths {
+            let (in_size, elapsed, result) = run_one(path);
+            finish(path, in_size, elapsed, result);
+        }
+        return;
+    };
+
+    let (tx, rx) = mpsc::channel();
+    pool.in_place_scope(|scope| {
+        for path in paths {
+            let (tx, run_one) = (tx.clone(), &run_one);
+            scope.spawn(move |_| {
+                let _ = tx.send((path, run_one(path)));
+            });
+        }
+        drop(tx);
+        for (path, (in_size, elapsed, result)) in rx {
+            finish(path, in_size, elapsed, result);
+        }
+    });
+}
+
+/// Remove the sources of `entries`, which the batch kept under
+/// `FailurePolicy::Abort` until every file had succeeded.
+pub fn remove_sources(entries: &[ManifestEntry]) {
+    for entry in entries.iter().filter(|e| e.source != e.output) {
+        if let Err(e) = fs::remove_file(&entry.source) {
+            warn!("  could not remove {}: {e}", entry.source.display());
+        }
+    }
+}
+
+/// Append a finished file to the journal. A failure here costs only the
+/// ability to resume, so it is a warning rather than a failed file.
+fn record_in_journal(
+    journal: Option<&mut Journal>,
+    entry: &ManifestEntry,
+    key: Option<&MasterKey>,
+    progress: &mut Progress,
+) {
+    if let Some(journal) = journal {
+        if let Err(e) = journal.record(entry, key) {
+            progress.note(Level::Warn, &format!("  could not record progress: {e}"));
+        }
+    }
+}
 
-    // Append .dna extension
+/// Encode a single file. Returns its manifest record, whose `output`
+/// is the written .dna file, or .bed under `opts.refer`.
+///
+/// The original's mtime goes on the output and into the record, so
+/// decode can restore it from the manifest once a copy, checkout or sync
+/// has reset the output's own (see manifest.rs).
+fn encode_file(path: &Path, master: &MasterKey, opts: &WrapOptions) -> Result<ManifestEntry> {
+    // Append .dna extension, or .bed for a file referred on
     let mut dna_path = path.to_path_buf();
     let new_name = format!(
-        "{}.dna",
+        "{}.{}",
         path.file_name()
             .and_then(|n| n.to_str())
-            .unwrap_or("file")
+            .unwrap_or("file"),
+        if opts.refer.is_some() { "bed" } else { "dna" }
     );
     dna_path.set_file_name(new_name);
+    check_output(&dna_path, opts.overwrite)?;
+
+    let meta = fs::metadata(path).map_err(DendecError::Io)?;
+    // Mapped and streamed, so memory stays bounded whatever the file size
+    let plaintext = map_file(path)?;
+    let source_sha256 = sha256(&plaintext);
+
+    prepare_output(&dna_path, opts.overwrite)?;
+    let output_sha256 = write_atomic_with(&dna_path, Some(meta.permissions()), meta.modified().ok(), |file| {
+        let out = match opts.refer {
+            // Refer reads the .dna twice, so this one is held in memory
+            Some(assembly) => {
+                let dna = encode_stream_with_master(&plaintext[..], Vec::new(), master, None)?;
+                let mut out = HashingWriter::new(file);
+                refer_bytes(&dna, assembly, &mut out)?;
+                out
+            }
+            None => encode_stream_with_master(&plaintext[..], HashingWriter::new(file), master, None)?,
+        };
+        Ok(out.finish().1)
+    })?;
+
+    if opts.verify {
+        if let Err(reason) = verify_output(&dna_path, master, &source_sha256) {
+            // A .dna that does not decode back is worse than none
+            let _ = fs::remove_file(&dna_path);
+            return Err(DendecError::WrapVerifyFailed { path: path.to_path_buf(), reason });
+        }
+    }
+
+    Ok(ManifestEntry {
+        source: path.to_path_buf(),
+        output: dna_path,
+        source_sha256,
+        output_sha256,
+        mtime: meta.modified().ok(),
+    })
+}
+
+/// Read a freshly written .dna (or refer .bed) back from disk, decode it
+/// under `master`, and check the plaintext hashes to `expected`. The
+/// error is the reason it does not.
+pub fn verify_output(dna_path: &Path, master: &MasterKey, expected: &[u8; 32]) -> std::result::Result<(), String> {
+    let written = open_payload(dna_path).map_err(|e| format!("cannot read back: {e}"))?;
+    let (_, decoded_sha256) = decode_stream_with_master(written, HashingWriter::new(io::sink()), master)
+        .map_err(|e| format!("does not decode: {e}"))?
+        .finish();
+    if decoded_sha256 != *expected {
+        return Err("decoded content differs from the original".into());
+    }
+    Ok(())
+}
 
-    let mut f = fs::File::create(&dna_path).map_err(DendecError::Io)?;
-    f.write_all(dna.as_bytes()).map_err(DendecError::Io)?;
+/// What decoding reads from: a .dna on disk, or one unreferred in memory.
+pub trait Payload: Read + Seek {}
 
-    Ok(dna_path)
+impl<T: Read + Seek> Payload for T {}
+
+/// Open a payload to decode: a .dna as it is, a refer BED unreferred into
+/// memory first. Only plain BEDs are expected, as `wrap --refer` writes
+/// them; a shuffled one fails here for want of a password.
+pub fn open_payload(path: &Path) -> Result<Box<dyn Payload>> {
+    if is_refer_file(path) {
+        return Ok(Box::new(io::Cursor::new(unrefer_quiet(path)?.into_bytes())));
+    }
+    Ok(Box::new(fs::File::open(path).map_err(DendecError::Io)?))
 }
 
-/// Decode a single .dna file. Returns the path of the restored file.
-fn decode_file(path: &Path, password: &str) -> Result<PathBuf> {
-    let dna_string = fs::read_to_string(path).map_err(DendecError::Io)?;
-    let plaintext = decode_raw(&dna_string, password)?;
+/// Decode a single .dna file. Returns its manifest record, whose
+/// `output` is the restored file.
+fn decode_file(
+    path: &Path,
+    keys: &MasterKeyCache,
+    opts: &WrapOptions,
+) -> Result<ManifestEntry> {
+    // Strip .dna extension to get original path; a sniffed payload
+    // without one is decoded in place
+    let out_path = strip_payload_extension(path);
+    let in_place = out_path == path;
+    if in_place && opts.keep {
+        return Err(DendecError::WrapFileFailed {
+            path: path.to_path_buf(),
+            reason: "decoding in place would replace it, which --keep forbids".into(),
+        });
+    }
+    if !in_place {
+        check_output(&out_path, opts.overwrite)?;
+    }
 
-    // Strip .dna extension to get original path
-    let out_path = strip_dna_extension(path);
+    let meta = fs::metadata(path).map_err(DendecError::Io)?;
+    let source_sha256 = sha256_file(path).map_err(DendecError::Io)?;
 
-    let mut f = fs::File::create(&out_path).map_err(DendecError::Io)?;
-    f.write_all(&plaintext).map_err(DendecError::Io)?;
+    if !in_place {
+        prepare_output(&out_path, opts.overwrite)?;
+    }
+    // The original's mtime as the encode manifest recorded it, or else as
+    // the .dna carries it (see encode_file)
+    let mtime = opts.mtimes.get(path, &source_sha256).or(meta.modified().ok());
+    // Streamed chunk by chunk into the temp file; a failure discards it
+    let output_sha256 = write_atomic_with(&out_path, Some(meta.permissions()), mtime, |file| {
+        let dna = open_payload(path)?;
+        Ok(decode_stream_cached(dna, HashingWriter::new(file), keys)?.finish().1)
+    })?;
 
-    Ok(out_path)
+    Ok(ManifestEntry {
+        source: path.to_path_buf(),
+        output: out_path,
+        source_sha256,
+        output_sha256,
+        mtime: meta.modified().ok(),
+    })
 }
 
-/// Strip the trailing `.dna` extension from a path.
+/// True if decoding `path` would reproduce the file already at its output
+/// path byte for byte. That file is then left alone, timestamp and all,
+/// so a build system does not see it as modified.
+fn already_decoded(path: &Path, keys: &MasterKeyCache) -> Result<bool> {
+    let out_path = strip_payload_extension(path);
+    if out_path == path {
+        return Ok(false);
+    }
+    let Ok(existing) = sha256_file(&out_path) else {
+        return Ok(false);
+    };
+    let dna = open_payload(path)?;
+    let (_, decoded) = decode_stream_cached(dna, HashingWriter::new(io::sink()), keys)?.finish();
+    Ok(decoded == existing)
+}
+
+/// Strip the trailing `.dna` or `.bed` extension from a path.
 /// `src/main.rs.dna` → `src/main.rs`
-fn strip_dna_extension(path: &Path) -> PathBuf {
+fn strip_payload_extension(path: &Path) -> PathBuf {
     let stem = path
         .file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("file");
 
-    let stripped = stem.strip_suffix(".dna").unwrap_or(stem);
+    let stripped = stem
+        .strip_suffix(".dna")
+        .or_else(|| stem.strip_suffix(".bed"))
+        .unwrap_or(stem);
 
     let mut out = path.to_path_buf();
     out.set_file_name(stripped);
@@ -195,7 +513,7 @@ fn strip_dna_extension(path: &Path) -> PathBuf {
 }
 
 /// Format byte count as human-readable string.
-fn human_size(bytes: u64) -> String {
+pub fn human_size(bytes: u64) -> String {
     if bytes < 1024 {
         format!("{bytes} B")
     } else if bytes < 1024 * 1024 {
@@ -207,16 +525,19 @@ fn human_size(bytes: u64) -> String {
 
 /// Print a summary report to stderr.
 pub fn print_summary(summary: &TransformSummary, mode: &str) {
-    eprintln!();
-    eprintln!(
+    info!("");
+    info!(
         "  {} files {}d  |  {} skipped  |  {} failed",
         summary.transformed, mode, summary.skipped, summary.failed
     );
+    if summary.not_attempted > 0 {
+        info!("  {} file(s) not attempted after the first failure", summary.not_attempted);
+    }
     if !summary.failures.is_empty() {
-        eprintln!();
-        eprintln!("  Failures:");
+        info!("");
+        info!("  Failures:");
         for (path, reason) in &summary.failures {
-            eprintln!("    {} — {}", path.display(), reason);
+            info!("    {} — {}", path.display(), reason);
         }
     }
 }
@@ -224,6 +545,7 @@ pub fn print_summary(summary: &TransformSummary, mode: &str) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::output::OverwritePolicy;
     use tempfile::tempdir;
 
     #[test]
@@ -233,24 +555,213 @@ mod tests {
         let content = b"fn main() { println!(\"hello\"); }\n";
         fs::write(&src, content).unwrap();
 
-        let password = "wraptest";
+        let master = crate::crypto::derive_master_key("wraptest").unwrap();
+        let keys = MasterKeyCache::new("wraptest");
 
         // Encode
-        let dna_path = encode_file(&src, password).unwrap();
+        let dna_path = encode_file(&src, &master, &WrapOptions::default()).unwrap().output;
         assert!(dna_path.exists());
         assert!(dna_path.to_str().unwrap().ends_with(".dna"));
 
         // Decode
-        let out_path = decode_file(&dna_path, password).unwrap();
+        let out_path = decode_file(&dna_path, &keys, &WrapOptions::default());
+        // The original is still on disk, so a plain decode must refuse
+        assert!(matches!(out_path, Err(DendecError::OutputExists(_))));
+        let backup = WrapOptions { overwrite: OverwritePolicy::Backup, ..Default::default() };
+        let out_path = decode_file(&dna_path, &keys, &backup).unwrap().output;
+        assert_eq!(fs::read(dir.path().join("hello.rs.~1~")).unwrap(), content);
         let decoded = fs::read(&out_path).unwrap();
         assert_eq!(decoded, content);
     }
 
     #[test]
-    fn test_strip_dna_extension() {
+    fn test_keep_preserves_sources() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("notes.txt");
+        fs::write(&src, b"mirror me\n").unwrap();
+        let dna_path = dir.path().join("notes.txt.dna");
+
+        let keep = WrapOptions { keep: true, ..Default::default() };
+        let master = crate::crypto::derive_master_key("keeptest").unwrap();
+        let summary = encode_files(std::slice::from_ref(&src), &master, &keep, None);
+        assert_eq!(summary.transformed, 1);
+        assert!(src.exists() && dna_path.exists());
+
+        // Decoding over an edited original needs --force as well
+        fs::write(&src, b"edited\n").unwrap();
+        let keep_force = WrapOptions {
+            keep: true,
+            overwrite: OverwritePolicy::Force,
+            ..Default::default()
+        };
+        let keys = MasterKeyCache::new("keeptest");
+        let summary = decode_files(std::slice::from_ref(&dna_path), &keys, &keep_force, None);
+        assert_eq!(summary.transformed, 1);
+        assert!(src.exists() && dna_path.exists());
+        assert_eq!(fs::read(&src).unwrap(), b"mirror me\n");
+    }
+
+    #[test]
+    fn test_verify_catches_bad_output() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("ledger.csv");
+        fs::write(&src, b"date,amount\n2024-01-01,12.50\n").unwrap();
+        let dna_path = dir.path().join("ledger.csv.dna");
+
+        let verify = WrapOptions { verify: true, ..Default::default() };
+        let master = crate::crypto::derive_master_key("verifytest").unwrap();
+        let summary = encode_files(std::slice::from_ref(&src), &master, &verify, None);
+        assert_eq!(summary.transformed, 1);
+        assert!(!src.exists());
+
+        let expected = summary.entries[0].source_sha256;
+        assert!(verify_output(&dna_path, &master, &expected).is_ok());
+        assert!(verify_output(&dna_path, &master, &[0u8; 32]).is_err());
+
+        // Flip one base in the body, as a bad write might
+        let mut dna = fs::read(&dna_path).unwrap();
+        let at = dna.len() - 10;
+        dna[at] = if dna[at] == b'A' { b'C' } else { b'A' };
+        fs::write(&dna_path, &dna).unwrap();
+        assert!(verify_output(&dna_path, &master, &expected).is_err());
+    }
+
+    #[test]
+    fn test_refer_roundtrip() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("config.toml");
+        fs::write(&src, b"[server]\nport = 8080\n").unwrap();
+
+        let refer = WrapOptions {
+            refer: Some(crate::refer::assembly::Assembly::Hg38),
+            verify: true,
+            ..Default::default()
+        };
+        let master = crate::crypto::derive_master_key("refertest").unwrap();
+        let summary = encode_files(std::slice::from_ref(&src), &master, &refer, None);
+        assert_eq!(summary.transformed, 1);
+        let bed = summary.entries[0].output.clone();
+        assert_eq!(bed, dir.path().join("config.toml.bed"));
+        assert!(fs::read_to_string(&bed).unwrap().starts_with("##dendec-refer"));
+        assert_eq!(summary.entries[0].output_sha256, sha256_file(&bed).unwrap());
+
+        // Decode needs no flag to take the .bed back
+        let keys = MasterKeyCache::new("refertest");
+        let summary = decode_files(std::slice::from_ref(&bed), &keys, &WrapOptions::default(), None);
+        assert_eq!(summary.transformed, 1);
+        assert!(!bed.exists());
+        assert_eq!(fs::read(&src).unwrap(), b"[server]\nport = 8080\n");
+    }
+
+    #[test]
+    fn test_parallel_jobs_roundtrip() {
+        let dir = tempdir().unwrap();
+        let files: Vec<PathBuf> = (0..6).map(|i| dir.path().join(format!("f{i}.txt"))).collect();
+        for (i, f) in files.iter().enumerate() {
+            fs::write(f, format!("file {i}\n")).unwrap();
+        }
+
+        let jobs = WrapOptions { jobs: Some(3), ..Default::default() };
+        let master = crate::crypto::derive_master_key("jobstest").unwrap();
+        let summary = encode_files(&files, &master, &jobs, None);
+        assert_eq!(summary.transformed, 6);
+        assert!(files.iter().all(|f| !f.exists()));
+
+        let dnas: Vec<PathBuf> = summary.entries.iter().map(|e| e.output.clone()).collect();
+        let keys = MasterKeyCache::new("jobstest");
+        let summary = decode_files(&dnas, &keys, &jobs, None);
+        assert_eq!(summary.transformed, 6);
+        assert_eq!(keys.len(), 1);
+        for (i, f) in files.iter().enumerate() {
+            assert_eq!(fs::read_to_string(f).unwrap(), format!("file {i}\n"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hooks_around_encode() {
+        let dir = tempdir().unwrap();
+        let trimmed = dir.path().join("trim.txt");
+        let refused = dir.path().join("refuse.txt");
+        fs::write(&trimmed, b"line   \n").unwrap();
+        fs::write(&refused, b"keep me\n").unwrap();
+
+        let hooks = WrapOptions {
+            pre_hook: Some(r#"case "$1" in *refuse*) exit 1;; esac; sed -i 's/ *$//' "$1""#.into()),
+            post_hook: Some(r#"chmod 600 "$DENDEC_FILE""#.into()),
+            ..Default::default()
+        };
+        let master = crate::crypto::derive_master_key("hooktest").unwrap();
+        let summary = encode_files(&[trimmed.clone(), refused.clone()], &master, &hooks, None);
+        assert_eq!((summary.transformed, summary.failed), (1, 1));
+        assert_eq!(fs::read(&refused).unwrap(), b"keep me\n");
+
+        // The pre-hook's edit is what got encoded
+        let entry = &summary.entries[0];
+        assert_eq!(entry.source_sha256, sha256(b"line\n"));
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&entry.output).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_mtime_restored_and_noop_decode_skipped() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("build.rs");
+        fs::write(&src, b"fn main() {}\n").unwrap();
+        let old = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options().write(true).open(&src).unwrap().set_modified(old).unwrap();
+
+        let keep = WrapOptions { keep: true, ..Default::default() };
+        let master = crate::crypto::derive_master_key("mtime").unwrap();
+        let summary = encode_files(std::slice::from_ref(&src), &master, &keep, None);
+        let dna_path = summary.entries[0].output.clone();
+        assert_eq!(fs::metadata(&dna_path).unwrap().modified().unwrap(), old);
+
+        // The kept original matches: nothing to write, no --force needed
+        let keys = MasterKeyCache::new("mtime");
+        let summary = decode_files(std::slice::from_ref(&dna_path), &keys, &WrapOptions::default(), None);
+        assert_eq!((summary.transformed, summary.skipped), (0, 1));
+        assert!(dna_path.exists());
+
+        // Once it is gone, decode brings it back with its old mtime
+        fs::remove_file(&src).unwrap();
+        let summary = decode_files(std::slice::from_ref(&dna_path), &keys, &WrapOptions::default(), None);
+        assert_eq!(summary.transformed, 1);
+        assert_eq!(fs::metadata(&src).unwrap().modified().unwrap(), old);
+    }
+
+    #[test]
+    fn test_mtime_restored_from_manifest_after_checkout() {
+        use crate::wrap::manifest::{Manifest, RecordedMtimes};
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("build.rs");
+        fs::write(&src, b"fn main() {}\n").unwrap();
+        let old = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options().write(true).open(&src).unwrap().set_modified(old).unwrap();
+
+        let master = crate::crypto::derive_master_key("mtime").unwrap();
+        let summary = encode_files(std::slice::from_ref(&src), &master, &WrapOptions::default(), None);
+        assert!(!src.exists());
+        Manifest { encode_mode: true, entries: summary.entries.clone() }.write(dir.path()).unwrap();
+
+        // A checkout gives the .dna the time it was written out
+        let dna_path = summary.entries[0].output.clone();
+        let now = std::time::SystemTime::now();
+        fs::File::options().write(true).open(&dna_path).unwrap().set_modified(now).unwrap();
+
+        let opts = WrapOptions { mtimes: RecordedMtimes::load(dir.path()), ..Default::default() };
+        let keys = MasterKeyCache::new("mtime");
+        let summary = decode_files(std::slice::from_ref(&dna_path), &keys, &opts, None);
+        assert_eq!(summary.transformed, 1);
+        assert_eq!(fs::metadata(&src).unwrap().modified().unwrap(), old);
+    }
+
+    #[test]
+    fn test_strip_payload_extension() {
         let p = PathBuf::from("src/main.rs.dna");
-        let stripped = strip_dna_extension(&p);
+        let stripped = strip_payload_extension(&p);
         assert_eq!(stripped, PathBuf::from("src/main.rs"));
+        assert_eq!(strip_payload_extension(Path::new("src/main.rs.bed")), PathBuf::from("src/main.rs"));
     }
 
     #[test]
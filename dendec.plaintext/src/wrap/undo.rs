@@ -0,0 +1,281 @@
+/// wrap/undo.rs — Reverse the last wrap using its manifest
+///
+/// For each manifest entry, in order of preference:
+///
+///   source still present and unchanged (wrap --keep, or already undone)
+///     → delete the output
+///
+///   source gone, output present and unchanged
+///     → run the opposite transform on the output, write the source back
+///       atomically, then delete the output
+///
+/// Undoing an encode decodes each .dna and checks the result against the
+/// recorded SHA-256 of the original, so the restored tree is byte-for-byte
+/// the pre-wrap tree. Undoing a decode re-encodes each file: the content
+/// is restored, but the new .dna bytes differ (fresh salts and nonces).
+///
+/// A payload that `wrap -d --sniff` decoded in place has the same source
+/// and output path; undo re-encodes it in place.
+///
+/// A `wrap --refer` output is unreferred before it is decoded. Undoing a
+/// decode of one refers the file again, under the default assembly: the
+/// manifest does not record which one the BED used.
+///
+/// Like wrap itself, undo streams: outputs and sources are hashed in
+/// pieces, and each file is transformed straight into its temp file, so
+/// memory stays bounded whatever the file size.
+///
+/// Files changed since the wrap are left alone unless `force` is set. The
+/// manifest is deleted once every entry has been undone; after a partial
+/// failure it stays, and running undo again finishes the job.
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+use crate::crypto::{derive_master_key, MasterKey, MasterKeyCache};
+use crate::encoding::{decode_stream_cached, encode_stream_with_master};
+use crate::error::{DendecError, Result};
+use crate::mmap::map_file;
+use crate::output::write_atomic_with;
+use crate::refer::assembly::Assembly;
+use crate::refer::refer_bytes;
+use crate::wrap::classify::has_refer_extension;
+use crate::wrap::manifest::{sha256_file, HashingWriter, Manifest, ManifestEntry};
+use crate::wrap::transform::{open_payload, print_summary, TransformSummary};
+
+/// True if undoing `manifest` has to run a transform, i.e. at least one
+/// source is missing or was decoded in place — the caller only prompts
+/// for a password then.
+pub fn needs_password(manifest: &Manifest) -> bool {
+    manifest
+        .entries
+        .iter()
+        .any(|e| e.source == e.output || !e.source.exists())
+}
+
+/// Undo the wrap recorded in `root`'s manifest.
+pub fn run_undo(root: &Path, manifest: &Manifest, password: &str, force: bool) -> Result<()> {
+    info!(
+        "Undoing {} of {} file(s) in {}...",
+        if manifest.encode_mode { "encode" } else { "decode" },
+        manifest.entries.len(),
+        root.display()
+    );
+    info!("");
+
+    let mut summary = TransformSummary::default();
+
+    // Undoing an encode decodes; undoing a decode encodes under one key.
+    let keys = MasterKeyCache::new(password);
+    let mut master: Option<MasterKey> = None;
+
+    for entry in &manifest.entries {
+        let result = if manifest.encode_mode {
+            undo_entry(entry, force, |out| {
+                decode_stream_cached(open_payload(&entry.output)?, out, &keys)?;
+                Ok(())
+            })
+        } else {
+            undo_entry(entry, force, |out| {
+                if master.is_none() {
+                    master = Some(derive_master_key(password)?);
+                }
+                let master = master.as_ref().expect("set above");
+                let plain = map_file(&entry.output)?;
+                if !has_refer_extension(&entry.source) {
+                    encode_stream_with_master(&plain[..], out, master, None)?;
+                    return Ok(());
+                }
+                // Refer reads the .dna twice, so this one is held in memory
+                let dna = encode_stream_with_master(&plain[..], Vec::new(), master, None)?;
+                refer_bytes(&dna, Assembly::default(), out)
+            })
+        };
+
+        match result {
+            Ok(note) => {
+                info!("  Restored {}  ({note})", entry.source.display());
+                summary.transformed += 1;
+            }
+            Err(e) => {
+                warn!("  Restoring {} failed: {e}", entry.source.display());
+                summary.failed += 1;
+                summary.failures.push((entry.source.clone(), e.to_string()));
+            }
+        }
+    }
+
+    print_summary(&summary, "restore");
+
+    if summary.failed > 0 {
+        return Err(DendecError::WrapFileFailed {
+            path: PathBuf::from("<multiple>"),
+            reason: format!(
+                "{} file(s) could not be restored; the manifest was kept",
+                summary.failed
+            ),
+        });
+    }
+
+    Manifest::remove(root)?;
+    Ok(())
+}
+
+/// Undo one entry. `reverse` reads the output and writes the source's
+/// bytes back to the writer it is given.
+fn undo_entry<F>(entry: &ManifestEntry, force: bool, reverse: F) -> Result<&'static str>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    let changed = |path: &Path, what: &str| DendecError::WrapFileFailed {
+        path: path.to_path_buf(),
+        reason: format!("{what} changed since the wrap (use --force to restore anyway)"),
+    };
+
+    let in_place = entry.source == entry.output;
+
+    // Source still in place: only the output has to go
+    let current = if in_place { None } else { sha256_file(&entry.source).ok() };
+    if let Some(current) = current {
+        if current == entry.source_sha256 {
+            match sha256_file(&entry.output) {
+                Err(_) => return Ok("already restored"),
+                Ok(out) if out != entry.output_sha256 && !force => {
+                    return Err(changed(&entry.output, "output"));
+                }
+                Ok(_) => {
+                    fs::remove_file(&entry.output).map_err(DendecError::Io)?;
+                    return Ok("kept source, output removed");
+                }
+            }
+        }
+        if !force {
+            return Err(changed(&entry.source, "source"));
+        }
+    }
+
+    let output = sha256_file(&entry.output).map_err(DendecError::Io)?;
+    if output != entry.output_sha256 && !force {
+        return Err(changed(&entry.output, "output"));
+    }
+
+    let meta = fs::metadata(&entry.output).map_err(DendecError::Io)?;
+    let mtime = entry.mtime.or(meta.modified().ok());
+    // Hashed on the way into the temp file, so nothing is read back
+    let restored = write_atomic_with(&entry.source, Some(meta.permissions()), mtime, |file| {
+        let mut out = HashingWriter::new(file);
+        reverse(&mut out)?;
+        Ok(out.finish().1)
+    })?;
+    if !in_place {
+        fs::remove_file(&entry.output).map_err(DendecError::Io)?;
+    }
+
+    Ok(if restored == entry.source_sha256 {
+        "verified"
+    } else {
+        "re-encoded"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{decode_raw_cached, encode_raw_with_master};
+    use crate::refer::unrefer_quiet;
+    use crate::wrap::manifest::Manifest;
+    use crate::wrap::transform::encode_files;
+    use crate::wrap::WrapOptions;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_undo_encode_restores_tree() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let a = root.join("a.txt");
+        let b = root.join("b.txt");
+        let c = root.join("c.bin");
+        fs::write(&a, b"alpha\n").unwrap();
+        fs::write(&b, b"beta\n").unwrap();
+        // Several stream chunks, restored without being held whole
+        let big: Vec<u8> = (0..3 * crate::encoding::CHUNK_SIZE + 17).map(|i| (i % 251) as u8).collect();
+        fs::write(&c, &big).unwrap();
+
+        let master = derive_master_key("undo").unwrap();
+        let summary = encode_files(&[a.clone(), b.clone(), c.clone()], &master, &WrapOptions::default(), None);
+        let manifest = Manifest { encode_mode: true, entries: summary.entries };
+        manifest.write(root).unwrap();
+        assert!(!a.exists() && !b.exists());
+
+        // A tampered output is refused without --force
+        let b_dna = root.join("b.txt.dna");
+        let mut dna = fs::read(&b_dna).unwrap();
+        dna.push(b'A');
+        fs::write(&b_dna, &dna).unwrap();
+        let manifest = Manifest::load(root).unwrap();
+        assert!(needs_password(&manifest));
+        assert!(run_undo(root, &manifest, "undo", false).is_err());
+        assert_eq!(fs::read(&a).unwrap(), b"alpha\n");
+        assert_eq!(fs::read(&c).unwrap(), big);
+        assert!(Manifest::path_in(root).exists());
+
+        // Repair the output; a second run finishes and removes the manifest
+        dna.pop();
+        fs::write(&b_dna, &dna).unwrap();
+        run_undo(root, &manifest, "undo", false).unwrap();
+        assert_eq!(fs::read(&b).unwrap(), b"beta\n");
+        assert!(!b_dna.exists());
+        assert!(!Manifest::path_in(root).exists());
+    }
+
+    #[test]
+    fn test_undo_refer_wrap_both_ways() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let a = root.join("a.txt");
+        fs::write(&a, b"alpha\n").unwrap();
+
+        let refer = WrapOptions { refer: Some(Assembly::Hg38), ..Default::default() };
+        let summary = encode_files(std::slice::from_ref(&a), &derive_master_key("undo").unwrap(), &refer, None);
+        Manifest { encode_mode: true, entries: summary.entries }.write(root).unwrap();
+        run_undo(root, &Manifest::load(root).unwrap(), "undo", false).unwrap();
+        assert_eq!(fs::read(&a).unwrap(), b"alpha\n");
+        assert!(!root.join("a.txt.bed").exists());
+
+        // Undoing a decode of a .bed refers the file again
+        let bed = root.join("a.txt.bed");
+        let summary = encode_files(std::slice::from_ref(&a), &derive_master_key("undo").unwrap(), &refer, None);
+        let keys = MasterKeyCache::new("undo");
+        let summary = crate::wrap::transform::decode_files(&[summary.entries[0].output.clone()], &keys, &WrapOptions::default(), None);
+        Manifest { encode_mode: false, entries: summary.entries }.write(root).unwrap();
+        run_undo(root, &Manifest::load(root).unwrap(), "undo", false).unwrap();
+        assert!(!a.exists());
+        assert_eq!(unrefer_quiet(&bed).map(|dna| decode_raw_cached(&dna, &keys).unwrap()).unwrap(), b"alpha\n");
+    }
+
+    #[test]
+    fn test_sniffed_payload_decoded_and_undone_in_place() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let notes = root.join("notes.txt");
+        let dna = encode_raw_with_master(b"hidden\n", &derive_master_key("sniff").unwrap(), None).unwrap();
+        fs::write(&notes, &dna).unwrap();
+
+        let sniff = WrapOptions { sniff: true, ..Default::default() };
+        let keys = MasterKeyCache::new("sniff");
+        let summary = crate::wrap::transform::decode_files(std::slice::from_ref(&notes), &keys, &sniff, None);
+        assert_eq!(summary.transformed, 1);
+        assert_eq!(fs::read(&notes).unwrap(), b"hidden\n");
+
+        let manifest = Manifest { encode_mode: false, entries: summary.entries };
+        manifest.write(root).unwrap();
+        assert!(needs_password(&manifest));
+        run_undo(root, &Manifest::load(root).unwrap(), "sniff", false).unwrap();
+
+        // Re-encoded in place: a payload again, under the same name
+        let again = fs::read_to_string(&notes).unwrap();
+        assert_eq!(decode_raw_cached(&again, &keys).unwrap(), b"hidden\n");
+    }
+}
@@ -0,0 +1,214 @@
+/// wrap/watch.rs — Continuously transform a directory as files arrive
+///
+/// `dendec wrap -e --watch DIR` turns DIR into an encrypted drop-box:
+/// every plaintext file created in (or moved into) it is encoded to
+/// `.dna` once it stops changing. `-d --watch` does the reverse for new
+/// `.dna` files. Files already present when the watch starts are left
+/// alone; run a plain `wrap -e DIR` first to cover them.
+///
+/// The password is stretched once for the whole session. Each file is
+/// transformed only after no event has touched it for `SETTLE`, so a
+/// file still being copied in is not read half-written. Everything else
+/// — classification, ignore rules, `.dendec.toml`, overwrite policy,
+/// `--keep` — behaves as in a one-shot wrap.
+///
+/// The manifest grows with each batch and is rewritten after it, so
+/// `dendec unwrap --undo DIR` reverses the whole session so far. The
+/// watch runs until interrupted (Ctrl-C); nothing is lost by stopping it.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use notify::event::{AccessKind, AccessMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use walkdir::WalkDir;
+
+use crate::crypto::{derive_master_key, MasterKeyCache};
+use crate::error::{DendecError, Result};
+use crate::output::is_atomic_temp;
+use crate::wrap::classify::{has_dna_extension, has_refer_extension};
+use crate::wrap::config::is_config;
+use crate::wrap::ignores::filter_ignored;
+use crate::wrap::manifest::{is_manifest, Manifest};
+use crate::wrap::transform::{decode_files, encode_files, print_summary};
+use crate::wrap::{apply_config, WrapOptions};
+
+/// Quiet period after a path's last event before it is transformed.
+pub(crate) const SETTLE: Duration = Duration::from_millis(750);
+
+/// How often pending paths are checked while no events arrive.
+pub(crate) const POLL: Duration = Duration::from_millis(200);
+
+/// Watch `dir` and transform new files until interrupted.
+pub fn run_watch(encode_mode: bool, dir: &Path, password: &str, opts: &WrapOptions) -> Result<()> {
+    // Events carry absolute paths; compare like with like
+    let root = dir.canonicalize().map_err(DendecError::Io)?;
+    if !root.is_dir() {
+        return Err(DendecError::WrapWatch(format!("{} is not a directory", dir.display())));
+    }
+    let opts = &apply_config(&root, opts)?;
+    let mode = if encode_mode { "encode" } else { "decode" };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(tx).map_err(|e| DendecError::WrapWatch(e.to_string()))?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(|e| DendecError::WrapWatch(e.to_string()))?;
+
+    // One key derivation for the whole session
+    let master = if encode_mode { Some(derive_master_key(password)?) } else { None };
+    let keys = MasterKeyCache::new(password);
+    let mut session = Manifest { encode_mode, entries: Vec::new() };
+    let mut pending = Pending::default();
+
+    info!(
+        "Watching {} — new {} are {mode}d as they appear. Press Ctrl-C to stop.",
+        root.display(),
+        if encode_mode { "files" } else { ".dna files" }
+    );
+
+    loop {
+        match rx.recv_timeout(POLL) {
+            Ok(Ok(event)) if is_content_event(&event.kind) => {
+                let now = Instant::now();
+                for path in event.paths.iter().flat_map(|p| files_at(p)) {
+                    if wanted(&path, encode_mode, opts) {
+                        pending.note(path, now);
+                    }
+                }
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => warn!("  watch error: {e}"),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let mut files: Vec<PathBuf> = pending
+            .take_settled(Instant::now())
+            .into_iter()
+            .filter(|p| p.is_file())
+            .collect();
+        if encode_mode && !opts.no_ignore {
+            files = filter_ignored(&root, files).0;
+        }
+        if files.is_empty() {
+            continue;
+        }
+
+        info!("");
+        let summary = match &master {
+            Some(master) => encode_files(&files, master, opts, None),
+            None => decode_files(&files, &keys, opts, None),
+        };
+        print_summary(&summary, mode);
+
+        if !summary.entries.is_empty() {
+            session.entries.extend(summary.entries);
+            session.write(&root)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Events that can mean a file has new content at its path.
+pub(crate) fn is_content_event(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+            | EventKind::Modify(_)
+            | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    )
+}
+
+/// The files an event path stands for. A new directory is watched only
+/// once its own event is handled, so anything written into it before
+/// then (or moved in along with it) must be picked up by walking it.
+fn files_at(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Whether a path is worth queueing: our own outputs, temp files, and
+/// bookkeeping are dropped quietly instead of being reported as skipped.
+fn wanted(path: &Path, encode_mode: bool, opts: &WrapOptions) -> bool {
+    if is_manifest(path) || is_config(path) || is_atomic_temp(path) {
+        return false;
+    }
+    if encode_mode {
+        // A .bed is only our output when encode refers
+        let ours = has_dna_extension(path) || (opts.refer.is_some() && has_refer_extension(path));
+        !ours
+    } else {
+        has_dna_extension(path) || has_refer_extension(path) || opts.sniff
+    }
+}
+
+/// Paths with recent events, waiting to go quiet.
+#[derive(Default)]
+struct Pending {
+    last_event: HashMap<PathBuf, Instant>,
+}
+
+impl Pending {
+    fn note(&mut self, path: PathBuf, at: Instant) {
+        self.last_event.insert(path, at);
+    }
+
+    /// Remove and return, sorted, the paths quiet for at least `SETTLE`.
+    fn take_settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut ready: Vec<PathBuf> = self
+            .last_event
+            .iter()
+            .filter(|(_, &at)| now.duration_since(at) >= SETTLE)
+            .map(|(p, _)| p.clone())
+            .collect();
+        for p in &ready {
+            self.last_event.remove(p);
+        }
+        ready.sort();
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_settle_before_transform() {
+        let t0 = Instant::now();
+        let mut pending = Pending::default();
+        pending.note(PathBuf::from("a.txt"), t0);
+        assert!(pending.take_settled(t0 + Duration::from_millis(100)).is_empty());
+
+        // A further write restarts the quiet period
+        pending.note(PathBuf::from("a.txt"), t0 + Duration::from_millis(500));
+        pending.note(PathBuf::from("b.txt"), t0 + Duration::from_millis(500));
+        assert!(pending.take_settled(t0 + SETTLE).is_empty());
+        let ready = pending.take_settled(t0 + Duration::from_millis(500) + SETTLE);
+        assert_eq!(ready, vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")]);
+        assert!(pending.take_settled(t0 + SETTLE * 10).is_empty());
+    }
+
+    #[test]
+    fn test_own_outputs_not_queued() {
+        let opts = WrapOptions::default();
+        assert!(wanted(Path::new("/box/report.pdf"), true, &opts));
+        assert!(!wanted(Path::new("/box/report.pdf.dna"), true, &opts));
+        assert!(!wanted(Path::new("/box/.dendec-wrap"), true, &opts));
+        assert!(!wanted(Path::new("/box/.dendec-a1B2c3.tmp"), true, &opts));
+        assert!(wanted(Path::new("/box/report.pdf.dna"), false, &opts));
+        assert!(!wanted(Path::new("/box/report.pdf"), false, &opts));
+    }
+}